@@ -0,0 +1,123 @@
+//! Interpreter throughput on small representative workloads. Run with
+//! `cargo bench`, or `cargo bench -- NAME` to run the workloads whose name
+//! contains NAME. Throughput is reported in instructions per second.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use simulator::{asm, console::BufferConsole, vm::Machine};
+
+/// Arithmetic and logic only, so mostly fetch, decode and dispatch
+const ALU: &str = r#"
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    ADD R2, R2, #3
+        AND R3, R2, #15
+        NOT R4, R3
+        ADD R5, R4, R2
+        AND R6, R5, R1
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #20000
+        .END
+"#;
+
+/// Every load and store addressing mode
+const MEMORY: &str = r#"
+        .ORIG x3000
+        LD R1, COUNT
+        LEA R4, DATA
+LOOP    LD R2, VALUE
+        ST R2, SLOT
+        LDR R3, R4, #0
+        STR R3, R4, #1
+        LDI R5, PTR
+        STI R5, PTR
+        ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #20000
+VALUE   .FILL x1234
+SLOT    .FILL 0
+PTR     .FILL DATA
+DATA    .FILL x5678
+        .FILL 0
+        .END
+"#;
+
+/// Instructions that set the condition codes, each followed by a branch on them
+const FLAGS: &str = r#"
+        .ORIG x3000
+        LD R1, COUNT
+LOOP    ADD R2, R1, #-8
+        BRn NEG
+        NOT R2, R2
+        BRz ZERO
+NEG     AND R3, R1, #1
+        BRz ZERO
+        ADD R3, R3, #0
+        BRnp ZERO
+ZERO    LD R4, COUNT
+        BRzp NEXT
+NEXT    ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+COUNT   .FILL #20000
+        .END
+"#;
+
+/// Subroutine calls, returns and jumps
+const CALLS: &str = r#"
+        .ORIG x3000
+        LD R1, COUNT
+        LEA R5, SUB
+LOOP    JSR SUB
+        JSRR R5
+        LEA R6, NEXT
+        JMP R6
+NEXT    ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+SUB     ADD R2, R2, #1
+        RET
+COUNT   .FILL #20000
+        .END
+"#;
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    for (name, src) in [
+        ("alu", ALU),
+        ("memory", MEMORY),
+        ("flags", FLAGS),
+        ("calls", CALLS),
+    ] {
+        let assembly = asm::assemble(src).expect("benchmark program should assemble");
+        let load = || {
+            let mut machine = Machine::with_console(Box::new(BufferConsole::default()));
+            machine
+                .load_words(assembly.origin, &assembly.words)
+                .unwrap();
+            machine
+        };
+
+        let mut machine = load();
+        machine.run().unwrap();
+        assert!(machine.halted());
+        group.throughput(Throughput::Elements(machine.step_count()));
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                load,
+                |mut machine| {
+                    machine.run().unwrap();
+                    machine
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
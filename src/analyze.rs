@@ -0,0 +1,229 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    enums::{RawOpCode, TrapCode},
+    image::Image,
+    memory::AddrSet,
+    utils::sign_extend,
+};
+
+/// Something likely wrong with an instruction reachable from the image origin
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub addr: u16,
+    pub message: String,
+}
+
+/// Result of statically walking an object image, see [`analyze`]
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub findings: Vec<Finding>,
+    /// Words reachable as instructions from the origin
+    pub code_words: usize,
+    pub data_words: usize,
+    /// Mnemonic -> number of reachable instructions using it
+    pub opcodes: BTreeMap<&'static str, usize>,
+    /// Trap vector -> number of reachable TRAP instructions
+    pub traps: BTreeMap<u8, usize>,
+}
+
+impl Analysis {
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for finding in &self.findings {
+            writeln!(out, "x{:04X}  {}", finding.addr, finding.message).unwrap();
+        }
+
+        writeln!(
+            out,
+            "{} words: {} code, {} data",
+            self.code_words + self.data_words,
+            self.code_words,
+            self.data_words
+        )
+        .unwrap();
+        let opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .map(|(name, count)| format!("{name} {count}"))
+            .collect();
+        writeln!(out, "Instructions: {}", opcodes.join(", ")).unwrap();
+        let traps: Vec<_> = self
+            .traps
+            .iter()
+            .map(|(vector, count)| match TrapCode::from_u8(*vector) {
+                Some(trap) => format!("{} {count}", trap.name()),
+                None => format!("x{vector:02X} {count}"),
+            })
+            .collect();
+        if !traps.is_empty() {
+            writeln!(out, "Traps: {}", traps.join(", ")).unwrap();
+        }
+        writeln!(out, "{} problems found", self.findings.len()).unwrap();
+
+        out
+    }
+}
+
+/// Walks every instruction reachable from the image origin, following
+/// branches and subroutine calls, without running anything.
+///
+/// Words that are never reached are assumed to be data, so only reachable
+/// code is checked for illegal encodings, jumps out of the image and traps
+/// to undefined vectors.
+pub fn analyze(image: &Image) -> Analysis {
+    let (first, last) = image.range();
+    let in_image = |addr: u16| (first..=last).contains(&addr);
+
+    let mut analysis = Analysis::default();
+    let mut code = AddrSet::default();
+    let mut work = vec![first];
+    let mut finding = |addr: u16, message: String| {
+        analysis.findings.push(Finding { addr, message });
+    };
+
+    while let Some(addr) = work.pop() {
+        if code.contains(addr) {
+            continue;
+        }
+        code.insert(addr);
+
+        let word = image.words[(addr - first) as usize];
+        let next = addr.wrapping_add(1);
+        let target = |bits: u16| next.wrapping_add(sign_extend(word & ((1 << bits) - 1), bits));
+        let mut successors = Vec::new();
+
+        match RawOpCode::from_u16(word >> 12).unwrap() {
+            RawOpCode::Br => {
+                let nzp = (word >> 9) & 0x7;
+                if nzp != 0 {
+                    successors.push((target(9), true));
+                }
+                if nzp != 0x7 {
+                    successors.push((next, false));
+                }
+            }
+            RawOpCode::Add | RawOpCode::And if (word >> 5) & 0x1 == 0 && word & 0x18 != 0 => {
+                finding(addr, format!("malformed register operand in x{word:04X}"));
+                successors.push((next, false));
+            }
+            RawOpCode::Not if word & 0x3F != 0x3F => {
+                finding(addr, format!("malformed NOT x{word:04X}"));
+                successors.push((next, false));
+            }
+            RawOpCode::Jmp => {
+                if word & 0x0E3F != 0 {
+                    finding(addr, format!("malformed JMP x{word:04X}"));
+                }
+            }
+            RawOpCode::Jsr => {
+                if (word >> 11) & 0x1 == 1 {
+                    successors.push((target(11), true));
+                } else if word & 0x063F != 0 {
+                    finding(addr, format!("malformed JSRR x{word:04X}"));
+                }
+                successors.push((next, false));
+            }
+            RawOpCode::Trap => {
+                let vector = (word & 0xFF) as u8;
+                *analysis.traps.entry(vector).or_default() += 1;
+                if word & 0x0F00 != 0 {
+                    finding(addr, format!("malformed TRAP x{word:04X}"));
+                }
+                match TrapCode::from_u8(vector) {
+                    Some(TrapCode::Halt) => (),
+                    Some(_) => successors.push((next, false)),
+                    None => finding(addr, format!("TRAP to undefined vector x{vector:02X}")),
+                }
+            }
+            RawOpCode::Rti => finding(addr, "RTI in a user program".to_owned()),
+            RawOpCode::Noop => {
+                finding(addr, format!("illegal opcode in x{word:04X}"));
+            }
+            _ => successors.push((next, false)),
+        }
+
+        *analysis.opcodes.entry(mnemonic(word)).or_default() += 1;
+
+        for (succ, jump) in successors {
+            if in_image(succ) {
+                work.push(succ);
+            } else if jump {
+                finding(addr, format!("jump to x{succ:04X}, outside the image"));
+            } else {
+                finding(addr, "execution runs past the end of the image".to_owned());
+            }
+        }
+    }
+
+    analysis.code_words = (first..=last).filter(|addr| code.contains(*addr)).count();
+    analysis.data_words = image.words.len() - analysis.code_words;
+    analysis.findings.sort_by_key(|finding| finding.addr);
+    analysis
+}
+
+fn mnemonic(word: u16) -> &'static str {
+    match RawOpCode::from_u16(word >> 12).unwrap() {
+        RawOpCode::Br => "BR",
+        RawOpCode::Add => "ADD",
+        RawOpCode::Ld => "LD",
+        RawOpCode::St => "ST",
+        RawOpCode::Jsr if (word >> 11) & 0x1 == 1 => "JSR",
+        RawOpCode::Jsr => "JSRR",
+        RawOpCode::And => "AND",
+        RawOpCode::Ldr => "LDR",
+        RawOpCode::Str => "STR",
+        RawOpCode::Rti => "RTI",
+        RawOpCode::Not => "NOT",
+        RawOpCode::Ldi => "LDI",
+        RawOpCode::Sti => "STI",
+        RawOpCode::Jmp if (word >> 6) & 0x7 == 7 => "RET",
+        RawOpCode::Jmp => "JMP",
+        RawOpCode::Noop => "reserved",
+        RawOpCode::Lea => "LEA",
+        RawOpCode::Trap => "TRAP",
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let image = Image {
+            origin: 0x3000,
+            words: vec![
+                0b0000_010_000000011, // BRz x3004
+                0b0100_1_11111111111, // JSR x3001 (itself)
+                0xF0FF,               // TRAP xFF
+                0b0000_111_011111111, // BRnzp x3103
+                0xD123,               // illegal
+                0xF025,               // HALT
+                0x1234,               // data, never reached
+            ],
+        };
+
+        let analysis = analyze(&image);
+        assert_eq!(
+            analysis.findings,
+            vec![
+                Finding {
+                    addr: 0x3002,
+                    message: "TRAP to undefined vector xFF".to_owned()
+                },
+                Finding {
+                    addr: 0x3004,
+                    message: "illegal opcode in xD123".to_owned()
+                },
+            ]
+        );
+        assert_eq!((analysis.code_words, analysis.data_words), (4, 3));
+        assert_eq!(analysis.opcodes["BR"], 1);
+        assert_eq!(analysis.traps[&0xFF], 1);
+        assert!(analysis.report().ends_with("2 problems found\n"));
+    }
+}
@@ -0,0 +1,536 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    error::{Error, ErrorKind, Result},
+    symbols::SymbolTable,
+    utils::{parse_number, parse_register},
+};
+
+/// Output of the assembler: a single contiguous image plus its labels
+pub struct Assembly {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    pub symbols: SymbolTable,
+}
+
+impl Assembly {
+    /// Object file contents: the origin followed by every word, big endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        std::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+}
+
+/// Assembles the file `source` into the object file `output`, with the
+/// symbol table next to it (`output` with a .sym extension)
+pub fn assemble_file(source: &Path, output: &Path) -> Result<()> {
+    let assembly = assemble(&fs::read_to_string(source)?)?;
+
+    fs::write(output, assembly.to_bytes())?;
+    fs::write(output.with_extension("sym"), assembly.symbols.to_string())?;
+
+    Ok(())
+}
+
+struct Line {
+    number: usize,
+    label: Option<String>,
+    op: Option<String>,
+    operands: Vec<String>,
+}
+
+/// Assembles LC-3 source in the dialect accepted by `lc3as`
+pub fn assemble(src: &str) -> Result<Assembly> {
+    let mut lines = Vec::new();
+    for (idx, text) in src.lines().enumerate() {
+        let line = parse_line(idx + 1, text)?;
+        if line.label.is_some() || line.op.is_some() {
+            lines.push(line);
+        }
+    }
+
+    let mut lines = lines.into_iter().peekable();
+    let origin = match lines.next() {
+        Some(Line {
+            op: Some(op),
+            operands,
+            label: None,
+            number,
+        }) if op == ".ORIG" => {
+            let [addr] = operands.as_slice() else {
+                return Err(line_error(number, "`.ORIG` takes one address"));
+            };
+            parse_number(addr).ok_or_else(|| line_error(number, "invalid `.ORIG` address"))?
+        }
+        Some(line) => return Err(line_error(line.number, "expected `.ORIG` first")),
+        None => return Err(line_error(0, "empty program")),
+    };
+
+    // Pass 1: assign addresses to labels
+    let mut body = Vec::new();
+    let mut symbols = SymbolTable::default();
+    let mut seen = HashMap::new();
+    let mut addr = origin;
+    for line in lines {
+        if line.op.as_deref() == Some(".END") {
+            break;
+        }
+
+        if let Some(label) = &line.label {
+            if let Some(prev) = seen.insert(label.clone(), line.number) {
+                return Err(line_error(
+                    line.number,
+                    &format!("label `{label}` already defined on line {prev}"),
+                ));
+            }
+            symbols.insert(label, addr);
+        }
+
+        let size = line_size(&line)?;
+        addr = addr.checked_add(size).ok_or_else(|| {
+            Error::with_message(
+                ErrorKind::AddressOutOfRange {
+                    addr: u32::from(addr) + u32::from(size),
+                },
+                format!("line {}: program runs past the end of memory", line.number),
+            )
+        })?;
+        body.push(line);
+    }
+
+    // Pass 2: encode
+    let mut words = Vec::new();
+    for line in &body {
+        let Some(op) = &line.op else {
+            continue;
+        };
+        let pc = origin.wrapping_add(words.len() as u16);
+        let at_line = |msg: String| line_error(line.number, &msg);
+
+        match op.as_str() {
+            ".FILL" => {
+                let [val] = line.operands.as_slice() else {
+                    return Err(at_line("`.FILL` takes one value".to_owned()));
+                };
+                words.push(value(val, &symbols).map_err(at_line)?);
+            }
+            ".BLKW" => {
+                let count = line_size(line)?;
+                words.extend(std::iter::repeat_n(0, count as usize));
+            }
+            ".STRINGZ" => {
+                let text = unquote(&line.operands[0]).map_err(at_line)?;
+                words.extend(text.bytes().map(u16::from));
+                words.push(0);
+            }
+            _ => words.push(encode(op, &line.operands, pc, &symbols).map_err(at_line)?),
+        }
+    }
+
+    Ok(Assembly {
+        origin,
+        words,
+        symbols,
+    })
+}
+
+/// Assembles a single instruction as if it were located at `addr`
+pub fn assemble_instruction(src: &str, addr: u16, symbols: &SymbolTable) -> Result<u16> {
+    let line = parse_line(1, src)?;
+    match (&line.label, &line.op) {
+        (None, Some(op)) if !op.starts_with('.') => {
+            encode(op, &line.operands, addr, symbols).map_err(|msg| line_error(1, &msg))
+        }
+        _ => Err(line_error(1, "expected a single instruction")),
+    }
+}
+
+fn line_error(number: usize, msg: &str) -> Error {
+    Error::with_message(ErrorKind::ParseError, format!("line {number}: {msg}"))
+}
+
+fn parse_line(number: usize, text: &str) -> Result<Line> {
+    let tokens = tokenize(text).map_err(|msg| line_error(number, &msg))?;
+    let mut tokens = tokens.into_iter().peekable();
+
+    let label = match tokens.peek() {
+        Some(first) if !is_op(first) => {
+            if !is_label(first) {
+                return Err(line_error(number, &format!("invalid label `{first}`")));
+            }
+            tokens.next()
+        }
+        _ => None,
+    };
+    let op = tokens.next().map(|op| op.to_ascii_uppercase());
+    if let Some(op) = &op {
+        if !is_op(op) {
+            return Err(line_error(number, &format!("unknown instruction `{op}`")));
+        }
+    }
+
+    Ok(Line {
+        number,
+        label,
+        op,
+        operands: tokens.collect(),
+    })
+}
+
+/// Splits a line into tokens on whitespace and commas, dropping comments.
+/// Quoted strings are kept whole, including their quotes.
+fn tokenize(text: &str) -> std::result::Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ';' => break,
+            '"' => {
+                current.push(ch);
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            current.push('\\');
+                            current.extend(chars.next());
+                        }
+                        Some('"') => {
+                            current.push('"');
+                            break;
+                        }
+                        Some(ch) => current.push(ch),
+                        None => return Err("unterminated string".to_owned()),
+                    }
+                }
+            }
+            ch if ch.is_whitespace() || ch == ',' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            ch => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
+const OPCODES: [&str; 29] = [
+    "ADD", "AND", "NOT", "BR", "JMP", "RET", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI",
+    "STR", "TRAP", "RTI", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT", "NOP", ".ORIG", ".FILL",
+    ".BLKW", ".STRINGZ", ".END",
+];
+
+fn is_op(token: &str) -> bool {
+    let upper = token.to_ascii_uppercase();
+    OPCODES.contains(&upper.as_str()) || branch_flags(&upper).is_some()
+}
+
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && parse_register(token).is_none()
+}
+
+/// Condition bits of a `BR[n][z][p]` mnemonic, `BR` alone meaning `BRnzp`
+fn branch_flags(op: &str) -> Option<u16> {
+    let flags = op.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some(0x7);
+    }
+
+    let mut bits = 0;
+    let mut rest = flags;
+    for (flag, bit) in [('N', 0x4), ('Z', 0x2), ('P', 0x1)] {
+        if let Some(r) = rest.strip_prefix(flag) {
+            bits |= bit;
+            rest = r;
+        }
+    }
+    rest.is_empty().then_some(bits)
+}
+
+fn line_size(line: &Line) -> Result<u16> {
+    let size = match line.op.as_deref() {
+        None => 0,
+        Some(".BLKW") => match line.operands.as_slice() {
+            [count] => parse_number(count)
+                .ok_or_else(|| line_error(line.number, "invalid `.BLKW` count"))?,
+            _ => return Err(line_error(line.number, "`.BLKW` takes one count")),
+        },
+        Some(".STRINGZ") => match line.operands.as_slice() {
+            [text] => {
+                let text = unquote(text).map_err(|msg| line_error(line.number, &msg))?;
+                text.len() as u16 + 1
+            }
+            _ => return Err(line_error(line.number, "`.STRINGZ` takes one string")),
+        },
+        Some(".ORIG") => return Err(line_error(line.number, "only one `.ORIG` is supported")),
+        Some(_) => 1,
+    };
+    Ok(size)
+}
+
+fn unquote(token: &str) -> std::result::Result<String, String> {
+    let inner = token
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found `{token}`"))?;
+
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('e') => out.push('\x1b'),
+            Some('0') => out.push('\0'),
+            Some(ch @ ('"' | '\\')) => out.push(ch),
+            _ => return Err("invalid escape sequence".to_owned()),
+        }
+    }
+
+    if !out.is_ascii() {
+        return Err("strings must be ASCII".to_owned());
+    }
+    Ok(out)
+}
+
+fn encode(
+    op: &str,
+    operands: &[String],
+    pc: u16,
+    symbols: &SymbolTable,
+) -> std::result::Result<u16, String> {
+    let args = |count: usize| {
+        if operands.len() == count {
+            Ok(())
+        } else {
+            Err(format!("`{op}` takes {count} operand(s)"))
+        }
+    };
+
+    let word = match op {
+        "ADD" | "AND" => {
+            args(3)?;
+            let base = if op == "ADD" { 0x1000 } else { 0x5000 };
+            let operand = match parse_register(&operands[2]) {
+                Some(_) => reg(&operands[2])?,
+                None => 0x20 | imm(&operands[2], 5)?,
+            };
+            base | reg(&operands[0])? << 9 | reg(&operands[1])? << 6 | operand
+        }
+        "NOT" => {
+            args(2)?;
+            0x903F | reg(&operands[0])? << 9 | reg(&operands[1])? << 6
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            args(2)?;
+            let base = match op {
+                "LD" => 0x2000,
+                "LDI" => 0xA000,
+                "LEA" => 0xE000,
+                "ST" => 0x3000,
+                _ => 0xB000,
+            };
+            base | reg(&operands[0])? << 9 | pc_offset(&operands[1], 9, pc, symbols)?
+        }
+        "LDR" | "STR" => {
+            args(3)?;
+            let base = if op == "LDR" { 0x6000 } else { 0x7000 };
+            base | reg(&operands[0])? << 9 | reg(&operands[1])? << 6 | imm(&operands[2], 6)?
+        }
+        "JMP" => {
+            args(1)?;
+            0xC000 | reg(&operands[0])? << 6
+        }
+        "JSRR" => {
+            args(1)?;
+            0x4000 | reg(&operands[0])? << 6
+        }
+        "JSR" => {
+            args(1)?;
+            0x4800 | pc_offset(&operands[0], 11, pc, symbols)?
+        }
+        "TRAP" => {
+            args(1)?;
+            let vector = parse_number(&operands[0])
+                .filter(|vector| *vector <= 0xFF)
+                .ok_or_else(|| format!("invalid trap vector `{}`", operands[0]))?;
+            0xF000 | vector
+        }
+        "RET" | "RTI" | "NOP" | "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => {
+            args(0)?;
+            match op {
+                "RET" => 0xC1C0,
+                "RTI" => 0x8000,
+                "NOP" => 0x0000,
+                "GETC" => 0xF020,
+                "OUT" => 0xF021,
+                "PUTS" => 0xF022,
+                "IN" => 0xF023,
+                "PUTSP" => 0xF024,
+                _ => 0xF025,
+            }
+        }
+        _ => {
+            let flags = branch_flags(op).ok_or_else(|| format!("unknown instruction `{op}`"))?;
+            args(1)?;
+            flags << 9 | pc_offset(&operands[0], 9, pc, symbols)?
+        }
+    };
+
+    Ok(word)
+}
+
+fn reg(token: &str) -> std::result::Result<u16, String> {
+    match parse_register(token) {
+        Some(reg) if (reg as u16) < 8 => Ok(reg as u16),
+        _ => Err(format!("expected a register, found `{token}`")),
+    }
+}
+
+/// Signed immediate that must fit in `bits` bits
+fn imm(token: &str, bits: u32) -> std::result::Result<u16, String> {
+    let val = parse_number(token).ok_or_else(|| format!("invalid immediate `{token}`"))?;
+    fit(val as i16 as i32, bits).ok_or_else(|| format!("`{token}` does not fit in {bits} bits"))
+}
+
+/// PC-relative offset to a label, or a literal offset
+fn pc_offset(
+    token: &str,
+    bits: u32,
+    pc: u16,
+    symbols: &SymbolTable,
+) -> std::result::Result<u16, String> {
+    if let Some(offset) = parse_number(token) {
+        return fit(offset as i16 as i32, bits)
+            .ok_or_else(|| format!("offset `{token}` does not fit in {bits} bits"));
+    }
+
+    let target = symbols
+        .lookup(token)
+        .ok_or_else(|| format!("undefined label `{token}`"))?;
+    let offset = target as i32 - (pc as i32 + 1);
+    fit(offset, bits).ok_or_else(|| format!("label `{token}` is too far away"))
+}
+
+fn value(token: &str, symbols: &SymbolTable) -> std::result::Result<u16, String> {
+    parse_number(token)
+        .or_else(|| symbols.lookup(token))
+        .ok_or_else(|| format!("invalid value `{token}`"))
+}
+
+fn fit(val: i32, bits: u32) -> Option<u16> {
+    let limit = 1 << (bits - 1);
+    (-limit..limit)
+        .contains(&val)
+        .then_some(val as u16 & ((1 << bits) - 1))
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_hello_world() {
+        let src = r#"
+            .ORIG x3000
+                    LEA R0, HELLO   ; load the string
+                    PUTS
+                    HALT
+            HELLO   .STRINGZ "Hello World!"
+            .END
+        "#;
+        let assembly = assemble(src).unwrap();
+
+        assert_eq!(assembly.origin, 0x3000);
+        assert_eq!(assembly.symbols.lookup("HELLO"), Some(0x3003));
+        assert_eq!(
+            assembly.to_bytes(),
+            std::fs::read("roms/hello-world.obj").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_assemble_instructions() {
+        let src = "
+            .ORIG x3000
+            LOOP ADD R3, R0, R1
+                 and r2, r4, #-13
+                 NOT R3, R0
+                 BRzp LOOP
+                 LDR R5, R0, #-27
+                 JSR DATA
+                 JSRR R5
+                 RET
+                 TRAP x30
+            DATA .FILL LOOP
+                 .BLKW 2
+            .END
+        ";
+        let assembly = assemble(src).unwrap();
+
+        assert_eq!(
+            assembly.words,
+            vec![
+                0b0001_011_000_0_00_001,
+                0b0101_010_100_1_10011,
+                0b1001_011_000_111111,
+                0b0000_0_1_1_111111100,
+                0b0110_101_000_100101,
+                0b0100_1_00000000011,
+                0b0100_0_00_101_000000,
+                0xC1C0,
+                0xF030,
+                0x3000,
+                0,
+                0,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(assemble("").is_err());
+        assert!(assemble("ADD R0, R0, R0").is_err());
+        assert!(assemble(".ORIG x3000\nADD R0, R0, #16\n.END").is_err());
+        assert!(assemble(".ORIG x3000\nBR NOWHERE\n.END").is_err());
+        assert!(assemble(".ORIG x3000\nA ADD R0, R0, R0\nA HALT\n.END").is_err());
+        assert!(assemble(".ORIG x3000\nFOO R0\n.END").is_err());
+
+        let err = assemble(".ORIG x3000\nHALT\nLD R0\n.END").err().unwrap();
+        assert_eq!(err.message(), "line 3: `LD` takes 2 operand(s)");
+    }
+
+    #[test]
+    fn test_assemble_instruction() {
+        let mut symbols = SymbolTable::default();
+        symbols.insert("LOOP", 0x3000);
+
+        assert_eq!(
+            assemble_instruction("ADD R1, R1, #1", 0x3050, &symbols).unwrap(),
+            0x1261
+        );
+        assert_eq!(
+            assemble_instruction("BRnzp LOOP", 0x3002, &symbols).unwrap(),
+            0x0FFD
+        );
+        assert!(assemble_instruction("LOOP HALT", 0x3000, &symbols).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{checks::MemRegion, memory::MemoryManager};
+
+/// Extra memory behind a window of the address space, for programs that
+/// outgrow 64K words. Writing a bank number to the bank select register
+/// (xFE18) maps that bank into the window; reading it gives the current bank.
+/// Bank 0 is what the window holds at startup, e.g. whatever was loaded there.
+#[derive(Clone, Debug)]
+pub struct Banks {
+    window: MemRegion,
+    /// Contents of every bank, except the current one's which live in memory
+    stored: Vec<Vec<u16>>,
+    current: u16,
+}
+
+impl Banks {
+    /// `count` banks (at least one) of the size of `window`
+    pub fn new(count: u16, window: MemRegion) -> Self {
+        let size = (window.last - window.first) as usize + 1;
+        Self {
+            window,
+            stored: vec![vec![0; size]; count.max(1) as usize],
+            current: 0,
+        }
+    }
+
+    pub fn window(&self) -> MemRegion {
+        self.window
+    }
+
+    pub fn count(&self) -> u16 {
+        self.stored.len() as u16
+    }
+
+    pub fn current(&self) -> u16 {
+        self.current
+    }
+
+    /// Maps `bank` into the window, saving the contents of the current one.
+    /// Returns `false`, leaving the window alone, if there is no such bank.
+    pub fn select(&mut self, bank: u16, mem: &mut MemoryManager) -> bool {
+        if bank >= self.count() {
+            return false;
+        }
+        if bank == self.current {
+            return true;
+        }
+
+        let window = self.window.first..=self.window.last;
+        for (slot, addr) in self.stored[self.current as usize]
+            .iter_mut()
+            .zip(window.clone())
+        {
+            *slot = mem.peek(addr);
+        }
+        for (word, addr) in self.stored[bank as usize].iter().zip(window) {
+            mem.load(addr, *word);
+        }
+        self.current = bank;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let mut mem = MemoryManager::default();
+        let window = MemRegion {
+            first: 0xC000,
+            last: 0xC001,
+        };
+        let mut banks = Banks::new(3, window);
+        mem.write(0xC000, 0x1111);
+
+        assert!(banks.select(2, &mut mem));
+        assert_eq!(mem.peek(0xC000), 0);
+        mem.write(0xC001, 0x2222);
+
+        assert!(banks.select(0, &mut mem));
+        assert_eq!((mem.peek(0xC000), mem.peek(0xC001)), (0x1111, 0));
+        assert!(banks.select(2, &mut mem));
+        assert_eq!((mem.peek(0xC000), mem.peek(0xC001)), (0, 0x2222));
+
+        assert!(!banks.select(3, &mut mem));
+        assert_eq!(banks.current(), 2);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{disasm, symbols::SymbolTable};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Taken/not-taken counts per conditional branch, keyed by the address of
+/// the BR. Unconditional branches (BR, BRnzp) and NOPs are left out since
+/// they always go the same way.
+#[derive(Default, Clone, Debug)]
+pub struct BranchStats {
+    sites: BTreeMap<u16, (u16, BranchCounts)>,
+}
+
+impl BranchStats {
+    /// Records the BR `word` at `pc` executing with `nzp` condition bits
+    pub fn record(&mut self, pc: u16, word: u16, nzp: u8, taken: bool) {
+        if nzp == 0 || nzp == 0b111 {
+            return;
+        }
+        let (_, counts) = self.sites.entry(pc).or_insert((word, Default::default()));
+        if taken {
+            counts.taken += 1;
+        } else {
+            counts.not_taken += 1;
+        }
+    }
+
+    pub fn get(&self, pc: u16) -> Option<BranchCounts> {
+        self.sites.get(&pc).map(|(_, counts)| *counts)
+    }
+
+    /// One line per branch site, in address order
+    pub fn report(&self, symbols: &SymbolTable) -> String {
+        let mut out = String::from("Branches:\n");
+        writeln!(
+            out,
+            "  {:<16}  {:<16}  {:>10}  {:>10}  {:>7}",
+            "site", "instruction", "taken", "not taken", "taken%"
+        )
+        .unwrap();
+        for (pc, (word, counts)) in &self.sites {
+            let site = match symbols.nearest(*pc) {
+                Some((name, addr)) if addr == *pc => name.to_owned(),
+                Some((name, addr)) => format!("{name}+{}", pc - addr),
+                None => format!("x{pc:04X}"),
+            };
+            let total = counts.taken + counts.not_taken;
+            writeln!(
+                out,
+                "  {site:<16}  {:<16}  {:>10}  {:>10}  {:>7.2}",
+                disasm::disassemble_with(*pc, *word, symbols),
+                counts.taken,
+                counts.not_taken,
+                counts.taken as f64 * 100.0 / total.max(1) as f64
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_stats() {
+        let mut stats = BranchStats::default();
+        // BRp x3001, taken twice then falling through
+        stats.record(0x3002, 0x03FE, 0b001, true);
+        stats.record(0x3002, 0x03FE, 0b001, true);
+        stats.record(0x3002, 0x03FE, 0b001, false);
+        // Unconditional branches aren't counted
+        stats.record(0x3003, 0x0FFD, 0b111, true);
+
+        assert_eq!(
+            stats.get(0x3002),
+            Some(BranchCounts {
+                taken: 2,
+                not_taken: 1
+            })
+        );
+        assert_eq!(stats.get(0x3003), None);
+
+        let mut symbols = SymbolTable::default();
+        symbols.insert("LOOP", 0x3001);
+        let report = stats.report(&symbols);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("  LOOP+1            BRp LOOP"));
+        assert!(lines[2].ends_with("2           1    66.67"));
+    }
+}
@@ -0,0 +1,103 @@
+/// Frames deeper than this are dropped from the bottom, so programs that
+/// JSR without ever returning can't grow the stack without bound
+const MAX_DEPTH: usize = 1 << 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Address of the JSR/JSRR instruction
+    pub call_site: u16,
+    /// Address of the called subroutine
+    pub entry: u16,
+    /// Where the subroutine is expected to RET to
+    pub return_addr: u16,
+}
+
+/// Shadow call stack, tracking JSR/JSRR and RET as the program executes.
+///
+/// LC-3 has no hardware stack, so this is a best effort reconstruction:
+/// a RET to an address no frame expects leaves the stack untouched.
+#[derive(Default, Clone, Debug)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn call(&mut self, frame: Frame) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    /// Records a RET to `target`, popping every frame above the one returning there
+    pub fn ret(&mut self, target: u16) -> Option<Frame> {
+        let idx = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_addr == target)?;
+        let frame = self.frames[idx];
+        self.frames.truncate(idx);
+        Some(frame)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn top(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    /// Frames from innermost to outermost
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(call_site: u16, entry: u16) -> Frame {
+        Frame {
+            call_site,
+            entry,
+            return_addr: call_site + 1,
+        }
+    }
+
+    #[test]
+    fn test_call_ret() {
+        let mut stack = CallStack::default();
+        stack.call(frame(0x3000, 0x3100));
+        stack.call(frame(0x3105, 0x3200));
+        assert_eq!(stack.depth(), 2);
+        assert_eq!(stack.top().unwrap().entry, 0x3200);
+
+        assert_eq!(stack.ret(0x4000), None);
+        assert_eq!(stack.depth(), 2);
+
+        assert_eq!(stack.ret(0x3106), Some(frame(0x3105, 0x3200)));
+        assert_eq!(stack.depth(), 1);
+    }
+
+    #[test]
+    fn test_ret_unwinds() {
+        let mut stack = CallStack::default();
+        stack.call(frame(0x3000, 0x3100));
+        stack.call(frame(0x3105, 0x3200));
+        stack.call(frame(0x3205, 0x3300));
+
+        assert_eq!(stack.ret(0x3001), Some(frame(0x3000, 0x3100)));
+        assert_eq!(stack.depth(), 0);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut stack = CallStack::default();
+        for _ in 0..=MAX_DEPTH {
+            stack.call(frame(0x3000, 0x3000));
+        }
+        assert_eq!(stack.depth(), MAX_DEPTH);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use crate::{
+    banking::Banks,
+    callstack::CallStack,
+    checks::ConventionCheck,
+    interrupt::{InterruptController, Psr},
+    memory::{PagedMemory, RegisterManager},
+    perfcount::PerfCounters,
+    taint::Taint,
+    watchdog::Watchdog,
+};
+
+/// Machine state captured at a given instruction count
+#[derive(Clone)]
+pub struct Checkpoint {
+    pub steps: u64,
+    pub reg: RegisterManager,
+    pub memory: PagedMemory,
+    pub call_stack: CallStack,
+    pub psr: Psr,
+    pub interrupts: InterruptController,
+    pub perf: Option<PerfCounters>,
+    pub watchdog: Option<Watchdog>,
+    /// Banks not mapped into the window live here, not in `memory`
+    pub banks: Option<Banks>,
+    /// Register values at entry of the frames on the call stack
+    pub convention_check: Option<ConventionCheck>,
+    pub taint: Option<Taint>,
+    /// Number of input bytes the program had consumed at this point
+    pub input_pos: usize,
+}
+
+/// Ring of checkpoints taken every `interval` instructions, oldest first
+#[derive(Clone)]
+pub struct Checkpoints {
+    interval: u64,
+    capacity: usize,
+    ring: VecDeque<Checkpoint>,
+}
+
+impl Checkpoints {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            ring: VecDeque::new(),
+        }
+    }
+
+    /// Whether a checkpoint should be taken before executing instruction number `steps`
+    pub fn is_due(&self, steps: u64) -> bool {
+        steps.is_multiple_of(self.interval) && self.ring.back().is_none_or(|cp| cp.steps < steps)
+    }
+
+    pub fn push(&mut self, checkpoint: Checkpoint) {
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(checkpoint);
+    }
+
+    /// Latest checkpoint at or before `steps`. Newer checkpoints are
+    /// discarded, since execution is about to continue from the older one.
+    pub fn rewind(&mut self, steps: u64) -> Option<Checkpoint> {
+        while self.ring.back().is_some_and(|cp| cp.steps > steps) {
+            self.ring.pop_back();
+        }
+        self.ring.back().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Instruction count of the oldest checkpoint still kept
+    pub fn oldest(&self) -> Option<u64> {
+        self.ring.front().map(|cp| cp.steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(steps: u64) -> Checkpoint {
+        Checkpoint {
+            steps,
+            reg: RegisterManager::default(),
+            memory: PagedMemory::default(),
+            call_stack: CallStack::default(),
+            psr: Psr::default(),
+            interrupts: InterruptController::default(),
+            perf: None,
+            watchdog: None,
+            banks: None,
+            convention_check: None,
+            taint: None,
+            input_pos: 0,
+        }
+    }
+
+    #[test]
+    fn test_ring() {
+        let mut checkpoints = Checkpoints::new(10, 2);
+        assert!(checkpoints.is_due(0));
+        assert!(!checkpoints.is_due(5));
+
+        checkpoints.push(checkpoint(0));
+        assert!(!checkpoints.is_due(0));
+        checkpoints.push(checkpoint(10));
+        checkpoints.push(checkpoint(20));
+        assert_eq!(checkpoints.len(), 2);
+        assert_eq!(checkpoints.oldest(), Some(10));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut checkpoints = Checkpoints::new(10, 8);
+        for steps in [0, 10, 20, 30] {
+            checkpoints.push(checkpoint(steps));
+        }
+
+        assert_eq!(checkpoints.rewind(25).unwrap().steps, 20);
+        assert_eq!(checkpoints.len(), 3);
+        assert!(!checkpoints.is_due(20));
+        assert!(checkpoints.is_due(30));
+    }
+}
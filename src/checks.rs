@@ -0,0 +1,389 @@
+use std::str::FromStr;
+
+use crate::{
+    callstack::Frame,
+    enums::{ArithTrap, IoTrap, RawOpCode, TrapCode},
+    ext::{ExtInstruction, Extensions},
+    trace::OpcodeSet,
+    utils::{parse_number, parse_register},
+};
+
+/// How the machine reacts when a runtime check catches a likely bug
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum CheckMode {
+    /// Print a warning the first time each address is involved, and keep going
+    Warn,
+    /// Pause in the debugger, or stop the machine when running without one
+    Strict,
+}
+
+/// Which check produced a report, so warnings are deduplicated per check
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    Uninit,
+    SelfModify,
+    Stack,
+    Convention,
+    ReadOnly,
+    Watchdog,
+    Bounds,
+}
+
+/// Region R6 is expected to stay within. The stack grows down from `high`
+/// (empty) towards `low` (full).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackBounds {
+    pub low: u16,
+    pub high: u16,
+}
+
+impl Default for StackBounds {
+    fn default() -> Self {
+        Self {
+            low: 0xF000,
+            high: 0xFE00,
+        }
+    }
+}
+
+impl FromStr for StackBounds {
+    type Err = String;
+
+    /// Parses `LOW:HIGH`, e.g. `xF000:xFE00`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (low, high) = s
+            .split_once(':')
+            .and_then(|(low, high)| Some((parse_number(low)?, parse_number(high)?)))
+            .ok_or_else(|| format!("expected LOW:HIGH, got `{s}`"))?;
+        if low > high {
+            return Err(format!("stack bounds {low:#06x} > {high:#06x}"));
+        }
+        Ok(Self { low, high })
+    }
+}
+
+/// Inclusive address range, e.g. a region to write-protect
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemRegion {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl FromStr for MemRegion {
+    type Err = String;
+
+    /// Parses `FIRST:LAST` or `FIRST-LAST`, e.g. `x3000:x30FF`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s
+            .split_once(':')
+            .or_else(|| s.split_once('-'))
+            .and_then(|(first, last)| Some((parse_number(first)?, parse_number(last)?)))
+            .ok_or_else(|| format!("expected FIRST:LAST, got `{s}`"))?;
+        if first > last {
+            return Err(format!("region {first:#06x} > {last:#06x}"));
+        }
+        Ok(Self { first, last })
+    }
+}
+
+/// Registers a subroutine must leave unchanged, as a bit per R0-R7
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CalleeSaved(u8);
+
+impl CalleeSaved {
+    pub fn contains(&self, reg: usize) -> bool {
+        self.0 & (1 << reg) != 0
+    }
+}
+
+impl FromStr for CalleeSaved {
+    type Err = String;
+
+    /// Parses a comma separated register list, e.g. `R1,R2,R3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mask = 0;
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match parse_register(name).map(|reg| reg as usize) {
+                Some(reg) if reg < 8 => mask |= 1 << reg,
+                _ => return Err(format!("`{name}` is not one of R0-R7")),
+            }
+        }
+        Ok(Self(mask))
+    }
+}
+
+/// Trap vectors a program may not use, as a bit per vector
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrapSet([u128; 2]);
+
+impl TrapSet {
+    pub fn contains(&self, vector: u8) -> bool {
+        self.0[vector as usize >> 7] & (1 << (vector & 0x7F)) != 0
+    }
+
+    pub fn insert(&mut self, vector: u8) {
+        self.0[vector as usize >> 7] |= 1 << (vector & 0x7F);
+    }
+}
+
+impl FromStr for TrapSet {
+    type Err = String;
+
+    /// Parses a comma separated list of trap aliases or vectors, e.g.
+    /// `PUTS,IN` or `x22,x23`. The aliases include the optional I/O and
+    /// arithmetic traps, e.g. `GETN` or `MUL`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = Self::default();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let alias = TrapCode::from_name(name)
+                .map(|trap| trap as u8)
+                .or_else(|| IoTrap::from_name(name).map(|trap| trap as u8))
+                .or_else(|| ArithTrap::from_name(name).map(|trap| trap as u8));
+            let vector = match alias {
+                Some(vector) => vector,
+                None => parse_number(name)
+                    .and_then(|vector| u8::try_from(vector).ok())
+                    .ok_or_else(|| format!("`{name}` is not a trap alias or vector"))?,
+            };
+            set.insert(vector);
+        }
+        Ok(set)
+    }
+}
+
+/// Instructions a program may not execute: LC-3 opcodes, plus extended
+/// instructions named `<extension>-ext`. `MUL-ext`, `DIV-ext` and `MOD-ext`
+/// name the arithmetic traps, which stand in for instructions the LC-3 lacks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ForbiddenOpcodes {
+    opcodes: OpcodeSet,
+    ext: Extensions,
+    arith: TrapSet,
+}
+
+impl ForbiddenOpcodes {
+    pub fn contains(&self, opcode: RawOpCode) -> bool {
+        self.opcodes.contains(opcode)
+    }
+
+    pub fn contains_ext(&self, instr: &ExtInstruction) -> bool {
+        match instr {
+            ExtInstruction::Shf { .. } => self.ext.shf,
+            ExtInstruction::Xor { .. } => self.ext.xor,
+        }
+    }
+
+    pub fn contains_arith(&self, op: ArithTrap) -> bool {
+        self.arith.contains(op as u8)
+    }
+}
+
+impl FromStr for ForbiddenOpcodes {
+    type Err = String;
+
+    /// Parses a comma separated list of mnemonics and extensions, e.g.
+    /// `LDI,xor-ext,MUL-ext`. Mnemonics are read like `--trace-opcodes`, so
+    /// RET forbids JMP too
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ext, opcodes): (Vec<_>, Vec<_>) = s
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .partition(|name| name.to_ascii_lowercase().ends_with("-ext"));
+        let mut arith = TrapSet::default();
+        let ext: Vec<_> = ext
+            .iter()
+            .map(|name| &name[..name.len() - "-ext".len()])
+            .filter(|name| match ArithTrap::from_name(name) {
+                Some(op) => {
+                    arith.insert(op as u8);
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        let ext = if ext.is_empty() {
+            Extensions::default()
+        } else {
+            ext.join(",").parse()?
+        };
+        Ok(Self {
+            opcodes: opcodes.join(",").parse()?,
+            ext,
+            arith,
+        })
+    }
+}
+
+/// Calling-convention lint: compares registers at subroutine entry and exit.
+///
+/// `entry_regs` mirrors the shadow call stack, one entry per frame.
+#[derive(Clone)]
+pub struct ConventionCheck {
+    pub mode: CheckMode,
+    callee_saved: CalleeSaved,
+    entry_regs: Vec<[u16; 8]>,
+}
+
+impl ConventionCheck {
+    pub fn new(mode: CheckMode, callee_saved: CalleeSaved) -> Self {
+        Self {
+            mode,
+            callee_saved,
+            entry_regs: Vec::new(),
+        }
+    }
+
+    /// Records a JSR/JSRR, `depth` being the call stack depth after the call
+    pub fn call(&mut self, regs: [u16; 8], depth: usize) {
+        self.entry_regs.push(regs);
+        if self.entry_regs.len() > depth {
+            self.entry_regs.remove(0);
+        }
+    }
+
+    /// Checks a RET at `ret_site`. `frame` is the frame the call stack popped
+    /// (if any), `top` the frame left on top and `depth` the depth afterwards.
+    /// Returns (key, message) pairs for every violation.
+    pub fn ret(
+        &mut self,
+        ret_site: u16,
+        regs: [u16; 8],
+        frame: Option<Frame>,
+        top: Option<&Frame>,
+        depth: usize,
+    ) -> Vec<(u16, String)> {
+        let mut found = Vec::new();
+
+        match frame {
+            Some(frame) => {
+                if let Some(entry) = self.entry_regs.get(depth) {
+                    for reg in (0..8).filter(|reg| self.callee_saved.contains(*reg)) {
+                        if entry[reg] != regs[reg] {
+                            found.push((
+                                frame.entry,
+                                format!(
+                                    "Subroutine {:#06x} returned with R{reg} changed: {:#06x} -> {:#06x}",
+                                    frame.entry, entry[reg], regs[reg]
+                                ),
+                            ));
+                        }
+                    }
+                }
+                self.entry_regs.truncate(depth);
+            }
+            None => {
+                if let Some(top) = top {
+                    found.push((
+                        ret_site,
+                        format!(
+                            "RET at {ret_site:#06x} went to {:#06x}, but {:#06x} was called from {:#06x}: \
+                             R7 was overwritten without being saved",
+                            regs[7], top.entry, top.call_site
+                        ),
+                    ));
+                }
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mem_region() {
+        assert_eq!(
+            "x3000:x30FF".parse(),
+            Ok(MemRegion {
+                first: 0x3000,
+                last: 0x30FF
+            })
+        );
+        assert_eq!("x3000-x30FF".parse(), "x3000:x30FF".parse::<MemRegion>());
+        assert!("x3000".parse::<MemRegion>().is_err());
+        assert!("x3001:x3000".parse::<MemRegion>().is_err());
+    }
+
+    #[test]
+    fn test_stack_bounds() {
+        assert_eq!(
+            "x4000:x5000".parse::<StackBounds>(),
+            Ok(StackBounds {
+                low: 0x4000,
+                high: 0x5000
+            })
+        );
+        assert!("x5000:x4000".parse::<StackBounds>().is_err());
+        assert!("x4000".parse::<StackBounds>().is_err());
+    }
+
+    #[test]
+    fn test_callee_saved() {
+        let saved = "R1, R6".parse::<CalleeSaved>().unwrap();
+        assert!(saved.contains(1) && saved.contains(6));
+        assert!(!saved.contains(0));
+        assert!("R1,PC".parse::<CalleeSaved>().is_err());
+    }
+
+    #[test]
+    fn test_trap_set() {
+        let traps = "puts, x23,xFF".parse::<TrapSet>().unwrap();
+        assert!(traps.contains(0x22) && traps.contains(0x23) && traps.contains(0xFF));
+        assert!(!traps.contains(0x25));
+        assert!(!TrapSet::default().contains(0x22));
+        assert!("PUTS,x100".parse::<TrapSet>().is_err());
+        assert!("PRINT".parse::<TrapSet>().is_err());
+
+        let traps = "MUL,getn".parse::<TrapSet>().unwrap();
+        assert!(traps.contains(0x40) && traps.contains(0x27));
+        assert!(!traps.contains(0x41));
+    }
+
+    #[test]
+    fn test_forbidden_opcodes() {
+        let forbidden = "LDI, xor-ext".parse::<ForbiddenOpcodes>().unwrap();
+        assert!(forbidden.contains(RawOpCode::Ldi));
+        assert!(!forbidden.contains(RawOpCode::Ld));
+        let ext = Extensions {
+            shf: true,
+            xor: true,
+        };
+        // XOR R0, R0, R0 and LSHF R0, R0, #1
+        assert!(forbidden.contains_ext(&ExtInstruction::decode(0xD020, ext).unwrap()));
+        assert!(!forbidden.contains_ext(&ExtInstruction::decode(0xD001, ext).unwrap()));
+        assert!(!forbidden.contains_arith(ArithTrap::Mul));
+
+        let forbidden = "MUL-ext, mod-ext".parse::<ForbiddenOpcodes>().unwrap();
+        assert!(forbidden.contains_arith(ArithTrap::Mul));
+        assert!(!forbidden.contains_arith(ArithTrap::Div));
+        assert!(forbidden.contains_arith(ArithTrap::Mod));
+        assert!("SQRT-ext".parse::<ForbiddenOpcodes>().is_err());
+        assert!("LDX".parse::<ForbiddenOpcodes>().is_err());
+    }
+
+    #[test]
+    fn test_convention() {
+        let frame = Frame {
+            call_site: 0x3000,
+            entry: 0x3100,
+            return_addr: 0x3001,
+        };
+        let mut check = ConventionCheck::new(CheckMode::Warn, "R1,R2".parse().unwrap());
+        check.call([0; 8], 1);
+
+        // R0 is the return value, R2 should have been restored
+        let regs = [5, 0, 7, 0, 0, 0, 0, 0x3001];
+        let found = check.ret(0x3105, regs, Some(frame), None, 0);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1.contains("R2 changed"));
+
+        check.call([0; 8], 1);
+        let found = check.ret(0x3105, [0; 8], None, Some(&frame), 1);
+        assert!(found[0].1.contains("R7 was overwritten"));
+    }
+}
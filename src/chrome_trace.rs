@@ -0,0 +1,130 @@
+use num_traits::FromPrimitive;
+
+use crate::{enums::TrapCode, symbols::SymbolTable, utils::json_escape};
+
+/// Timestamps are instruction counts, which the trace viewer shows as microseconds
+#[derive(Clone)]
+enum Event {
+    Call { ts: u64, entry: u16 },
+    Return { ts: u64 },
+    Trap { ts: u64, vector: u8 },
+    Instant { ts: u64, name: String },
+}
+
+/// Execution trace in Chrome's trace-event format, viewable in Perfetto or `chrome://tracing`.
+///
+/// Subroutine calls become nested duration events, traps become one-instruction slices.
+#[derive(Default, Clone)]
+pub struct ChromeTrace {
+    events: Vec<Event>,
+    /// Calls that haven't returned yet
+    open: usize,
+}
+
+impl ChromeTrace {
+    pub fn call(&mut self, ts: u64, entry: u16) {
+        self.open += 1;
+        self.events.push(Event::Call { ts, entry });
+    }
+
+    /// Records `frames` subroutines returning at once, e.g. when a RET unwinds several calls
+    pub fn ret(&mut self, ts: u64, frames: usize) {
+        let frames = frames.min(self.open);
+        self.open -= frames;
+        for _ in 0..frames {
+            self.events.push(Event::Return { ts });
+        }
+    }
+
+    pub fn trap(&mut self, ts: u64, vector: u8) {
+        self.events.push(Event::Trap { ts, vector });
+    }
+
+    /// Marks a point in time, e.g. a check firing
+    pub fn instant(&mut self, ts: u64, name: &str) {
+        self.events.push(Event::Instant {
+            ts,
+            name: name.to_owned(),
+        });
+    }
+
+    /// Serializes the trace, closing calls still open at instruction `end`
+    pub fn to_json(&self, symbols: &SymbolTable, end: u64) -> String {
+        let mut out = String::from("{\"traceEvents\":[\n");
+        let mut first = true;
+        let mut push = |event: String| {
+            if !first {
+                out.push_str(",\n");
+            }
+            first = false;
+            out.push_str(&event);
+        };
+
+        for event in &self.events {
+            push(match event {
+                Event::Call { ts, entry } => {
+                    let name = match symbols.name_at(*entry) {
+                        Some(name) => name.to_owned(),
+                        None => format!("x{entry:04X}"),
+                    };
+                    format!(
+                        "{{\"name\":\"{}\",\"cat\":\"call\",\"ph\":\"B\",\"ts\":{ts},\"pid\":1,\"tid\":1}}",
+                        json_escape(&name)
+                    )
+                }
+                Event::Return { ts } => {
+                    format!("{{\"ph\":\"E\",\"ts\":{ts},\"pid\":1,\"tid\":1}}")
+                }
+                Event::Trap { ts, vector } => {
+                    let name = match TrapCode::from_u8(*vector) {
+                        Some(trap) => format!("TRAP {}", trap.name()),
+                        None => format!("TRAP x{vector:02X}"),
+                    };
+                    format!(
+                        "{{\"name\":\"{name}\",\"cat\":\"trap\",\"ph\":\"X\",\"ts\":{ts},\"dur\":1,\"pid\":1,\"tid\":1}}"
+                    )
+                }
+                Event::Instant { ts, name } => format!(
+                    "{{\"name\":\"{}\",\"cat\":\"check\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{ts},\"pid\":1,\"tid\":1}}",
+                    json_escape(name)
+                ),
+            });
+        }
+        for _ in 0..self.open {
+            push(format!("{{\"ph\":\"E\",\"ts\":{end},\"pid\":1,\"tid\":1}}"));
+        }
+
+        out.push_str("\n]}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let mut trace = ChromeTrace::default();
+        trace.call(1, 0x3100);
+        trace.trap(2, 0x21);
+        trace.call(3, 0x3200);
+        trace.ret(5, 1);
+        trace.ret(6, 5);
+        trace.call(7, 0x3100);
+
+        let mut symbols = SymbolTable::default();
+        symbols.insert("PRINT", 0x3100);
+        let json = trace.to_json(&symbols, 9);
+        let lines: Vec<_> = json.lines().collect();
+
+        assert_eq!(lines.len(), 9);
+        assert_eq!(
+            lines[1],
+            "{\"name\":\"PRINT\",\"cat\":\"call\",\"ph\":\"B\",\"ts\":1,\"pid\":1,\"tid\":1},"
+        );
+        assert!(lines[2].contains("\"name\":\"TRAP OUT\""));
+        assert!(lines[3].contains("\"name\":\"x3200\""));
+        assert_eq!(lines[7], "{\"ph\":\"E\",\"ts\":9,\"pid\":1,\"tid\":1}");
+    }
+}
@@ -1,17 +1,659 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    checks::{CalleeSaved, CheckMode, ForbiddenOpcodes, MemRegion, StackBounds, TrapSet},
+    console::{Echo, InStyle},
+    ext::Extensions,
+    image::DataFile,
+    trace::OpcodeSet,
+    watchdog::WatchdogAction,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Disable colored output (also honours the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run an object file
+    Run(RunArgs),
+
+    /// Run an object file in the step debugger
+    Debug(DebugArgs),
+
+    /// Assemble a source file into an object file
+    Asm(AsmArgs),
+
+    /// Print the disassembly of an object file
+    Disasm(DisasmArgs),
+
+    /// Statically check an object file for illegal instructions, stray jumps and undefined traps
+    Check(CheckImageArgs),
+
+    /// Run golden tests against expected output
+    Test(TestArgs),
+
+    /// Check the interpreter against the built-in ISA conformance programs
+    Selftest,
+
+    /// Measure interpreter throughput on an object file
+    Bench(BenchArgs),
+
+    /// Try every keyboard input sequence and report which ones reach an address
+    Explore(ExploreArgs),
+
+    /// List the registers and memory words that differ between two snapshots
+    Diffstate(DiffStateArgs),
+
+    /// Run an object file in a window with its console, registers and memory
+    #[cfg(feature = "gui")]
+    Gui(GuiArgs),
+}
+
+/// Object files to load before running
+#[derive(Args)]
+pub struct ImageArgs {
     /// Path to object file
     ///
     /// Object file extension should generally be .obj
-    /// but it's not strictly checked
+    /// but it's not strictly checked. Repeat (or list several) to load
+    /// library routines alongside the program; images must not overlap.
+    /// `-` reads the object file from stdin, leaving no keyboard input
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        required_unless_present = "project",
+        num_args = 1..
+    )]
+    pub file: Vec<PathBuf>,
+
+    /// Project manifest (lc3.toml), or a directory containing one, naming the
+    /// program, its symbols and listing, OS images and device settings.
+    /// --file images are loaded alongside the project's program
+    #[arg(long, value_name = "PATH")]
+    pub project: Option<PathBuf>,
+
+    /// Image allowed to load into the OS region below x3000, e.g. trap
+    /// vectors and service routines. Loaded before --file
+    #[arg(long, value_name = "FILE")]
+    pub os: Vec<PathBuf>,
+
+    /// Raw big endian words to load at ADDR before running, e.g. a dataset
+    /// for the program. Can be repeated
+    #[arg(long, value_name = "FILE@ADDR")]
+    pub data: Vec<DataFile>,
+
+    /// Like --data, but one word per byte of the file, e.g. for text
+    #[arg(long, value_name = "FILE@ADDR")]
+    pub data_bytes: Vec<DataFile>,
+}
+
+impl ImageArgs {
+    /// The user program, which is the last --file given
+    pub fn program(&self) -> &Path {
+        self.file.last().expect("clap requires at least one --file")
+    }
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub images: ImageArgs,
+
+    /// Symbol table produced by the assembler (.sym), used to name routines in reports
+    #[arg(short, long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Rerun the program whenever its object file changes, stopping a run
+    /// still in progress. Input piped in on stdin is fed to every run
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Assembly source of the program, assembled into the object file before
+    /// every run under --watch and watched instead of it
+    #[arg(long, value_name = "FILE", requires = "watch")]
+    pub source: Option<PathBuf>,
+
+    /// Listing produced by the assembler (.lst), for --coverage
+    #[arg(short, long, value_name = "FILE")]
+    pub listing: Option<PathBuf>,
+
+    /// Write which source lines of --listing executed to FILE, as an HTML
+    /// page if it ends in .html and as an lcov tracefile otherwise
+    #[arg(long, value_name = "FILE", requires = "listing")]
+    pub coverage: Option<PathBuf>,
+
+    /// Print a per-subroutine profile (flat + call graph) once the program stops
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Handle writes to the hypercall register at xFE16: 1 asserts R0 = R1,
+    /// 2 and 3 end the run as passed or failed, 4 prints R0. `simulator test`
+    /// always does
+    #[arg(long)]
+    pub hypercalls: bool,
+
+    /// Print how often each conditional branch was taken once the program stops
+    #[arg(long)]
+    pub branch_stats: bool,
+
+    /// Print the instruction count and how often each trap was used once the
+    /// program stops
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Write subroutine calls and traps as a Chrome trace-event JSON file,
+    /// viewable in Perfetto (timestamps are instruction counts)
+    #[arg(long, value_name = "FILE")]
+    pub chrome_trace: Option<PathBuf>,
+
+    /// Log every executed instruction to stderr, narrowed down by
+    /// --trace-opcodes and --trace-range
+    #[arg(long)]
+    pub trace: bool,
+
+    /// Only trace these opcodes, e.g. BR,JSR (RET counts as JMP, JSRR as JSR)
+    #[arg(long, value_name = "OPS", requires = "trace")]
+    pub trace_opcodes: Option<OpcodeSet>,
+
+    /// Only trace instructions fetched from this region, e.g. x3000-x30FF
+    #[arg(long, value_name = "FIRST-LAST", requires = "trace")]
+    pub trace_range: Option<MemRegion>,
+
+    /// Track data derived from keyboard input and report what it reached once the program stops
+    #[arg(long)]
+    pub taint: bool,
+
+    /// Save every key the program reads, with the instruction count it arrived at
+    #[arg(long, value_name = "FILE")]
+    pub record_input: Option<PathBuf>,
+
+    /// Log every store the program executes (instruction count, PC of the
+    /// store, address, old and new value) to FILE
+    #[arg(long, value_name = "FILE")]
+    pub write_log: Option<PathBuf>,
+
+    /// Write a JSON report of the run to FILE: why it ended, instruction
+    /// count, wall time, final registers, trap counts and warnings
+    #[arg(long, value_name = "FILE")]
+    pub summary: Option<PathBuf>,
+
+    /// Save the registers and all of memory to FILE once the program stops,
+    /// for graders to check final memory. `simulator diffstate` compares two
+    #[arg(long, value_name = "FILE")]
+    pub save_final_state: Option<PathBuf>,
+
+    /// Feed keys saved by --record-input, each arriving at the instruction count
+    /// it was recorded at. The terminal takes over once they run out
+    #[arg(long, value_name = "FILE")]
+    pub replay_input: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub dump: DumpArgs,
+
+    /// Frames per second signalled by the vsync register at xFE12. 0 signals a
+    /// new frame on every read, like headless runs do
+    #[arg(long, value_name = "N", default_value_t = 60)]
+    pub fps: u32,
+
+    /// Read keyboard input a line at a time, echoed by the terminal, instead of
+    /// single keys in raw mode. Helps over ssh and in IDE terminals
+    #[arg(long)]
+    pub line_input: bool,
+
+    /// Have arrow, navigation and function keys arrive as single codes from
+    /// x80 (Up, Down, Right, Left, Home, End, Insert, Delete, PgUp, PgDn) and
+    /// x90 (F1-F12) rather than as escape sequences
+    #[arg(long)]
+    pub extended_keys: bool,
+
+    /// Execute extended instructions in the reserved opcode 1101, e.g.
+    /// `shf,xor` for LSHF/RSHFL/RSHFA and XOR. Otherwise it is illegal
+    #[arg(long, value_name = "EXTS")]
+    pub ext: Option<Extensions>,
+
+    /// Provide MUL (x40), DIV (x41) and MOD (x42) traps on R0 and R1, with
+    /// results in R0 and R1
+    #[arg(long)]
+    pub arith_traps: bool,
+
+    /// Provide PUTN (x26), printing R0 in decimal, GETN (x27), reading a
+    /// decimal number typed on a line into R0, and GETS (x28), reading a line
+    /// of at most R1 characters into memory at R0
+    #[arg(long)]
+    pub io_traps: bool,
+
+    /// Map read-only instruction and cycle counters at xFE1A-xFE20, for
+    /// programs to time their own routines
+    #[arg(long)]
+    pub perf_counters: bool,
+
+    /// Map the d-pad and buttons of connected controllers to the gamepad
+    /// register at xFE14
+    #[cfg(feature = "gamepad")]
+    #[arg(long)]
+    pub gamepad: bool,
+
+    /// Number of recently executed instructions to keep, printed when the
+    /// program crashes or hits a step limit. 0 keeps none
+    #[arg(long, value_name = "N", default_value_t = 32)]
+    pub history: usize,
+
+    #[command(flatten)]
+    pub banks: BankArgs,
+
+    #[command(flatten)]
+    pub raw: RawArgs,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+
+    #[command(flatten)]
+    pub checks: CheckArgs,
+}
+
+#[derive(Args)]
+pub struct DebugArgs {
+    #[command(flatten)]
+    pub images: ImageArgs,
+
+    /// Symbol table produced by the assembler (.sym)
+    #[arg(short, long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Listing produced by the assembler (.lst), to show source lines and
+    /// set breakpoints by line number
+    #[arg(short, long, value_name = "FILE")]
+    pub listing: Option<PathBuf>,
+
+    /// Debugger commands to run at startup, one per line
+    ///
+    /// Once the script runs out, commands are read from the terminal
+    /// (or the debugger quits if stdin is closed)
+    #[arg(long, value_name = "FILE")]
+    pub debug_script: Option<PathBuf>,
+
+    /// Snapshot the machine every N instructions so `rewind` can go back in time
+    #[arg(long, value_name = "N")]
+    pub checkpoint_every: Option<u64>,
+
+    /// Number of checkpoints to keep, older ones are dropped
+    #[arg(long, value_name = "N", default_value_t = 32)]
+    pub checkpoint_count: usize,
+
+    /// Pause `continue`, `next` and `finish` after at most N instructions, so
+    /// an infinite loop returns to the prompt
+    #[arg(long, value_name = "N")]
+    pub continue_budget: Option<u64>,
+
+    /// Only show debug output, e.g. tracepoints, for code in this region, can
+    /// be repeated. Keeps OS and trap routines quiet
+    #[arg(long, value_name = "FIRST-LAST")]
+    pub log_range: Vec<MemRegion>,
+
+    /// Frames per second signalled by the vsync register at xFE12, 0 for a
+    /// new frame on every read
+    #[arg(long, value_name = "N", default_value_t = 60)]
+    pub fps: u32,
+
+    /// Have arrow, navigation and function keys arrive as single codes from
+    /// x80 rather than as escape sequences
+    #[arg(long)]
+    pub extended_keys: bool,
+
+    /// Execute extended instructions in the reserved opcode 1101, e.g.
+    /// `shf,xor` for LSHF/RSHFL/RSHFA and XOR. Otherwise it is illegal
+    #[arg(long, value_name = "EXTS")]
+    pub ext: Option<Extensions>,
+
+    /// Provide MUL (x40), DIV (x41) and MOD (x42) traps on R0 and R1, with
+    /// results in R0 and R1
+    #[arg(long)]
+    pub arith_traps: bool,
+
+    /// Provide PUTN (x26), printing R0 in decimal, GETN (x27), reading a
+    /// decimal number typed on a line into R0, and GETS (x28), reading a line
+    /// of at most R1 characters into memory at R0
+    #[arg(long)]
+    pub io_traps: bool,
+
+    /// Map read-only instruction and cycle counters at xFE1A-xFE20, for
+    /// programs to time their own routines
+    #[arg(long)]
+    pub perf_counters: bool,
+
+    /// Map the d-pad and buttons of connected controllers to the gamepad
+    /// register at xFE14
+    #[cfg(feature = "gamepad")]
+    #[arg(long)]
+    pub gamepad: bool,
+
+    /// Number of recently executed instructions to keep, printed when the
+    /// program crashes or hits a step limit. 0 keeps none
+    #[arg(long, value_name = "N", default_value_t = 32)]
+    pub history: usize,
+
+    #[command(flatten)]
+    pub banks: BankArgs,
+
+    #[command(flatten)]
+    pub raw: RawArgs,
+
+    #[command(flatten)]
+    pub dump: DumpArgs,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+
+    #[command(flatten)]
+    pub checks: CheckArgs,
+}
+
+/// JSON state dumps, written on SIGUSR1 or by the debugger's `dumpstate`
+#[derive(Args)]
+pub struct DumpArgs {
+    /// Memory to include in state dumps, defaults to 16 words from PC
+    #[arg(long, value_name = "FIRST:LAST")]
+    pub dump_window: Option<MemRegion>,
+
+    /// File SIGUSR1 writes the state dump to, instead of stderr
+    #[arg(long, value_name = "FILE")]
+    pub dump_file: Option<PathBuf>,
+}
+
+/// Bank-switched extended memory, for programs that outgrow 64K words
+#[derive(Args)]
+pub struct BankArgs {
+    /// Number of memory banks behind --bank-window, selected by writing the
+    /// bank number to xFE18. Bank 0 is what the window holds at startup
+    #[arg(long, value_name = "N")]
+    pub banks: Option<u16>,
+
+    /// Region of memory the selected bank appears in
+    #[arg(
+        long,
+        value_name = "FIRST-LAST",
+        default_value = "xC000-xDFFF",
+        requires = "banks"
+    )]
+    pub bank_window: MemRegion,
+}
+
+/// Whether the terminal is put in raw mode, which by default it is when both
+/// stdin and stdout are terminals
+#[derive(Args)]
+pub struct RawArgs {
+    /// Put the terminal in raw mode even when stdin or stdout is redirected
+    #[arg(long, conflicts_with = "no_raw")]
+    pub raw: bool,
+
+    /// Leave the terminal alone, e.g. under a test harness that allocates a
+    /// terminal but feeds it scripted input
+    #[arg(long)]
+    pub no_raw: bool,
+}
+
+impl RawArgs {
+    /// `None` to detect it
+    pub fn raw(&self) -> Option<bool> {
+        match (self.raw, self.no_raw) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// What the keyboard traps print, reference transcripts differ between courses
+#[derive(Args)]
+pub struct EchoArgs {
+    /// Have GETC echo the character it read
+    #[arg(long)]
+    pub echo_getc: bool,
+
+    /// Prompt and echo behavior of the IN trap
+    #[arg(long, value_name = "STYLE", default_value = "plain")]
+    pub in_style: InStyle,
+}
+
+impl EchoArgs {
+    pub fn echo(&self) -> Echo {
+        Echo {
+            getc: self.echo_getc,
+            in_style: self.in_style,
+        }
+    }
+}
+
+/// Runtime checks for common LC-3 programming mistakes
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Catch loads from memory that neither the loader nor the program ever wrote
+    #[arg(long, value_name = "MODE")]
+    pub uninit: Option<CheckMode>,
+
+    /// Catch writes to addresses that have already been executed as instructions
+    #[arg(long, value_name = "MODE")]
+    pub self_modify: Option<CheckMode>,
+
+    /// Catch R6 leaving the stack bounds and reads below the stack pointer
+    #[arg(long, value_name = "MODE")]
+    pub stack: Option<CheckMode>,
+
+    /// Stack region used by --stack and --bounds, the stack grows down from HIGH
+    #[arg(long, value_name = "LOW:HIGH", default_value = "xF000:xFE00")]
+    pub stack_bounds: StackBounds,
+
+    /// Catch subroutines that return with callee-saved registers changed
+    /// or with R7 clobbered
+    #[arg(long, value_name = "MODE")]
+    pub convention: Option<CheckMode>,
+
+    /// Catch writes to read-only memory: --os images and --protect regions.
+    /// Such writes are dropped
+    #[arg(long, value_name = "MODE")]
+    pub read_only: Option<CheckMode>,
+
+    /// Region to write-protect under --read-only, can be repeated
+    #[arg(long, value_name = "FIRST:LAST")]
+    pub protect: Vec<MemRegion>,
+
+    /// Catch stores outside the loaded images, the stack and --writable
+    /// regions, e.g. through a wild pointer
+    #[arg(long, value_name = "MODE")]
+    pub bounds: Option<CheckMode>,
+
+    /// Region --bounds allows stores to besides the program and the stack,
+    /// can be repeated
+    #[arg(long, value_name = "FIRST:LAST")]
+    pub writable: Vec<MemRegion>,
+
+    /// Stop the program if it goes N instructions without writing to the
+    /// watchdog register at xFE10
+    #[arg(long, value_name = "N")]
+    pub watchdog: Option<u64>,
+
+    /// What happens when the watchdog expires
+    #[arg(long, value_name = "ACTION", default_value = "halt")]
+    pub watchdog_action: WatchdogAction,
+
+    /// Registers --convention expects subroutines to preserve
+    #[arg(long, value_name = "REGS", default_value = "R1,R2,R3,R4,R5,R6")]
+    pub callee_saved: CalleeSaved,
+
+    /// Stop with an error when the program uses one of these traps, given
+    /// by alias or vector, e.g. PUTS,IN, MUL or x22
+    #[arg(long, value_name = "TRAPS")]
+    pub forbid_traps: Option<TrapSet>,
+
+    /// Stop with an error when the program executes one of these
+    /// instructions, e.g. LDI,STI. Extended instructions are named after
+    /// their extension, e.g. xor-ext, and MUL-ext, DIV-ext and MOD-ext name
+    /// the arithmetic traps
+    #[arg(long, value_name = "OPS")]
+    pub forbid_opcodes: Option<ForbiddenOpcodes>,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Args)]
+pub struct GuiArgs {
+    #[command(flatten)]
+    pub images: ImageArgs,
+
+    /// Execute extended instructions in the reserved opcode 1101, e.g.
+    /// `shf,xor` for LSHF/RSHFL/RSHFA and XOR. Otherwise it is illegal
+    #[arg(long, value_name = "EXTS")]
+    pub ext: Option<Extensions>,
+
+    /// Provide MUL (x40), DIV (x41) and MOD (x42) traps on R0 and R1, with
+    /// results in R0 and R1
+    #[arg(long)]
+    pub arith_traps: bool,
+
+    /// Provide PUTN (x26), GETN (x27) and GETS (x28) for decimal numbers and
+    /// lines of text
+    #[arg(long)]
+    pub io_traps: bool,
+}
+
+#[derive(Args)]
+pub struct AsmArgs {
+    /// Assembly source file
+    pub source: PathBuf,
+
+    /// Output object file, defaults to the source path with an .obj extension
+    ///
+    /// A symbol table is written next to it with a .sym extension
     #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DiffStateArgs {
+    /// Snapshot from before, e.g. saved by the debugger's `snapshot` command
+    pub before: PathBuf,
+
+    /// Snapshot from after
+    pub after: PathBuf,
+
+    /// Symbol table produced by the assembler (.sym), to name changed addresses
+    #[arg(short, long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Listing produced by the assembler (.lst), so only words it marks as
+    /// code are disassembled. Without one every changed word is
+    #[arg(short, long, value_name = "FILE")]
+    pub listing: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct DisasmArgs {
+    /// Path to object file
     pub file: PathBuf,
 
-    /// Turn on step-debugger-mode
-    #[arg(short, long, default_value_t = false)]
-    pub debug: bool,
+    /// Symbol table produced by the assembler (.sym), to show labels instead of
+    /// branch and load target addresses
+    #[arg(short, long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CheckImageArgs {
+    /// Path to object file
+    pub file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    /// Object files or directories containing them
+    ///
+    /// Each `name.obj` is fed `name.in` as input (if present) and its output
+    /// is compared against `name.out` (if present)
+    #[arg(required_unless_present = "project")]
+    pub paths: Vec<PathBuf>,
+
+    /// Also run the `[[test]]` entries of this project manifest (lc3.toml),
+    /// or of the one in this directory
+    #[arg(long, value_name = "PATH")]
+    pub project: Option<PathBuf>,
+
+    /// Fail tests that don't halt within this many instructions
+    #[arg(long, value_name = "N", default_value_t = 10_000_000)]
+    pub max_steps: u64,
+
+    /// Write the results as a JUnit XML report, for CI dashboards
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+
+    /// Write the results as JSON, in the shape Gradescope's autograder reads
+    #[arg(long, value_name = "FILE")]
+    pub json: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Path to object file
+    pub file: PathBuf,
+
+    /// File fed to the program as keyboard input
+    #[arg(short, long, value_name = "FILE")]
+    pub input: Option<PathBuf>,
+
+    /// Number of times to run the program
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub iterations: u32,
+
+    /// Stop each run after this many instructions
+    #[arg(long, value_name = "N", default_value_t = 100_000_000)]
+    pub max_steps: u64,
+
+    #[command(flatten)]
+    pub echo: EchoArgs,
+}
+
+#[derive(Args)]
+pub struct ExploreArgs {
+    #[command(flatten)]
+    pub images: ImageArgs,
+
+    /// Symbol table produced by the assembler (.sym), so --target can be a label
+    #[arg(short, long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Address (or label) the program should reach, e.g. a menu handler
+    #[arg(short, long, value_name = "ADDR")]
+    pub target: String,
+
+    /// Characters to try at every keyboard read
+    #[arg(long, value_name = "CHARS", default_value = "0123456789")]
+    pub alphabet: String,
+
+    /// Keyboard reads to branch at along one path
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub max_inputs: usize,
+
+    /// Instructions a single path may execute
+    #[arg(long, value_name = "N", default_value_t = 1_000_000)]
+    pub max_steps: u64,
+
+    /// Total number of paths to explore
+    #[arg(long, value_name = "N", default_value_t = 100_000)]
+    pub max_paths: usize,
+
+    /// Treat input as unknown values and only fork where the program branches on them,
+    /// also reporting inputs that crash the program (experimental)
+    #[arg(long)]
+    pub symbolic: bool,
 }
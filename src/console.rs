@@ -0,0 +1,557 @@
+use std::{
+    collections::VecDeque,
+    io::{self, IsTerminal, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{terminal, utils::handle_newline};
+
+/// How the IN trap prompts for a character
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InStyle {
+    /// `Enter a character: `, without echoing the character
+    #[default]
+    Plain,
+    /// Like lc3sim's OS: a newline, `Input a character> `, then the character
+    /// echoed and another newline
+    Lc3sim,
+}
+
+/// What the keyboard traps print on the console besides the program's output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Echo {
+    /// GETC prints the character it read
+    pub getc: bool,
+    pub in_style: InStyle,
+}
+
+/// Where the machine's keyboard input comes from and its display output goes to
+pub trait Console: Send + Sync {
+    /// Blocks until a byte of input is available. `None` means the input is exhausted.
+    fn read_byte(&mut self) -> Option<u8>;
+
+    fn write_str(&mut self, s: &str) -> io::Result<()>;
+
+    /// Waits up to `timeout` for input, returning whether [`Console::read_byte`]
+    /// would now return without blocking. Scripted consoles never block.
+    fn poll(&mut self, _timeout: Duration) -> bool {
+        true
+    }
+
+    /// Messages from the simulator itself (e.g. "Machine Halted"),
+    /// which aren't part of the program's output
+    fn status(&mut self, _msg: &str) {}
+
+    /// Waits until everything written so far has been output, for consoles
+    /// that write in the background
+    fn flush(&mut self) {}
+
+    /// Copy of the console for a cloned machine
+    fn boxed_clone(&self) -> Box<dyn Console>;
+}
+
+impl Clone for Box<dyn Console> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+impl Default for Box<dyn Console> {
+    fn default() -> Self {
+        Box::new(TerminalConsole::default())
+    }
+}
+
+/// Keeps the host terminal in raw mode, with bracketed paste on, while alive.
+/// Dropping it, including while unwinding from a panic or returning early
+/// with an error, puts the terminal back.
+pub struct RawMode {
+    enabled: bool,
+}
+
+impl RawMode {
+    /// Best effort, so scripted runs work without a terminal
+    pub fn enable() -> Self {
+        let enabled = terminal::backend().enable_raw_mode().is_ok();
+        Self { enabled }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if self.enabled {
+            let _ = terminal::backend().disable_raw_mode();
+        }
+    }
+}
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// What KBDR reads for keys that send escape sequences, when the console
+/// decodes them (`--extended-keys`). The codes are above ASCII, so they can't
+/// be mistaken for typed characters.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendedKey {
+    Up = 0x80,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    F1 = 0x90,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+}
+
+/// Escape sequences xterm-compatible terminals send for the extended keys,
+/// in both normal and application cursor mode
+const KEY_SEQUENCES: &[(&[u8], ExtendedKey)] = &[
+    (b"\x1b[A", ExtendedKey::Up),
+    (b"\x1b[B", ExtendedKey::Down),
+    (b"\x1b[C", ExtendedKey::Right),
+    (b"\x1b[D", ExtendedKey::Left),
+    (b"\x1b[H", ExtendedKey::Home),
+    (b"\x1b[F", ExtendedKey::End),
+    (b"\x1bOA", ExtendedKey::Up),
+    (b"\x1bOB", ExtendedKey::Down),
+    (b"\x1bOC", ExtendedKey::Right),
+    (b"\x1bOD", ExtendedKey::Left),
+    (b"\x1bOH", ExtendedKey::Home),
+    (b"\x1bOF", ExtendedKey::End),
+    (b"\x1b[1~", ExtendedKey::Home),
+    (b"\x1b[2~", ExtendedKey::Insert),
+    (b"\x1b[3~", ExtendedKey::Delete),
+    (b"\x1b[4~", ExtendedKey::End),
+    (b"\x1b[5~", ExtendedKey::PageUp),
+    (b"\x1b[6~", ExtendedKey::PageDown),
+    (b"\x1bOP", ExtendedKey::F1),
+    (b"\x1bOQ", ExtendedKey::F2),
+    (b"\x1bOR", ExtendedKey::F3),
+    (b"\x1bOS", ExtendedKey::F4),
+    (b"\x1b[15~", ExtendedKey::F5),
+    (b"\x1b[17~", ExtendedKey::F6),
+    (b"\x1b[18~", ExtendedKey::F7),
+    (b"\x1b[19~", ExtendedKey::F8),
+    (b"\x1b[20~", ExtendedKey::F9),
+    (b"\x1b[21~", ExtendedKey::F10),
+    (b"\x1b[23~", ExtendedKey::F11),
+    (b"\x1b[24~", ExtendedKey::F12),
+];
+
+/// Turns the bytes a terminal sends into keys for the program. Removes the
+/// markers bracketed paste mode wraps pasted text in, so a paste reaches the
+/// program as the keys it contains, and with `extended` set turns the escape
+/// sequences of arrow and function keys into [`ExtendedKey`] codes. Input is
+/// fed in whatever chunks it was read in; a sequence split across chunks is
+/// held back until the rest arrives.
+#[derive(Default)]
+struct KeyDecoder {
+    extended: bool,
+    partial: Vec<u8>,
+}
+
+impl KeyDecoder {
+    fn new(extended: bool) -> Self {
+        Self {
+            extended,
+            partial: Vec::new(),
+        }
+    }
+
+    fn decode(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut bytes = std::mem::take(&mut self.partial);
+        bytes.extend_from_slice(chunk);
+
+        let mut keys = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            if let Some(marker) = [PASTE_START, PASTE_END]
+                .into_iter()
+                .find(|m| rest.starts_with(m))
+            {
+                i += marker.len();
+                continue;
+            }
+            if let Some((seq, key)) = KEY_SEQUENCES
+                .iter()
+                .filter(|_| self.extended)
+                .find(|(seq, _)| rest.starts_with(seq))
+            {
+                keys.push(*key as u8);
+                i += seq.len();
+                continue;
+            }
+            // A lone ESC is a key press, only hold back what is clearly the
+            // start of a sequence
+            if rest.len() > 1
+                && self
+                    .sequences()
+                    .any(|seq| seq.len() > rest.len() && seq.starts_with(rest))
+            {
+                self.partial = rest.to_vec();
+                break;
+            }
+            keys.push(bytes[i]);
+            i += 1;
+        }
+        keys
+    }
+
+    fn sequences(&self) -> impl Iterator<Item = &[u8]> {
+        let keys = KEY_SEQUENCES.iter().filter(|_| self.extended);
+        [PASTE_START, PASTE_END]
+            .into_iter()
+            .chain(keys.map(|(seq, _)| *seq))
+    }
+}
+
+/// Restores the terminal before a panic message is printed, so the message
+/// isn't mangled by raw mode and the shell is usable even if the panic aborts
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = terminal::backend().disable_raw_mode();
+        default_hook(info);
+    }));
+}
+
+/// Keys read ahead of the program. Once full, further input waits in the
+/// host's own buffer
+const KEY_BUFFER: usize = 256;
+/// Writes queued for the output thread before the program waits for it
+const OUTPUT_BUFFER: usize = 1024;
+
+enum Output {
+    Text(String),
+    /// Acknowledged once everything before it has been written
+    Flush(SyncSender<()>),
+}
+
+/// Thread writing the program's output, so a slow terminal doesn't stall
+/// the interpreter until the queue fills up
+struct OutputThread {
+    tx: SyncSender<Output>,
+}
+
+impl OutputThread {
+    fn spawn(raw: bool) -> Self {
+        let (tx, rx) = mpsc::sync_channel(OUTPUT_BUFFER);
+        thread::spawn(move || {
+            let mut stdout = io::stdout();
+            for output in rx {
+                match output {
+                    Output::Text(s) => {
+                        let written = if raw {
+                            write!(stdout, "{}", handle_newline(&s))
+                        } else {
+                            write!(stdout, "{s}")
+                        };
+                        // Writes fail from then on, see `write_str`
+                        if written.and_then(|_| stdout.flush()).is_err() {
+                            return;
+                        }
+                    }
+                    Output::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self { tx }
+    }
+
+    fn flush(&self) {
+        let (done, flushed) = mpsc::sync_channel(1);
+        if self.tx.send(Output::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+impl Drop for OutputThread {
+    /// Output still queued is written before the console goes away
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Console backed by the host terminal. Keys are read and output written by
+/// background threads talking to the interpreter over bounded channels
+#[derive(Default)]
+pub struct TerminalConsole {
+    /// Input arrives a line at a time, echoed and editable by the terminal,
+    /// rather than a key at a time
+    line_input: bool,
+    /// Decode arrow and function keys into [`ExtendedKey`] codes
+    extended_keys: bool,
+    /// Put the terminal in raw mode, `None` to do so when stdin and stdout
+    /// are both terminals
+    raw: Option<bool>,
+    /// Keys read by a background thread, started the first time input is
+    /// polled. Only ever used through `&mut self`, the mutex just makes the
+    /// console `Sync`
+    keys: Option<Mutex<Receiver<u8>>>,
+    /// Keys received, not yet read. A single read can return several, e.g. a paste
+    pending: VecDeque<u8>,
+    /// For reads made before the background thread starts
+    decoder: KeyDecoder,
+    /// Started by the first write
+    output: Option<OutputThread>,
+}
+
+impl TerminalConsole {
+    /// Console reading whole lines, for terminals where raw mode misbehaves
+    /// (e.g. over ssh or in IDE terminals)
+    pub fn line_input() -> Self {
+        Self {
+            line_input: true,
+            ..Default::default()
+        }
+    }
+
+    /// Have arrow and function keys reach the program as single
+    /// [`ExtendedKey`] codes instead of escape sequences
+    pub fn with_extended_keys(self, extended_keys: bool) -> Self {
+        Self {
+            extended_keys,
+            decoder: KeyDecoder::new(extended_keys),
+            ..self
+        }
+    }
+
+    /// Force raw mode on or off, rather than detecting whether it is wanted
+    pub fn with_raw(self, raw: Option<bool>) -> Self {
+        Self { raw, ..self }
+    }
+
+    /// Whether the terminal is read in raw mode. Without an override it is
+    /// when stdin and stdout are both terminals, so redirected runs keep the
+    /// output's newlines as they are
+    pub fn raw(&self) -> bool {
+        !self.line_input
+            && self
+                .raw
+                .unwrap_or_else(|| io::stdin().is_terminal() && io::stdout().is_terminal())
+    }
+
+    /// Puts the terminal in the mode this console reads in until the guard is
+    /// dropped: raw mode for single keys, nothing for line input since the
+    /// terminal's cooked mode already buffers and echoes lines
+    pub fn terminal_mode(&self) -> Option<RawMode> {
+        self.raw().then(RawMode::enable)
+    }
+
+    fn keys(&mut self) -> &Receiver<u8> {
+        let extended_keys = self.extended_keys;
+        let keys = self.keys.get_or_insert_with(|| {
+            let (tx, rx) = mpsc::sync_channel(KEY_BUFFER);
+            thread::spawn(move || {
+                let mut decoder = KeyDecoder::new(extended_keys);
+                let mut buff = [0; 256];
+                while let Ok(len @ 1..) = io::stdin().read(&mut buff) {
+                    for key in decoder.decode(&buff[..len]) {
+                        if tx.send(key).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+            Mutex::new(rx)
+        });
+        keys.get_mut().unwrap()
+    }
+}
+
+impl Console for TerminalConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        if let Some(key) = self.pending.pop_front() {
+            return Some(key);
+        }
+        if let Some(keys) = &mut self.keys {
+            return keys.get_mut().unwrap().recv().ok();
+        }
+
+        let mut buff = [0; 256];
+        while self.pending.is_empty() {
+            let len = io::stdin().read(&mut buff).ok().filter(|&len| len > 0)?;
+            self.pending.extend(self.decoder.decode(&buff[..len]));
+        }
+        self.pending.pop_front()
+    }
+
+    fn poll(&mut self, timeout: Duration) -> bool {
+        if !self.pending.is_empty() {
+            return true;
+        }
+        match self.keys().recv_timeout(timeout) {
+            Ok(key) => {
+                self.pending.push_back(key);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            // End of input, reading reports it
+            Err(RecvTimeoutError::Disconnected) => true,
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        if self.output.is_none() {
+            self.output = Some(OutputThread::spawn(self.raw()));
+        }
+        let output = self.output.as_ref().unwrap();
+        output
+            .tx
+            .send(Output::Text(s.to_owned()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "failed to write to stdout"))
+    }
+
+    fn status(&mut self, msg: &str) {
+        self.flush();
+        write!(io::stderr(), "{msg}\r\n").expect("Failed to write to stderr");
+    }
+
+    fn flush(&mut self) {
+        if let Some(output) = &self.output {
+            output.flush();
+        }
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Console> {
+        Box::new(
+            TerminalConsole {
+                line_input: self.line_input,
+                raw: self.raw,
+                ..Default::default()
+            }
+            .with_extended_keys(self.extended_keys),
+        )
+    }
+}
+
+/// Console with scripted input and captured output, for headless runs
+#[derive(Default)]
+pub struct BufferConsole {
+    input: VecDeque<u8>,
+    output: Arc<Mutex<String>>,
+}
+
+impl BufferConsole {
+    pub fn new(input: &[u8]) -> Self {
+        Self {
+            input: input.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Handle to the captured output, which stays valid after the
+    /// console has been handed to a machine
+    pub fn output(&self) -> Arc<Mutex<String>> {
+        Arc::clone(&self.output)
+    }
+}
+
+impl Console for BufferConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.output.lock().unwrap().push_str(s);
+        Ok(())
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+/// The clone gets the remaining input and its own output buffer,
+/// starting with a copy of the output captured so far
+impl Clone for BufferConsole {
+    fn clone(&self) -> Self {
+        Self {
+            input: self.input.clone(),
+            output: Arc::new(Mutex::new(self.output.lock().unwrap().clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_console() {
+        let mut console = BufferConsole::new(b"ab");
+        let output = console.output();
+
+        assert_eq!(console.read_byte(), Some(b'a'));
+        assert_eq!(console.read_byte(), Some(b'b'));
+        assert_eq!(console.read_byte(), None);
+
+        console.write_str("Hello\n").unwrap();
+        console.status("Machine Halted");
+        assert_eq!(*output.lock().unwrap(), "Hello\n");
+    }
+
+    #[test]
+    fn test_raw_override() {
+        assert!(TerminalConsole::default().with_raw(Some(true)).raw());
+        assert!(!TerminalConsole::default().with_raw(Some(false)).raw());
+        // Line input never uses raw mode
+        assert!(!TerminalConsole::line_input().with_raw(Some(true)).raw());
+    }
+
+    #[test]
+    fn test_key_decoder() {
+        let mut decoder = KeyDecoder::default();
+        assert_eq!(decoder.decode(b"\x1b[200~12 34\r\x1b[201~"), b"12 34\r");
+        // Escape key and arrow keys pass through
+        assert_eq!(decoder.decode(b"\x1b"), b"\x1b");
+        assert_eq!(decoder.decode(b"\x1b[A"), b"\x1b[A");
+        // Markers split across reads
+        assert_eq!(decoder.decode(b"a\x1b[20"), b"a");
+        assert_eq!(decoder.decode(b"0~bc\x1b"), b"bc\x1b");
+        assert_eq!(decoder.decode(b"[201~"), b"[201~");
+        assert_eq!(decoder.decode(b"\x1b[2"), b"");
+        assert_eq!(decoder.decode(b"01~d"), b"d");
+
+        let mut decoder = KeyDecoder::new(true);
+        assert_eq!(
+            decoder.decode(b"\x1b[Aw\x1bOD\x1b[15~\x1b[3~"),
+            [
+                ExtendedKey::Up as u8,
+                b'w',
+                ExtendedKey::Left as u8,
+                ExtendedKey::F5 as u8,
+                ExtendedKey::Delete as u8
+            ]
+        );
+        assert_eq!(decoder.decode(b"\x1b[2"), b"");
+        assert_eq!(decoder.decode(b"4~\x1b"), [ExtendedKey::F12 as u8, 0x1b]);
+        // Unknown sequences, e.g. with modifiers, pass through
+        assert_eq!(decoder.decode(b"\x1b[1;5A"), b"\x1b[1;5A");
+    }
+}
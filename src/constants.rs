@@ -1,2 +1,34 @@
+use std::time::Duration;
+
 pub const MAX_MEMORY: usize = 1 << 16;
 pub const PC_START: u16 = 0x3000;
+pub const DEVICE_START: u16 = 0xFE00;
+pub const USER_START: u16 = 0x3000;
+/// Initial supervisor stack pointer, the stack grows down into the OS region
+pub const SUPERVISOR_STACK: u16 = 0x3000;
+/// Exception vector table, entries for the exceptions below
+pub const EXCEPTION_TABLE: u16 = 0x0000;
+pub const PRIVILEGE_VECTOR: u8 = 0x00;
+pub const ILLEGAL_OPCODE_VECTOR: u8 = 0x01;
+pub const ACCESS_VIOLATION_VECTOR: u8 = 0x02;
+/// Interrupt vector table, entry N holds the address of the handler for vector N
+pub const INTERRUPT_TABLE: u16 = 0x0100;
+
+pub const KEYBOARD_VECTOR: u8 = 0x80;
+pub const KEYBOARD_PRIORITY: u8 = 4;
+pub const WATCHDOG_VECTOR: u8 = 0x81;
+pub const WATCHDOG_PRIORITY: u8 = 7;
+/// KBSR bit set while KBDR holds a key the program hasn't read yet
+pub const KBSR_READY: u16 = 1 << 15;
+/// KBSR bit a program sets to have the keyboard interrupt when a key arrives
+pub const KBSR_INTERRUPT_ENABLE: u16 = 1 << 14;
+/// MCR bit that keeps the clock running, a store clearing it stops the machine
+pub const MCR_CLOCK_ENABLE: u16 = 1 << 15;
+
+/// Empty KBSR polls in a row before the machine starts waiting for input between polls
+pub const IDLE_SPIN_POLLS: u32 = 100;
+/// Most instructions between two empty KBSR polls for them to count as the
+/// same spin, rather than a program doing work and checking for keys now and then
+pub const IDLE_SPIN_GAP: u64 = 8;
+/// How long an idle KBSR poll waits for a key, which wakes it early
+pub const IDLE_WAIT: Duration = Duration::from_millis(10);
@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    listing::{is_data, Listing},
+    report::xml_escape,
+};
+
+/// How often each address was executed as an instruction
+#[derive(Default, Clone, Debug)]
+pub struct Coverage {
+    hits: BTreeMap<u16, u64>,
+}
+
+/// A source line of the listing and how often its instruction ran. `hits` is
+/// `None` for lines without instructions (comments, directives, data)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineCoverage {
+    pub line: usize,
+    pub text: String,
+    pub hits: Option<u64>,
+}
+
+impl Coverage {
+    pub fn record(&mut self, pc: u16) {
+        *self.hits.entry(pc).or_default() += 1;
+    }
+
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits.get(&addr).copied().unwrap_or_default()
+    }
+
+    /// Every line of `listing`, with the hits of the instructions it assembled to
+    pub fn lines(&self, listing: &Listing) -> Vec<LineCoverage> {
+        let mut hits: BTreeMap<usize, u64> = BTreeMap::new();
+        for (addr, line) in listing.addrs() {
+            let count = hits.entry(line).or_default();
+            *count = (*count).max(self.hits(addr));
+        }
+        listing
+            .lines()
+            .map(|(line, text)| LineCoverage {
+                line,
+                text: text.to_owned(),
+                hits: hits.get(&line).copied().filter(|_| !is_data(text)),
+            })
+            .collect()
+    }
+
+    /// (lines executed, lines with instructions)
+    pub fn summary(&self, listing: &Listing) -> (usize, usize) {
+        let lines = self.lines(listing);
+        let code = lines.iter().filter_map(|line| line.hits);
+        (code.clone().filter(|&hits| hits > 0).count(), code.count())
+    }
+
+    /// Line coverage in the lcov tracefile format, for `source` (the `.asm`
+    /// the listing was made from)
+    pub fn lcov(&self, listing: &Listing, source: &str) -> String {
+        let mut out = format!("TN:\nSF:{source}\n");
+        for line in self.lines(listing) {
+            if let Some(hits) = line.hits {
+                writeln!(out, "DA:{},{hits}", line.line).unwrap();
+            }
+        }
+        let (hit, found) = self.summary(listing);
+        write!(out, "LF:{found}\nLH:{hit}\nend_of_record\n").unwrap();
+        out
+    }
+
+    /// The listing's source as an HTML page, lines never executed in red
+    pub fn html(&self, listing: &Listing, title: &str) -> String {
+        let (hit, found) = self.summary(listing);
+        let mut rows = String::new();
+        for line in self.lines(listing) {
+            let (class, hits) = match line.hits {
+                Some(0) => ("miss", "0".to_owned()),
+                Some(hits) => ("hit", hits.to_string()),
+                None => ("none", String::new()),
+            };
+            writeln!(
+                rows,
+                "<tr class=\"{class}\"><td>{}</td><td>{hits}</td><td><pre>{}</pre></td></tr>",
+                line.line,
+                xml_escape(&line.text)
+            )
+            .unwrap();
+        }
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Coverage of {title}</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; }}
+td {{ padding: 0 0.5em; vertical-align: top; }}
+td:nth-child(-n+2) {{ text-align: right; color: #666; }}
+pre {{ margin: 0; }}
+.hit {{ background: #dfd; }}
+.miss {{ background: #fcc; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{hit} of {found} lines executed ({:.1}%)</p>
+<table>
+{rows}</table>
+</body>
+</html>
+",
+            hit as f64 * 100.0 / found.max(1) as f64,
+            title = xml_escape(title),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "\
+  (0000) 3000  0011000000000000 (   1)                 .ORIG x3000
+  (3000) 0E01  0000111000000001 (   2)                 BRnzp SKIP
+  (3001) F022  1111000000100010 (   3)                 PUTS
+                                (   4) ; never printed
+  (3002) F025  1111000000100101 (   5) SKIP            HALT
+  (3003) 0048  0000000001001000 (   6) HELLO           .STRINGZ \"H\"
+  (3004) 0000  0000000000000000 (   6)
+";
+
+    #[test]
+    fn test_coverage() {
+        let listing = Listing::parse(LISTING);
+        let mut coverage = Coverage::default();
+        coverage.record(0x3000);
+        coverage.record(0x3002);
+        coverage.record(0x3002);
+
+        let hits: Vec<_> = coverage
+            .lines(&listing)
+            .iter()
+            .map(|line| (line.line, line.hits))
+            .collect();
+        assert_eq!(
+            hits,
+            [
+                (1, None),
+                (2, Some(1)),
+                (3, Some(0)),
+                (4, None),
+                (5, Some(2)),
+                (6, None)
+            ]
+        );
+        assert_eq!(coverage.summary(&listing), (2, 3));
+        assert_eq!(
+            coverage.lcov(&listing, "prog.asm"),
+            "TN:\nSF:prog.asm\nDA:2,1\nDA:3,0\nDA:5,2\nLF:3\nLH:2\nend_of_record\n"
+        );
+
+        let html = coverage.html(&listing, "prog.asm");
+        assert!(
+            html.contains("<tr class=\"miss\"><td>3</td><td>0</td><td><pre>PUTS</pre></td></tr>")
+        );
+        assert!(html.contains("2 of 3 lines executed (66.7%)"));
+    }
+}
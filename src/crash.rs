@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+use crate::{disasm, error::VmError, symbols::SymbolTable, vm::Machine};
+
+/// Where and why a program ended abnormally
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    /// Address of the instruction at fault
+    pub pc: u16,
+    pub word: u16,
+    pub reason: String,
+}
+
+impl From<&VmError> for Fault {
+    fn from(error: &VmError) -> Self {
+        Self {
+            pc: error.pc,
+            word: error.word,
+            reason: error.to_string(),
+        }
+    }
+}
+
+/// Diagnostic printed when a program crashes: the faulting instruction, the
+/// register file and, if kept, the instructions leading up to it
+pub fn report(machine: &Machine, fault: &Fault, symbols: &SymbolTable) -> String {
+    let mut out = format!("Crash: {}\n", fault.reason);
+    let site = match symbols.nearest(fault.pc) {
+        Some((name, addr)) if addr == fault.pc => format!(" <{name}>"),
+        Some((name, addr)) => format!(" <{name}+{}>", fault.pc - addr),
+        None => String::new(),
+    };
+    writeln!(
+        out,
+        "  at x{:04X}{site}: x{:04X}  {}",
+        fault.pc,
+        fault.word,
+        disasm::disassemble_with(fault.pc, fault.word, symbols)
+    )
+    .unwrap();
+
+    out.push_str("Registers:\n");
+    for line in machine.reg().to_string().lines() {
+        writeln!(out, "  {line}").unwrap();
+    }
+    writeln!(out, "  {} instructions executed", machine.step_count()).unwrap();
+
+    if let Some(history) = machine.history().filter(|history| !history.is_empty()) {
+        writeln!(out, "Last {} instructions:", history.len()).unwrap();
+        for line in history.report(history.len(), symbols).lines() {
+            writeln!(out, "  {line}").unwrap();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+
+    #[test]
+    fn test_report() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_history(4);
+        test_mach.load_words(0x3000, &[0x1261, 0xD000]).unwrap();
+        let fault = Fault::from(&test_mach.run().unwrap_err());
+
+        let mut symbols = SymbolTable::default();
+        symbols.insert("MAIN", 0x3000);
+        let report = report(&test_mach, &fault, &symbols);
+        assert!(report.starts_with("Crash: illegal opcode xD000 at x3001\n"));
+        assert!(report.contains("  at x3001 <MAIN+1>: xD000  .FILL xD000\n"));
+        assert!(report.contains("R1 = x0001"));
+        assert!(report.contains("Last 1 instructions:\n"));
+        assert!(report.contains("ADD R1, R1, #1"));
+    }
+}
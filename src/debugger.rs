@@ -0,0 +1,1339 @@
+pub mod expr;
+pub mod session;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use num_traits::FromPrimitive;
+
+use crate::{
+    asm, disasm,
+    enums::{CondFlag, RawOpCode, Register, TrapCode},
+    error::{Error, ErrorKind, Result},
+    listing::Listing,
+    memory::{flag_name, RegisterManager},
+    snapshot::Snapshot,
+    symbols::SymbolTable,
+    utils::parse_register,
+    vm::Machine,
+};
+
+const HELP: &str = "\
+Commands:
+  step, s, <enter>        Execute one instruction
+  continue, c [n]         Run until a breakpoint or watchpoint triggers, or for at
+                          most <n> instructions
+  next, n                 Step, treating a JSR/JSRR as a single instruction
+  finish, f               Run until the current subroutine returns
+  rewind <n>              Go back <n> instructions (needs --checkpoint-every)
+  jump <expr>             Move PC to <expr> without executing anything
+  call <expr>             Call the subroutine at <expr> and pause once it
+                          returns to the current PC
+  backtrace, bt           Show the subroutine call stack
+  history [n]             Show the last <n> instructions executed (see --history)
+  break, b <expr>         Set a breakpoint
+  break trap <vector>     Stop before a TRAP to <vector> (number or name, e.g. HALT)
+  break line <n>          Set a breakpoint on source line <n> (needs a listing)
+  break when <reg> becomes <expr>
+                          Pause when <reg> changes to <expr>, e.g.
+                          break when COND becomes Z
+  delete, d <expr>        Remove a breakpoint
+  delete trap <vector>    Remove a trap breakpoint
+  delete line <n>         Remove the breakpoint on source line <n>
+  delete when <reg> becomes <expr>
+                          Remove a register transition breakpoint
+  watch, w <expr>         Pause when the memory cell at <expr> is written
+  rwatch <expr>           Pause when the program reads the memory cell at <expr>
+  unwatch <expr>          Remove a watchpoint
+  watchreg <reg>          Pause when register <reg> (R0-R7, PC, COND) changes value
+  unwatchreg <reg>        Remove a register watchpoint
+  trace <expr> \"<fmt>\"    Log <fmt> whenever <expr> executes, without pausing;
+                          {R0}, {mem[x4000]:d} and {R1:c} are expanded
+  untrace <expr>          Remove a tracepoint
+  display <expr>          Show <expr> every time the machine pauses
+  undisplay <n>           Remove display expression number <n>
+  print, p <expr>         Evaluate an expression
+  set mem <expr> = <expr> Write a value to memory, e.g. set mem x4000 = x1234
+  set reg <reg> = <expr>  Set a register, e.g. set reg R2 = -5
+  asm <expr> \"<instr>\"    Assemble one instruction into memory at <expr>,
+                          e.g. asm x3050 \"ADD R1, R1, #1\"
+  regs, r                 Dump the register file
+  dumpstate [file]        Print (or write to <file>) registers and memory as JSON
+  snapshot <file>         Save registers and all of memory to <file>, for
+                          `simulator diffstate`
+  symbols <file>          Load a lc3as symbol table
+  listing <file>          Load a lc3as listing (.lst) to show source lines
+  info, i                 List breakpoints, watchpoints and displays
+  examine, x <expr> [n]   Dump <n> memory cells starting at <expr>
+  find <start> <end> <expr|\"text\">
+                          List where a word, or a string as PUTS or PUTSP
+                          prints it, occurs between <start> and <end>
+  source <file>           Run the debugger commands in <file>
+  save                    Write the session file now
+  quit, q                 Stop the machine
+  help, h                 Show this message
+
+Expressions: x3000, #10, R0-R7, PC, COND, mem[<expr>], LABEL, a + b, a - b";
+
+/// What the run loop should do after the debugger hands control back
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Stay at the prompt
+    Prompt,
+    Step,
+    Continue,
+    Quit,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    /// Trap vectors to stop at before the TRAP executes
+    trap_breaks: BTreeSet<u8>,
+    /// Watched address -> last value seen there
+    watchpoints: BTreeMap<u16, u16>,
+    read_watchpoints: BTreeSet<u16>,
+    /// Watched register -> last value seen in it
+    register_watches: Vec<(Register, u16)>,
+    /// Register, the value that pauses the machine when the register changes
+    /// to it, and the last value seen in the register
+    transitions: Vec<(Register, u16, u16)>,
+    /// Address -> message template logged when it executes, without pausing
+    tracepoints: BTreeMap<u16, String>,
+    displays: Vec<String>,
+    symbols: SymbolTable,
+    symbol_path: Option<PathBuf>,
+    listing: Listing,
+    listing_path: Option<PathBuf>,
+    session_path: Option<PathBuf>,
+    /// Commands to run once the machine is loaded, e.g. from a session file
+    pending: Vec<String>,
+    /// Commands fed to the prompt before falling back to the terminal
+    script: VecDeque<String>,
+    running: bool,
+    /// Stop once PC reaches this address with at most this many frames on the call stack
+    until: Option<(u16, usize)>,
+    /// Stop once the machine has executed this many instructions
+    stop_at_step: Option<u64>,
+    /// Instructions a `continue` runs for at most, unless it says otherwise
+    continue_budget: Option<u64>,
+    /// Register file at the previous pause, to highlight what changed since
+    last_regs: Option<RegisterManager>,
+}
+
+impl Debugger {
+    /// Creates a debugger whose state is persisted to `session_path`,
+    /// restoring whatever was saved there by a previous run
+    pub fn with_session(session_path: PathBuf) -> Result<Self> {
+        Ok(Self {
+            pending: session::load(&session_path)?,
+            session_path: Some(session_path),
+            ..Default::default()
+        })
+    }
+
+    /// Runs `cmd` once the machine starts, after any restored session commands
+    pub fn queue(&mut self, cmd: String) {
+        self.pending.push(cmd);
+    }
+
+    /// Feeds the commands in a script file to the prompt ahead of any
+    /// remaining script input, falling back to the terminal once they run out
+    pub fn source(&mut self, path: &Path) -> Result<()> {
+        let commands = session::read_script(path)?;
+        for cmd in commands.into_iter().rev() {
+            self.script.push_front(cmd);
+        }
+        Ok(())
+    }
+
+    /// Have every `continue`, `next` and `finish` pause after at most
+    /// `budget` instructions, so runaway loops return to the prompt
+    pub fn set_continue_budget(&mut self, budget: Option<u64>) {
+        self.continue_budget = budget;
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Called before every instruction. Returns `false` if the user asked to quit.
+    pub fn before_instruction(&mut self, machine: &mut Machine) -> bool {
+        for cmd in std::mem::take(&mut self.pending) {
+            if let Err(e) = self.execute(machine, &cmd) {
+                debug!(machine, "Session command `{cmd}` failed: {}", e.message());
+            }
+        }
+
+        let pc = machine.reg().get(Register::PC);
+        if let Some(template) = self.tracepoints.get(&pc).filter(|_| machine.logs_at(pc)) {
+            let msg = expr::format(template, machine, &self.symbols);
+            debug!(machine, "Trace {}: {msg}", self.describe(pc));
+        }
+
+        if self.running && !self.should_stop(machine) {
+            return true;
+        }
+        self.running = false;
+        self.until = None;
+        self.stop_at_step = None;
+
+        machine.flush_output();
+        self.show_status(machine);
+
+        loop {
+            let line = match self.script.pop_front() {
+                Some(line) => {
+                    debug!(machine, "(lc3db) {line}");
+                    line
+                }
+                None => match read_line("(lc3db) ") {
+                    Some(line) => line,
+                    None => return false,
+                },
+            };
+
+            match self.execute(machine, &line) {
+                Ok(Flow::Prompt) => (),
+                Ok(Flow::Step) => return true,
+                Ok(Flow::Continue) => {
+                    self.running = true;
+                    if let Some(budget) = self.continue_budget {
+                        self.stop_at_step
+                            .get_or_insert(machine.step_count() + budget);
+                    }
+                    return true;
+                }
+                Ok(Flow::Quit) => return false,
+                Err(e) => machine.debug(e.message()),
+            }
+        }
+    }
+
+    /// Whether a running machine has reached a breakpoint or the target of `next`/`finish`
+    fn should_stop(&self, machine: &Machine) -> bool {
+        let pc = machine.reg().get(Register::PC);
+
+        if let Some((addr, depth)) = self.until {
+            if pc == addr && machine.call_stack().depth() <= depth {
+                return true;
+            }
+        }
+
+        if self.breakpoints.contains(&pc) {
+            debug!(machine, "Breakpoint hit at {}", self.describe(pc));
+            return true;
+        }
+
+        if self
+            .stop_at_step
+            .is_some_and(|step| machine.step_count() >= step)
+        {
+            debug!(
+                machine,
+                "Paused after the instruction budget, at {}",
+                self.describe(pc)
+            );
+            return true;
+        }
+
+        let instr = machine.mem().peek(pc);
+        if instr >> 12 == RawOpCode::Trap as u16 && self.trap_breaks.contains(&(instr as u8)) {
+            let msg = format!(
+                "{} about to execute at {}",
+                trap_name(instr as u8),
+                self.describe(pc)
+            );
+            machine.debug(msg.as_str());
+            return true;
+        }
+
+        false
+    }
+
+    /// Called after every instruction to check watchpoints
+    pub fn after_instruction(&mut self, machine: &mut Machine) {
+        if let Some(reason) = machine.take_break_request() {
+            machine.debug(reason.as_str());
+            self.running = false;
+        }
+
+        let hits: BTreeSet<u16> = machine.mem_mut().take_read_hits().into_iter().collect();
+        for addr in hits {
+            let val = machine.mem().peek(addr);
+            let msg = format!("Read watchpoint {addr:#06x}: value {val:#06x}");
+            machine.debug(msg.as_str());
+            self.running = false;
+        }
+
+        for (addr, last) in self.watchpoints.iter_mut() {
+            let val = machine.mem().peek(*addr);
+            if val != *last {
+                let msg = format!("Watchpoint {addr:#06x}: {last:#06x} -> {val:#06x}");
+                machine.debug(msg.as_str());
+                *last = val;
+                self.running = false;
+            }
+        }
+
+        for (reg, last) in self.register_watches.iter_mut() {
+            let val = machine.reg().get(*reg);
+            if val != *last {
+                let msg = format!("Register watchpoint {reg:?}: {last:#06x} -> {val:#06x}");
+                machine.debug(msg.as_str());
+                *last = val;
+                self.running = false;
+            }
+        }
+
+        for (reg, target, last) in self.transitions.iter_mut() {
+            let val = machine.reg().get(*reg);
+            if val != *last && val == *target {
+                let msg = format!(
+                    "{reg:?} became {} (was {})",
+                    reg_value(*reg, val),
+                    reg_value(*reg, *last)
+                );
+                machine.debug(msg.as_str());
+                self.running = false;
+            }
+            *last = val;
+        }
+    }
+
+    /// Called once the machine stops running
+    pub fn finish(&mut self, machine: &Machine) {
+        if let Err(e) = self.save_session() {
+            debug!(machine, "Could not save session: {}", e.message());
+        }
+    }
+
+    pub fn execute(&mut self, machine: &mut Machine, line: &str) -> Result<Flow> {
+        let line = line.trim();
+        let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let arg = arg.trim();
+
+        match cmd {
+            "" | "s" | "step" => return Ok(Flow::Step),
+            "c" | "continue" if arg.is_empty() => return Ok(Flow::Continue),
+            "c" | "continue" => {
+                let count = arg
+                    .parse::<u64>()
+                    .map_err(|_| command_error(format!("`{arg}` is not an instruction count")))?;
+                self.stop_at_step = Some(machine.step_count() + count);
+                return Ok(Flow::Continue);
+            }
+            "n" | "next" => {
+                let pc = machine.reg().get(Register::PC);
+                if machine.mem().peek(pc) >> 12 != RawOpCode::Jsr as u16 {
+                    return Ok(Flow::Step);
+                }
+                self.until = Some((pc.wrapping_add(1), machine.call_stack().depth()));
+                return Ok(Flow::Continue);
+            }
+            "f" | "finish" => {
+                let frame = machine
+                    .call_stack()
+                    .top()
+                    .ok_or_else(|| command_error("Not inside a subroutine".to_owned()))?;
+                let depth = machine.call_stack().depth() - 1;
+                self.until = Some((frame.return_addr, depth));
+                return Ok(Flow::Continue);
+            }
+            "jump" => {
+                let addr = self.eval(machine, arg)?;
+                machine.reg_mut().set(Register::PC, addr);
+                self.show_status(machine);
+            }
+            "call" => {
+                let entry = self.eval(machine, arg)?;
+                let pc = machine.reg().get(Register::PC);
+                let depth = machine.call_stack().depth();
+                machine.call(entry);
+                debug!(
+                    machine,
+                    "Calling {}, returning to {}",
+                    self.describe(entry),
+                    self.describe(pc)
+                );
+                self.until = Some((pc, depth));
+                return Ok(Flow::Continue);
+            }
+            "rewind" => {
+                let count = match arg.parse::<u64>() {
+                    Ok(count) => count,
+                    Err(_) => self.eval(machine, arg)? as u64,
+                };
+                machine.rewind_to(machine.step_count().saturating_sub(count))?;
+                self.sync_watches(machine);
+                self.show_status(machine);
+            }
+            "bt" | "backtrace" => {
+                for (idx, frame) in machine.call_stack().frames().enumerate() {
+                    let msg = format!(
+                        "#{idx} {} called from {}",
+                        self.describe(frame.entry),
+                        self.describe(frame.call_site)
+                    );
+                    machine.debug(msg.as_str());
+                }
+            }
+            "history" => {
+                let history = machine
+                    .history()
+                    .ok_or_else(|| command_error("History is off, see --history".to_owned()))?;
+                let count = match arg {
+                    "" => history.len(),
+                    _ => arg
+                        .parse()
+                        .map_err(|_| command_error(format!("`{arg}` is not a count")))?,
+                };
+                for line in history.report(count, &self.symbols).lines() {
+                    machine.debug(line);
+                }
+            }
+            "q" | "quit" => return Ok(Flow::Quit),
+            "h" | "help" => machine.debug(HELP),
+            "b" | "break" if arg.starts_with("trap ") => {
+                let vector = self.eval_trap(machine, &arg[5..])?;
+                self.trap_breaks.insert(vector);
+                debug!(machine, "Breakpoint set on {}", trap_name(vector));
+            }
+            "b" | "break" if arg.starts_with("when ") => {
+                let (reg, target) = self.eval_transition(machine, &arg[5..])?;
+                self.transitions
+                    .retain(|(watched, value, _)| (*watched, *value) != (reg, target));
+                self.transitions.push((reg, target, machine.reg().get(reg)));
+                debug!(
+                    machine,
+                    "Breakpoint set when {reg:?} becomes {}",
+                    reg_value(reg, target)
+                );
+            }
+            "d" | "delete" if arg.starts_with("when ") => {
+                let (reg, target) = self.eval_transition(machine, &arg[5..])?;
+                let before = self.transitions.len();
+                self.transitions
+                    .retain(|(watched, value, _)| (*watched, *value) != (reg, target));
+                if self.transitions.len() == before {
+                    return Err(command_error(format!(
+                        "No breakpoint when {reg:?} becomes {}",
+                        reg_value(reg, target)
+                    )));
+                }
+            }
+            "b" | "break" if arg.starts_with("line ") => {
+                let addr = self.eval_line(&arg[5..])?;
+                self.breakpoints.insert(addr);
+                debug!(machine, "Breakpoint set at {}", self.describe(addr));
+            }
+            "d" | "delete" if arg.starts_with("line ") => {
+                let addr = self.eval_line(&arg[5..])?;
+                if !self.breakpoints.remove(&addr) {
+                    return Err(command_error(format!("No breakpoint at {addr:#06x}")));
+                }
+            }
+            "d" | "delete" if arg.starts_with("trap ") => {
+                let vector = self.eval_trap(machine, &arg[5..])?;
+                if !self.trap_breaks.remove(&vector) {
+                    return Err(command_error(format!(
+                        "No breakpoint on {}",
+                        trap_name(vector)
+                    )));
+                }
+            }
+            "b" | "break" => {
+                let addr = self.eval(machine, arg)?;
+                self.breakpoints.insert(addr);
+                debug!(machine, "Breakpoint set at {}", self.describe(addr));
+            }
+            "d" | "delete" => {
+                let addr = self.eval(machine, arg)?;
+                if !self.breakpoints.remove(&addr) {
+                    return Err(command_error(format!("No breakpoint at {addr:#06x}")));
+                }
+            }
+            "w" | "watch" => {
+                let addr = self.eval(machine, arg)?;
+                self.watchpoints.insert(addr, machine.mem().peek(addr));
+                debug!(machine, "Watching {}", self.describe(addr));
+            }
+            "trace" => {
+                let (addr, template) = arg
+                    .split_once('"')
+                    .and_then(|(addr, rest)| Some((addr, rest.strip_suffix('"')?)))
+                    .ok_or_else(|| command_error("Usage: trace <expr> \"<format>\"".to_owned()))?;
+                let addr = self.eval(machine, addr)?;
+                self.tracepoints.insert(addr, template.to_owned());
+                debug!(machine, "Tracepoint set at {}", self.describe(addr));
+            }
+            "untrace" => {
+                let addr = self.eval(machine, arg)?;
+                if self.tracepoints.remove(&addr).is_none() {
+                    return Err(command_error(format!("No tracepoint at {addr:#06x}")));
+                }
+            }
+            "rwatch" => {
+                let addr = self.eval(machine, arg)?;
+                self.read_watchpoints.insert(addr);
+                machine.mem_mut().watch_reads(addr);
+                debug!(machine, "Watching reads of {}", self.describe(addr));
+            }
+            "unwatch" => {
+                let addr = self.eval(machine, arg)?;
+                let write = self.watchpoints.remove(&addr).is_some();
+                let read = self.read_watchpoints.remove(&addr);
+                machine.mem_mut().unwatch_reads(addr);
+                if !write && !read {
+                    return Err(command_error(format!("No watchpoint at {addr:#06x}")));
+                }
+            }
+            "watchreg" => {
+                let reg = parse_register(arg)
+                    .ok_or_else(|| command_error(format!("`{arg}` is not a register")))?;
+                if !self
+                    .register_watches
+                    .iter()
+                    .any(|(watched, _)| *watched == reg)
+                {
+                    self.register_watches.push((reg, machine.reg().get(reg)));
+                }
+                debug!(machine, "Watching {reg:?}");
+            }
+            "unwatchreg" => {
+                let reg = parse_register(arg)
+                    .ok_or_else(|| command_error(format!("`{arg}` is not a register")))?;
+                let before = self.register_watches.len();
+                self.register_watches.retain(|(watched, _)| *watched != reg);
+                if self.register_watches.len() == before {
+                    return Err(command_error(format!("{reg:?} is not watched")));
+                }
+            }
+            "display" => {
+                self.eval(machine, arg)?;
+                self.displays.push(arg.to_owned());
+            }
+            "undisplay" => {
+                let idx = arg
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|idx| *idx < self.displays.len())
+                    .ok_or_else(|| command_error(format!("No display number `{arg}`")))?;
+                self.displays.remove(idx);
+            }
+            "p" | "print" => {
+                let val = self.eval(machine, arg)?;
+                debug!(machine, "{arg} = {val:#06x} ({})", val as i16);
+            }
+            "set" => {
+                let usage = || {
+                    command_error(
+                        "Usage: set mem <expr> = <expr> or set reg <reg> = <expr>".to_owned(),
+                    )
+                };
+                let (target, value) = arg.split_once('=').ok_or_else(usage)?;
+                let value = self.eval(machine, value)?;
+                let (kind, target) = target.trim().split_once(' ').ok_or_else(usage)?;
+                match kind {
+                    "mem" => {
+                        let addr = self.eval(machine, target)?;
+                        machine.mem_mut().load(addr, value);
+                        debug!(machine, "{} = {value:#06x}", self.describe(addr));
+                    }
+                    "reg" => {
+                        let reg = parse_register(target.trim()).ok_or_else(|| {
+                            command_error(format!("`{}` is not a register", target.trim()))
+                        })?;
+                        machine.reg_mut().set(reg, value);
+                        debug!(machine, "{reg:?} = {value:#06x} ({})", value as i16);
+                    }
+                    _ => return Err(usage()),
+                }
+                self.sync_watches(machine);
+            }
+            "asm" => {
+                let (addr, src) = arg
+                    .split_once('"')
+                    .and_then(|(addr, rest)| Some((addr, rest.strip_suffix('"')?)))
+                    .ok_or_else(|| command_error("Usage: asm <expr> \"<instr>\"".to_owned()))?;
+                let addr = self.eval(machine, addr)?;
+                let word = asm::assemble_instruction(src, addr, &self.symbols)?;
+                machine.mem_mut().load(addr, word);
+                self.sync_watches(machine);
+                let instr = disasm::highlight(&disasm::disassemble_with(addr, word, &self.symbols));
+                let msg = format!("{}: {word:#06x}  {instr}", self.describe(addr));
+                machine.debug(msg.as_str());
+            }
+            "r" | "regs" => machine.reg().debug_all(),
+            "dumpstate" if arg.is_empty() => machine.debug(&machine.state_json()),
+            "dumpstate" => fs::write(arg, machine.state_json() + "\n")?,
+            "snapshot" if arg.is_empty() => {
+                return Err(command_error("Usage: snapshot <file>".to_owned()))
+            }
+            "snapshot" => {
+                Snapshot::capture(machine).save(Path::new(arg))?;
+                debug!(machine, "Snapshot saved to {arg}");
+            }
+            "listing" => {
+                let path = PathBuf::from(arg);
+                self.listing = Listing::load(&path)?;
+                self.listing_path = Some(path);
+            }
+            "symbols" => {
+                let path = PathBuf::from(arg);
+                self.symbols = SymbolTable::load(&path)?;
+                self.symbol_path = Some(path);
+            }
+            "i" | "info" => self.show_info(machine),
+            "x" | "examine" => {
+                let (addr, count) = match arg.rsplit_once(' ') {
+                    Some((addr, count)) if count.parse::<u16>().is_ok() => {
+                        (addr, count.parse::<u16>().unwrap())
+                    }
+                    _ => (arg, 1),
+                };
+                let addr = self.eval(machine, addr)?;
+                for offset in 0..count {
+                    let addr = addr.wrapping_add(offset);
+                    let word = machine.mem().peek(addr);
+                    let instr =
+                        disasm::highlight(&disasm::disassemble_with(addr, word, &self.symbols));
+                    let msg = format!("{}: {word:#06x}  {instr}", self.describe(addr));
+                    machine.debug(msg.as_str());
+                }
+            }
+            "find" => {
+                let usage =
+                    || command_error("Usage: find <start> <end> <expr|\"text\">".to_owned());
+                let mut parts = arg.splitn(3, ' ');
+                let (Some(first), Some(last), Some(needle)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(usage());
+                };
+                let (first, last) = (self.eval(machine, first)?, self.eval(machine, last)?);
+                let needle = match needle.trim().strip_prefix('"') {
+                    Some(text) => {
+                        Needle::Text(text.strip_suffix('"').ok_or_else(usage)?.to_owned())
+                    }
+                    None => Needle::Word(self.eval(machine, needle)?),
+                };
+                let found = find(machine, first, last, &needle);
+                for (addr, kind) in &found {
+                    debug!(machine, "Found {kind} at {}", self.describe(*addr));
+                }
+                debug!(
+                    machine,
+                    "{} match(es) in {first:#06x}-{last:#06x}",
+                    found.len()
+                );
+            }
+            "source" => self.source(Path::new(arg))?,
+            "save" => self.save_session()?,
+            _ => {
+                return Err(command_error(format!(
+                    "Unknown command `{cmd}`, try `help`"
+                )))
+            }
+        }
+
+        Ok(Flow::Prompt)
+    }
+
+    pub fn save_session(&self) -> Result<()> {
+        match &self.session_path {
+            Some(path) => session::save(path, &self.session_commands()),
+            None => Ok(()),
+        }
+    }
+
+    /// Debugger state expressed as the commands that recreate it
+    fn session_commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+
+        if let Some(path) = &self.symbol_path {
+            commands.push(format!("symbols {}", path.display()));
+        }
+        if let Some(path) = &self.listing_path {
+            commands.push(format!("listing {}", path.display()));
+        }
+        for addr in &self.breakpoints {
+            commands.push(format!("break x{addr:04X}"));
+        }
+        for vector in &self.trap_breaks {
+            commands.push(format!("break trap x{vector:02X}"));
+        }
+        for addr in self.watchpoints.keys() {
+            commands.push(format!("watch x{addr:04X}"));
+        }
+        for addr in &self.read_watchpoints {
+            commands.push(format!("rwatch x{addr:04X}"));
+        }
+        for (reg, _) in &self.register_watches {
+            commands.push(format!("watchreg {reg:?}"));
+        }
+        for (reg, target, _) in &self.transitions {
+            commands.push(format!(
+                "break when {reg:?} becomes {}",
+                reg_value(*reg, *target)
+            ));
+        }
+        for (addr, template) in &self.tracepoints {
+            commands.push(format!("trace x{addr:04X} \"{template}\""));
+        }
+        for expr in &self.displays {
+            commands.push(format!("display {expr}"));
+        }
+
+        commands
+    }
+
+    /// Catches watchpoints up with state changed from the prompt (`rewind`,
+    /// `set`), so that isn't reported as the program writing it
+    fn sync_watches(&mut self, machine: &Machine) {
+        for (addr, last) in self.watchpoints.iter_mut() {
+            *last = machine.mem().peek(*addr);
+        }
+        for (reg, last) in self.register_watches.iter_mut() {
+            *last = machine.reg().get(*reg);
+        }
+        for (reg, _, last) in self.transitions.iter_mut() {
+            *last = machine.reg().get(*reg);
+        }
+    }
+
+    fn eval(&self, machine: &Machine, src: &str) -> Result<u16> {
+        expr::eval(src, machine, &self.symbols)
+    }
+
+    /// Address of the code on source line `src`, according to the listing
+    fn eval_line(&self, src: &str) -> Result<u16> {
+        let line = src
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| command_error(format!("`{src}` is not a line number")))?;
+        if self.listing.is_empty() {
+            return Err(command_error(
+                "No listing loaded, try `listing <file>`".to_owned(),
+            ));
+        }
+        self.listing
+            .addr_of_line(line)
+            .ok_or_else(|| command_error(format!("No code at or after line {line}")))
+    }
+
+    /// `<reg> becomes <value>`, the value of COND given as N, Z or P
+    fn eval_transition(&self, machine: &Machine, src: &str) -> Result<(Register, u16)> {
+        let usage = || command_error("Usage: break when <reg> becomes <expr>".to_owned());
+        let (reg, value) = src.split_once(" becomes ").ok_or_else(usage)?;
+        let reg = parse_register(reg.trim())
+            .ok_or_else(|| command_error(format!("`{}` is not a register", reg.trim())))?;
+        let value = value.trim();
+        if reg != Register::COND {
+            return Ok((reg, self.eval(machine, value)?));
+        }
+        match value.to_ascii_uppercase().as_str() {
+            "P" => Ok((reg, CondFlag::Pos as u16)),
+            "Z" => Ok((reg, CondFlag::Zero as u16)),
+            "N" => Ok((reg, CondFlag::Neg as u16)),
+            _ => Err(command_error(format!("`{value}` is not one of N, Z or P"))),
+        }
+    }
+
+    /// Trap vector given by number or by name, e.g. `x25` or `HALT`
+    fn eval_trap(&self, machine: &Machine, src: &str) -> Result<u8> {
+        if let Some(trap) = TrapCode::from_name(src.trim()) {
+            return Ok(trap as u8);
+        }
+
+        let vector = self.eval(machine, src)?;
+        u8::try_from(vector)
+            .map_err(|_| command_error(format!("Trap vector {vector:#x} is out of range")))
+    }
+
+    /// Formats an address along with the closest symbol, e.g. `0x3004 <LOOP+2>`
+    fn describe(&self, addr: u16) -> String {
+        match self.symbols.nearest(addr) {
+            Some((name, base)) if base == addr => format!("{addr:#06x} <{name}>"),
+            Some((name, base)) => format!("{addr:#06x} <{name}+{}>", addr - base),
+            None => format!("{addr:#06x}"),
+        }
+    }
+
+    fn show_status(&mut self, machine: &Machine) {
+        let pc = machine.reg().get(Register::PC);
+        let posn = format!("[PC = {}]", self.describe(pc)).yellow();
+        debug!(machine, "Paused at {posn}");
+        if let Some((line, source)) = self.listing.line_at(pc) {
+            debug!(machine, "Source line {line}: {}", source.green());
+        }
+        let raw_instr = machine.mem().peek(pc);
+        let instr = disasm::highlight(&disasm::disassemble_with(pc, raw_instr, &self.symbols));
+        let formatted = format!("{raw_instr:#018b}").dimmed();
+        debug!(machine, "Next Instruction: {instr} {formatted}");
+
+        match &self.last_regs {
+            Some(prev) => machine.reg().debug_changes(prev),
+            None => machine.reg().debug_all(),
+        }
+        self.last_regs = Some(machine.reg().clone());
+
+        for (idx, expr) in self.displays.iter().enumerate() {
+            let shown = match self.eval(machine, expr) {
+                Ok(val) => format!("{idx}: {expr} = {val:#06x}"),
+                Err(e) => format!("{idx}: {expr} = <{}>", e.message()),
+            };
+            machine.debug(shown.as_str());
+        }
+    }
+
+    fn show_info(&self, machine: &Machine) {
+        for addr in &self.breakpoints {
+            debug!(machine, "Breakpoint at {}", self.describe(*addr));
+        }
+        for vector in &self.trap_breaks {
+            debug!(machine, "Breakpoint on {}", trap_name(*vector));
+        }
+        for addr in self.watchpoints.keys() {
+            debug!(machine, "Watchpoint at {}", self.describe(*addr));
+        }
+        for addr in &self.read_watchpoints {
+            debug!(machine, "Read watchpoint at {}", self.describe(*addr));
+        }
+        for (reg, _) in &self.register_watches {
+            debug!(machine, "Register watchpoint on {reg:?}");
+        }
+        for (reg, target, _) in &self.transitions {
+            debug!(
+                machine,
+                "Breakpoint when {reg:?} becomes {}",
+                reg_value(*reg, *target)
+            );
+        }
+        for (addr, template) in &self.tracepoints {
+            let msg = format!("Tracepoint at {}: \"{template}\"", self.describe(*addr));
+            machine.debug(msg.as_str());
+        }
+        for (idx, expr) in self.displays.iter().enumerate() {
+            debug!(machine, "Display {idx}: {expr}");
+        }
+        if let Some(path) = &self.symbol_path {
+            debug!(machine, "Symbols loaded from {}", path.display());
+        }
+        if let Some(path) = &self.listing_path {
+            debug!(machine, "Listing loaded from {}", path.display());
+        }
+        if let Some(oldest) = machine.checkpoints().and_then(|cps| cps.oldest()) {
+            let msg = format!(
+                "{} checkpoints, can rewind to step {oldest} (now at step {})",
+                machine.checkpoints().map_or(0, |cps| cps.len()),
+                machine.step_count()
+            );
+            machine.debug(msg.as_str());
+        }
+    }
+}
+
+/// What `find` looks for
+enum Needle {
+    Word(u16),
+    Text(String),
+}
+
+/// Addresses in `first..=last` where `needle` starts, with how it is stored
+/// there: a single word, or a string as PUTS (a character per word) or PUTSP
+/// (two per word, low byte first) would print it
+fn find(machine: &Machine, first: u16, last: u16, needle: &Needle) -> Vec<(u16, &'static str)> {
+    let patterns = match needle {
+        Needle::Word(word) => vec![("word", vec![*word])],
+        Needle::Text(text) => {
+            let bytes = text.as_bytes();
+            let unpacked: Vec<u16> = bytes.iter().map(|&byte| byte as u16).collect();
+            let packed: Vec<u16> = bytes
+                .chunks(2)
+                .map(|pair| pair[0] as u16 | pair.get(1).map_or(0, |&hi| (hi as u16) << 8))
+                .collect();
+            if packed == unpacked {
+                vec![("string", unpacked)]
+            } else {
+                vec![("string", unpacked), ("packed string", packed)]
+            }
+        }
+    };
+
+    let mut found = Vec::new();
+    for addr in first..=last {
+        for (kind, words) in &patterns {
+            let fits = (last - addr) as usize >= words.len().saturating_sub(1);
+            let matches = words
+                .iter()
+                .zip(addr..)
+                .all(|(word, at)| machine.mem().peek(at) == *word);
+            if !words.is_empty() && fits && matches {
+                found.push((addr, *kind));
+            }
+        }
+    }
+    found
+}
+
+fn trap_name(vector: u8) -> String {
+    match TrapCode::from_u8(vector) {
+        Some(trap) => format!("TRAP x{vector:02X} ({})", trap.name()),
+        None => format!("TRAP x{vector:02X}"),
+    }
+}
+
+/// A register's value as `break when` takes it: N, Z or P for COND
+fn reg_value(reg: Register, val: u16) -> String {
+    match reg {
+        Register::COND => flag_name(val).to_owned(),
+        _ => format!("x{val:04X}"),
+    }
+}
+
+fn command_error(message: String) -> Error {
+    Error::with_message(ErrorKind::ParseError, message)
+}
+
+/// Reads one line from stdin while the terminal is in raw mode, echoing
+/// input to stderr with the prompt and handling backspace. Returns `None` on EOF, Ctrl-C or Ctrl-D.
+fn read_line(prompt: &str) -> Option<String> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{}", prompt.cyan()).expect("Failed to write to stderr");
+    stderr.flush().expect("Failed to flush stderr");
+
+    let mut line = String::new();
+    let mut buf = [0; 1];
+    loop {
+        if io::stdin().read_exact(&mut buf).is_err() {
+            return None;
+        }
+
+        match buf[0] {
+            b'\r' | b'\n' => break,
+            0x03 | 0x04 => return None,
+            0x08 | 0x7f if line.pop().is_some() => {
+                write!(stderr, "\x08 \x08").expect("Failed to write to stderr");
+            }
+            ch if ch.is_ascii() && !ch.is_ascii_control() => {
+                line.push(ch as char);
+                write!(stderr, "{}", ch as char).expect("Failed to write to stderr");
+            }
+            _ => (),
+        }
+        stderr.flush().expect("Failed to flush stderr");
+    }
+
+    write!(stderr, "\r\n").expect("Failed to write to stderr");
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+
+        assert_eq!(dbg.execute(&mut test_mach, "").unwrap(), Flow::Step);
+        assert_eq!(dbg.execute(&mut test_mach, "c").unwrap(), Flow::Continue);
+        assert_eq!(dbg.execute(&mut test_mach, "quit").unwrap(), Flow::Quit);
+        assert_eq!(
+            dbg.execute(&mut test_mach, "break PC + 2").unwrap(),
+            Flow::Prompt
+        );
+        assert!(dbg.breakpoints.contains(&0x3002));
+        assert!(dbg.execute(&mut test_mach, "delete x3001").is_err());
+        assert!(dbg.execute(&mut test_mach, "bogus").is_err());
+        assert!(dbg.execute(&mut test_mach, "undisplay 0").is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let mut test_mach = Machine::default();
+        test_mach
+            .load_words(0x4000, &[0x1234, b'h' as u16, b'i' as u16, 0x6968, 0x1234])
+            .unwrap();
+        let found = |needle: &Needle| find(&test_mach, 0x4000, 0x4003, needle);
+
+        assert_eq!(found(&Needle::Word(0x1234)), [(0x4000, "word")]);
+        assert_eq!(
+            found(&Needle::Text("hi".to_owned())),
+            [(0x4001, "string"), (0x4003, "packed string")]
+        );
+        assert_eq!(found(&Needle::Text("hih".to_owned())), []);
+
+        let mut dbg = Debugger::default();
+        assert!(dbg
+            .execute(&mut test_mach, "find x4000 x4004 \"hi\"")
+            .is_ok());
+        assert!(dbg
+            .execute(&mut test_mach, "find x4000 x4004 R0 + 1")
+            .is_ok());
+        assert!(dbg.execute(&mut test_mach, "find x4000 \"hi\"").is_err());
+    }
+
+    #[allow(clippy::unusual_byte_groupings)]
+    #[test]
+    fn test_next_and_finish() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+
+        // Not a call, so `next` is a plain step
+        assert_eq!(dbg.execute(&mut test_mach, "next").unwrap(), Flow::Step);
+        assert!(dbg.execute(&mut test_mach, "finish").is_err());
+
+        // JSR #15 at x3000
+        test_mach.mem_mut().write(0x3000, 0b0100_1_00000001111);
+        assert_eq!(dbg.execute(&mut test_mach, "next").unwrap(), Flow::Continue);
+        assert_eq!(dbg.until, Some((0x3001, 0)));
+        assert!(!dbg.should_stop(&test_mach));
+
+        test_mach.reg_mut().set(Register::PC, 0x3001);
+        assert!(dbg.should_stop(&test_mach));
+    }
+
+    #[test]
+    fn test_jump_and_call() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        dbg.symbols.insert("DOUBLE", 0x3010);
+        // DOUBLE: ADD R0, R0, R0; RET
+        test_mach.mem_mut().write(0x3010, 0x1000);
+        test_mach.mem_mut().write(0x3011, 0xC1C0);
+
+        dbg.execute(&mut test_mach, "jump x3005").unwrap();
+        assert_eq!(test_mach.reg().get(Register::PC), 0x3005);
+
+        test_mach.reg_mut().set(Register::R0, 21);
+        assert_eq!(
+            dbg.execute(&mut test_mach, "call DOUBLE").unwrap(),
+            Flow::Continue
+        );
+        assert_eq!(test_mach.reg().get(Register::PC), 0x3010);
+        assert_eq!(test_mach.reg().get(Register::R7), 0x3005);
+        assert_eq!(test_mach.call_stack().depth(), 1);
+        assert_eq!(dbg.until, Some((0x3005, 0)));
+
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+        assert!(dbg.should_stop(&test_mach));
+        assert_eq!(test_mach.reg().get(Register::R0), 42);
+        assert_eq!(test_mach.call_stack().depth(), 0);
+    }
+
+    #[test]
+    fn test_continue_budget() {
+        let mut test_mach = Machine::default();
+        // BRnzp to itself, forever
+        test_mach.mem_mut().write(0x3000, 0x0FFF);
+        let mut dbg = Debugger::default();
+        dbg.script.extend(["continue 5", "quit"].map(String::from));
+        test_mach.attach_debugger(dbg);
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), 5);
+
+        let mut test_mach = Machine::default();
+        test_mach.mem_mut().write(0x3000, 0x0FFF);
+        let mut dbg = Debugger::default();
+        dbg.set_continue_budget(Some(7));
+        dbg.script.extend(["c", "c 2", "quit"].map(String::from));
+        test_mach.attach_debugger(dbg);
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), 9);
+
+        assert!(Debugger::default()
+            .execute(&mut test_mach, "continue forever")
+            .is_err());
+    }
+
+    #[test]
+    fn test_trap_breakpoint() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        test_mach.mem_mut().write(0x3000, 0xF022);
+
+        dbg.execute(&mut test_mach, "break trap x25").unwrap();
+        assert!(!dbg.should_stop(&test_mach));
+        dbg.execute(&mut test_mach, "break trap puts").unwrap();
+        assert!(dbg.should_stop(&test_mach));
+        assert_eq!(
+            dbg.session_commands(),
+            vec!["break trap x22", "break trap x25"]
+        );
+
+        dbg.execute(&mut test_mach, "delete trap x22").unwrap();
+        assert!(!dbg.should_stop(&test_mach));
+        assert!(dbg.execute(&mut test_mach, "break trap x100").is_err());
+    }
+
+    #[test]
+    fn test_tracepoint() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "trace x3000 \"R0 is {R0:d}\"")
+            .unwrap();
+        assert_eq!(dbg.tracepoints[&0x3000], "R0 is {R0:d}");
+        assert!(dbg.before_instruction(&mut test_mach));
+        assert!(dbg.running);
+        assert_eq!(dbg.session_commands(), vec!["trace x3000 \"R0 is {R0:d}\""]);
+
+        assert!(dbg
+            .execute(&mut test_mach, "trace x3000 no quotes")
+            .is_err());
+        dbg.execute(&mut test_mach, "untrace x3000").unwrap();
+        assert!(dbg.tracepoints.is_empty());
+    }
+
+    #[test]
+    fn test_script() {
+        let path = std::env::temp_dir().join("simulator_test_script.lc3dbg");
+        std::fs::write(&path, "# comment\nbreak x3001\n\ncontinue\n").unwrap();
+
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        dbg.source(&path).unwrap();
+        assert_eq!(dbg.script, vec!["break x3001", "continue"]);
+
+        // The script sets the breakpoint and continues without touching the terminal
+        assert!(dbg.before_instruction(&mut test_mach));
+        assert!(dbg.running);
+        assert!(dbg.breakpoints.contains(&0x3001));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(dbg.source(&path).is_err());
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "watch x4000").unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(dbg.running);
+
+        test_mach.mem_mut().write(0x4000, 1);
+        dbg.after_instruction(&mut test_mach);
+        assert!(!dbg.running);
+        assert_eq!(dbg.watchpoints[&0x4000], 1);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "watch x4000").unwrap();
+        dbg.execute(&mut test_mach, "set mem x4000 = x1234")
+            .unwrap();
+        assert_eq!(test_mach.mem().peek(0x4000), 0x1234);
+        dbg.after_instruction(&mut test_mach);
+        assert!(dbg.running);
+
+        dbg.execute(&mut test_mach, "set reg R2 = -5").unwrap();
+        assert_eq!(test_mach.reg().get(Register::R2), 0xFFFB);
+        dbg.execute(&mut test_mach, "set mem R2 + 5 = mem[x4000] + 1")
+            .unwrap();
+        assert_eq!(test_mach.mem().peek(0), 0x1235);
+
+        assert!(dbg.execute(&mut test_mach, "set reg R9 = 1").is_err());
+        assert!(dbg.execute(&mut test_mach, "set mem x4000").is_err());
+        assert!(dbg.execute(&mut test_mach, "set pc = 1").is_err());
+    }
+
+    #[test]
+    fn test_asm() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        dbg.symbols.insert("LOOP", 0x3000);
+
+        dbg.execute(&mut test_mach, "asm x3050 \"ADD R1, R1, #1\"")
+            .unwrap();
+        assert_eq!(test_mach.mem().peek(0x3050), 0x1261);
+        dbg.execute(&mut test_mach, "asm PC + 1 \"BRnzp LOOP\"")
+            .unwrap();
+        assert_eq!(test_mach.mem().peek(0x3001), 0x0FFE);
+
+        assert!(dbg
+            .execute(&mut test_mach, "asm x3000 ADD R1, R1, #1")
+            .is_err());
+        assert!(dbg
+            .execute(&mut test_mach, "asm x3000 \"ADD R1, R9\"")
+            .is_err());
+    }
+
+    #[test]
+    fn test_break_line() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        assert!(dbg.execute(&mut test_mach, "break line 3").is_err());
+
+        dbg.listing.insert(0x3000, 2, "LEA R0 HELLO");
+        dbg.listing.insert(0x3001, 4, "PUTS");
+        dbg.execute(&mut test_mach, "break line 3").unwrap();
+        assert!(dbg.breakpoints.contains(&0x3001));
+        assert!(dbg.execute(&mut test_mach, "break line 5").is_err());
+
+        dbg.execute(&mut test_mach, "delete line 4").unwrap();
+        assert!(dbg.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_register_watchpoint() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "watchreg r6").unwrap();
+        assert!(dbg.execute(&mut test_mach, "watchreg x4000").is_err());
+        // ADD R0, R0, #1 leaves R6 alone
+        test_mach.mem_mut().write(0x3000, 0x1021);
+        test_mach.step().unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(dbg.running);
+
+        // ADD R6, R6, #-1
+        test_mach.mem_mut().write(0x3001, 0x1DBF);
+        test_mach.step().unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(!dbg.running);
+        assert_eq!(dbg.register_watches, vec![(Register::R6, 0xFFFF)]);
+        assert_eq!(dbg.session_commands(), vec!["watchreg R6".to_owned()]);
+
+        dbg.execute(&mut test_mach, "unwatchreg R6").unwrap();
+        assert!(dbg.execute(&mut test_mach, "unwatchreg R6").is_err());
+    }
+
+    #[test]
+    fn test_break_when() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "break when COND becomes Z")
+            .unwrap();
+        dbg.execute(&mut test_mach, "break when R1 becomes R1 + 2")
+            .unwrap();
+        assert!(dbg
+            .execute(&mut test_mach, "break when COND becomes 0")
+            .is_err());
+        assert!(dbg.execute(&mut test_mach, "break when R1").is_err());
+        assert_eq!(
+            dbg.session_commands(),
+            ["break when COND becomes Z", "break when R1 becomes x0002"]
+        );
+
+        // ADD R1, R1, #1 twice, then AND R0, R0, #0
+        for (addr, word) in [(0x3000, 0x1261), (0x3001, 0x1261), (0x3002, 0x5020)] {
+            test_mach.mem_mut().write(addr, word);
+        }
+        test_mach.step().unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(dbg.running);
+        test_mach.step().unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(!dbg.running);
+
+        dbg.running = true;
+        test_mach.step().unwrap();
+        dbg.after_instruction(&mut test_mach);
+        assert!(!dbg.running);
+
+        dbg.execute(&mut test_mach, "delete when COND becomes z")
+            .unwrap();
+        assert!(dbg
+            .execute(&mut test_mach, "delete when COND becomes Z")
+            .is_err());
+    }
+
+    #[test]
+    fn test_read_watchpoint() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger {
+            running: true,
+            ..Default::default()
+        };
+
+        dbg.execute(&mut test_mach, "rwatch x4000").unwrap();
+        test_mach.mem_mut().peek(0x4000);
+        dbg.after_instruction(&mut test_mach);
+        assert!(dbg.running);
+
+        test_mach.mem_mut().read(0x4000);
+        dbg.after_instruction(&mut test_mach);
+        assert!(!dbg.running);
+
+        dbg.execute(&mut test_mach, "unwatch x4000").unwrap();
+        assert!(dbg.execute(&mut test_mach, "unwatch x4000").is_err());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+        assert!(dbg.execute(&mut test_mach, "rewind 1").is_err());
+
+        // ADD R0, R0, #1 forever
+        test_mach.mem_mut().write(0x3000, 0x1021);
+        test_mach.mem_mut().write(0x3001, 0x0FFE);
+        test_mach.enable_checkpoints(4, 2);
+        test_mach.set_step_limit(Some(20));
+        test_mach.run().unwrap();
+        dbg.execute(&mut test_mach, "watch x3000").unwrap();
+
+        dbg.execute(&mut test_mach, "rewind 5").unwrap();
+        assert_eq!(test_mach.step_count(), 15);
+        assert_eq!(test_mach.reg().get(Register::R0), 8);
+        assert!(dbg.execute(&mut test_mach, "history 3").is_err());
+        test_mach.enable_history(4);
+        test_mach.step().unwrap();
+        dbg.execute(&mut test_mach, "history 3").unwrap();
+        assert!(dbg.execute(&mut test_mach, "rewind 100").is_err());
+    }
+
+    #[test]
+    fn test_session_commands() {
+        let mut test_mach = Machine::default();
+        let mut dbg = Debugger::default();
+
+        dbg.execute(&mut test_mach, "break x3002").unwrap();
+        dbg.execute(&mut test_mach, "watch x4000").unwrap();
+        dbg.execute(&mut test_mach, "display mem[R0]").unwrap();
+        let commands = dbg.session_commands();
+        assert_eq!(
+            commands,
+            vec!["break x3002", "watch x4000", "display mem[R0]"]
+        );
+
+        let mut restored = Debugger {
+            pending: commands,
+            running: true,
+            ..Default::default()
+        };
+        restored.before_instruction(&mut test_mach);
+        assert_eq!(restored.session_commands(), dbg.session_commands());
+    }
+}
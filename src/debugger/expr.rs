@@ -0,0 +1,228 @@
+use crate::{
+    error::{Error, ErrorKind, Result},
+    symbols::SymbolTable,
+    utils::{parse_number, parse_register},
+    vm::Machine,
+};
+
+/// Evaluates a debugger expression against the current machine state.
+///
+/// Supported terms are numbers (`x3000`, `0x3000`, `#10`, `10`), registers
+/// (`R0`-`R7`, `PC`, `COND`), memory cells (`mem[<expr>]`) and symbols, which
+/// can be combined with `+` and `-`. All arithmetic wraps at 16 bits.
+pub fn eval(src: &str, machine: &Machine, symbols: &SymbolTable) -> Result<u16> {
+    let mut parser = Parser {
+        src: src.as_bytes(),
+        pos: 0,
+        machine,
+        symbols,
+    };
+
+    let val = parser.expr()?;
+    parser.skip_ws();
+    if parser.pos != parser.src.len() {
+        return Err(parser.error("unexpected trailing input"));
+    }
+
+    Ok(val)
+}
+
+/// Expands `{expr}` placeholders in a tracepoint template.
+///
+/// Values are shown in hex by default; `{expr:d}` shows signed decimal and
+/// `{expr:c}` an ASCII character. Use `{{` and `}}` for literal braces.
+pub fn format(template: &str, machine: &Machine, symbols: &SymbolTable) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(idx) = rest.find(['{', '}']) {
+        out.push_str(&rest[..idx]);
+        let tail = &rest[idx..];
+
+        if let Some(tail) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = tail;
+        } else if let Some(tail) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = tail;
+        } else if let Some(end) = tail.find('}').filter(|_| tail.starts_with('{')) {
+            let spec = &tail[1..end];
+            let (src, style) = match spec.rsplit_once(':') {
+                Some((src, style @ ("d" | "c" | "x"))) => (src, style),
+                _ => (spec, "x"),
+            };
+            let shown = match eval(src, machine, symbols) {
+                Ok(val) if style == "d" => (val as i16).to_string(),
+                Ok(val) if style == "c" => (val as u8 as char).to_string(),
+                Ok(val) => format!("{val:#06x}"),
+                Err(e) => format!("<{}>", e.message()),
+            };
+            out.push_str(&shown);
+            rest = &tail[end + 1..];
+        } else {
+            out.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+    machine: &'a Machine,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Result<u16> {
+        let mut val = self.term()?;
+
+        loop {
+            self.skip_ws();
+            match self.peek() {
+                Some(b'+') => {
+                    self.pos += 1;
+                    val = val.wrapping_add(self.term()?);
+                }
+                Some(b'-') => {
+                    self.pos += 1;
+                    val = val.wrapping_sub(self.term()?);
+                }
+                _ => return Ok(val),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<u16> {
+        self.skip_ws();
+
+        match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                Ok(self.term()?.wrapping_neg())
+            }
+            Some(b'(') => {
+                self.pos += 1;
+                let val = self.expr()?;
+                self.expect(b')')?;
+                Ok(val)
+            }
+            Some(_) => {
+                let word = self.word();
+                if word.is_empty() {
+                    return Err(self.error("expected a value"));
+                }
+
+                if word.eq_ignore_ascii_case("mem") {
+                    self.skip_ws();
+                    self.expect(b'[')?;
+                    let addr = self.expr()?;
+                    self.expect(b']')?;
+                    return Ok(self.machine.mem().peek(addr));
+                }
+
+                if let Some(reg) = parse_register(word) {
+                    return Ok(self.machine.reg().get(reg));
+                }
+
+                parse_number(word)
+                    .or_else(|| self.symbols.lookup(word))
+                    .ok_or_else(|| self.error(&format!("unknown value `{word}`")))
+            }
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+
+    fn word(&mut self) -> &'a str {
+        let src = self.src;
+        let start = self.pos;
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'#' {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        std::str::from_utf8(&src[start..self.pos]).unwrap_or_default()
+    }
+
+    fn expect(&mut self, ch: u8) -> Result<()> {
+        self.skip_ws();
+        if self.peek() == Some(ch) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", ch as char)))
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(|ch| ch.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.get(self.pos).copied()
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        Error::with_message(
+            ErrorKind::ParseError,
+            format!("{msg} (at column {})", self.pos),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::Register;
+
+    #[test]
+    fn test_eval() {
+        let mut test_mach = Machine::default();
+        test_mach.reg_mut().set(Register::R1, 0x4000);
+        test_mach.mem_mut().write(0x4002, 0x69);
+        let mut symbols = SymbolTable::default();
+        symbols.insert("DATA", 0x4000);
+
+        assert_eq!(eval("R1", &test_mach, &symbols).unwrap(), 0x4000);
+        assert_eq!(eval("pc", &test_mach, &symbols).unwrap(), 0x3000);
+        assert_eq!(eval("mem[R1 + 2]", &test_mach, &symbols).unwrap(), 0x69);
+        assert_eq!(
+            eval("mem[DATA+#2] - 1", &test_mach, &symbols).unwrap(),
+            0x68
+        );
+        assert_eq!(eval("-(x10 - 1)", &test_mach, &symbols).unwrap(), 0xfff1);
+        assert!(eval("R1 +", &test_mach, &symbols).is_err());
+        assert!(eval("mem[R1", &test_mach, &symbols).is_err());
+        assert!(eval("NOPE", &test_mach, &symbols).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let mut test_mach = Machine::default();
+        test_mach.reg_mut().set(Register::R0, 0x41);
+        test_mach.reg_mut().set(Register::R1, 0xfffe);
+        test_mach.mem_mut().write(0x4000, 7);
+        let symbols = SymbolTable::default();
+
+        assert_eq!(
+            format(
+                "R0={R0} ch={R0:c} n={R1:d} m={mem[x4000]}",
+                &test_mach,
+                &symbols
+            ),
+            "R0=0x0041 ch=A n=-2 m=0x0007"
+        );
+        assert_eq!(
+            format("{{R0}} {R9}", &test_mach, &symbols),
+            "{R0} <unknown value `R9` (at column 2)>"
+        );
+        assert_eq!(format("unclosed {R0", &test_mach, &symbols), "unclosed {R0");
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs,
+    io::ErrorKind as IoErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::error::Result;
+
+const HEADER: &str = "# lc3dbg session";
+
+/// Location of the session file belonging to an object file,
+/// e.g. `roms/2048.obj` -> `roms/2048.lc3dbg`
+pub fn session_path(image: &Path) -> PathBuf {
+    image.with_extension("lc3dbg")
+}
+
+/// Reads the debugger commands stored in a session file.
+///
+/// A missing session file is not an error, it just yields no commands.
+pub fn load(path: &Path) -> Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(src) => Ok(parse(&src)),
+        Err(e) if e.kind() == IoErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads a file of debugger commands, one per line, `#` starting a comment line
+pub fn read_script(path: &Path) -> Result<Vec<String>> {
+    Ok(parse(&fs::read_to_string(path)?))
+}
+
+pub fn save(path: &Path, commands: &[String]) -> Result<()> {
+    let mut out = String::from(HEADER);
+    out.push('\n');
+    for cmd in commands {
+        out.push_str(cmd);
+        out.push('\n');
+    }
+
+    fs::write(path, out)?;
+    Ok(())
+}
+
+fn parse(src: &str) -> Vec<String> {
+    src.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_path() {
+        assert_eq!(
+            session_path(Path::new("roms/2048.obj")),
+            PathBuf::from("roms/2048.lc3dbg")
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join("simulator_test_round_trip.lc3dbg");
+        let commands = vec!["break x3002".to_owned(), "display R0".to_owned()];
+
+        save(&path, &commands).unwrap();
+        assert_eq!(load(&path).unwrap(), commands);
+
+        fs::remove_file(&path).unwrap();
+        assert!(load(&path).unwrap().is_empty());
+    }
+}
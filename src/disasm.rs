@@ -0,0 +1,188 @@
+use colored::Colorize;
+use num_traits::FromPrimitive;
+
+use crate::{
+    enums::{Register, TrapCode},
+    instruction::{AluOperand, Instruction},
+    symbols::SymbolTable,
+};
+
+/// Renders the instruction `word`, located at `addr`, as LC-3 assembly.
+///
+/// PC-relative operands are shown as absolute target addresses.
+pub fn disassemble(addr: u16, word: u16) -> String {
+    disassemble_with(addr, word, &SymbolTable::default())
+}
+
+/// Like [`disassemble`], naming PC-relative targets that have a label in
+/// `symbols`, e.g. `BRz LOOP_END` or `LD R1, COUNT`
+pub fn disassemble_with(addr: u16, word: u16, symbols: &SymbolTable) -> String {
+    let target = |offset: i16| {
+        let target = addr.wrapping_add(1).wrapping_add_signed(offset);
+        match symbols.name_at(target) {
+            Some(name) => name.to_owned(),
+            None => format!("x{target:04X}"),
+        }
+    };
+    let Ok(instr) = Instruction::decode(word) else {
+        return format!(".FILL x{word:04X}");
+    };
+
+    match instr {
+        Instruction::Br { nzp: 0, .. } => "NOP".to_owned(),
+        Instruction::Br { nzp, offset } => {
+            let mut mnemonic = String::from("BR");
+            for (bit, flag) in [(0x4, 'n'), (0x2, 'z'), (0x1, 'p')] {
+                if nzp & bit != 0 {
+                    mnemonic.push(flag);
+                }
+            }
+            format!("{mnemonic} {}", target(offset))
+        }
+        Instruction::Add { dst, src1, src2 } => {
+            format!("ADD {dst:?}, {src1:?}, {}", alu_operand(src2))
+        }
+        Instruction::And { dst, src1, src2 } => {
+            format!("AND {dst:?}, {src1:?}, {}", alu_operand(src2))
+        }
+        Instruction::Not { dst, src } => format!("NOT {dst:?}, {src:?}"),
+        Instruction::Ld { dst, offset } => format!("LD {dst:?}, {}", target(offset)),
+        Instruction::Ldi { dst, offset } => format!("LDI {dst:?}, {}", target(offset)),
+        Instruction::Lea { dst, offset } => format!("LEA {dst:?}, {}", target(offset)),
+        Instruction::St { src, offset } => format!("ST {src:?}, {}", target(offset)),
+        Instruction::Sti { src, offset } => format!("STI {src:?}, {}", target(offset)),
+        Instruction::Ldr { dst, base, offset } => format!("LDR {dst:?}, {base:?}, #{offset}"),
+        Instruction::Str { src, base, offset } => format!("STR {src:?}, {base:?}, #{offset}"),
+        Instruction::Jsr { offset } => format!("JSR {}", target(offset)),
+        Instruction::Jsrr { base } => format!("JSRR {base:?}"),
+        Instruction::Jmp { base: Register::R7 } => "RET".to_owned(),
+        Instruction::Jmp { base } => format!("JMP {base:?}"),
+        Instruction::Rti => "RTI".to_owned(),
+        Instruction::Trap { vector } => match TrapCode::from_u8(vector) {
+            Some(trap) => trap.name().to_owned(),
+            None => format!("TRAP x{vector:02X}"),
+        },
+    }
+}
+
+/// Adds syntax colors to a line produced by [`disassemble`]
+pub fn highlight(asm: &str) -> String {
+    let (mnemonic, operands) = asm.split_once(' ').unwrap_or((asm, ""));
+    let mut out = mnemonic.blue().bold().to_string();
+
+    for (idx, operand) in operands.split(", ").filter(|op| !op.is_empty()).enumerate() {
+        out.push_str(if idx == 0 { " " } else { ", " });
+        let colored = if is_register(operand) {
+            operand.yellow()
+        } else if is_number(operand) {
+            operand.green()
+        } else {
+            operand.magenta()
+        };
+        out.push_str(&colored.to_string());
+    }
+
+    out
+}
+
+/// `R0` to `R7`, not a label like `RESULT`
+fn is_register(operand: &str) -> bool {
+    matches!(operand.as_bytes(), [b'R', b'0'..=b'7'])
+}
+
+/// `x` followed by hex digits or `#` followed by a decimal, not a label like `xPOS`
+fn is_number(operand: &str) -> bool {
+    let digits = |s: &str, radix| !s.is_empty() && s.chars().all(|ch| ch.is_digit(radix));
+    if let Some(hex) = operand.strip_prefix('x') {
+        digits(hex, 16)
+    } else if let Some(dec) = operand.strip_prefix('#') {
+        digits(dec.strip_prefix('-').unwrap_or(dec), 10)
+    } else {
+        false
+    }
+}
+
+fn alu_operand(operand: AluOperand) -> String {
+    match operand {
+        AluOperand::Reg(reg) => format!("{reg:?}"),
+        AluOperand::Imm(imm) => format!("#{imm}"),
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(
+            disassemble(0x3000, 0b0001_011_000_0_00_001),
+            "ADD R3, R0, R1"
+        );
+        assert_eq!(
+            disassemble(0x3000, 0b0101_010_100_1_10011),
+            "AND R2, R4, #-13"
+        );
+        assert_eq!(disassemble(0x3000, 0b1001_011_000_111111), "NOT R3, R0");
+        assert_eq!(disassemble(0x3000, 0b0000_0_1_1_111111101), "BRzp x2FFE");
+        assert_eq!(disassemble(0x3000, 0), "NOP");
+        assert_eq!(disassemble(0x3000, 0xE002), "LEA R0, x3003");
+        assert_eq!(
+            disassemble(0x3000, 0b0110_101_000_100101),
+            "LDR R5, R0, #-27"
+        );
+        assert_eq!(disassemble(0x3000, 0b0100_1_00000000100), "JSR x3005");
+        assert_eq!(disassemble(0x3000, 0b0100_0_00_101_000000), "JSRR R5");
+        assert_eq!(disassemble(0x3000, 0xC1C0), "RET");
+        assert_eq!(disassemble(0x3000, 0xC140), "JMP R5");
+        assert_eq!(disassemble(0x3000, 0xF025), "HALT");
+        assert_eq!(disassemble(0x3000, 0xF030), "TRAP x30");
+        assert_eq!(disassemble(0x3000, 0xD123), ".FILL xD123");
+    }
+
+    #[test]
+    fn test_disassemble_with_symbols() {
+        let mut symbols = SymbolTable::default();
+        symbols.insert("LOOP_END", 0x2FFE);
+        symbols.insert("COUNT", 0x3003);
+
+        assert_eq!(
+            disassemble_with(0x3000, 0b0000_0_1_0_111111101, &symbols),
+            "BRz LOOP_END"
+        );
+        assert_eq!(
+            disassemble_with(0x3000, 0b0010_001_000000010, &symbols),
+            "LD R1, COUNT"
+        );
+        assert_eq!(
+            disassemble_with(0x3000, 0b0100_1_00000000100, &symbols),
+            "JSR x3005"
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let strip = |s: &str| {
+            let mut out = String::new();
+            let mut in_escape = false;
+            for ch in s.chars() {
+                match ch {
+                    '\x1b' => in_escape = true,
+                    'm' if in_escape => in_escape = false,
+                    _ if in_escape => (),
+                    ch => out.push(ch),
+                }
+            }
+            out
+        };
+
+        for asm in ["ADD R3, R0, #-1", "LEA R0, x3003", "RET", "BRnzp x3000"] {
+            assert_eq!(strip(&highlight(asm)), asm);
+        }
+
+        assert!(is_register("R7") && !is_register("R8") && !is_register("RESULT"));
+        assert!(is_number("x3000") && is_number("#-13") && is_number("#0"));
+        assert!(!is_number("xPOS") && !is_number("x") && !is_number("#-") && !is_number("LOOP"));
+    }
+}
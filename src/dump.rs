@@ -0,0 +1,101 @@
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{checks::MemRegion, enums::Register, memory::flag_name, vm::Machine};
+
+/// State dumps requested from outside the run loop, e.g. by SIGUSR1, so an
+/// external monitor can inspect a run that seems stuck
+#[derive(Clone, Debug, Default)]
+pub struct StateDump {
+    /// Set to request a dump, checked between instructions
+    pub trigger: Arc<AtomicBool>,
+    /// Memory to include, 16 words from PC when unset
+    pub window: Option<MemRegion>,
+    /// File to write dumps to, stderr when unset
+    pub path: Option<PathBuf>,
+}
+
+impl StateDump {
+    /// Whether a dump was requested since the last check
+    pub fn requested(&self) -> bool {
+        self.trigger.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn window(&self, machine: &Machine) -> MemRegion {
+        self.window.unwrap_or_else(|| {
+            let pc = machine.reg().get(Register::PC);
+            MemRegion {
+                first: pc,
+                last: pc.saturating_add(15),
+            }
+        })
+    }
+}
+
+/// Machine state as a single line of JSON: instruction count, registers,
+/// PSR, condition flag and the words in `window`
+pub fn state_json(machine: &Machine, window: MemRegion) -> String {
+    let reg = machine.reg();
+    let mut out = format!(
+        "{{\"steps\":{},\"halted\":{},\"pc\":{},\"psr\":{},\"cond\":\"{}\",\"registers\":{{",
+        machine.step_count(),
+        machine.halted(),
+        reg.get(Register::PC),
+        machine.psr(),
+        flag_name(reg.get(Register::COND)),
+    );
+    for (i, reg) in Register::GENERAL.into_iter().enumerate() {
+        let sep = if i > 0 { "," } else { "" };
+        write!(out, "{sep}\"{reg:?}\":{}", machine.reg().get(reg)).unwrap();
+    }
+
+    let words: Vec<_> = (window.first..=window.last)
+        .map(|addr| machine.mem().peek(addr).to_string())
+        .collect();
+    write!(
+        out,
+        "}},\"memory\":{{\"start\":{},\"words\":[{}]}}}}",
+        window.first,
+        words.join(",")
+    )
+    .unwrap();
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_json() {
+        let mut test_mach = Machine::default();
+        test_mach.load_words(0x3000, &[0xF025, 0x1234]).unwrap();
+        test_mach.reg_mut().set(Register::R6, 0xFE00);
+        test_mach.reg_mut().set(Register::COND, 0b010);
+
+        let dump = StateDump::default();
+        assert!(!dump.requested());
+        dump.trigger.store(true, Ordering::Relaxed);
+        assert!(dump.requested());
+        assert!(!dump.requested());
+
+        let window = MemRegion {
+            first: 0x3000,
+            last: 0x3002,
+        };
+        assert_eq!(dump.window(&test_mach).last, 0x300F);
+        assert_eq!(
+            state_json(&test_mach, window),
+            "{\"steps\":0,\"halted\":false,\"pc\":12288,\"psr\":32770,\"cond\":\"Z\",\
+             \"registers\":{\"R0\":0,\"R1\":0,\"R2\":0,\"R3\":0,\"R4\":0,\"R5\":0,\"R6\":65024,\"R7\":0},\
+             \"memory\":{\"start\":12288,\"words\":[61477,4660,0]}}"
+        );
+    }
+}
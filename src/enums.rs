@@ -1,7 +1,7 @@
 use num_derive::{FromPrimitive, ToPrimitive};
 
 #[repr(usize)]
-#[derive(FromPrimitive, ToPrimitive, Clone, Copy)]
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Register {
     R0 = 0,
     R1,
@@ -16,8 +16,26 @@ pub enum Register {
     COUNT,
 }
 
+impl Register {
+    pub const GENERAL: [Register; 8] = [
+        Self::R0,
+        Self::R1,
+        Self::R2,
+        Self::R3,
+        Self::R4,
+        Self::R5,
+        Self::R6,
+        Self::R7,
+    ];
+
+    /// General purpose register named by the low 3 bits of `bits`
+    pub fn from_field(bits: u16) -> Self {
+        Self::GENERAL[(bits & 0x7) as usize]
+    }
+}
+
 #[repr(u8)]
-#[derive(FromPrimitive, ToPrimitive)]
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
 // Raw opcode values
 pub enum RawOpCode {
     Br = 0,
@@ -48,7 +66,7 @@ pub enum CondFlag {
 }
 
 #[repr(u8)]
-#[derive(ToPrimitive, FromPrimitive)]
+#[derive(ToPrimitive, FromPrimitive, Clone, Copy)]
 pub enum TrapCode {
     GetC = 0x20,
     Out,
@@ -58,6 +76,100 @@ pub enum TrapCode {
     Halt,
 }
 
+impl TrapCode {
+    pub const ALL: [TrapCode; 6] = [
+        Self::GetC,
+        Self::Out,
+        Self::Puts,
+        Self::In,
+        Self::PutsP,
+        Self::Halt,
+    ];
+
+    /// Assembler alias of the trap, e.g. `HALT` for x25
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GetC => "GETC",
+            Self::Out => "OUT",
+            Self::Puts => "PUTS",
+            Self::In => "IN",
+            Self::PutsP => "PUTSP",
+            Self::Halt => "HALT",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|trap| trap.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Optional console traps for decimal numbers and lines of text, see
+/// [`Machine::enable_io_traps`](crate::vm::Machine::enable_io_traps)
+#[repr(u8)]
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoTrap {
+    /// Prints R0 as a signed decimal number
+    PutN = 0x26,
+    /// Reads a line holding a signed decimal number into R0
+    GetN,
+    /// Reads a line of at most R1 characters into memory at R0, NUL
+    /// terminated, leaving its length in R1
+    GetS,
+}
+
+impl IoTrap {
+    pub const ALL: [IoTrap; 3] = [Self::PutN, Self::GetN, Self::GetS];
+
+    /// Assembler alias of the trap, e.g. `PUTN` for x26
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PutN => "PUTN",
+            Self::GetN => "GETN",
+            Self::GetS => "GETS",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|trap| trap.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Optional arithmetic traps on signed 16-bit values in R0 and R1, see
+/// [`Machine::enable_arith_traps`](crate::vm::Machine::enable_arith_traps)
+#[repr(u8)]
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithTrap {
+    /// R0 * R1, low word in R0 and high word in R1
+    Mul = 0x40,
+    /// R0 / R1 rounded towards zero in R0, remainder in R1
+    Div,
+    /// R0 modulo R1 in R0, never negative
+    Mod,
+}
+
+impl ArithTrap {
+    pub const ALL: [ArithTrap; 3] = [Self::Mul, Self::Div, Self::Mod];
+
+    /// Assembler alias of the trap, e.g. `MUL` for x40
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mul => "MUL",
+            Self::Div => "DIV",
+            Self::Mod => "MOD",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|trap| trap.name().eq_ignore_ascii_case(name))
+    }
+}
+
 impl CondFlag {
     pub fn from_reg_value(val: u16) -> Self {
         if val == 0 {
@@ -75,4 +187,22 @@ impl CondFlag {
 pub enum MemMappedReg {
     Kbsr = 0xFE00,
     Kbdr = 0xFE02,
+    /// Watchdog, see [`crate::watchdog::Watchdog`]
+    Wdt = 0xFE10,
+    /// Vsync, see [`crate::vsync::Vsync`]
+    Vsr = 0xFE12,
+    /// Gamepad buttons, with the `gamepad` feature
+    Gpr = 0xFE14,
+    /// Host hypercalls, see [`crate::hypercall::Hypercall`]
+    Hcr = 0xFE16,
+    /// Bank select, see [`crate::banking::Banks`]
+    Bsr = 0xFE18,
+    /// Instruction counter, see [`crate::perfcount::PerfCounters`]
+    Icl = 0xFE1A,
+    Ich = 0xFE1C,
+    /// Cycle counter, see [`crate::perfcount::PerfCounters`]
+    Ccl = 0xFE1E,
+    Cch = 0xFE20,
+    /// Machine control, clearing bit 15 stops the machine
+    Mcr = 0xFFFE,
 }
@@ -1,8 +1,70 @@
-use std::{fmt, io, result::Result as StdResult};
+use num_traits::FromPrimitive;
+use std::{error, fmt, io, result::Result as StdResult};
 
-#[derive(Clone, Copy, Debug)]
+use crate::enums::TrapCode;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     IOError,
+    ParseError,
+    CheckpointError,
+    /// Object file whose words would run past xFFFF
+    ImageTooLarge {
+        origin: u16,
+        words: usize,
+    },
+    /// Object file loading somewhere it isn't allowed to
+    BadOrigin {
+        origin: u16,
+    },
+    /// Object file with an origin but no words
+    EmptyImage,
+    /// Object file loading over the inclusive range `first..=last` of one loaded earlier
+    ImageOverlap {
+        first: u16,
+        last: u16,
+    },
+    /// An address past the end of the 16-bit address space
+    AddressOutOfRange {
+        addr: u32,
+    },
+    /// Reserved opcode executed
+    IllegalOpcode {
+        pc: u16,
+        word: u16,
+    },
+    /// TRAP to a vector with no service routine
+    UnknownTrap {
+        vector: u8,
+        pc: u16,
+    },
+    /// RTI executed in user mode
+    PrivilegeViolation {
+        pc: u16,
+    },
+    /// PUTS or PUTSP string with no NUL terminator anywhere in memory
+    UnterminatedString {
+        start: u16,
+        pc: u16,
+    },
+    /// A device (the console) failed while the instruction at `pc` used it
+    DeviceError {
+        pc: u16,
+    },
+    /// DIV or MOD trap with R1 = 0
+    DivideByZero {
+        pc: u16,
+    },
+    /// TRAP to a vector the run forbids, see `--forbid-traps`
+    ForbiddenTrap {
+        vector: u8,
+        pc: u16,
+    },
+    /// Instruction the run forbids, see `--forbid-opcodes`
+    ForbiddenOpcode {
+        pc: u16,
+        word: u16,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -13,27 +75,103 @@ pub struct Error {
 
 impl Error {
     pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            message: kind.to_string(),
+            kind,
+        }
+    }
+
+    pub fn with_message(kind: ErrorKind, message: impl Into<String>) -> Self {
         Self {
             kind,
-            message: kind.as_str().to_owned(),
+            message: message.into(),
         }
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl ErrorKind {
     pub fn as_str(&self) -> &'static str {
         match self {
             ErrorKind::IOError => "io error",
+            ErrorKind::ParseError => "parse error",
+            ErrorKind::CheckpointError => "checkpoint error",
+            ErrorKind::ImageTooLarge { .. } => "image too large",
+            ErrorKind::BadOrigin { .. } => "bad origin",
+            ErrorKind::EmptyImage => "empty image",
+            ErrorKind::ImageOverlap { .. } => "image overlap",
+            ErrorKind::AddressOutOfRange { .. } => "address out of range",
+            ErrorKind::IllegalOpcode { .. } => "illegal opcode",
+            ErrorKind::UnknownTrap { .. } => "unknown trap",
+            ErrorKind::PrivilegeViolation { .. } => "privilege violation",
+            ErrorKind::UnterminatedString { .. } => "unterminated string",
+            ErrorKind::DeviceError { .. } => "device error",
+            ErrorKind::DivideByZero { .. } => "divide by zero",
+            ErrorKind::ForbiddenTrap { .. } => "forbidden trap",
+            ErrorKind::ForbiddenOpcode { .. } => "forbidden opcode",
+        }
+    }
+}
+
+/// The kind along with its context, e.g. `unknown trap xFF at x3001`
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::ImageTooLarge { origin, words } => write!(
+                f,
+                "image of {words} words at x{origin:04X} runs past xFFFF (file offset {})",
+                2 + 2 * (0x10000 - *origin as usize)
+            ),
+            ErrorKind::BadOrigin { origin } => write!(f, "bad origin x{origin:04X}"),
+            ErrorKind::ImageOverlap { first, last } => {
+                write!(f, "image overlaps x{first:04X}-x{last:04X}")
+            }
+            ErrorKind::AddressOutOfRange { addr } => {
+                write!(f, "address x{addr:X} is out of range")
+            }
+            ErrorKind::IllegalOpcode { pc, word } => {
+                write!(f, "illegal opcode x{word:04X} at x{pc:04X}")
+            }
+            ErrorKind::UnknownTrap { vector, pc } => {
+                write!(f, "unknown trap vector x{vector:02X} at x{pc:04X}")
+            }
+            ErrorKind::PrivilegeViolation { pc } => {
+                write!(f, "privilege mode violation at x{pc:04X}")
+            }
+            ErrorKind::UnterminatedString { start, pc } => {
+                write!(
+                    f,
+                    "string at x{start:04X} printed by x{pc:04X} has no terminator"
+                )
+            }
+            ErrorKind::DeviceError { pc } => write!(f, "device error at x{pc:04X}"),
+            ErrorKind::DivideByZero { pc } => write!(f, "divide by zero at x{pc:04X}"),
+            ErrorKind::ForbiddenTrap { vector, pc } => {
+                write!(f, "forbidden trap {} at x{pc:04X}", trap_name(*vector))
+            }
+            ErrorKind::ForbiddenOpcode { pc, word } => {
+                write!(f, "forbidden instruction x{word:04X} at x{pc:04X}")
+            }
+            kind => f.write_str(kind.as_str()),
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{:?}] {}", self.kind, self.message)
+        write!(f, "[{}] {}", self.kind.as_str(), self.message)
     }
 }
 
+impl error::Error for Error {}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Self {
         Self {
@@ -43,4 +181,141 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<VmError> for Error {
+    fn from(error: VmError) -> Self {
+        let (pc, word) = (error.pc, error.word);
+        let kind = match &error.kind {
+            VmErrorKind::IllegalOpcode => ErrorKind::IllegalOpcode { pc, word },
+            VmErrorKind::UnknownTrap => ErrorKind::UnknownTrap {
+                vector: word as u8,
+                pc,
+            },
+            VmErrorKind::PrivilegeViolation => ErrorKind::PrivilegeViolation { pc },
+            VmErrorKind::UnterminatedString { start } => {
+                ErrorKind::UnterminatedString { start: *start, pc }
+            }
+            VmErrorKind::IoFailure(_) => ErrorKind::DeviceError { pc },
+            VmErrorKind::DivideByZero => ErrorKind::DivideByZero { pc },
+            VmErrorKind::ForbiddenTrap => ErrorKind::ForbiddenTrap {
+                vector: word as u8,
+                pc,
+            },
+            VmErrorKind::ForbiddenOpcode(_) => ErrorKind::ForbiddenOpcode { pc, word },
+        };
+        Self::with_message(kind, error.to_string())
+    }
+}
+
+/// What went wrong executing an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmErrorKind {
+    /// Reserved opcode 1101
+    IllegalOpcode,
+    /// TRAP to a vector with no service routine
+    UnknownTrap,
+    /// RTI in user mode
+    PrivilegeViolation,
+    /// PUTS/PUTSP found no NUL after the string at `start`, even wrapping around memory
+    UnterminatedString { start: u16 },
+    /// The console failed to read or write
+    IoFailure(String),
+    /// DIV or MOD trap dividing by zero
+    DivideByZero,
+    /// TRAP to a vector in [`Machine::forbid_traps`](crate::vm::Machine::forbid_traps)
+    ForbiddenTrap,
+    /// Instruction in [`Machine::forbid_opcodes`](crate::vm::Machine::forbid_opcodes),
+    /// with its disassembly
+    ForbiddenOpcode(String),
+}
+
+/// Error raised by the machine while executing the instruction `word` at `pc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmError {
+    pub kind: VmErrorKind,
+    pub pc: u16,
+    pub word: u16,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (pc, word) = (self.pc, self.word);
+        match &self.kind {
+            VmErrorKind::IllegalOpcode => write!(f, "illegal opcode x{word:04X} at x{pc:04X}"),
+            VmErrorKind::UnknownTrap => {
+                write!(f, "unknown trap vector x{:02X} at x{pc:04X}", word & 0xFF)
+            }
+            VmErrorKind::PrivilegeViolation => {
+                write!(f, "RTI in user mode at x{pc:04X}")
+            }
+            VmErrorKind::UnterminatedString { start } => write!(
+                f,
+                "no NUL terminator after the string at x{start:04X} printed by x{pc:04X}"
+            ),
+            VmErrorKind::IoFailure(msg) => write!(f, "console I/O failed at x{pc:04X}: {msg}"),
+            VmErrorKind::DivideByZero => {
+                write!(f, "TRAP x{:02X} divided by zero at x{pc:04X}", word & 0xFF)
+            }
+            VmErrorKind::ForbiddenTrap => write!(
+                f,
+                "{} is forbidden in this run, used at x{pc:04X}",
+                trap_name(word as u8)
+            ),
+            VmErrorKind::ForbiddenOpcode(asm) => {
+                write!(f, "{asm} is forbidden in this run, at x{pc:04X}")
+            }
+        }
+    }
+}
+
+impl error::Error for VmError {}
+
+/// The assembler alias of a trap vector, e.g. `PUTS`, or `xNN` for vectors
+/// without one
+pub fn trap_name(vector: u8) -> String {
+    match TrapCode::from_u8(vector) {
+        Some(trap) => trap.name().to_owned(),
+        None => format!("x{vector:02X}"),
+    }
+}
+
 pub type Result<T, E = Error> = StdResult<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vm_error() {
+        let error = Error::from(VmError {
+            kind: VmErrorKind::UnknownTrap,
+            pc: 0x3001,
+            word: 0xF0FF,
+        });
+        assert_eq!(
+            *error.kind(),
+            ErrorKind::UnknownTrap {
+                vector: 0xFF,
+                pc: 0x3001
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "[unknown trap] unknown trap vector xFF at x3001"
+        );
+
+        let boxed: Box<dyn error::Error> = Box::new(error);
+        assert!(boxed.to_string().contains("x3001"));
+    }
+
+    #[test]
+    fn test_kind_context() {
+        let error = Error::new(ErrorKind::ImageTooLarge {
+            origin: 0xFFFE,
+            words: 4,
+        });
+        assert_eq!(
+            error.message(),
+            "image of 4 words at xFFFE runs past xFFFF (file offset 6)"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use crate::{console::BufferConsole, enums::Register, vm::Machine};
+
+/// Bounds for [`explore`]
+#[derive(Clone, Debug)]
+pub struct ExploreLimits {
+    /// Bytes tried at every input read
+    pub alphabet: Vec<u8>,
+    /// Input reads to fork at along a single path
+    pub max_inputs: usize,
+    /// Instructions executed along a single path
+    pub max_steps: u64,
+    /// Paths explored in total
+    pub max_paths: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Exploration {
+    /// Inputs that reached the target, shortest first
+    pub reached: Vec<Vec<u8>>,
+    pub paths: usize,
+    /// Whether some path was cut short by the limits
+    pub truncated: bool,
+}
+
+/// Runs `machine` breadth-first over every input sequence drawn from the
+/// alphabet, forking a copy of the machine whenever the program reads a key.
+///
+/// A path ends when it reaches `target`, halts, fails, or runs into a limit.
+pub fn explore(machine: &Machine, target: u16, limits: &ExploreLimits) -> Exploration {
+    let mut root = machine.clone();
+    root.set_console(Box::new(BufferConsole::default()));
+
+    let mut result = Exploration::default();
+    let mut queue = VecDeque::from([(root, Vec::new())]);
+    while let Some((mut fork, input)) = queue.pop_front() {
+        result.paths += 1;
+        let start = fork.step_count();
+
+        loop {
+            if fork.reg().get(Register::PC) == target {
+                result.reached.push(input);
+                break;
+            }
+
+            if fork.needs_input() {
+                if input.len() == limits.max_inputs
+                    || result.paths + queue.len() + limits.alphabet.len() > limits.max_paths
+                {
+                    result.truncated = true;
+                    break;
+                }
+                for &byte in &limits.alphabet {
+                    let mut child = fork.clone();
+                    child.feed_input(&[byte]);
+                    let mut child_input = input.clone();
+                    child_input.push(byte);
+                    queue.push_back((child, child_input));
+                }
+                break;
+            }
+
+            if fork.step_count() - start >= limits.max_steps {
+                result.truncated = true;
+                break;
+            }
+            match fork.step() {
+                Ok(info) if !info.halted() => (),
+                _ => break,
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn test_explore() {
+        let assembly = asm::assemble(
+            "
+            .ORIG x3000
+            GETC
+            LD R1, NEG_Q
+            ADD R0, R0, R1
+            BRz QUIT
+            HALT
+            QUIT HALT
+            NEG_Q .FILL #-113
+            .END
+            ",
+        )
+        .unwrap();
+        let mut test_mach = Machine::default();
+        test_mach.load_bytes(&assembly.to_bytes()).unwrap();
+
+        let limits = ExploreLimits {
+            alphabet: b"aq1".to_vec(),
+            max_inputs: 2,
+            max_steps: 100,
+            max_paths: 100,
+        };
+        let quit = assembly.symbols.lookup("QUIT").unwrap();
+        let result = explore(&test_mach, quit, &limits);
+
+        assert_eq!(result.reached, vec![b"q".to_vec()]);
+        assert_eq!(result.paths, 4);
+        assert!(!result.truncated);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{fmt, str::FromStr};
+
+use crate::enums::{RawOpCode, Register};
+
+/// Extended instructions enabled in the reserved opcode (1101), for courses
+/// teaching an extended LC-3. Without them the opcode stays illegal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Extensions {
+    pub shf: bool,
+    pub xor: bool,
+}
+
+impl FromStr for Extensions {
+    type Err = String;
+
+    /// Parses a comma separated list, e.g. `shf,xor`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ext = Self::default();
+        for name in s.split(',').map(str::trim) {
+            match name.to_ascii_lowercase().as_str() {
+                "shf" => ext.shf = true,
+                "xor" => ext.xor = true,
+                _ => return Err(format!("unknown extension `{name}`, expected shf or xor")),
+            }
+        }
+        Ok(ext)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shift {
+    Left,
+    /// Fills with zeros
+    RightLogical,
+    /// Fills with the sign bit
+    RightArithmetic,
+}
+
+/// An instruction in the reserved opcode. Bits 5-4 pick the operation, with
+/// the shifts encoded like the LC-3b's SHF:
+///
+/// ```text
+/// 1101 DR SR1 00 amount4    LSHF  DR, SR1, #amount
+/// 1101 DR SR1 01 amount4    RSHFL DR, SR1, #amount
+/// 1101 DR SR1 11 amount4    RSHFA DR, SR1, #amount
+/// 1101 DR SR1 10 0 SR2      XOR   DR, SR1, SR2
+/// ```
+///
+/// Both set the condition codes from DR.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtInstruction {
+    Shf {
+        dst: Register,
+        src: Register,
+        shift: Shift,
+        amount: u16,
+    },
+    Xor {
+        dst: Register,
+        src1: Register,
+        src2: Register,
+    },
+}
+
+impl ExtInstruction {
+    /// Decodes `word` if it is an instruction of an enabled extension
+    pub fn decode(word: u16, ext: Extensions) -> Option<Self> {
+        if word >> 12 != RawOpCode::Noop as u16 {
+            return None;
+        }
+        let dst = Register::from_field(word >> 9);
+        let src = Register::from_field(word >> 6);
+        let shift = match (word >> 4) & 0x3 {
+            0b00 => Shift::Left,
+            0b01 => Shift::RightLogical,
+            0b11 => Shift::RightArithmetic,
+            _ => {
+                return ext.xor.then_some(Self::Xor {
+                    dst,
+                    src1: src,
+                    src2: Register::from_field(word),
+                })
+            }
+        };
+        ext.shf.then_some(Self::Shf {
+            dst,
+            src,
+            shift,
+            amount: word & 0xF,
+        })
+    }
+
+    /// Result written to DR, given the values of the source registers
+    pub fn apply(&self, src1: u16, src2: u16) -> u16 {
+        match *self {
+            Self::Shf { shift, amount, .. } => match shift {
+                Shift::Left => src1 << amount,
+                Shift::RightLogical => src1 >> amount,
+                Shift::RightArithmetic => ((src1 as i16) >> amount) as u16,
+            },
+            Self::Xor { .. } => src1 ^ src2,
+        }
+    }
+}
+
+impl fmt::Display for ExtInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shf {
+                dst,
+                src,
+                shift,
+                amount,
+            } => {
+                let mnemonic = match shift {
+                    Shift::Left => "LSHF",
+                    Shift::RightLogical => "RSHFL",
+                    Shift::RightArithmetic => "RSHFA",
+                };
+                write!(f, "{mnemonic} {dst:?}, {src:?}, #{amount}")
+            }
+            Self::Xor { dst, src1, src2 } => write!(f, "XOR {dst:?}, {src1:?}, {src2:?}"),
+        }
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let all = Extensions::from_str("shf,xor").unwrap();
+        let shf = ExtInstruction::decode(0b1101_001_010_11_0011, all).unwrap();
+        assert_eq!(shf.to_string(), "RSHFA R1, R2, #3");
+        assert_eq!(shf.apply(0x8010, 0), 0xF002);
+
+        let xor = ExtInstruction::decode(0b1101_001_010_10_0_011, all).unwrap();
+        assert_eq!(xor.to_string(), "XOR R1, R2, R3");
+        assert_eq!(xor.apply(0x0FF0, 0x00FF), 0x0F0F);
+
+        let only_shf = Extensions::from_str("shf").unwrap();
+        assert_eq!(
+            ExtInstruction::decode(0b1101_001_010_10_0_011, only_shf),
+            None
+        );
+        assert_eq!(ExtInstruction::decode(0x1262, all), None);
+        assert!(Extensions::from_str("shf,mul").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+use gilrs::{EventType, Gilrs};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// Bits of the gamepad register (xFE14), set while the button is held
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Up = 1 << 0,
+    Down = 1 << 1,
+    Left = 1 << 2,
+    Right = 1 << 3,
+    A = 1 << 4,
+    B = 1 << 5,
+    X = 1 << 6,
+    Y = 1 << 7,
+    L = 1 << 8,
+    R = 1 << 9,
+    Select = 1 << 10,
+    Start = 1 << 11,
+}
+
+/// Bit set in the gamepad register while a controller is connected, so a
+/// program can tell "nothing pressed" from "nothing there"
+pub const GPR_CONNECTED: u16 = 1 << 15;
+
+/// Button state behind the gamepad register. The machine reads it, a
+/// frontend polling a real controller (or a test) updates it from any
+/// thread through a clone got with
+/// [`Machine::gamepad`](crate::vm::Machine::gamepad).
+#[derive(Clone, Debug, Default)]
+pub struct Gamepad {
+    state: Arc<AtomicU16>,
+}
+
+impl Gamepad {
+    /// Value of the register as seen by a read
+    pub fn state(&self) -> u16 {
+        self.state.load(Ordering::SeqCst)
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        if connected {
+            self.state.fetch_or(GPR_CONNECTED, Ordering::SeqCst);
+        } else {
+            // Buttons of a removed controller aren't held anymore
+            self.state.store(0, Ordering::SeqCst);
+        }
+    }
+
+    pub fn press(&self, button: Button) {
+        self.state.fetch_or(button as u16, Ordering::SeqCst);
+    }
+
+    pub fn release(&self, button: Button) {
+        self.state.fetch_and(!(button as u16), Ordering::SeqCst);
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.state() & button as u16 != 0
+    }
+
+    /// Drive this gamepad from the controllers plugged into the host, on a
+    /// thread that lives as long as the process. All controllers share the
+    /// one register
+    pub fn poll_controllers(&self) -> Result<()> {
+        let gamepad = self.clone();
+        let (ready, started) = mpsc::channel();
+        thread::spawn(move || {
+            let mut gilrs = match Gilrs::new() {
+                Ok(gilrs) => gilrs,
+                Err(e) => {
+                    let _ = ready.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            gamepad.set_connected(gilrs.gamepads().next().is_some());
+            while let Some(event) = gilrs.next_event_blocking(None) {
+                match event.event {
+                    EventType::ButtonPressed(button, _) => {
+                        if let Some(button) = Button::from_gilrs(button) {
+                            gamepad.press(button);
+                        }
+                    }
+                    EventType::ButtonReleased(button, _) => {
+                        if let Some(button) = Button::from_gilrs(button) {
+                            gamepad.release(button);
+                        }
+                    }
+                    EventType::Connected => gamepad.set_connected(true),
+                    EventType::Disconnected => {
+                        gamepad.set_connected(gilrs.gamepads().next().is_some())
+                    }
+                    _ => {}
+                }
+            }
+        });
+        match started.recv() {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(msg)) => Err(Error::with_message(
+                ErrorKind::IOError,
+                format!("can't read gamepads: {msg}"),
+            )),
+            Err(_) => Err(Error::with_message(
+                ErrorKind::IOError,
+                "gamepad thread exited",
+            )),
+        }
+    }
+}
+
+impl Button {
+    /// The register bit for a controller button, laid out like an SNES pad
+    /// with A on the right of the face buttons
+    fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        match button {
+            gilrs::Button::DPadUp => Some(Self::Up),
+            gilrs::Button::DPadDown => Some(Self::Down),
+            gilrs::Button::DPadLeft => Some(Self::Left),
+            gilrs::Button::DPadRight => Some(Self::Right),
+            gilrs::Button::East => Some(Self::A),
+            gilrs::Button::South => Some(Self::B),
+            gilrs::Button::North => Some(Self::X),
+            gilrs::Button::West => Some(Self::Y),
+            gilrs::Button::LeftTrigger => Some(Self::L),
+            gilrs::Button::RightTrigger => Some(Self::R),
+            gilrs::Button::Select => Some(Self::Select),
+            gilrs::Button::Start => Some(Self::Start),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gamepad() {
+        let gamepad = Gamepad::default();
+        assert_eq!(gamepad.state(), 0);
+
+        let frontend = gamepad.clone();
+        frontend.set_connected(true);
+        frontend.press(Button::Left);
+        frontend.press(Button::A);
+        assert_eq!(gamepad.state(), GPR_CONNECTED | 0b10100);
+        assert!(gamepad.is_pressed(Button::A));
+
+        frontend.release(Button::A);
+        assert!(!gamepad.is_pressed(Button::A));
+        frontend.set_connected(false);
+        assert_eq!(gamepad.state(), 0);
+    }
+
+    #[test]
+    fn test_from_gilrs() {
+        assert_eq!(
+            Button::from_gilrs(gilrs::Button::DPadLeft),
+            Some(Button::Left)
+        );
+        assert_eq!(Button::from_gilrs(gilrs::Button::East), Some(Button::A));
+        assert_eq!(
+            Button::from_gilrs(gilrs::Button::Start),
+            Some(Button::Start)
+        );
+        assert_eq!(Button::from_gilrs(gilrs::Button::Mode), None);
+    }
+}
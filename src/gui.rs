@@ -0,0 +1,397 @@
+//! `simulator gui`: the machine in a window, with its console, registers and
+//! memory side by side and buttons to run, pause and step. Meant for students
+//! who'd rather click Step than learn the debugger's commands.
+//!
+//! Everything happens on the UI thread through [`Machine::step`], a batch of
+//! instructions per frame. A program reading the keyboard with GETC or IN is
+//! held before the TRAP until a key has been typed, so the window never
+//! blocks on input.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{self, Color32, RichText};
+
+use crate::{
+    console::Console,
+    disasm,
+    enums::{IoTrap, Register, TrapCode},
+    error::{Error, ErrorKind, Result},
+    memory::RegisterManager,
+    utils::parse_number,
+    vm::{Machine, StepOutcome},
+};
+
+/// Time a frame may spend executing while running, so the window stays responsive
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+/// Instructions executed between looks at the clock
+const BATCH: usize = 1024;
+
+/// Words shown in the memory view
+const MEMORY_ROWS: u16 = 24;
+
+/// Console for the window: input is the keys typed into it, output is shown
+/// in its console pane. Clones share both, there's one window.
+#[derive(Clone, Default)]
+pub struct GuiConsole {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Arc<Mutex<String>>,
+}
+
+impl GuiConsole {
+    pub fn push_input(&self, bytes: &[u8]) {
+        self.input.lock().unwrap().extend(bytes);
+    }
+
+    /// Everything the program printed so far
+    pub fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+
+    fn clear(&self) {
+        self.input.lock().unwrap().clear();
+        self.output.lock().unwrap().clear();
+    }
+
+    /// Whether the instruction `word` would wait for input that hasn't been
+    /// typed yet: a key for GETC and IN, a whole line for GETN and GETS
+    fn would_block(&self, word: u16) -> bool {
+        if word >> 12 != 0xF {
+            return false;
+        }
+        let vector = word as u8;
+        let input = self.input.lock().unwrap();
+        if vector == TrapCode::GetC as u8 || vector == TrapCode::In as u8 {
+            input.is_empty()
+        } else if vector == IoTrap::GetN as u8 || vector == IoTrap::GetS as u8 {
+            !input.contains(&b'\n')
+        } else {
+            false
+        }
+    }
+}
+
+impl Console for GuiConsole {
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.lock().unwrap().pop_front()
+    }
+
+    fn write_str(&mut self, s: &str) -> io::Result<()> {
+        self.output.lock().unwrap().push_str(s);
+        Ok(())
+    }
+
+    fn poll(&mut self, _timeout: Duration) -> bool {
+        !self.input.lock().unwrap().is_empty()
+    }
+
+    fn boxed_clone(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+/// Opens the window on `machine`, whose console must be `console`, and
+/// returns once it is closed
+pub fn run(machine: Machine, console: GuiConsole, title: &str) -> Result<()> {
+    let app = App::new(machine, console);
+    eframe::run_native(
+        &format!("LC-3 simulator - {title}"),
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(app))),
+    )
+    .map_err(|e| Error::with_message(ErrorKind::IOError, format!("can't open the window: {e}")))
+}
+
+struct App {
+    machine: Machine,
+    /// The machine as loaded, for Reset
+    initial: Machine,
+    console: GuiConsole,
+    running: bool,
+    /// Why the program can't go on, e.g. it halted or hit an error
+    stopped: Option<String>,
+    /// Registers when the program last paused, to highlight what changed
+    prev: RegisterManager,
+    /// Start of the memory view, when it doesn't follow PC
+    mem_start: u16,
+    follow_pc: bool,
+    mem_addr: String,
+}
+
+impl App {
+    fn new(machine: Machine, console: GuiConsole) -> Self {
+        Self {
+            initial: machine.clone(),
+            prev: machine.reg().clone(),
+            mem_start: machine.reg().get(Register::PC),
+            machine,
+            console,
+            running: false,
+            stopped: None,
+            follow_pc: true,
+            mem_addr: String::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.console.clear();
+        self.machine = self.initial.clone();
+        self.prev = self.machine.reg().clone();
+        self.running = false;
+        self.stopped = None;
+    }
+
+    /// Whether the next instruction waits for a key that hasn't been typed
+    fn waiting(&self) -> bool {
+        let pc = self.machine.reg().get(Register::PC);
+        self.console.would_block(self.machine.mem().peek(pc))
+    }
+
+    /// Executes one instruction, returning whether the program can go on
+    /// right away
+    fn step(&mut self) -> bool {
+        if self.stopped.is_some() || self.waiting() {
+            return false;
+        }
+        let reason = match self.machine.step() {
+            Ok(info) if info.halted() => "Halted".to_owned(),
+            Ok(info) if info.outcome == StepOutcome::Stopped => match self.machine.fault() {
+                Some(fault) => fault.reason.clone(),
+                None => match self.machine.exit_reason() {
+                    Some(reason) => format!("Stopped: {}", reason.as_str()),
+                    None => "Stopped".to_owned(),
+                },
+            },
+            Ok(_) => return true,
+            Err(e) => e.to_string(),
+        };
+        self.stopped = Some(reason);
+        self.running = false;
+        false
+    }
+
+    /// Runs for up to `budget`, or until the program stops or waits for input
+    fn run_for(&mut self, budget: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < budget {
+            for _ in 0..BATCH {
+                if !self.step() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Keys typed while no text field has focus go to the program
+    fn forward_keys(&mut self, ui: &egui::Ui) {
+        if ui.ctx().memory(|memory| memory.focused().is_some()) {
+            return;
+        }
+        ui.input(|input| {
+            for event in &input.events {
+                match event {
+                    egui::Event::Text(text) | egui::Event::Paste(text) => {
+                        self.console.push_input(text.as_bytes())
+                    }
+                    egui::Event::Key {
+                        key: egui::Key::Enter,
+                        pressed: true,
+                        ..
+                    } => self.console.push_input(b"\n"),
+                    egui::Event::Key {
+                        key: egui::Key::Backspace,
+                        pressed: true,
+                        ..
+                    } => self.console.push_input(b"\x08"),
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let can_run = self.stopped.is_none();
+            let label = if self.running { "Pause" } else { "Run" };
+            if ui.add_enabled(can_run, egui::Button::new(label)).clicked() {
+                self.running = !self.running;
+                if self.running {
+                    self.prev = self.machine.reg().clone();
+                }
+            }
+            if ui
+                .add_enabled(can_run && !self.running, egui::Button::new("Step"))
+                .clicked()
+            {
+                self.prev = self.machine.reg().clone();
+                self.step();
+            }
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+            ui.separator();
+
+            let status = match &self.stopped {
+                Some(reason) => RichText::new(reason).color(Color32::RED),
+                None if self.waiting() => RichText::new("Waiting for input"),
+                None if self.running => RichText::new("Running"),
+                None => RichText::new("Paused"),
+            };
+            ui.label(status);
+            ui.label(format!("{} instructions", self.machine.step_count()));
+        });
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let changed: Vec<_> = if self.running {
+            Vec::new()
+        } else {
+            self.machine
+                .reg()
+                .changes(&self.prev)
+                .into_iter()
+                .map(|(reg, _, _)| reg)
+                .collect()
+        };
+        let cell = |reg: Register, text: String| {
+            let text = RichText::new(text).monospace();
+            if changed.contains(&reg) {
+                text.color(Color32::RED).strong()
+            } else {
+                text
+            }
+        };
+
+        let reg = self.machine.reg();
+        egui::Grid::new("registers").striped(true).show(ui, |ui| {
+            for r in Register::GENERAL {
+                let value = reg.get(r);
+                ui.label(cell(r, format!("{r:?}")));
+                ui.label(cell(r, format!("x{value:04X}")));
+                ui.label(cell(r, format!("{}", value as i16)));
+                ui.end_row();
+            }
+            let pc = reg.get(Register::PC);
+            ui.label(cell(Register::PC, "PC".to_owned()));
+            ui.label(cell(Register::PC, format!("x{pc:04X}")));
+            ui.end_row();
+            ui.label(cell(Register::COND, "COND".to_owned()));
+            ui.label(cell(Register::COND, reg.flag_name().to_owned()));
+            ui.end_row();
+        });
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let field = ui.add(egui::TextEdit::singleline(&mut self.mem_addr).desired_width(60.0));
+            let go = ui.button("Go").clicked()
+                || (field.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter)));
+            if go {
+                if let Some(addr) = parse_number(self.mem_addr.trim()) {
+                    self.mem_start = addr;
+                    self.follow_pc = false;
+                }
+            }
+            ui.checkbox(&mut self.follow_pc, "Follow PC");
+        });
+
+        let pc = self.machine.reg().get(Register::PC);
+        if self.follow_pc {
+            self.mem_start = pc.saturating_sub(4);
+        }
+        egui::Grid::new("memory").striped(true).show(ui, |ui| {
+            for addr in (0..MEMORY_ROWS).map(|i| self.mem_start.wrapping_add(i)) {
+                let word = self.machine.mem().peek(addr);
+                let marker = if addr == pc { "▶" } else { "" };
+                ui.label(marker);
+                ui.label(RichText::new(format!("x{addr:04X}")).monospace());
+                ui.label(RichText::new(format!("x{word:04X}")).monospace());
+                ui.label(RichText::new(disasm::disassemble(addr, word)).monospace());
+                ui.end_row();
+            }
+        });
+    }
+}
+
+impl eframe::App for App {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.forward_keys(ui);
+        if self.running {
+            self.run_for(FRAME_BUDGET);
+            // Typing a key repaints on its own while the program waits for one
+            if self.running && !self.waiting() {
+                ui.ctx().request_repaint();
+            }
+        }
+
+        egui::Panel::top("controls").show(ui, |ui| self.controls(ui));
+        egui::Panel::right("state").show(ui, |ui| {
+            ui.heading("Registers");
+            self.registers(ui);
+            ui.separator();
+            ui.heading("Memory");
+            self.memory(ui);
+        });
+        egui::CentralPanel::default().show(ui, |ui| {
+            ui.heading("Console");
+            ui.label("Keys typed in the window go to the program");
+            ui.separator();
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    ui.label(RichText::new(self.console.output()).monospace());
+                });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_would_block() {
+        let console = GuiConsole::default();
+        // GETC, GETN, OUT
+        assert!(console.would_block(0xF020));
+        assert!(console.would_block(0xF027));
+        assert!(!console.would_block(0xF021));
+
+        console.push_input(b"12");
+        assert!(!console.would_block(0xF020));
+        assert!(console.would_block(0xF027));
+        console.push_input(b"\n");
+        assert!(!console.would_block(0xF027));
+    }
+
+    #[test]
+    fn test_run_for() {
+        let console = GuiConsole::default();
+        let mut machine = Machine::with_console(Box::new(console.clone()));
+        // GETC, OUT, HALT
+        machine
+            .load_words(0x3000, &[0xF020, 0xF021, 0xF025])
+            .unwrap();
+        let mut app = App::new(machine, console.clone());
+
+        app.running = true;
+        app.run_for(FRAME_BUDGET);
+        assert!(app.waiting());
+        assert_eq!(app.machine.step_count(), 0);
+
+        console.push_input(b"a");
+        app.run_for(FRAME_BUDGET);
+        assert_eq!(app.stopped.as_deref(), Some("Halted"));
+        assert!(!app.running);
+        assert_eq!(console.output(), "a");
+
+        app.reset();
+        assert_eq!((app.machine.step_count(), app.stopped.as_ref()), (0, None));
+        assert!(console.output().is_empty());
+    }
+}
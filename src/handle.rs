@@ -0,0 +1,95 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, Thread},
+};
+
+#[derive(Debug, Default)]
+struct Control {
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    /// Thread running the machine, to wake up when resumed or stopped
+    runner: Mutex<Option<Thread>>,
+}
+
+/// Controls a machine running on another thread, e.g. from a GUI or server.
+/// Get one with [`Machine::handle`](crate::vm::Machine::handle); requests
+/// take effect between instructions.
+#[derive(Clone, Debug, Default)]
+pub struct MachineHandle {
+    control: Arc<Control>,
+}
+
+impl MachineHandle {
+    /// Suspends the run loop before the next instruction, without returning from it
+    pub fn pause(&self) {
+        self.control.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.control.paused.store(false, Ordering::SeqCst);
+        self.wake();
+    }
+
+    /// Makes the current (or next) run return before executing another instruction
+    pub fn stop(&self) {
+        self.control.stopped.store(true, Ordering::SeqCst);
+        self.wake();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.control.paused.load(Ordering::SeqCst)
+    }
+
+    /// Called by the run loop between instructions. Blocks while paused and
+    /// returns whether the run should stop, consuming the stop request.
+    pub(crate) fn should_stop(&self) -> bool {
+        if self.is_paused() {
+            *self.control.runner.lock().unwrap() = Some(thread::current());
+            while self.is_paused() && !self.control.stopped.load(Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+        self.control.stopped.swap(false, Ordering::SeqCst)
+    }
+
+    fn wake(&self) {
+        if let Some(runner) = self.control.runner.lock().unwrap().as_ref() {
+            runner.unpark();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{console::BufferConsole, enums::Register, vm::Machine};
+    use std::{sync::mpsc, time::Duration};
+
+    #[test]
+    fn test_handle() {
+        let (tx, rx) = mpsc::channel();
+        let runner = thread::spawn(move || {
+            let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+            // BRnzp to itself, forever
+            test_mach.load_words(0x3000, &[0x0FFF]).unwrap();
+            test_mach.reg_mut().set(Register::COND, 0b010);
+            tx.send(test_mach.handle()).unwrap();
+            test_mach.run().unwrap();
+            test_mach.step_count()
+        });
+
+        let handle = rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        handle.pause();
+        assert!(handle.is_paused());
+        thread::sleep(Duration::from_millis(10));
+        handle.resume();
+        handle.stop();
+
+        assert!(runner.join().unwrap() > 0);
+        assert!(!handle.should_stop());
+    }
+}
@@ -0,0 +1,126 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::{disasm, memory::flag_name, symbols::SymbolTable};
+
+/// An instruction that executed, with the registers it left behind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Executed {
+    /// Instruction count before it executed
+    pub step: u64,
+    pub pc: u16,
+    pub word: u16,
+    /// R0-R7 afterwards
+    pub regs: [u16; 8],
+    pub cond: u16,
+}
+
+/// The last few instructions executed, to show how a program got where it
+/// is, e.g. when it crashes or runs away
+#[derive(Clone, Debug)]
+pub struct History {
+    entries: VecDeque<Executed>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, executed: Executed) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(executed);
+    }
+
+    /// Forgets instructions from `step` on, e.g. when rewinding to it
+    pub fn truncate_from(&mut self, step: u64) {
+        while self.entries.back().is_some_and(|last| last.step >= step) {
+            self.entries.pop_back();
+        }
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Executed> + ExactSizeIterator {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// One line per instruction, the last `count` of them, oldest first
+    pub fn report(&self, count: usize, symbols: &SymbolTable) -> String {
+        let mut out = String::new();
+        for executed in self.iter().skip(self.len().saturating_sub(count)) {
+            let site = match symbols.nearest(executed.pc) {
+                Some((name, addr)) if addr == executed.pc => format!(" <{name}>"),
+                Some((name, addr)) => format!(" <{name}+{}>", executed.pc - addr),
+                None => String::new(),
+            };
+            let regs = executed
+                .regs
+                .iter()
+                .enumerate()
+                .map(|(idx, val)| format!("R{idx}=x{val:04X}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(
+                out,
+                "[{:>8}] x{:04X}{site:<12}  {:<20}  {regs} {}",
+                executed.step,
+                executed.pc,
+                disasm::disassemble_with(executed.pc, executed.word, symbols),
+                flag_name(executed.cond)
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::new(2);
+        for step in 0..3 {
+            history.push(Executed {
+                step,
+                pc: 0x3000 + step as u16,
+                word: 0x1261,
+                regs: [0, step as u16, 0, 0, 0, 0, 0, 0],
+                cond: 0b001,
+            });
+        }
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.iter().next().unwrap().pc, 0x3001);
+        history.truncate_from(2);
+        assert_eq!(history.len(), 1);
+        history.push(Executed {
+            step: 2,
+            pc: 0x3002,
+            word: 0x1261,
+            regs: [0, 2, 0, 0, 0, 0, 0, 0],
+            cond: 0b001,
+        });
+
+        let mut symbols = SymbolTable::default();
+        symbols.insert("LOOP", 0x3001);
+        let report = history.report(1, &symbols);
+        assert_eq!(report.lines().count(), 1);
+        assert!(report.starts_with("[       2] x3002 <LOOP+1>"));
+        assert!(report.contains("ADD R1, R1, #1"));
+        assert!(report.contains("R1=x0002"));
+    }
+}
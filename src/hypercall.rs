@@ -0,0 +1,27 @@
+use num_derive::FromPrimitive;
+
+/// Commands a program writes to the hypercall register (xFE16) to talk to
+/// the host, e.g. a test harness. Operands are taken from R0 and R1.
+#[repr(u16)]
+#[derive(FromPrimitive, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hypercall {
+    /// Fail the test unless R0 equals R1
+    AssertEq = 1,
+    /// Mark the test passed and stop the machine
+    Pass,
+    /// Mark the test failed and stop the machine
+    Fail,
+    /// Print R0 on stderr
+    Print,
+}
+
+/// What a program reported through the hypercall register
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostReport {
+    /// The program signalled [`Hypercall::Pass`]
+    pub passed: bool,
+    /// The program ended the run itself, with a pass or a fail
+    pub finished: bool,
+    /// Failed assertions and [`Hypercall::Fail`]s, in order
+    pub failures: Vec<String>,
+}
@@ -0,0 +1,228 @@
+use byteorder::{BigEndian, ReadBytesExt};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    result::Result as StdResult,
+    str::FromStr,
+};
+
+use crate::{
+    constants::{DEVICE_START, USER_START},
+    error::{Error, ErrorKind, Result},
+    utils::parse_number,
+};
+
+/// Contents of an object file: a load address followed by big endian words
+pub struct Image {
+    pub origin: u16,
+    pub words: Vec<u16>,
+}
+
+impl Image {
+    /// Reads an object file, `-` meaning standard input
+    pub fn read(path: &Path) -> Result<Self> {
+        if path == Path::new("-") {
+            return Self::from_reader(io::stdin().lock());
+        }
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader(mut reader: impl Read) -> Result<Self> {
+        let origin = reader.read_u16::<BigEndian>()?;
+        let mut words = Vec::new();
+
+        loop {
+            match reader.read_u16::<BigEndian>() {
+                Ok(word) => words.push(word),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(Self { origin, words })
+    }
+
+    /// Reads a raw data file to place at `origin`: big endian words like an
+    /// object file without the origin, or with `bytes` one word per byte,
+    /// e.g. for text
+    pub fn read_data(path: &Path, origin: u16, bytes: bool) -> Result<Self> {
+        let data = fs::read(path)?;
+        let words = if bytes {
+            data.into_iter().map(u16::from).collect()
+        } else if data.len() % 2 != 0 {
+            return Err(Error::with_message(
+                ErrorKind::ParseError,
+                format!(
+                    "{} has an odd number of bytes, expected big endian words",
+                    path.display()
+                ),
+            ));
+        } else {
+            data.chunks(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect()
+        };
+        Ok(Self { origin, words })
+    }
+
+    /// Checks the image fits in user memory: at least one word, not wrapping
+    /// past xFFFF and clear of both the OS region and the device registers
+    pub fn validate(&self) -> Result<()> {
+        self.check(false)
+    }
+
+    /// Like [`Image::validate`], but allows loading into the OS region,
+    /// e.g. for trap vector tables and service routines
+    pub fn validate_system(&self) -> Result<()> {
+        self.check(true)
+    }
+
+    /// Inclusive address range the image occupies, once validated
+    pub fn range(&self) -> (u16, u16) {
+        let last = self.origin as usize + self.words.len().max(1) - 1;
+        (self.origin, last as u16)
+    }
+
+    fn check(&self, allow_os: bool) -> Result<()> {
+        let origin = self.origin;
+        let end = origin as usize + self.words.len();
+
+        if self.words.is_empty() {
+            return Err(Error::new(ErrorKind::EmptyImage));
+        }
+        if end > 0x10000 {
+            return Err(Error::new(ErrorKind::ImageTooLarge {
+                origin,
+                words: self.words.len(),
+            }));
+        }
+        if origin < USER_START && !allow_os {
+            return Err(Error::with_message(
+                ErrorKind::BadOrigin { origin },
+                format!(
+                    "image at x{origin:04X} overlaps the OS region x0000-x{:04X}",
+                    USER_START - 1
+                ),
+            ));
+        }
+        if end > DEVICE_START as usize {
+            return Err(Error::with_message(
+                ErrorKind::BadOrigin { origin },
+                format!(
+                    "image at x{origin:04X} ends at x{:04X}, overlapping the device registers from x{DEVICE_START:04X}",
+                    end - 1
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Address of every word along with the word itself
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .map(|(idx, word)| (self.origin.wrapping_add(idx as u16), *word))
+    }
+}
+
+/// A raw data file and where to load it, `PATH@ADDR` on the command line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataFile {
+    pub path: PathBuf,
+    pub addr: u16,
+}
+
+impl FromStr for DataFile {
+    type Err = String;
+
+    /// Parses `PATH@ADDR`, e.g. `input.bin@x5000`
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        s.rsplit_once('@')
+            .and_then(|(path, addr)| {
+                Some(Self {
+                    path: PathBuf::from(path),
+                    addr: parse_number(addr)?,
+                })
+            })
+            .filter(|data| !data.path.as_os_str().is_empty())
+            .ok_or_else(|| format!("expected PATH@ADDR, got `{s}`"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let image = Image::read(Path::new("roms/hello-world.obj")).unwrap();
+        assert_eq!(image.origin, 0x3000);
+        assert_eq!(image.words[..3], [0xE002, 0xF022, 0xF025]);
+        assert_eq!(image.iter().nth(1), Some((0x3001, 0xF022)));
+    }
+
+    #[test]
+    fn test_from_reader() {
+        assert!(Image::from_reader(&[0x30][..]).is_err());
+
+        let image = Image::from_reader(&[0x30, 0x00, 0x12, 0x34, 0x56][..]).unwrap();
+        assert_eq!(image.words, vec![0x1234]);
+    }
+
+    #[test]
+    fn test_read_data() {
+        let path = std::env::temp_dir().join("simulator_test_data.bin");
+        fs::write(&path, [0x12, 0x34, 0x00, 0x41]).unwrap();
+
+        let image = Image::read_data(&path, 0x5000, false).unwrap();
+        assert_eq!((image.origin, image.words), (0x5000, vec![0x1234, 0x0041]));
+        let image = Image::read_data(&path, 0x5000, true).unwrap();
+        assert_eq!(image.words, [0x12, 0x34, 0x00, 0x41]);
+
+        fs::write(&path, [0x12, 0x34, 0x56]).unwrap();
+        assert!(Image::read_data(&path, 0x5000, false).is_err());
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            "data/in.bin@x5000".parse(),
+            Ok(DataFile {
+                path: PathBuf::from("data/in.bin"),
+                addr: 0x5000
+            })
+        );
+        assert!("in.bin".parse::<DataFile>().is_err());
+        assert!("@x5000".parse::<DataFile>().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let image = |origin, len| Image {
+            origin,
+            words: vec![0; len],
+        };
+
+        assert!(image(0x3000, 1).validate().is_ok());
+        assert!(image(0xFDFF, 1).validate().is_ok());
+        assert_eq!(
+            *image(0x3000, 0).validate().unwrap_err().kind(),
+            ErrorKind::EmptyImage
+        );
+        assert_eq!(
+            *image(0xFFFF, 2).validate().unwrap_err().kind(),
+            ErrorKind::ImageTooLarge {
+                origin: 0xFFFF,
+                words: 2
+            }
+        );
+        assert_eq!(
+            *image(0x2FFF, 1).validate().unwrap_err().kind(),
+            ErrorKind::BadOrigin { origin: 0x2FFF }
+        );
+        assert!(image(0xFDFF, 2).validate().is_err());
+        assert!(image(0x0000, 0x200).validate_system().is_ok());
+        assert_eq!(image(0x0000, 0x200).range(), (0x0000, 0x01FF));
+    }
+}
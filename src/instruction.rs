@@ -0,0 +1,323 @@
+use num_traits::FromPrimitive;
+
+use crate::{
+    enums::{RawOpCode, Register},
+    error::VmErrorKind,
+    utils::sign_extend,
+};
+
+/// Second operand of ADD and AND
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AluOperand {
+    Reg(Register),
+    /// 5 bit immediate, sign extended
+    Imm(i16),
+}
+
+impl From<Register> for AluOperand {
+    fn from(reg: Register) -> Self {
+        Self::Reg(reg)
+    }
+}
+
+impl From<i16> for AluOperand {
+    fn from(imm: i16) -> Self {
+        Self::Imm(imm)
+    }
+}
+
+/// A decoded LC-3 instruction. Offsets are sign extended, and PC-relative
+/// ones are relative to the incremented PC like in the encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Add {
+        dst: Register,
+        src1: Register,
+        src2: AluOperand,
+    },
+    And {
+        dst: Register,
+        src1: Register,
+        src2: AluOperand,
+    },
+    Not {
+        dst: Register,
+        src: Register,
+    },
+    /// Branches if any of the condition codes in `nzp` (bits 2-0) is set.
+    /// With none set it never branches, which is how NOP is encoded
+    Br {
+        nzp: u8,
+        offset: i16,
+    },
+    /// RET is `JMP R7`
+    Jmp {
+        base: Register,
+    },
+    Jsr {
+        offset: i16,
+    },
+    Jsrr {
+        base: Register,
+    },
+    Ld {
+        dst: Register,
+        offset: i16,
+    },
+    Ldi {
+        dst: Register,
+        offset: i16,
+    },
+    Ldr {
+        dst: Register,
+        base: Register,
+        offset: i16,
+    },
+    Lea {
+        dst: Register,
+        offset: i16,
+    },
+    St {
+        src: Register,
+        offset: i16,
+    },
+    Sti {
+        src: Register,
+        offset: i16,
+    },
+    Str {
+        src: Register,
+        base: Register,
+        offset: i16,
+    },
+    Rti,
+    Trap {
+        vector: u8,
+    },
+}
+
+impl Instruction {
+    /// Decodes `word`. Bits the ISA leaves unused are ignored, like the
+    /// hardware does; only the reserved opcode 1101 doesn't decode.
+    pub fn decode(word: u16) -> Result<Self, VmErrorKind> {
+        let reg = |shift: u16| Register::from_field(word >> shift);
+        let offset = |bits: u16| sign_extend(word & ((1 << bits) - 1), bits) as i16;
+        let alu_operand = || {
+            if (word >> 5) & 0x1 == 1 {
+                AluOperand::Imm(offset(5))
+            } else {
+                AluOperand::Reg(reg(0))
+            }
+        };
+
+        let instr = match RawOpCode::from_u16(word >> 12).unwrap() {
+            RawOpCode::Add => Self::Add {
+                dst: reg(9),
+                src1: reg(6),
+                src2: alu_operand(),
+            },
+            RawOpCode::And => Self::And {
+                dst: reg(9),
+                src1: reg(6),
+                src2: alu_operand(),
+            },
+            RawOpCode::Not => Self::Not {
+                dst: reg(9),
+                src: reg(6),
+            },
+            RawOpCode::Br => Self::Br {
+                nzp: ((word >> 9) & 0x7) as u8,
+                offset: offset(9),
+            },
+            RawOpCode::Jmp => Self::Jmp { base: reg(6) },
+            RawOpCode::Jsr if (word >> 11) & 0x1 == 1 => Self::Jsr { offset: offset(11) },
+            RawOpCode::Jsr => Self::Jsrr { base: reg(6) },
+            RawOpCode::Ld => Self::Ld {
+                dst: reg(9),
+                offset: offset(9),
+            },
+            RawOpCode::Ldi => Self::Ldi {
+                dst: reg(9),
+                offset: offset(9),
+            },
+            RawOpCode::Ldr => Self::Ldr {
+                dst: reg(9),
+                base: reg(6),
+                offset: offset(6),
+            },
+            RawOpCode::Lea => Self::Lea {
+                dst: reg(9),
+                offset: offset(9),
+            },
+            RawOpCode::St => Self::St {
+                src: reg(9),
+                offset: offset(9),
+            },
+            RawOpCode::Sti => Self::Sti {
+                src: reg(9),
+                offset: offset(9),
+            },
+            RawOpCode::Str => Self::Str {
+                src: reg(9),
+                base: reg(6),
+                offset: offset(6),
+            },
+            RawOpCode::Rti => Self::Rti,
+            RawOpCode::Trap => Self::Trap { vector: word as u8 },
+            RawOpCode::Noop => return Err(VmErrorKind::IllegalOpcode),
+        };
+
+        Ok(instr)
+    }
+
+    /// Encodes the instruction, the inverse of [`Instruction::decode`]. Unused
+    /// bits are zero, except NOT's which are all ones like assemblers emit.
+    /// Offsets and immediates must fit in their field.
+    pub fn encode(&self) -> u16 {
+        let reg = |reg: Register, shift: u16| (reg as u16 & 0x7) << shift;
+        let field = |offset: i16, bits: u16| {
+            let limit = 1 << (bits - 1);
+            debug_assert!(
+                (-limit..limit).contains(&offset),
+                "{offset} does not fit in {bits} bits"
+            );
+            offset as u16 & ((1 << bits) - 1)
+        };
+        let alu_operand = |operand: AluOperand| match operand {
+            AluOperand::Reg(src2) => reg(src2, 0),
+            AluOperand::Imm(imm) => 0x20 | field(imm, 5),
+        };
+        let op = |op: RawOpCode| (op as u16) << 12;
+
+        match *self {
+            Self::Add { dst, src1, src2 } => {
+                op(RawOpCode::Add) | reg(dst, 9) | reg(src1, 6) | alu_operand(src2)
+            }
+            Self::And { dst, src1, src2 } => {
+                op(RawOpCode::And) | reg(dst, 9) | reg(src1, 6) | alu_operand(src2)
+            }
+            Self::Not { dst, src } => op(RawOpCode::Not) | reg(dst, 9) | reg(src, 6) | 0x3F,
+            Self::Br { nzp, offset } => {
+                op(RawOpCode::Br) | (nzp as u16 & 0x7) << 9 | field(offset, 9)
+            }
+            Self::Jmp { base } => op(RawOpCode::Jmp) | reg(base, 6),
+            Self::Jsr { offset } => op(RawOpCode::Jsr) | 1 << 11 | field(offset, 11),
+            Self::Jsrr { base } => op(RawOpCode::Jsr) | reg(base, 6),
+            Self::Ld { dst, offset } => op(RawOpCode::Ld) | reg(dst, 9) | field(offset, 9),
+            Self::Ldi { dst, offset } => op(RawOpCode::Ldi) | reg(dst, 9) | field(offset, 9),
+            Self::Ldr { dst, base, offset } => {
+                op(RawOpCode::Ldr) | reg(dst, 9) | reg(base, 6) | field(offset, 6)
+            }
+            Self::Lea { dst, offset } => op(RawOpCode::Lea) | reg(dst, 9) | field(offset, 9),
+            Self::St { src, offset } => op(RawOpCode::St) | reg(src, 9) | field(offset, 9),
+            Self::Sti { src, offset } => op(RawOpCode::Sti) | reg(src, 9) | field(offset, 9),
+            Self::Str { src, base, offset } => {
+                op(RawOpCode::Str) | reg(src, 9) | reg(base, 6) | field(offset, 6)
+            }
+            Self::Rti => op(RawOpCode::Rti),
+            Self::Trap { vector } => op(RawOpCode::Trap) | vector as u16,
+        }
+    }
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            Instruction::decode(0b0001_011_000_0_00_001),
+            Ok(Instruction::Add {
+                dst: Register::R3,
+                src1: Register::R0,
+                src2: AluOperand::Reg(Register::R1),
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0b0101_010_100_1_10011),
+            Ok(Instruction::And {
+                dst: Register::R2,
+                src1: Register::R4,
+                src2: AluOperand::Imm(-13),
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0b0000_0_1_1_111111101),
+            Ok(Instruction::Br {
+                nzp: 0b011,
+                offset: -3
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0b0110_101_000_100101),
+            Ok(Instruction::Ldr {
+                dst: Register::R5,
+                base: Register::R0,
+                offset: -27
+            })
+        );
+        assert_eq!(
+            Instruction::decode(0b0100_1_00000000100),
+            Ok(Instruction::Jsr { offset: 4 })
+        );
+        assert_eq!(
+            Instruction::decode(0b0100_0_00_101_000000),
+            Ok(Instruction::Jsrr { base: Register::R5 })
+        );
+        assert_eq!(
+            Instruction::decode(0xC1C0),
+            Ok(Instruction::Jmp { base: Register::R7 })
+        );
+        assert_eq!(
+            Instruction::decode(0xF025),
+            Ok(Instruction::Trap { vector: 0x25 })
+        );
+        assert_eq!(Instruction::decode(0x8000), Ok(Instruction::Rti));
+        assert_eq!(Instruction::decode(0xD123), Err(VmErrorKind::IllegalOpcode));
+    }
+
+    #[test]
+    fn test_encode() {
+        let add = Instruction::Add {
+            dst: Register::R3,
+            src1: Register::R0,
+            src2: AluOperand::Reg(Register::R1),
+        };
+        assert_eq!(add.encode(), 0b0001_011_000_0_00_001);
+        assert_eq!(Instruction::Trap { vector: 0x25 }.encode(), 0xF025);
+        assert_eq!(
+            Instruction::Not {
+                dst: Register::R1,
+                src: Register::R2
+            }
+            .encode(),
+            0x92BF
+        );
+
+        // Words as assemblers emit them come back unchanged
+        for word in [
+            0x1262, 0x56BF, 0x0FFE, 0xC1C0, 0x4803, 0x4140, 0x2BFF, 0xA001,
+        ] {
+            assert_eq!(Instruction::decode(word).unwrap().encode(), word);
+        }
+        for word in [0x6F7F, 0xE1FD, 0x3600, 0xB7FF, 0x7E1F, 0x8000] {
+            assert_eq!(Instruction::decode(word).unwrap().encode(), word);
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        // Every instruction is the decoding of some word, so this covers them all
+        for word in 0..=u16::MAX {
+            if let Ok(instr) = Instruction::decode(word) {
+                assert_eq!(
+                    Instruction::decode(instr.encode()),
+                    Ok(instr),
+                    "{word:#06x}"
+                );
+            }
+        }
+    }
+}
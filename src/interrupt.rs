@@ -0,0 +1,122 @@
+use crate::constants::SUPERVISOR_STACK;
+
+/// Privilege mode and priority level, the parts of the PSR that aren't
+/// condition codes (those live in COND)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Psr {
+    pub supervisor: bool,
+    /// PL0-PL7, only a device with a higher priority can interrupt
+    pub priority: u8,
+    /// R6 of the mode that isn't running (Saved.SSP or Saved.USP)
+    pub saved_sp: u16,
+}
+
+impl Default for Psr {
+    fn default() -> Self {
+        Self {
+            supervisor: false,
+            priority: 0,
+            saved_sp: SUPERVISOR_STACK,
+        }
+    }
+}
+
+impl Psr {
+    /// The PSR as a word: bit 15 set in user mode, priority in bits 10-8
+    /// and the condition codes `cond` in bits 2-0
+    pub fn word(&self, cond: u16) -> u16 {
+        let user = if self.supervisor { 0 } else { 1 << 15 };
+        user | ((self.priority as u16 & 0x7) << 8) | (cond & 0x7)
+    }
+}
+
+/// Interrupt request from a device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interrupt {
+    pub priority: u8,
+    /// Entry in the interrupt vector table at x0100-x01FF
+    pub vector: u8,
+}
+
+/// Interrupt requests waiting for the processor. Any number of devices can
+/// request at once; the highest priority one is serviced first, and only if
+/// its priority is above the priority the processor is running at.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptController {
+    /// In the order they were raised
+    pending: Vec<Interrupt>,
+}
+
+impl InterruptController {
+    /// Requests an interrupt through `vector` at `priority` (0-7). It stays
+    /// pending until serviced; raising a pending vector again does nothing.
+    pub fn raise(&mut self, priority: u8, vector: u8) {
+        if !self.is_pending(vector) {
+            self.pending.push(Interrupt {
+                priority: priority.min(7),
+                vector,
+            });
+        }
+    }
+
+    /// Withdraws a request that hasn't been serviced yet
+    pub fn clear(&mut self, vector: u8) {
+        self.pending.retain(|irq| irq.vector != vector);
+    }
+
+    pub fn is_pending(&self, vector: u8) -> bool {
+        self.pending.iter().any(|irq| irq.vector == vector)
+    }
+
+    /// Takes the request to service when running at `current` priority: the
+    /// highest priority one above `current`, earliest raised among equals
+    pub fn accept(&mut self, current: u8) -> Option<Interrupt> {
+        let (idx, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, irq)| irq.priority > current)
+            .min_by_key(|(idx, irq)| (std::cmp::Reverse(irq.priority), *idx))?;
+        Some(self.pending.remove(idx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept() {
+        let mut interrupts = InterruptController::default();
+        interrupts.raise(4, 0x80);
+        interrupts.raise(6, 0x81);
+        interrupts.raise(6, 0x82);
+        interrupts.raise(1, 0x80);
+        assert!(interrupts.is_pending(0x80));
+
+        assert_eq!(interrupts.accept(6), None);
+        assert_eq!(interrupts.accept(4).unwrap().vector, 0x81);
+        assert_eq!(interrupts.accept(4).unwrap().vector, 0x82);
+        assert_eq!(interrupts.accept(4), None);
+        assert_eq!(
+            interrupts.accept(0),
+            Some(Interrupt {
+                priority: 4,
+                vector: 0x80
+            })
+        );
+
+        interrupts.raise(2, 0x83);
+        interrupts.clear(0x83);
+        assert_eq!(interrupts.accept(0), None);
+    }
+
+    #[test]
+    fn test_psr_word() {
+        let mut psr = Psr::default();
+        assert_eq!(psr.word(0b010), 0x8002);
+        psr.supervisor = true;
+        psr.priority = 4;
+        assert_eq!(psr.word(0b100), 0x0404);
+    }
+}
@@ -0,0 +1,90 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// A key the program received, and how many instructions had executed when it did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keystroke {
+    pub step: u64,
+    pub key: u8,
+}
+
+impl Keystroke {
+    /// Key available right away, e.g. scripted input
+    pub fn now(key: u8) -> Self {
+        Self { step: 0, key }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Keystroke>> {
+    parse(&fs::read_to_string(path)?)
+}
+
+pub fn save(path: &Path, keys: &[Keystroke]) -> Result<()> {
+    Ok(fs::write(path, format(keys))?)
+}
+
+/// Parses a recording, one `STEP xKEY` line per key, e.g. `1520 x61`.
+/// Blank lines and `;` comments are skipped.
+pub fn parse(src: &str) -> Result<Vec<Keystroke>> {
+    let mut keys = Vec::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let keystroke = line
+            .split_once(char::is_whitespace)
+            .and_then(|(step, key)| {
+                let key = key.trim().trim_start_matches(['x', 'X']);
+                Some(Keystroke {
+                    step: step.parse().ok()?,
+                    key: u8::from_str_radix(key, 16).ok()?,
+                })
+            });
+        let Some(keystroke) = keystroke else {
+            return Err(Error::with_message(
+                ErrorKind::ParseError,
+                format!("line {}: expected `STEP xKEY`, got `{line}`", i + 1),
+            ));
+        };
+        keys.push(keystroke);
+    }
+
+    Ok(keys)
+}
+
+pub fn format(keys: &[Keystroke]) -> String {
+    let mut out = String::new();
+    for keystroke in keys {
+        writeln!(out, "{} x{:02X}", keystroke.step, keystroke.key).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let keys = parse("; recorded keys\n12 x61\n\n480 x0A ; enter\n").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                Keystroke {
+                    step: 12,
+                    key: b'a'
+                },
+                Keystroke {
+                    step: 480,
+                    key: b'\n'
+                },
+            ]
+        );
+        assert_eq!(format(&keys), "12 x61\n480 x0A\n");
+        assert!(parse("12").is_err());
+    }
+}
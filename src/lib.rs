@@ -0,0 +1,59 @@
+//! LC-3 virtual machine, assembler and tools. The `simulator` binary is a thin
+//! command line front end over this library.
+
+#[macro_use]
+mod macros;
+
+pub mod analyze;
+pub mod asm;
+pub mod banking;
+pub mod branches;
+pub mod callstack;
+pub mod checkpoint;
+pub mod checks;
+pub mod chrome_trace;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod console;
+pub mod constants;
+pub mod coverage;
+pub mod crash;
+pub mod debugger;
+pub mod disasm;
+pub mod dump;
+pub mod enums;
+pub mod error;
+pub mod explore;
+pub mod ext;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod handle;
+pub mod history;
+pub mod hypercall;
+pub mod image;
+pub mod instruction;
+pub mod interrupt;
+pub mod keylog;
+pub mod listing;
+pub mod memory;
+pub mod perfcount;
+pub mod profile;
+pub mod program;
+pub mod project;
+pub mod report;
+pub mod runner;
+pub mod selftest;
+pub mod snapshot;
+pub mod symbolic;
+pub mod symbols;
+pub mod taint;
+pub mod terminal;
+pub mod trace;
+pub mod utils;
+pub mod vm;
+pub mod vsync;
+pub mod watch;
+pub mod watchdog;
+pub mod writelog;
@@ -0,0 +1,169 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use crate::error::Result;
+
+/// Address <-> source line mapping, as produced by `lc3as` in `.lst` files
+#[derive(Default, Clone, Debug)]
+pub struct Listing {
+    /// Address -> (line number, source text)
+    by_addr: HashMap<u16, (usize, String)>,
+    /// Line number -> first address it assembled to
+    by_line: BTreeMap<usize, u16>,
+    /// Line number -> source text, for every line including comments
+    text: BTreeMap<usize, String>,
+}
+
+impl Listing {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the lc3as listing format:
+    ///
+    /// ```text
+    ///   (0000) 3000  0011000000000000 (   1)                 .ORIG x3000
+    ///   (3000) E002  1110000000000010 (   2)                 LEA   R0 HELLO ; greet
+    ///   (3001) F022  1111000000100010 (   3)                 PUTS
+    ///                                 (   4) ; no code on this line
+    /// ```
+    ///
+    /// Only lines that assembled to a word are mapped. The `.ORIG` line lists
+    /// the origin as its word, so it's skipped.
+    pub fn parse(src: &str) -> Self {
+        let mut listing = Self::default();
+
+        for line in src.lines() {
+            let Some(rest) = line.trim_start().strip_prefix('(') else {
+                continue;
+            };
+            let Some((first, rest)) = rest.split_once(')') else {
+                continue;
+            };
+            // Skip the hex and binary encodings of the word. Lines without
+            // code only have the line number
+            let (addr, number, source) = match rest.split_once('(') {
+                Some((_, rest)) => match rest.split_once(')') {
+                    Some((number, source)) => (Some(first), number, source),
+                    None => continue,
+                },
+                None => (None, first, rest),
+            };
+            let Ok(number) = number.trim().parse::<usize>() else {
+                continue;
+            };
+
+            let source = source.trim();
+            if !source.is_empty() {
+                listing
+                    .text
+                    .entry(number)
+                    .or_insert_with(|| source.to_owned());
+            }
+            let Some(Ok(addr)) = addr.map(|addr| u16::from_str_radix(addr.trim(), 16)) else {
+                continue;
+            };
+            if source.to_ascii_uppercase().contains(".ORIG") {
+                continue;
+            }
+            listing.insert(addr, number, source);
+        }
+
+        listing
+    }
+
+    /// Maps `addr` to a source line. Continuation lines (e.g. the rest of a
+    /// `.STRINGZ`) have no source text of their own and reuse the line's.
+    pub fn insert(&mut self, addr: u16, line: usize, source: &str) {
+        let source = match self.by_line.get(&line) {
+            Some(first) if source.is_empty() => self.by_addr[first].1.clone(),
+            _ => source.to_owned(),
+        };
+        self.text.entry(line).or_insert_with(|| source.clone());
+        self.by_addr.insert(addr, (line, source));
+        self.by_line.entry(line).or_insert(addr);
+    }
+
+    /// Source line number and text `addr` was assembled from
+    pub fn line_at(&self, addr: u16) -> Option<(usize, &str)> {
+        self.by_addr
+            .get(&addr)
+            .map(|(line, source)| (*line, source.as_str()))
+    }
+
+    /// First address assembled from source line `line`, or from the next line
+    /// with code after it (e.g. when `line` is a comment)
+    pub fn addr_of_line(&self, line: usize) -> Option<u16> {
+        self.by_line.range(line..).next().map(|(_, addr)| *addr)
+    }
+
+    /// Every mapped address with its source line number
+    pub fn addrs(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.by_addr.iter().map(|(addr, (line, _))| (*addr, *line))
+    }
+
+    /// Source lines in order, with their text. Blank lines are left out
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.text.iter().map(|(line, text)| (*line, text.as_str()))
+    }
+
+    /// Whether `addr` holds an instruction according to the listing, rather
+    /// than data or nothing the listing knows about
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.line_at(addr)
+            .is_some_and(|(_, source)| !is_data(source))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+}
+
+/// Whether a listing line holds data (`.FILL`, `.STRINGZ`, `.BLKW`) rather
+/// than an instruction, after an optional label
+pub(crate) fn is_data(text: &str) -> bool {
+    let code = text.split(';').next().unwrap_or_default();
+    code.split_whitespace()
+        .take(2)
+        .any(|token| token.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "\
+  (0000) 3000  0011000000000000 (   1)                 .ORIG x3000
+  (3000) E002  1110000000000010 (   2)                 LEA   R0 HELLO ; greet
+                                (   3) ; print it
+  (3001) F022  1111000000100010 (   4)                 PUTS
+  (3002) F025  1111000000100101 (   5)                 HALT
+  (3003) 0048  0000000001001000 (   6) HELLO           .STRINGZ \"Hi\"
+  (3004) 0069  0000000001101001 (   6)
+";
+
+    #[test]
+    fn test_parse() {
+        let listing = Listing::parse(LISTING);
+
+        assert_eq!(listing.line_at(0x3000), Some((2, "LEA   R0 HELLO ; greet")));
+        assert_eq!(
+            listing.line_at(0x3004),
+            Some((6, "HELLO           .STRINGZ \"Hi\""))
+        );
+        assert_eq!(listing.line_at(0x0000), None);
+        assert_eq!(listing.addr_of_line(4), Some(0x3001));
+        assert_eq!(listing.addr_of_line(3), Some(0x3001));
+        assert_eq!(listing.addr_of_line(6), Some(0x3003));
+        assert_eq!(listing.addr_of_line(7), None);
+
+        let lines: Vec<_> = listing.lines().map(|(line, _)| line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(listing.lines().nth(2), Some((3, "; print it")));
+        assert!(listing.is_code(0x3001));
+        assert!(!listing.is_code(0x3004) && !listing.is_code(0x3005));
+    }
+}
@@ -0,0 +1,17 @@
+/// Prints a debug message from `machine` like
+/// [`Machine::debug`](crate::vm::Machine::debug), taking `format!` arguments.
+/// Nothing is formatted unless the machine is in debug mode, so it can be
+/// used on hot paths.
+///
+/// ```
+/// # let machine = simulator::vm::Machine::default();
+/// simulator::debug!(machine, "Instr: {:#018b}", 0x1234);
+/// ```
+#[macro_export]
+macro_rules! debug {
+    ($machine:expr, $($arg:tt)+) => {
+        if $machine.debug_enabled() {
+            $machine.debug(&format!($($arg)+));
+        }
+    };
+}
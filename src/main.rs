@@ -1,35 +1,653 @@
-pub mod cli;
-pub mod constants;
-pub mod enums;
-pub mod error;
-pub mod memory;
-pub mod utils;
-pub mod vm;
+use std::{
+    fs,
+    io::{self, IsTerminal, Read},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    process,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 
 use clap::Parser;
-use cli::Cli;
-use crossterm::terminal;
-use error::Result;
-use vm::Machine;
+use colored::Colorize;
+use simulator::{
+    analyze, asm,
+    cli::{
+        AsmArgs, BankArgs, BenchArgs, CheckArgs, CheckImageArgs, Cli, Command, DebugArgs,
+        DiffStateArgs, DisasmArgs, DumpArgs, ExploreArgs, ImageArgs, RunArgs, TestArgs,
+    },
+    console::{self, TerminalConsole},
+    coverage::Coverage,
+    crash::{self, Fault},
+    debugger::{session, Debugger},
+    disasm,
+    dump::StateDump,
+    error::{Error, ErrorKind, Result},
+    explore::{self, ExploreLimits},
+    image::Image,
+    keylog::{self, Keystroke},
+    listing::Listing,
+    project::{Devices, Project},
+    report, runner, selftest,
+    snapshot::{self, Snapshot},
+    symbolic::{self, FindingKind},
+    symbols::SymbolTable,
+    trace::TraceFilter,
+    utils,
+    vm::{ExitReason, Machine},
+    watch::{self, Watcher},
+    watchdog::Watchdog,
+    writelog,
+};
+#[cfg(feature = "gui")]
+use simulator::{
+    cli::GuiArgs,
+    gui::{self, GuiConsole},
+};
 
 fn main() -> Result<()> {
     let args = Cli::parse();
+    console::install_panic_hook();
 
-    // Setup code
-    terminal::enable_raw_mode().expect("Could not turn on raw mode");
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+
+    match args.command {
+        Command::Run(args) => run_program(args),
+        Command::Debug(args) => debug(args),
+        Command::Asm(args) => assemble(args),
+        Command::Disasm(args) => disassemble(args),
+        Command::Check(args) => check(args),
+        Command::Test(args) => test(args),
+        Command::Selftest => selftest(),
+        Command::Bench(args) => bench(args),
+        Command::Explore(args) => explore(args),
+        Command::Diffstate(args) => diffstate(args),
+        #[cfg(feature = "gui")]
+        Command::Gui(args) => gui(args),
+    }
+}
+
+fn run_program(mut args: RunArgs) -> Result<()> {
+    let mut devices = Devices::default();
+    if let Some(project) = open_project(&mut args.images)? {
+        args.symbols = args.symbols.or(project.symbols);
+        args.listing = args.listing.or(project.listing);
+        args.source = args.source.or(project.source);
+        devices = project.devices;
+    }
+
+    if args.watch {
+        return watch_program(&args, &devices);
+    }
+    let machine = run_once(&args, |machine| devices.apply(machine))?;
+    exit_on_error(&machine);
+
+    Ok(())
+}
+
+/// Loads the manifest given with --project, assembling the program if its
+/// object file is out of date, and adds its images to `images`
+fn open_project(images: &mut ImageArgs) -> Result<Option<Project>> {
+    let Some(path) = &images.project else {
+        return Ok(None);
+    };
+    let project = Project::load(path)?;
+    project.build()?;
+    images.os.splice(0..0, project.os.iter().cloned());
+    images.file.push(project.object.clone());
+    Ok(Some(project))
+}
+
+/// Runs the program with everything `args` asks for and writes the reports.
+/// `prepare` gets the machine before it starts
+fn run_once(args: &RunArgs, prepare: impl FnOnce(&mut Machine)) -> Result<Machine> {
+    let symbols = match &args.symbols {
+        Some(path) => SymbolTable::load(path)?,
+        None => SymbolTable::default(),
+    };
 
-    // Run machine
     let mut machine = Machine::default();
+    apply_checks(&mut machine, &args.checks);
+    machine.set_echo(args.echo.echo());
+    machine.enable_state_dumps(state_dump(&args.dump)?);
+    machine.set_fps(args.fps);
+    apply_banks(&mut machine, &args.banks);
+    machine.set_extensions(args.ext.unwrap_or_default());
+    machine.enable_arith_traps(args.arith_traps);
+    machine.enable_io_traps(args.io_traps);
+    machine.enable_perf_counters(args.perf_counters);
+    machine.enable_history(args.history);
+    #[cfg(feature = "gamepad")]
+    if args.gamepad {
+        machine.gamepad().poll_controllers()?;
+    }
+    if args.hypercalls {
+        machine.enable_hypercalls();
+    }
+    if args.branch_stats {
+        machine.enable_branch_stats();
+    }
+    if args.coverage.is_some() {
+        machine.enable_coverage();
+    }
+    if args.profile {
+        machine.enable_profiling();
+    }
+    if args.chrome_trace.is_some() {
+        machine.enable_chrome_trace();
+    }
+    if args.trace {
+        machine.enable_trace(TraceFilter {
+            opcodes: args.trace_opcodes,
+            range: args.trace_range,
+        });
+    }
+    if args.taint {
+        machine.enable_taint();
+    }
+    if let Some(path) = &args.replay_input {
+        machine.replay_keys(&keylog::load(path)?);
+    }
+    if args.record_input.is_some() {
+        machine.record_input();
+    }
+    if args.write_log.is_some() {
+        machine.enable_write_log();
+    }
+    prepare(&mut machine);
+    let console = if args.line_input {
+        TerminalConsole::line_input()
+    } else {
+        TerminalConsole::default()
+    }
+    .with_extended_keys(args.extended_keys)
+    .with_raw(args.raw.raw());
+    let machine = run(machine, &args.images, console, &symbols)?;
+    eprint!("{}", report::exit_summary(&machine));
+
+    if let Some(path) = &args.record_input {
+        keylog::save(path, machine.input_log())?;
+    }
+    if let Some(path) = &args.write_log {
+        writelog::save(path, machine.write_log())?;
+    }
+    if let Some(profiler) = machine.profiler() {
+        print!("{}", profiler.report(&symbols));
+    }
+    if let Some(stats) = machine.branch_stats() {
+        print!("{}", stats.report(&symbols));
+    }
+    if let Some(taint) = machine.taint() {
+        print!("{}", taint.report());
+    }
+    if args.stats {
+        print!("{}", report::stats(&machine));
+    }
+    if let (Some(path), Some(trace)) = (&args.chrome_trace, machine.chrome_trace()) {
+        fs::write(path, trace.to_json(&symbols, machine.step_count()))?;
+    }
+    if let (Some(path), Some(listing), Some(coverage)) =
+        (&args.coverage, &args.listing, machine.coverage())
+    {
+        write_coverage(path, listing, coverage)?;
+    }
+    if let Some(path) = &args.summary {
+        fs::write(path, report::summary_json(&machine))?;
+    }
+    if let Some(path) = &args.save_final_state {
+        Snapshot::capture(&machine).save(path)?;
+    }
+
+    Ok(machine)
+}
+
+/// Reruns the program whenever one of its images (or --source, reassembled
+/// first) changes, stopping a run that is still going. Input piped in on
+/// stdin is fed to every run
+fn watch_program(args: &RunArgs, devices: &Devices) -> Result<()> {
+    let program = args.images.program();
+    let input: Vec<_> = if io::stdin().is_terminal() {
+        Vec::new()
+    } else {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+            .into_iter()
+            .map(|key| Keystroke { step: 0, key })
+            .collect()
+    };
+    let paths: Vec<_> = args
+        .images
+        .os
+        .iter()
+        .chain(&args.images.file)
+        .map(|path| match &args.source {
+            Some(source) if path == program => source.clone(),
+            _ => path.clone(),
+        })
+        .collect();
+    let mut watcher = Watcher::new(paths.clone());
+
+    loop {
+        let assembled = match &args.source {
+            Some(source) => asm::assemble_file(source, program),
+            None => Ok(()),
+        };
+        // Dropped once the run is over, which ends the thread polling the files
+        let (done, finished) = mpsc::channel::<()>();
+        let result = assembled.and_then(|_| {
+            run_once(args, |machine| {
+                devices.apply(machine);
+                machine.replay_keys(&input);
+                let handle = machine.handle();
+                let mut poller = Watcher::new(paths.clone());
+                thread::spawn(move || {
+                    while let Err(RecvTimeoutError::Timeout) =
+                        finished.recv_timeout(watch::POLL_INTERVAL)
+                    {
+                        if poller.changed() {
+                            handle.stop();
+                        }
+                    }
+                });
+            })
+        });
+        drop(done);
+        if let Err(e) = result {
+            eprintln!("{e}");
+        }
+
+        if !watcher.changed() {
+            let names: Vec<_> = watcher
+                .paths()
+                .map(|path| path.display().to_string())
+                .collect();
+            eprintln!("Watching {} for changes", names.join(", "));
+            watcher.wait();
+        }
+    }
+}
+
+/// Writes the coverage of the source `listing` was made from, in the format
+/// `path`'s extension asks for
+fn write_coverage(path: &Path, listing_path: &Path, coverage: &Coverage) -> Result<()> {
+    let listing = Listing::load(listing_path)?;
+    let source = listing_path.with_extension("asm").display().to_string();
+    let report = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html" | "htm") => coverage.html(&listing, &source),
+        _ => coverage.lcov(&listing, &source),
+    };
+    fs::write(path, report)?;
+
+    let (hit, found) = coverage.summary(&listing);
+    eprintln!("Coverage: {hit} of {found} lines executed");
+    Ok(())
+}
+
+fn exit_on_error(machine: &Machine) {
+    if machine.exit_reason() == Some(ExitReason::Error) {
+        process::exit(1);
+    }
+}
 
-    if args.debug {
-        machine.enter_debug_mode();
+fn apply_banks(machine: &mut Machine, banks: &BankArgs) {
+    if let Some(count) = banks.banks {
+        machine.enable_banking(count, banks.bank_window);
     }
+}
 
-    machine.load_image(args.file)?;
-    machine.run();
+fn apply_checks(machine: &mut Machine, checks: &CheckArgs) {
+    machine.check_uninit(checks.uninit);
+    machine.check_self_modifying(checks.self_modify);
+    machine.check_stack(checks.stack, checks.stack_bounds);
+    machine.check_convention(checks.convention, checks.callee_saved);
+    machine.check_read_only(checks.read_only, &checks.protect);
+    machine.check_bounds(checks.bounds, checks.stack_bounds, &checks.writable);
+    machine.forbid_traps(checks.forbid_traps.unwrap_or_default());
+    machine.forbid_opcodes(checks.forbid_opcodes);
+    machine.enable_watchdog(
+        checks
+            .watchdog
+            .map(|timeout| Watchdog::new(timeout, checks.watchdog_action)),
+    );
+}
 
-    // Cleanup code
-    terminal::disable_raw_mode().expect("Could not turn off raw mode");
+/// State dumps configured by `args`, written whenever the process gets SIGUSR1
+fn state_dump(args: &DumpArgs) -> Result<StateDump> {
+    let dump = StateDump {
+        window: args.dump_window,
+        path: args.dump_file.clone(),
+        ..Default::default()
+    };
+    #[cfg(unix)]
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&dump.trigger))?;
+    Ok(dump)
+}
+
+fn load_images(machine: &mut Machine, images: &ImageArgs) -> Result<()> {
+    for path in &images.os {
+        machine.load_os_image(path.clone())?;
+    }
+    for path in &images.file {
+        machine.load_image(path.clone())?;
+    }
+    for data in &images.data {
+        machine.load_data(data, false)?;
+    }
+    for data in &images.data_bytes {
+        machine.load_data(data, true)?;
+    }
+    Ok(())
+}
+
+/// Runs the program on the terminal. Crashes print a diagnostic naming code
+/// after `symbols`
+fn run(
+    mut machine: Machine,
+    images: &ImageArgs,
+    console: TerminalConsole,
+    symbols: &SymbolTable,
+) -> Result<Machine> {
+    load_images(&mut machine, images)?;
+
+    let terminal_mode = console.terminal_mode();
+    machine.set_console(Box::new(console));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| machine.run()));
+    drop(terminal_mode);
+    // Execution errors are kept as the machine's fault, callers exit with
+    // an error once they're done with the machine
+    match result {
+        Ok(_) => {
+            if let Some(fault) = machine.fault() {
+                eprint!("{}", crash::report(&machine, fault, symbols));
+            }
+        }
+        Err(payload) => {
+            let (pc, word) = machine.last_instruction();
+            let fault = Fault {
+                pc,
+                word,
+                reason: "the simulator panicked".to_owned(),
+            };
+            eprint!("{}", crash::report(&machine, &fault, symbols));
+            panic::resume_unwind(payload);
+        }
+    }
+
+    Ok(machine)
+}
+
+fn debug(mut args: DebugArgs) -> Result<()> {
+    let mut devices = Devices::default();
+    if let Some(project) = open_project(&mut args.images)? {
+        args.symbols = args.symbols.or(project.symbols);
+        args.listing = args.listing.or(project.listing);
+        devices = project.devices;
+    }
+
+    // For crash reports, the debugger loads its own copy
+    let symbols = match &args.symbols {
+        Some(path) => SymbolTable::load(path)?,
+        None => SymbolTable::default(),
+    };
+    let mut debugger = Debugger::with_session(session::session_path(args.images.program()))?;
+    if let Some(path) = args.symbols {
+        debugger.queue(format!("symbols {}", path.display()));
+    }
+    if let Some(path) = args.listing {
+        debugger.queue(format!("listing {}", path.display()));
+    }
+    if let Some(path) = args.debug_script {
+        debugger.source(&path)?;
+    }
+    debugger.set_continue_budget(args.continue_budget);
+
+    let mut machine = Machine::default();
+    apply_checks(&mut machine, &args.checks);
+    machine.set_echo(args.echo.echo());
+    machine.enable_state_dumps(state_dump(&args.dump)?);
+    machine.set_fps(args.fps);
+    machine.set_log_ranges(args.log_range);
+    apply_banks(&mut machine, &args.banks);
+    machine.set_extensions(args.ext.unwrap_or_default());
+    machine.enable_arith_traps(args.arith_traps);
+    machine.enable_io_traps(args.io_traps);
+    machine.enable_perf_counters(args.perf_counters);
+    machine.enable_history(args.history);
+    #[cfg(feature = "gamepad")]
+    if args.gamepad {
+        machine.gamepad().poll_controllers()?;
+    }
+    if let Some(interval) = args.checkpoint_every {
+        machine.enable_checkpoints(interval, args.checkpoint_count);
+    }
+    devices.apply(&mut machine);
+    machine.attach_debugger(debugger);
+    let console = TerminalConsole::default()
+        .with_extended_keys(args.extended_keys)
+        .with_raw(args.raw.raw());
+    let machine = run(machine, &args.images, console, &symbols)?;
+    exit_on_error(&machine);
+
+    Ok(())
+}
+
+#[cfg(feature = "gui")]
+fn gui(mut args: GuiArgs) -> Result<()> {
+    let mut devices = Devices::default();
+    if let Some(project) = open_project(&mut args.images)? {
+        devices = project.devices;
+    }
+
+    let console = GuiConsole::default();
+    let mut machine = Machine::with_console(Box::new(console.clone()));
+    machine.set_extensions(args.ext.unwrap_or_default());
+    machine.enable_arith_traps(args.arith_traps);
+    machine.enable_io_traps(args.io_traps);
+    devices.apply(&mut machine);
+    load_images(&mut machine, &args.images)?;
+    let title = args.images.program().display().to_string();
+    gui::run(machine, console, &title)
+}
+
+fn assemble(args: AsmArgs) -> Result<()> {
+    let output = args
+        .output
+        .unwrap_or_else(|| args.source.with_extension("obj"));
+    asm::assemble_file(&args.source, &output)
+}
+
+fn disassemble(args: DisasmArgs) -> Result<()> {
+    let image = Image::read(&args.file)?;
+    let symbols = match &args.symbols {
+        Some(path) => SymbolTable::load(path)?,
+        None => SymbolTable::default(),
+    };
+
+    for (addr, word) in image.iter() {
+        let instr = disasm::disassemble_with(addr, word, &symbols);
+        println!("x{addr:04X}  x{word:04X}  {instr}");
+    }
+
+    Ok(())
+}
+
+fn diffstate(args: DiffStateArgs) -> Result<()> {
+    let before = Snapshot::load(&args.before)?;
+    let after = Snapshot::load(&args.after)?;
+    let symbols = match &args.symbols {
+        Some(path) => SymbolTable::load(path)?,
+        None => SymbolTable::default(),
+    };
+    let listing = match &args.listing {
+        Some(path) => Listing::load(path)?,
+        None => Listing::default(),
+    };
+    print!("{}", snapshot::diff(&before, &after, &symbols, &listing));
+    Ok(())
+}
+
+fn check(args: CheckImageArgs) -> Result<()> {
+    let image = Image::read(&args.file)?;
+    let analysis = analyze::analyze(&image);
+    print!("{}", analysis.report());
+
+    if !analysis.findings.is_empty() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn test(args: TestArgs) -> Result<()> {
+    let mut tests = runner::discover(&args.paths)?;
+    if let Some(path) = &args.project {
+        let project = Project::load(path)?;
+        project.build()?;
+        tests.extend(project.golden_tests());
+    }
+    let mut results = Vec::with_capacity(tests.len());
+    let mut failed = 0;
+
+    for test in &tests {
+        let result = test.run(Some(args.max_steps), args.echo.echo())?;
+        match &result.failure {
+            None => println!(
+                "{} {} ({} instructions)",
+                "PASS".green().bold(),
+                result.name,
+                result.steps
+            ),
+            Some(reason) => {
+                failed += 1;
+                println!("{} {}: {reason}", "FAIL".red().bold(), result.name);
+            }
+        }
+        results.push(result);
+    }
+
+    println!("{} passed, {failed} failed", tests.len() - failed);
+    if let Some(path) = &args.junit {
+        fs::write(path, report::junit_xml(&results))?;
+    }
+    if let Some(path) = &args.json {
+        fs::write(path, report::json(&results))?;
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn selftest() -> Result<()> {
+    let mut failed = 0;
+    for test in selftest::SUITE {
+        match test.run()? {
+            None => println!("{} {}", "PASS".green().bold(), test.name),
+            Some(reason) => {
+                failed += 1;
+                println!("{} {}: {reason}", "FAIL".red().bold(), test.name);
+            }
+        }
+    }
+
+    println!("{} passed, {failed} failed", selftest::SUITE.len() - failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn bench(args: BenchArgs) -> Result<()> {
+    let input = match &args.input {
+        Some(path) => fs::read(path)?,
+        None => Vec::new(),
+    };
+
+    let mut steps = 0;
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..args.iterations {
+        let run = runner::run_headless(&args.file, &input, Some(args.max_steps), args.echo.echo())?;
+        steps += run.steps;
+        elapsed += run.elapsed;
+    }
+
+    let mips = steps as f64 / elapsed.as_secs_f64().max(f64::EPSILON) / 1e6;
+    println!(
+        "{} runs, {} instructions per run, {:.3?} per run, {mips:.2} MIPS",
+        args.iterations,
+        steps / u64::from(args.iterations.max(1)),
+        elapsed / args.iterations.max(1),
+    );
+
+    Ok(())
+}
+
+fn explore(mut args: ExploreArgs) -> Result<()> {
+    if let Some(project) = open_project(&mut args.images)? {
+        args.symbols = args.symbols.or(project.symbols);
+    }
+    let symbols = match &args.symbols {
+        Some(path) => SymbolTable::load(path)?,
+        None => SymbolTable::default(),
+    };
+    let target = utils::parse_number(&args.target)
+        .or_else(|| symbols.lookup(&args.target))
+        .ok_or_else(|| {
+            Error::with_message(
+                ErrorKind::ParseError,
+                format!("Unknown target `{}`", args.target),
+            )
+        })?;
+
+    let mut machine = Machine::default();
+    load_images(&mut machine, &args.images)?;
+
+    let limits = ExploreLimits {
+        alphabet: args.alphabet.into_bytes(),
+        max_inputs: args.max_inputs,
+        max_steps: args.max_steps,
+        max_paths: args.max_paths,
+    };
+    if args.symbolic {
+        let report = symbolic::explore(&machine, target, &limits);
+        for finding in &report.findings {
+            let what = match finding.kind {
+                FindingKind::Reached => format!("reaches {}", args.target),
+                FindingKind::IllegalOpcode => format!("illegal opcode at x{:04X}", finding.pc),
+                FindingKind::UnknownTrap => format!("unknown trap at x{:04X}", finding.pc),
+            };
+            println!("{:?}: {what}", String::from_utf8_lossy(&finding.input));
+        }
+        println!("{} paths explored", report.paths);
+        if report.truncated {
+            println!("Some paths were cut short by --max-inputs, --max-steps or --max-paths");
+        }
+        return Ok(());
+    }
+
+    let result = explore::explore(&machine, target, &limits);
+
+    for input in &result.reached {
+        println!("{:?}", String::from_utf8_lossy(input));
+    }
+    println!(
+        "{} of {} paths reached {}",
+        result.reached.len(),
+        result.paths,
+        args.target
+    );
+    if result.truncated {
+        println!("Some paths were cut short by --max-inputs, --max-steps or --max-paths");
+    }
 
     Ok(())
 }
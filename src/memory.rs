@@ -1,15 +1,16 @@
-use std::io::{self, Read};
+use std::{collections::BTreeSet, fmt, io, ops::RangeInclusive, sync::Arc};
 
 use colored::Colorize;
 use num_traits::ToPrimitive;
 
 use crate::{
-    constants::{MAX_MEMORY, PC_START},
-    enums::{MemMappedReg, Register},
+    constants::{DEVICE_START, MAX_MEMORY, PC_START},
+    enums::Register,
 };
 
 use ::std::io::Write;
 
+#[derive(Clone)]
 pub struct RegisterManager {
     registers: [u16; 11],
 }
@@ -45,56 +46,624 @@ impl RegisterManager {
         self.registers[sink.to_usize().unwrap()] = self.registers[src.to_usize().unwrap()];
     }
 
-    #[allow(clippy::explicit_counter_loop)]
+    /// R0-R7
+    pub fn general(&self) -> [u16; 8] {
+        let mut regs = [0; 8];
+        regs.copy_from_slice(&self.registers[..8]);
+        regs
+    }
+
     pub fn debug_all(&self) {
-        let mut i = 0;
-        for reg in &self.registers[..8] {
-            let formatted = format!("[R{i} = {reg:#x}]").yellow();
-            write!(io::stdout(), "{formatted}\r\n").expect("Failed to write to stdout");
-            i += 1;
+        self.print_registers(None);
+    }
+
+    /// Like [`RegisterManager::debug_all`], highlighting values that differ from
+    /// `prev` along with what they were, e.g. `R3 = x0006 (was x0005)`. PC
+    /// moves every step, so it's only highlighted
+    pub fn debug_changes(&self, prev: &RegisterManager) {
+        self.print_registers(Some(prev));
+    }
+
+    /// Condition flag as `N`, `Z` or `P`, `-` before any flag has been set
+    pub fn flag_name(&self) -> &'static str {
+        flag_name(self.get(Register::COND))
+    }
+
+    /// Registers (including PC and COND) that differ from `prev`, as (register, before, after)
+    pub fn changes(&self, prev: &RegisterManager) -> Vec<(Register, u16, u16)> {
+        Register::GENERAL
+            .into_iter()
+            .chain([Register::PC, Register::COND])
+            .map(|reg| (reg, prev.get(reg), self.get(reg)))
+            .filter(|(_, before, after)| before != after)
+            .collect()
+    }
+
+    /// The register dump laid out in rows, each cell paired with the index of its register
+    fn rows(&self) -> [Vec<(usize, String)>; 3] {
+        let cell = |i: usize| (i, format!("R{i} = x{:04X}", self.registers[i]));
+        let pc = Register::PC.to_usize().unwrap();
+        let cond = Register::COND.to_usize().unwrap();
+        [
+            (0..4).map(cell).collect(),
+            (4..8).map(cell).collect(),
+            vec![
+                (pc, format!("PC = x{:04X}", self.registers[pc])),
+                (cond, format!("COND = {}", self.flag_name())),
+            ],
+        ]
+    }
+
+    /// The register dump's rows, each cell marked with whether it differs
+    /// from `prev` and saying what it was if so
+    fn rows_since(&self, prev: Option<&RegisterManager>) -> [Vec<(bool, String)>; 3] {
+        let pc = Register::PC.to_usize().unwrap();
+        let cond = Register::COND.to_usize().unwrap();
+        self.rows().map(|row| {
+            row.into_iter()
+                .map(|(idx, cell)| match prev {
+                    Some(prev) if prev.registers[idx] != self.registers[idx] => {
+                        let was = prev.registers[idx];
+                        let cell = match idx {
+                            _ if idx == pc => cell,
+                            _ if idx == cond => format!("{cell} (was {})", flag_name(was)),
+                            _ => format!("{cell} (was x{was:04X})"),
+                        };
+                        (true, cell)
+                    }
+                    _ => (false, cell),
+                })
+                .collect()
+        })
+    }
+
+    fn print_registers(&self, prev: Option<&RegisterManager>) {
+        for row in self.rows_since(prev) {
+            let cells: Vec<_> = row
+                .into_iter()
+                .map(|(changed, cell)| {
+                    if changed {
+                        cell.red().bold().to_string()
+                    } else {
+                        cell.yellow().to_string()
+                    }
+                })
+                .collect();
+            write!(io::stderr(), "{}\r\n", cells.join("  ")).expect("Failed to write to stderr");
         }
-        let formatted = format!("[COND = {:#x}]", self.get(Register::COND)).yellow();
-        write!(io::stdout(), "{formatted} ").expect("Failed to write to stdout");
-        write!(io::stdout(), "\r\n").expect("Failed to write to stdout");
     }
 }
 
-pub struct MemoryManager {
-    memory: [u16; MAX_MEMORY],
+impl fmt::Display for RegisterManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, row) in self.rows().into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let cells: Vec<_> = row.into_iter().map(|(_, cell)| cell).collect();
+            write!(f, "{}", cells.join("  "))?;
+        }
+        Ok(())
+    }
 }
 
-impl Default for MemoryManager {
+impl fmt::Debug for RegisterManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut dbg = f.debug_struct("RegisterManager");
+        for (i, val) in self.general().iter().enumerate() {
+            dbg.field(&format!("r{i}"), &format_args!("{val:#06x}"));
+        }
+        dbg.field("pc", &format_args!("{:#06x}", self.get(Register::PC)))
+            .field("cond", &format_args!("{}", self.flag_name()))
+            .finish()
+    }
+}
+
+/// A window of memory formatted as rows of 8 words, see [`MemoryManager::view`]
+pub struct MemoryView<'a> {
+    mem: &'a MemoryManager,
+    start: u16,
+    len: u16,
+}
+
+impl fmt::Display for MemoryView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in (0..self.len).step_by(8) {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            let addr = self.start.wrapping_add(row);
+            write!(f, "x{addr:04X}:")?;
+            for offset in row..(row + 8).min(self.len) {
+                write!(
+                    f,
+                    " x{:04X}",
+                    self.mem.peek(self.start.wrapping_add(offset))
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MemoryView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MemoryView(x{:04X}, {})\n{self}", self.start, self.len)
+    }
+}
+
+/// COND register value as `N`, `Z` or `P`, `-` when no flag is set
+pub fn flag_name(cond: u16) -> &'static str {
+    match cond {
+        1 => "P",
+        2 => "Z",
+        4 => "N",
+        _ => "-",
+    }
+}
+
+/// One bit per address
+#[derive(Clone)]
+pub struct AddrSet(Box<[u64; MAX_MEMORY / 64]>);
+
+impl Default for AddrSet {
+    fn default() -> Self {
+        Self(Box::new([0; MAX_MEMORY / 64]))
+    }
+}
+
+impl AddrSet {
+    pub fn insert(&mut self, addr: u16) {
+        self.0[addr as usize / 64] |= 1 << (addr % 64);
+    }
+
+    pub fn remove(&mut self, addr: u16) {
+        self.0[addr as usize / 64] &= !(1 << (addr % 64));
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.0[addr as usize / 64] & (1 << (addr % 64)) != 0
+    }
+
+    /// Contiguous runs of addresses in the set, as inclusive (first, last) pairs
+    pub fn ranges(&self) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for addr in (0..=u16::MAX).filter(|addr| self.contains(*addr)) {
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == addr => *last = addr,
+                _ => ranges.push((addr, addr)),
+            }
+        }
+        ranges
+    }
+}
+
+pub(crate) const PAGE_SIZE: usize = 256;
+
+/// The 64K address space, split into pages allocated on first write.
+///
+/// Clones share pages until one side writes to them, so copies of a machine
+/// and checkpoints only cost the pages that differ.
+#[derive(Clone)]
+pub struct PagedMemory {
+    pages: Vec<Option<Arc<[u16; PAGE_SIZE]>>>,
+}
+
+impl Default for PagedMemory {
     fn default() -> Self {
         Self {
-            memory: [0; MAX_MEMORY],
+            pages: vec![None; MAX_MEMORY / PAGE_SIZE],
         }
     }
 }
 
+impl PagedMemory {
+    pub fn get(&self, addr: u16) -> u16 {
+        let addr = addr as usize;
+        self.pages[addr / PAGE_SIZE]
+            .as_ref()
+            .map_or(0, |page| page[addr % PAGE_SIZE])
+    }
+
+    pub fn set(&mut self, addr: u16, val: u16) {
+        let addr = addr as usize;
+        let page = self.pages[addr / PAGE_SIZE].get_or_insert_with(|| Arc::new([0; PAGE_SIZE]));
+        Arc::make_mut(page)[addr % PAGE_SIZE] = val;
+    }
+
+    /// Number of pages allocated so far
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.iter().filter(|page| page.is_some()).count()
+    }
+
+    /// Allocated pages, as (first address, words)
+    pub(crate) fn allocated(&self) -> impl Iterator<Item = (usize, &[u16; PAGE_SIZE])> {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, page)| Some((idx * PAGE_SIZE, page.as_deref()?)))
+    }
+}
+
+/// Whether an observed access was a read or a write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Called with the address, the value read or written, and the kind of access
+pub type Observer = Arc<dyn Fn(u16, u16, Access) + Send + Sync>;
+
+/// Handle returned by [`MemoryManager::observe`], to stop observing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObserverId(usize);
+
+#[derive(Clone, Default)]
+pub struct MemoryManager {
+    memory: PagedMemory,
+    /// Addresses whose reads by the program get recorded in `read_hits`
+    read_watch: BTreeSet<u16>,
+    read_hits: Vec<u16>,
+    /// Addresses the loader or the program has written, only tracked while
+    /// [`MemoryManager::check_uninit`] is on so plain machines stay cheap to clone
+    written: Option<AddrSet>,
+    uninit_reads: Vec<u16>,
+    /// Addresses instructions have been fetched from, see [`MemoryManager::mark_executed`]
+    executed: Option<AddrSet>,
+    code_writes: Vec<u16>,
+    /// Addresses the program can't write, see [`MemoryManager::protect`]
+    read_only: Option<AddrSet>,
+    read_only_writes: Vec<u16>,
+    /// Callbacks for program accesses to a range, see [`MemoryManager::observe`].
+    /// Clones share them
+    observers: Vec<(ObserverId, RangeInclusive<u16>, Observer)>,
+    next_observer: usize,
+}
+
 impl MemoryManager {
+    /// Data read performed by the program (loads, trap routines walking strings)
     pub fn read(&mut self, addr: u16) -> u16 {
-        if addr == MemMappedReg::Kbsr.to_u16().unwrap() {
-            let mut buf = [0; 1];
-            io::stdin().read_exact(&mut buf).unwrap();
-            if buf[0] != 0 {
-                self.write(MemMappedReg::Kbsr.to_u16().unwrap(), 1 << 15);
-                self.write(MemMappedReg::Kbdr.to_u16().unwrap(), buf[0] as u16);
-            } else {
-                self.write(MemMappedReg::Kbsr.to_u16().unwrap(), 0);
-            }
+        if !self.read_watch.is_empty() && self.read_watch.contains(&addr) {
+            self.read_hits.push(addr);
+        }
+        if addr < DEVICE_START && !self.is_initialized(addr) {
+            self.uninit_reads.push(addr);
+        }
+        let val = self.memory.get(addr);
+        self.notify(addr, val, Access::Read);
+        val
+    }
+
+    /// Whether `addr` has been written since the uninitialized read check was
+    /// turned on. Always true while it's off
+    pub fn is_initialized(&self, addr: u16) -> bool {
+        self.written
+            .as_ref()
+            .is_none_or(|written| written.contains(addr))
+    }
+
+    /// Record program reads of never-written memory (device registers excluded).
+    /// Turn it on before loading anything, earlier writes aren't known
+    pub fn check_uninit(&mut self, enabled: bool) {
+        if !enabled {
+            self.written = None;
+        } else if self.written.is_none() {
+            self.written = Some(AddrSet::default());
         }
+    }
+
+    /// Uninitialized addresses read since the last call, in the order they were read
+    pub fn take_uninit_reads(&mut self) -> Vec<u16> {
+        std::mem::take(&mut self.uninit_reads)
+    }
+
+    pub fn watch_reads(&mut self, addr: u16) {
+        self.read_watch.insert(addr);
+    }
 
-        self.memory[addr as usize]
+    pub fn unwatch_reads(&mut self, addr: u16) -> bool {
+        self.read_watch.remove(&addr)
+    }
+
+    /// Watched addresses read since the last call, in the order they were read
+    pub fn take_read_hits(&mut self) -> Vec<u16> {
+        std::mem::take(&mut self.read_hits)
+    }
+
+    /// `len` words starting at `start`, wrapping around the end of memory
+    pub fn view(&self, start: u16, len: u16) -> MemoryView<'_> {
+        MemoryView {
+            mem: self,
+            start,
+            len,
+        }
+    }
+
+    /// Reads a memory cell without triggering device side effects or read watches
+    pub fn peek(&self, addr: u16) -> u16 {
+        self.memory.get(addr)
     }
 
     pub fn write(&mut self, addr: u16, val: u16) {
-        self.memory[addr as usize] = val;
+        if self.is_read_only(addr) {
+            self.read_only_writes.push(addr);
+            return;
+        }
+        if self
+            .executed
+            .as_ref()
+            .is_some_and(|executed| executed.contains(addr))
+        {
+            self.code_writes.push(addr);
+        }
+        self.load(addr, val);
+        self.notify(addr, val, Access::Write);
+    }
+
+    /// Register update by a simulated device, e.g. KBSR getting a key. The
+    /// program didn't make it, so observers and checks don't see it
+    pub fn set_device(&mut self, addr: u16, val: u16) {
+        self.memory.set(addr, val);
+    }
+
+    /// Write performed by the loader, which may fill read-only regions
+    pub fn load(&mut self, addr: u16, val: u16) {
+        self.memory.set(addr, val);
+        if let Some(written) = self.written.as_mut() {
+            written.insert(addr);
+        }
+    }
+
+    /// Makes `first..=last` read-only: program writes there are dropped and
+    /// reported by [`MemoryManager::take_read_only_writes`]
+    pub fn protect(&mut self, first: u16, last: u16) {
+        let read_only = self.read_only.get_or_insert_with(AddrSet::default);
+        for addr in first..=last {
+            read_only.insert(addr);
+        }
+    }
+
+    pub fn is_read_only(&self, addr: u16) -> bool {
+        self.read_only
+            .as_ref()
+            .is_some_and(|read_only| read_only.contains(addr))
+    }
+
+    /// Dropped writes to read-only addresses since the last call, in the order they were attempted
+    pub fn take_read_only_writes(&mut self) -> Vec<u16> {
+        std::mem::take(&mut self.read_only_writes)
+    }
+
+    /// Records an instruction fetch from `addr`, so later writes there are
+    /// reported by [`MemoryManager::take_code_writes`]
+    pub fn mark_executed(&mut self, addr: u16) {
+        self.executed
+            .get_or_insert_with(AddrSet::default)
+            .insert(addr);
+    }
+
+    /// Already executed addresses written since the last call, in the order they were written
+    pub fn take_code_writes(&mut self) -> Vec<u16> {
+        std::mem::take(&mut self.code_writes)
+    }
+
+    /// Calls `callback` on every read and write the program makes in `range`,
+    /// so a visualizer (e.g. of a framebuffer) can follow it without scanning
+    /// memory every frame. Loads, peeks and dropped read-only writes aren't
+    /// reported. The callback runs on the interpreter's thread and should be quick
+    pub fn observe(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: impl Fn(u16, u16, Access) + Send + Sync + 'static,
+    ) -> ObserverId {
+        let id = ObserverId(self.next_observer);
+        self.next_observer += 1;
+        self.observers.push((id, range, Arc::new(callback)));
+        id
+    }
+
+    /// Stops calling an observer, returns whether it was registered
+    pub fn unobserve(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|(other, _, _)| *other != id);
+        self.observers.len() != len
+    }
+
+    fn notify(&self, addr: u16, val: u16, access: Access) {
+        for (_, range, callback) in &self.observers {
+            if range.contains(&addr) {
+                callback(addr, val, access);
+            }
+        }
+    }
+
+    /// The whole address space, e.g. for snapshotting
+    pub fn contents(&self) -> &PagedMemory {
+        &self.memory
+    }
+
+    /// Overwrites memory with a snapshot taken by [`MemoryManager::contents`]
+    pub fn restore(&mut self, contents: &PagedMemory) {
+        self.memory = contents.clone();
+    }
+
+    /// Runs of non-zero memory as (origin, words), the sparse form used for serialization
+    pub fn segments(&self) -> Vec<(u16, Vec<u16>)> {
+        let mut segments: Vec<(u16, Vec<u16>)> = Vec::new();
+        let words = self
+            .memory
+            .allocated()
+            .flat_map(|(start, page)| page.iter().enumerate().map(move |(i, w)| (start + i, *w)));
+        for (addr, word) in words {
+            if word == 0 {
+                continue;
+            }
+            match segments.last_mut() {
+                Some((origin, words)) if *origin as usize + words.len() == addr => words.push(word),
+                _ => segments.push((addr as u16, vec![word])),
+            }
+        }
+        segments
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{MemoryManager, RegisterManager};
+    use crate::enums::Register;
+
+    #[derive(Serialize, Deserialize)]
+    struct Registers {
+        r: [u16; 8],
+        pc: u16,
+        cond: u16,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Segment {
+        origin: u16,
+        words: Vec<u16>,
+    }
+
+    impl Serialize for RegisterManager {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            Registers {
+                r: self.general(),
+                pc: self.get(Register::PC),
+                cond: self.get(Register::COND),
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for RegisterManager {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let state = Registers::deserialize(deserializer)?;
+            let mut reg = RegisterManager::default();
+            for (reg_name, val) in Register::GENERAL.into_iter().zip(state.r) {
+                reg.set(reg_name, val);
+            }
+            reg.set(Register::PC, state.pc);
+            reg.set(Register::COND, state.cond);
+            Ok(reg)
+        }
+    }
+
+    /// Only non-zero runs of memory are stored, see [`MemoryManager::segments`]
+    impl Serialize for MemoryManager {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let segments: Vec<_> = self
+                .segments()
+                .into_iter()
+                .map(|(origin, words)| Segment { origin, words })
+                .collect();
+            segments.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for MemoryManager {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut mem = MemoryManager::default();
+            for segment in Vec::<Segment>::deserialize(deserializer)? {
+                for (addr, word) in (segment.origin..=u16::MAX).zip(segment.words) {
+                    mem.write(addr, word);
+                }
+            }
+            Ok(mem)
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_register_changes() {
+        let prev = RegisterManager::default();
+        let mut reg = prev.clone();
+        reg.set(Register::R3, 6);
+        reg.set(Register::COND, 1);
+
+        assert_eq!(
+            reg.changes(&prev),
+            vec![(Register::R3, 0, 6), (Register::COND, 0, 1)]
+        );
+        assert!(prev.changes(&prev).is_empty());
+    }
+
+    #[test]
+    fn test_rows_since() {
+        let mut prev = RegisterManager::default();
+        prev.set(Register::R3, 5);
+        prev.set(Register::COND, 2);
+        let mut reg = prev.clone();
+        reg.set(Register::R3, 6);
+        reg.set(Register::PC, 0x3001);
+        reg.set(Register::COND, 1);
+
+        let rows = reg.rows_since(Some(&prev));
+        assert_eq!(rows[0][2], (false, "R2 = x0000".to_string()));
+        assert_eq!(rows[0][3], (true, "R3 = x0006 (was x0005)".to_string()));
+        assert_eq!(rows[2][0], (true, "PC = x3001".to_string()));
+        assert_eq!(rows[2][1], (true, "COND = P (was Z)".to_string()));
+        assert!(reg
+            .rows_since(None)
+            .iter()
+            .flatten()
+            .all(|(changed, _)| !changed));
+    }
+
+    #[test]
+    fn test_paged_memory() {
+        let mut mem = PagedMemory::default();
+        assert_eq!(mem.get(0x3000), 0);
+        assert_eq!(mem.allocated_pages(), 0);
+
+        mem.set(0x3000, 1);
+        let snapshot = mem.clone();
+        mem.set(0x3001, 2);
+        mem.set(0x4000, 3);
+
+        assert_eq!(mem.allocated_pages(), 2);
+        assert_eq!((mem.get(0x3000), mem.get(0x3001)), (1, 2));
+        assert_eq!((snapshot.get(0x3000), snapshot.get(0x3001)), (1, 0));
+        assert_eq!(snapshot.allocated_pages(), 1);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut mem = MemoryManager::default();
+        mem.protect(0x3000, 0x3001);
+        mem.load(0x3000, 1);
+        mem.write(0x3001, 2);
+        mem.write(0x3002, 3);
+
+        assert_eq!(mem.peek(0x3000), 1);
+        assert_eq!(mem.peek(0x3001), 0);
+        assert_eq!(mem.peek(0x3002), 3);
+        assert_eq!(mem.take_read_only_writes(), vec![0x3001]);
+        assert!(mem.take_read_only_writes().is_empty());
+    }
+
+    #[test]
+    fn test_segments() {
+        let mut mem = MemoryManager::default();
+        mem.write(0x3000, 1);
+        mem.write(0x3001, 2);
+        mem.write(0x3002, 0);
+        mem.write(0x3003, 3);
+        mem.write(0xFFFF, 4);
+
+        assert_eq!(
+            mem.segments(),
+            vec![(0x3000, vec![1, 2]), (0x3003, vec![3]), (0xFFFF, vec![4])]
+        );
+    }
 
     #[test]
     fn test_register_api() {
@@ -126,5 +695,103 @@ mod tests {
 
         mem.write(0xffff, 0x7f);
         assert_eq!(mem.read(0xffff), 0x7f);
+
+        let snapshot = mem.contents().clone();
+        mem.write(0, 0);
+        mem.restore(&snapshot);
+        assert_eq!(mem.peek(0), 0x69);
+    }
+
+    #[test]
+    fn test_read_watch() {
+        let mut mem = MemoryManager::default();
+        mem.watch_reads(0x4000);
+
+        mem.read(0x3fff);
+        mem.peek(0x4000);
+        assert!(mem.take_read_hits().is_empty());
+
+        mem.read(0x4000);
+        mem.read(0x4000);
+        assert_eq!(mem.take_read_hits(), vec![0x4000, 0x4000]);
+        assert!(mem.take_read_hits().is_empty());
+
+        assert!(mem.unwatch_reads(0x4000));
+        mem.read(0x4000);
+        assert!(mem.take_read_hits().is_empty());
+    }
+
+    #[test]
+    fn test_observe() {
+        let mut mem = MemoryManager::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let id = mem.observe(0xC000..=0xC0FF, move |addr, val, access| {
+            sink.lock().unwrap().push((addr, val, access));
+        });
+
+        mem.write(0xC010, 7);
+        mem.read(0xC010);
+        mem.write(0xC100, 1);
+        mem.peek(0xC010);
+        mem.load(0xC020, 2);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(0xC010, 7, Access::Write), (0xC010, 7, Access::Read)]
+        );
+
+        assert!(mem.unobserve(id));
+        assert!(!mem.unobserve(id));
+        mem.write(0xC010, 8);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_uninit_reads() {
+        let mut mem = MemoryManager::default();
+        mem.check_uninit(true);
+        mem.write(0x3000, 0);
+
+        mem.read(0x3000);
+        mem.read(0xFE02);
+        mem.peek(0x4000);
+        assert!(mem.take_uninit_reads().is_empty());
+
+        mem.read(0x4000);
+        assert_eq!(mem.take_uninit_reads(), vec![0x4000]);
+        assert!(mem.is_initialized(0x3000));
+        assert!(!mem.is_initialized(0x3001));
+
+        // Nothing is tracked while the check is off
+        mem.check_uninit(false);
+        assert!(mem.written.is_none());
+        mem.read(0x4001);
+        assert!(mem.take_uninit_reads().is_empty());
+    }
+
+    #[test]
+    fn test_lazy_address_sets() {
+        let mut mem = MemoryManager::default();
+        mem.write(0x3000, 1);
+        mem.load(0x3001, 2);
+        assert!(mem.written.is_none() && mem.executed.is_none() && mem.read_only.is_none());
+        assert!(!mem.is_read_only(0x3000));
+
+        mem.protect(0x3000, 0x3000);
+        assert!(mem.is_read_only(0x3000) && !mem.is_read_only(0x3001));
+    }
+
+    #[test]
+    fn test_code_writes() {
+        let mut mem = MemoryManager::default();
+        mem.write(0x3000, 0x1021);
+        mem.mark_executed(0x3000);
+
+        mem.write(0x3001, 0);
+        assert!(mem.take_code_writes().is_empty());
+
+        mem.write(0x3000, 0x1022);
+        assert_eq!(mem.take_code_writes(), vec![0x3000]);
+        assert!(mem.take_code_writes().is_empty());
     }
 }
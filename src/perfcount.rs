@@ -0,0 +1,83 @@
+use crate::enums::MemMappedReg;
+
+/// Read-only performance counters, for programs to time their own routines:
+///
+/// - xFE1A / xFE1C: instructions executed, low and high word
+/// - xFE1E / xFE20: cycles, low and high word
+///
+/// Reading a low word latches the matching high word, so reading low then
+/// high gives a consistent 32-bit count. Cycles follow a simple model: one
+/// to execute each instruction plus one per memory access, fetch included,
+/// so ADD takes 2 cycles, LDR 3 and LDI 4. Stores to the counters are dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    cycles: u64,
+    /// High words as of the last read of the low ones
+    instr_high: u16,
+    cycle_high: u16,
+}
+
+impl PerfCounters {
+    pub fn is_counter(addr: u16) -> bool {
+        [
+            MemMappedReg::Icl,
+            MemMappedReg::Ich,
+            MemMappedReg::Ccl,
+            MemMappedReg::Cch,
+        ]
+        .into_iter()
+        .any(|reg| reg as u16 == addr)
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Value of the counter register at `addr` with `steps` instructions
+    /// executed, `None` for other addresses
+    pub fn read(&mut self, addr: u16, steps: u64) -> Option<u16> {
+        if addr == MemMappedReg::Icl as u16 {
+            self.instr_high = (steps >> 16) as u16;
+            Some(steps as u16)
+        } else if addr == MemMappedReg::Ich as u16 {
+            Some(self.instr_high)
+        } else if addr == MemMappedReg::Ccl as u16 {
+            self.cycle_high = (self.cycles >> 16) as u16;
+            Some(self.cycles as u16)
+        } else if addr == MemMappedReg::Cch as u16 {
+            Some(self.cycle_high)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latch() {
+        let mut counters = PerfCounters::default();
+        counters.tick(0x1_0005);
+        assert_eq!(
+            counters.read(MemMappedReg::Icl as u16, 0x2_FFFF),
+            Some(0xFFFF)
+        );
+        assert_eq!(counters.read(MemMappedReg::Ccl as u16, 0), Some(0x0005));
+
+        // The high words stay as latched until the low words are read again
+        counters.tick(0x1_0000);
+        assert_eq!(counters.read(MemMappedReg::Ich as u16, 0x3_0000), Some(2));
+        assert_eq!(counters.read(MemMappedReg::Cch as u16, 0), Some(1));
+        assert_eq!(counters.read(MemMappedReg::Ccl as u16, 0), Some(0x0005));
+        assert_eq!(counters.read(MemMappedReg::Cch as u16, 0), Some(2));
+
+        assert_eq!(counters.read(0xFE00, 0), None);
+        assert!(PerfCounters::is_counter(0xFE20) && !PerfCounters::is_counter(0xFE22));
+    }
+}
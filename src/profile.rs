@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use crate::{callstack::CallStack, symbols::SymbolTable};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub calls: u64,
+    /// Instructions executed in the routine itself
+    pub self_count: u64,
+    /// Instructions executed while the routine was on the call stack
+    pub total: u64,
+}
+
+/// Attributes executed instructions to subroutines using the shadow call stack.
+///
+/// Routines are identified by their entry address. Code running outside any
+/// subroutine is attributed to the address execution started at.
+#[derive(Default, Clone)]
+pub struct Profiler {
+    root: Option<u16>,
+    instructions: u64,
+    routines: BTreeMap<u16, RoutineStats>,
+    /// (caller, callee) -> number of calls
+    edges: BTreeMap<(u16, u16), u64>,
+    /// Instruction number a routine's total was last bumped at, so recursive
+    /// routines are only counted once per instruction
+    last_counted: HashMap<u16, u64>,
+}
+
+impl Profiler {
+    /// Records one instruction about to execute at `pc`
+    pub fn record(&mut self, call_stack: &CallStack, pc: u16) {
+        let root = *self.root.get_or_insert(pc);
+        let now = self.instructions;
+        self.instructions += 1;
+
+        let current = self.current(call_stack);
+        self.routines.entry(current).or_default().self_count += 1;
+
+        let active = call_stack
+            .frames()
+            .map(|frame| frame.entry)
+            .chain(std::iter::once(root));
+        for entry in active {
+            if self.last_counted.insert(entry, now) != Some(now) {
+                self.routines.entry(entry).or_default().total += 1;
+            }
+        }
+    }
+
+    /// Records a JSR/JSRR from whatever routine is on top of `call_stack` into `callee`
+    pub fn call(&mut self, call_stack: &CallStack, callee: u16) {
+        let caller = self.current(call_stack);
+        self.routines.entry(callee).or_default().calls += 1;
+        *self.edges.entry((caller, callee)).or_default() += 1;
+    }
+
+    pub fn routines(&self) -> &BTreeMap<u16, RoutineStats> {
+        &self.routines
+    }
+
+    /// Number of calls made along each (caller, callee) edge of the call graph
+    pub fn edges(&self) -> &BTreeMap<(u16, u16), u64> {
+        &self.edges
+    }
+
+    /// Flat profile followed by the call graph, like a gprof report
+    pub fn report(&self, symbols: &SymbolTable) -> String {
+        let name = |addr: u16| match symbols.name_at(addr) {
+            Some(name) => name.to_owned(),
+            None => format!("x{addr:04X}"),
+        };
+        let percent = |count: u64| count as f64 * 100.0 / self.instructions.max(1) as f64;
+
+        let mut flat: Vec<_> = self.routines.iter().collect();
+        flat.sort_by(|a, b| b.1.self_count.cmp(&a.1.self_count).then(a.0.cmp(b.0)));
+
+        let mut out = String::from("Flat profile:\n");
+        writeln!(
+            out,
+            "  {:>6}  {:>10}  {:>6}  {:>10}  {:>8}  routine",
+            "self%", "self", "total%", "total", "calls"
+        )
+        .unwrap();
+        for (addr, stats) in flat {
+            writeln!(
+                out,
+                "  {:>6.2}  {:>10}  {:>6.2}  {:>10}  {:>8}  {}",
+                percent(stats.self_count),
+                stats.self_count,
+                percent(stats.total),
+                stats.total,
+                stats.calls,
+                name(*addr)
+            )
+            .unwrap();
+        }
+
+        out.push_str("\nCall graph:\n");
+        for ((caller, callee), calls) in &self.edges {
+            writeln!(
+                out,
+                "  {} -> {}  {calls} calls",
+                name(*caller),
+                name(*callee)
+            )
+            .unwrap();
+        }
+
+        out
+    }
+
+    fn current(&self, call_stack: &CallStack) -> u16 {
+        call_stack
+            .top()
+            .map(|frame| frame.entry)
+            .or(self.root)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callstack::Frame;
+
+    #[test]
+    fn test_profile() {
+        let mut stack = CallStack::default();
+        let mut profiler = Profiler::default();
+
+        profiler.record(&stack, 0x3000);
+        profiler.call(&stack, 0x3100);
+        stack.call(Frame {
+            call_site: 0x3000,
+            entry: 0x3100,
+            return_addr: 0x3001,
+        });
+        profiler.record(&stack, 0x3100);
+        profiler.record(&stack, 0x3101);
+
+        // Recursive call, still counted once per instruction in the total
+        profiler.call(&stack, 0x3100);
+        stack.call(Frame {
+            call_site: 0x3101,
+            entry: 0x3100,
+            return_addr: 0x3102,
+        });
+        profiler.record(&stack, 0x3100);
+
+        stack.ret(0x3001);
+        profiler.record(&stack, 0x3001);
+
+        let main = profiler.routines()[&0x3000];
+        assert_eq!((main.calls, main.self_count, main.total), (0, 2, 5));
+        let sub = profiler.routines()[&0x3100];
+        assert_eq!((sub.calls, sub.self_count, sub.total), (2, 3, 3));
+        assert_eq!(profiler.edges()[&(0x3000, 0x3100)], 1);
+        assert_eq!(profiler.edges()[&(0x3100, 0x3100)], 1);
+
+        let mut symbols = SymbolTable::default();
+        symbols.insert("PRINT", 0x3100);
+        let report = profiler.report(&symbols);
+        assert!(report.contains("x3000 -> PRINT  1 calls"));
+        assert!(report.contains("PRINT -> PRINT  1 calls"));
+    }
+}
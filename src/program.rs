@@ -0,0 +1,320 @@
+use crate::{
+    asm::Assembly,
+    enums::{Register, TrapCode},
+    error::{Error, ErrorKind, Result},
+    instruction::{AluOperand, Instruction},
+    symbols::SymbolTable,
+};
+
+/// How a label is filled into a word that refers to it
+#[derive(Clone, Copy, Debug)]
+enum Fixup {
+    /// PC-relative offset in the low `bits` bits
+    Offset { bits: u32 },
+    /// The label's address, for `.FILL LABEL`
+    Address,
+}
+
+/// Builds an LC-3 program in Rust, for tests and examples that would
+/// otherwise hand-encode instructions. Methods are named after the assembly
+/// mnemonics; labels can be used before they are defined and are resolved
+/// by [`Program::assemble`].
+///
+/// ```
+/// use simulator::{enums::Register::*, program::Program};
+///
+/// let program = Program::new(0x3000)
+///     .and(R1, R1, 0)
+///     .add(R1, R1, 3)
+///     .label("LOOP")
+///     .add(R1, R1, -1)
+///     .br("p", "LOOP")
+///     .halt()
+///     .assemble()
+///     .unwrap();
+/// assert_eq!(program.words, [0x5260, 0x1263, 0x127F, 0x03FE, 0xF025]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Program {
+    origin: u16,
+    words: Vec<u16>,
+    labels: Vec<(String, u16)>,
+    /// Words referring to a label: their index, the label and how it is filled in
+    fixups: Vec<(usize, String, Fixup)>,
+}
+
+impl Program {
+    pub fn new(origin: u16) -> Self {
+        Self {
+            origin,
+            words: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        }
+    }
+
+    /// Address of the next word
+    pub fn here(&self) -> u16 {
+        self.origin.wrapping_add(self.words.len() as u16)
+    }
+
+    /// Names the address of the next word
+    pub fn label(mut self, name: &str) -> Self {
+        let addr = self.here();
+        self.labels.push((name.to_owned(), addr));
+        self
+    }
+
+    pub fn instr(mut self, instr: Instruction) -> Self {
+        self.words.push(instr.encode());
+        self
+    }
+
+    /// `instr` with its PC-relative offset pointing at `label`
+    fn pointing_at(mut self, instr: Instruction, label: &str, bits: u32) -> Self {
+        self.fixups
+            .push((self.words.len(), label.to_owned(), Fixup::Offset { bits }));
+        self.instr(instr)
+    }
+
+    pub fn add(self, dst: Register, src1: Register, src2: impl Into<AluOperand>) -> Self {
+        self.instr(Instruction::Add {
+            dst,
+            src1,
+            src2: src2.into(),
+        })
+    }
+
+    pub fn and(self, dst: Register, src1: Register, src2: impl Into<AluOperand>) -> Self {
+        self.instr(Instruction::And {
+            dst,
+            src1,
+            src2: src2.into(),
+        })
+    }
+
+    pub fn not(self, dst: Register, src: Register) -> Self {
+        self.instr(Instruction::Not { dst, src })
+    }
+
+    /// Branch to `label` on the condition codes in `nzp`, e.g. `"nz"`, or
+    /// always when empty like a bare `BR`
+    pub fn br(self, nzp: &str, label: &str) -> Self {
+        let nzp = match nzp {
+            "" => 0b111,
+            _ => [('n', 0b100), ('z', 0b010), ('p', 0b001)]
+                .into_iter()
+                .filter(|(flag, _)| nzp.contains(*flag))
+                .fold(0, |acc, (_, bit)| acc | bit),
+        };
+        self.pointing_at(Instruction::Br { nzp, offset: 0 }, label, 9)
+    }
+
+    pub fn jmp(self, base: Register) -> Self {
+        self.instr(Instruction::Jmp { base })
+    }
+
+    pub fn ret(self) -> Self {
+        self.jmp(Register::R7)
+    }
+
+    pub fn jsr(self, label: &str) -> Self {
+        self.pointing_at(Instruction::Jsr { offset: 0 }, label, 11)
+    }
+
+    pub fn jsrr(self, base: Register) -> Self {
+        self.instr(Instruction::Jsrr { base })
+    }
+
+    pub fn ld(self, dst: Register, label: &str) -> Self {
+        self.pointing_at(Instruction::Ld { dst, offset: 0 }, label, 9)
+    }
+
+    pub fn ldi(self, dst: Register, label: &str) -> Self {
+        self.pointing_at(Instruction::Ldi { dst, offset: 0 }, label, 9)
+    }
+
+    pub fn ldr(self, dst: Register, base: Register, offset: i16) -> Self {
+        self.instr(Instruction::Ldr { dst, base, offset })
+    }
+
+    pub fn lea(self, dst: Register, label: &str) -> Self {
+        self.pointing_at(Instruction::Lea { dst, offset: 0 }, label, 9)
+    }
+
+    pub fn st(self, src: Register, label: &str) -> Self {
+        self.pointing_at(Instruction::St { src, offset: 0 }, label, 9)
+    }
+
+    pub fn sti(self, src: Register, label: &str) -> Self {
+        self.pointing_at(Instruction::Sti { src, offset: 0 }, label, 9)
+    }
+
+    pub fn str(self, src: Register, base: Register, offset: i16) -> Self {
+        self.instr(Instruction::Str { src, base, offset })
+    }
+
+    pub fn rti(self) -> Self {
+        self.instr(Instruction::Rti)
+    }
+
+    pub fn trap(self, vector: u8) -> Self {
+        self.instr(Instruction::Trap { vector })
+    }
+
+    pub fn getc(self) -> Self {
+        self.trap(TrapCode::GetC as u8)
+    }
+
+    pub fn out(self) -> Self {
+        self.trap(TrapCode::Out as u8)
+    }
+
+    pub fn puts(self) -> Self {
+        self.trap(TrapCode::Puts as u8)
+    }
+
+    pub fn halt(self) -> Self {
+        self.trap(TrapCode::Halt as u8)
+    }
+
+    /// `.FILL`
+    pub fn fill(mut self, word: u16) -> Self {
+        self.words.push(word);
+        self
+    }
+
+    /// `.FILL LABEL`, the address of `label`
+    pub fn fill_label(mut self, label: &str) -> Self {
+        self.fixups
+            .push((self.words.len(), label.to_owned(), Fixup::Address));
+        self.fill(0)
+    }
+
+    /// `.BLKW`, `count` zero words
+    pub fn blkw(mut self, count: usize) -> Self {
+        self.words.resize(self.words.len() + count, 0);
+        self
+    }
+
+    /// `.STRINGZ`, one character per word and a NUL
+    pub fn stringz(mut self, s: &str) -> Self {
+        self.words.extend(s.bytes().map(u16::from));
+        self.fill(0)
+    }
+
+    /// Resolves labels, failing on undefined or duplicate labels and on
+    /// labels too far away for the instruction referring to them
+    pub fn assemble(self) -> Result<Assembly> {
+        let mut symbols = SymbolTable::default();
+        for (name, addr) in &self.labels {
+            if symbols.lookup(name).is_some() {
+                return Err(program_error(format!("duplicate label `{name}`")));
+            }
+            symbols.insert(name, *addr);
+        }
+
+        let mut words = self.words;
+        for (idx, label, fixup) in self.fixups {
+            let target = symbols
+                .lookup(&label)
+                .ok_or_else(|| program_error(format!("undefined label `{label}`")))?;
+            let addr = self.origin.wrapping_add(idx as u16);
+            words[idx] |= match fixup {
+                Fixup::Offset { bits } => {
+                    let offset = target as i32 - (addr as i32 + 1);
+                    let limit = 1 << (bits - 1);
+                    if !(-limit..limit).contains(&offset) {
+                        return Err(program_error(format!(
+                            "label `{label}` is too far from x{addr:04X}"
+                        )));
+                    }
+                    offset as u16 & ((1 << bits) - 1)
+                }
+                Fixup::Address => target,
+            };
+        }
+
+        Ok(Assembly {
+            origin: self.origin,
+            words,
+            symbols,
+        })
+    }
+}
+
+fn program_error(msg: String) -> Error {
+    Error::with_message(ErrorKind::ParseError, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, enums::Register::*};
+
+    #[test]
+    fn test_program() {
+        let program = Program::new(0x3000)
+            .lea(R0, "HELLO")
+            .puts()
+            .ld(R1, "COUNT")
+            .label("LOOP")
+            .add(R1, R1, -1)
+            .br("p", "LOOP")
+            .jsr("SUB")
+            .halt()
+            .label("SUB")
+            .and(R2, R2, R1)
+            .not(R3, R2)
+            .ldr(R4, R6, -2)
+            .str(R4, R6, 5)
+            .ret()
+            .label("COUNT")
+            .fill(10)
+            .fill_label("LOOP")
+            .label("HELLO")
+            .stringz("Hi")
+            .assemble()
+            .unwrap();
+
+        let src = r#"
+            .ORIG x3000
+                    LEA R0, HELLO
+                    PUTS
+                    LD R1, COUNT
+            LOOP    ADD R1, R1, #-1
+                    BRp LOOP
+                    JSR SUB
+                    HALT
+            SUB     AND R2, R2, R1
+                    NOT R3, R2
+                    LDR R4, R6, #-2
+                    STR R4, R6, #5
+                    RET
+            COUNT   .FILL #10
+                    .FILL LOOP
+            HELLO   .STRINGZ "Hi"
+            .END
+        "#;
+        let expected = assemble(src).unwrap();
+        assert_eq!(program.origin, 0x3000);
+        assert_eq!(program.words, expected.words);
+        assert_eq!(program.symbols.lookup("SUB"), Some(0x3007));
+    }
+
+    #[test]
+    fn test_program_errors() {
+        assert!(Program::new(0x3000).br("", "NOWHERE").assemble().is_err());
+        assert!(Program::new(0x3000)
+            .label("A")
+            .label("A")
+            .assemble()
+            .is_err());
+        assert!(Program::new(0x3000)
+            .ld(R0, "FAR")
+            .blkw(300)
+            .label("FAR")
+            .assemble()
+            .is_err());
+    }
+}
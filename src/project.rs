@@ -0,0 +1,522 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    asm,
+    checks::MemRegion,
+    error::{Error, ErrorKind, Result},
+    ext::Extensions,
+    runner::GoldenTest,
+    vm::Machine,
+};
+
+/// Name of the manifest looked for when a project is given as a directory
+pub const MANIFEST: &str = "lc3.toml";
+
+/// A project described by an `lc3.toml` manifest, so `run` and `test` don't
+/// need every file and setting spelled out on the command line:
+///
+/// ```toml
+/// [program]
+/// source = "lab.asm"      # assembled into `object` when newer than it
+/// object = "lab.obj"      # defaults to the source's .obj
+/// symbols = "lab.sym"
+/// listing = "lab.lst"
+/// os = ["os.obj"]
+///
+/// [devices]
+/// fps = 30
+/// ext = "shf,xor"
+/// arith_traps = true
+/// io_traps = true
+/// hypercalls = true
+/// perf_counters = true
+/// banks = 4
+/// bank_window = "xC000-xDFFF"
+///
+/// [[test]]
+/// name = "adds two numbers"
+/// input = "tests/add.in"
+/// expected = "tests/add.out"
+/// max_steps = 50000      # fails when it takes more instructions
+/// ```
+///
+/// Paths are relative to the manifest's directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Project {
+    pub source: Option<PathBuf>,
+    pub object: PathBuf,
+    pub symbols: Option<PathBuf>,
+    pub listing: Option<PathBuf>,
+    pub os: Vec<PathBuf>,
+    pub devices: Devices,
+    pub tests: Vec<ProjectTest>,
+}
+
+/// The `[devices]` table, applied over the command line's settings
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Devices {
+    pub fps: Option<u32>,
+    pub ext: Option<Extensions>,
+    pub arith_traps: bool,
+    pub io_traps: bool,
+    pub hypercalls: bool,
+    pub perf_counters: bool,
+    pub banks: Option<u16>,
+    pub bank_window: Option<MemRegion>,
+}
+
+/// A `[[test]]` entry, run like a golden test of the project's object file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProjectTest {
+    pub name: String,
+    pub input: Option<PathBuf>,
+    pub expected: Option<PathBuf>,
+    pub max_steps: Option<u64>,
+}
+
+impl Project {
+    /// Reads the manifest at `path`, or `path/lc3.toml` for a directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let path = if path.is_dir() {
+            path.join(MANIFEST)
+        } else {
+            path.to_owned()
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&fs::read_to_string(&path)?, dir)
+    }
+
+    /// Parses a manifest whose paths are relative to `dir`
+    pub fn parse(src: &str, dir: &Path) -> Result<Self> {
+        let mut project = Self::default();
+        let mut object = None;
+
+        for table in parse_toml(src)? {
+            match (table.header.as_str(), table.array) {
+                ("program", false) => {
+                    for (key, value, line) in table.entries {
+                        match key.as_str() {
+                            "source" => project.source = Some(dir.join(value.string(line)?)),
+                            "object" => object = Some(dir.join(value.string(line)?)),
+                            "symbols" => project.symbols = Some(dir.join(value.string(line)?)),
+                            "listing" => project.listing = Some(dir.join(value.string(line)?)),
+                            "os" => {
+                                for path in value.strings(line)? {
+                                    project.os.push(dir.join(path));
+                                }
+                            }
+                            _ => return Err(unknown_key("program", &key, line)),
+                        }
+                    }
+                }
+                ("devices", false) => {
+                    let devices = &mut project.devices;
+                    for (key, value, line) in table.entries {
+                        match key.as_str() {
+                            "fps" => devices.fps = Some(value.int(line)?),
+                            "ext" => devices.ext = Some(value.parsed(line)?),
+                            "arith_traps" => devices.arith_traps = value.bool(line)?,
+                            "io_traps" => devices.io_traps = value.bool(line)?,
+                            "hypercalls" => devices.hypercalls = value.bool(line)?,
+                            "perf_counters" => devices.perf_counters = value.bool(line)?,
+                            "banks" => devices.banks = Some(value.int(line)?),
+                            "bank_window" => devices.bank_window = Some(value.parsed(line)?),
+                            _ => return Err(unknown_key("devices", &key, line)),
+                        }
+                    }
+                }
+                ("test", true) => {
+                    let mut test = ProjectTest {
+                        name: format!("test {}", project.tests.len() + 1),
+                        input: None,
+                        expected: None,
+                        max_steps: None,
+                    };
+                    for (key, value, line) in table.entries {
+                        match key.as_str() {
+                            "name" => test.name = value.string(line)?,
+                            "input" => test.input = Some(dir.join(value.string(line)?)),
+                            "expected" => test.expected = Some(dir.join(value.string(line)?)),
+                            "max_steps" => test.max_steps = Some(value.int(line)?),
+                            _ => return Err(unknown_key("test", &key, line)),
+                        }
+                    }
+                    project.tests.push(test);
+                }
+                ("", false) if table.entries.is_empty() => (),
+                (header, _) => {
+                    return Err(manifest_error(
+                        table.line,
+                        format!("unexpected table `{header}`"),
+                    ))
+                }
+            }
+        }
+
+        project.object = match (object, &project.source) {
+            (Some(object), _) => object,
+            (None, Some(source)) => source.with_extension("obj"),
+            (None, None) => {
+                return Err(manifest_error(
+                    0,
+                    "[program] needs an object or a source".to_owned(),
+                ))
+            }
+        };
+        Ok(project)
+    }
+
+    /// Assembles the source, if any, when the object file is missing or older
+    pub fn build(&self) -> Result<()> {
+        let Some(source) = &self.source else {
+            return Ok(());
+        };
+        let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if modified(&self.object).is_some_and(|object| Some(object) >= modified(source)) {
+            return Ok(());
+        }
+        asm::assemble_file(source, &self.object)
+    }
+
+    /// The `[[test]]` entries as golden tests of the object file
+    pub fn golden_tests(&self) -> Vec<GoldenTest> {
+        self.tests
+            .iter()
+            .map(|test| GoldenTest {
+                name: test.name.clone(),
+                image: self.object.clone(),
+                input: test.input.clone(),
+                expected: test.expected.clone(),
+                max_steps: test.max_steps,
+                os: self.os.clone(),
+                devices: self.devices.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Devices {
+    /// Sets up the machine's devices as the manifest asks, after the command line
+    pub fn apply(&self, machine: &mut Machine) {
+        if let Some(fps) = self.fps {
+            machine.set_fps(fps);
+        }
+        if let Some(ext) = self.ext {
+            machine.set_extensions(ext);
+        }
+        if self.arith_traps {
+            machine.enable_arith_traps(true);
+        }
+        if self.io_traps {
+            machine.enable_io_traps(true);
+        }
+        if self.hypercalls {
+            machine.enable_hypercalls();
+        }
+        if self.perf_counters {
+            machine.enable_perf_counters(true);
+        }
+        if let Some(count) = self.banks {
+            let window = self.bank_window.unwrap_or(MemRegion {
+                first: 0xC000,
+                last: 0xDFFF,
+            });
+            machine.enable_banking(count, window);
+        }
+    }
+}
+
+fn manifest_error(line: usize, msg: String) -> Error {
+    let msg = match line {
+        0 => format!("{MANIFEST}: {msg}"),
+        line => format!("{MANIFEST} line {line}: {msg}"),
+    };
+    Error::with_message(ErrorKind::ParseError, msg)
+}
+
+fn unknown_key(table: &str, key: &str, line: usize) -> Error {
+    manifest_error(line, format!("unknown key `{key}` in [{table}]"))
+}
+
+/// A value in the subset of TOML manifests use
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn string(self, line: usize) -> Result<String> {
+        match self {
+            Self::Str(s) => Ok(s),
+            _ => Err(manifest_error(line, "expected a string".to_owned())),
+        }
+    }
+
+    fn strings(self, line: usize) -> Result<Vec<String>> {
+        match self {
+            Self::Array(values) => values.into_iter().map(|v| v.string(line)).collect(),
+            _ => Err(manifest_error(line, "expected an array".to_owned())),
+        }
+    }
+
+    fn int<T: TryFrom<i64>>(self, line: usize) -> Result<T> {
+        match self {
+            Self::Int(n) => {
+                T::try_from(n).map_err(|_| manifest_error(line, format!("{n} is out of range")))
+            }
+            _ => Err(manifest_error(line, "expected a number".to_owned())),
+        }
+    }
+
+    fn bool(self, line: usize) -> Result<bool> {
+        match self {
+            Self::Bool(b) => Ok(b),
+            _ => Err(manifest_error(line, "expected true or false".to_owned())),
+        }
+    }
+
+    /// A string parsed like the matching command line option
+    fn parsed<T: std::str::FromStr<Err = String>>(self, line: usize) -> Result<T> {
+        self.string(line)?
+            .parse()
+            .map_err(|e| manifest_error(line, e))
+    }
+}
+
+/// Entries under a `[header]`, or `[[header]]` when `array`. The entries
+/// before the first header have an empty one
+struct Table {
+    header: String,
+    array: bool,
+    line: usize,
+    entries: Vec<(String, Value, usize)>,
+}
+
+/// Parses tables of `key = value` lines with strings, integers, booleans and
+/// single-line arrays, which is all a manifest needs
+fn parse_toml(src: &str) -> Result<Vec<Table>> {
+    let mut tables = vec![Table {
+        header: String::new(),
+        array: false,
+        line: 0,
+        entries: Vec::new(),
+    }];
+
+    for (idx, text) in src.lines().enumerate() {
+        let line = idx + 1;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = text.strip_prefix('[') {
+            let (array, rest) = match rest.strip_prefix('[') {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            let close = if array { "]]" } else { "]" };
+            let Some((header, trailing)) = rest.split_once(close) else {
+                return Err(manifest_error(line, format!("missing `{close}`")));
+            };
+            if !is_comment(trailing) {
+                return Err(manifest_error(
+                    line,
+                    "unexpected text after the header".to_owned(),
+                ));
+            }
+            tables.push(Table {
+                header: header.trim().to_owned(),
+                array,
+                line,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some((key, mut rest)) = text.split_once('=') else {
+            return Err(manifest_error(line, "expected `key = value`".to_owned()));
+        };
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(manifest_error(line, format!("bad key `{key}`")));
+        }
+        let value =
+            parse_value(&mut rest).ok_or_else(|| manifest_error(line, "bad value".to_owned()))?;
+        if !is_comment(rest) {
+            return Err(manifest_error(
+                line,
+                "unexpected text after the value".to_owned(),
+            ));
+        }
+        tables
+            .last_mut()
+            .unwrap()
+            .entries
+            .push((key.to_owned(), value, line));
+    }
+
+    Ok(tables)
+}
+
+fn is_comment(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.starts_with('#')
+}
+
+/// Parses a value at the start of `s`, leaving the rest in `s`
+fn parse_value(s: &mut &str) -> Option<Value> {
+    *s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        loop {
+            match chars.next()? {
+                (end, '"') => {
+                    *s = &rest[end + 1..];
+                    return Some(Value::Str(value));
+                }
+                (_, '\\') => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                }),
+                (_, c) => value.push(c),
+            }
+        }
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        *s = rest;
+        let mut values = Vec::new();
+        loop {
+            *s = s.trim_start();
+            if let Some(rest) = s.strip_prefix(']') {
+                *s = rest;
+                return Some(Value::Array(values));
+            }
+            values.push(parse_value(s)?);
+            *s = s.trim_start();
+            match s.strip_prefix(',') {
+                Some(rest) => *s = rest,
+                None if s.starts_with(']') => (),
+                None => return None,
+            }
+        }
+    }
+
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+'))
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    *s = rest;
+    match word {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => {
+            let digits = word.replace('_', "");
+            let n = match digits.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                None => digits.parse().ok()?,
+            };
+            Some(Value::Int(n))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST_SRC: &str = r#"
+# Lab 3
+[program]
+source = "lab.asm"
+os = ["os.obj", "traps.obj"]   # loaded first
+
+[devices]
+fps = 30
+ext = "shf,xor"
+hypercalls = true
+banks = 4
+
+[[test]]
+name = "adds \"two\" numbers"
+input = "tests/add.in"
+expected = "tests/add.out"
+max_steps = 50000
+
+[[test]]
+input = "tests/empty.in"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let project = Project::parse(MANIFEST_SRC, Path::new("proj")).unwrap();
+        assert_eq!(project.source, Some(PathBuf::from("proj/lab.asm")));
+        assert_eq!(project.object, PathBuf::from("proj/lab.obj"));
+        assert_eq!(
+            project.os,
+            [
+                PathBuf::from("proj/os.obj"),
+                PathBuf::from("proj/traps.obj")
+            ]
+        );
+        assert_eq!(project.devices.fps, Some(30));
+        assert_eq!(project.devices.ext, Some("shf,xor".parse().unwrap()));
+        assert!(project.devices.hypercalls && !project.devices.io_traps);
+        assert_eq!(project.devices.banks, Some(4));
+
+        let tests = project.golden_tests();
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].name, "adds \"two\" numbers");
+        assert_eq!(tests[0].image, PathBuf::from("proj/lab.obj"));
+        assert_eq!(tests[0].max_steps, Some(50000));
+        assert_eq!(tests[1].name, "test 2");
+        assert_eq!(tests[1].expected, None);
+        assert_eq!(tests[1].max_steps, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |src: &str| Project::parse(src, Path::new(".")).unwrap_err();
+        assert!(parse("[program]\nobject = \"a.obj\"\nfsp = 3")
+            .message()
+            .contains("line 3: unknown key `fsp`"));
+        assert!(parse("[devices]\nfps = \"fast\"")
+            .message()
+            .contains("line 2: expected a number"));
+        assert!(parse("[program]\nobject = \"a.obj\" junk")
+            .message()
+            .contains("line 2"));
+        assert!(parse("[devices]\nfps = 30")
+            .message()
+            .contains("needs an object"));
+    }
+
+    #[test]
+    fn test_build() {
+        let dir = std::env::temp_dir().join("simulator_test_project");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(MANIFEST), "[program]\nsource = \"prog.asm\"\n").unwrap();
+        fs::write(dir.join("prog.asm"), ".ORIG x3000\nHALT\n.END\n").unwrap();
+
+        let project = Project::load(&dir).unwrap();
+        project.build().unwrap();
+        assert_eq!(
+            fs::read(dir.join("prog.obj")).unwrap(),
+            [0x30, 0x00, 0xF0, 0x25]
+        );
+        assert!(dir.join("prog.sym").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt::Write;
+
+use crate::{
+    enums::Register, error::trap_name, memory::flag_name, runner::TestResult, utils::json_escape,
+    vm::Machine,
+};
+
+/// Golden test results as a JUnit XML test suite, the format CI servers
+/// (GitHub Actions, GitLab, Jenkins) show test results from. Instruction
+/// counts go in a `steps` property of each test case.
+pub fn junit_xml(results: &[TestResult]) -> String {
+    let failures = results.iter().filter(|result| !result.passed()).count();
+    let time: f64 = results.iter().map(|r| r.elapsed.as_secs_f64()).sum();
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        out,
+        "<testsuite name=\"lc3\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" time=\"{time:.6}\">",
+        results.len()
+    )
+    .unwrap();
+    for result in results {
+        writeln!(
+            out,
+            "  <testcase name=\"{}\" classname=\"lc3\" time=\"{:.6}\">",
+            xml_escape(&result.name),
+            result.elapsed.as_secs_f64()
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    <properties><property name=\"steps\" value=\"{}\"/></properties>",
+            result.steps
+        )
+        .unwrap();
+        if let Some(failure) = &result.failure {
+            let kind = if result.timed_out {
+                "timeout"
+            } else {
+                "mismatch"
+            };
+            let message = failure.lines().next().unwrap_or_default();
+            writeln!(
+                out,
+                "    <failure type=\"{kind}\" message=\"{}\">{}</failure>",
+                xml_escape(message),
+                xml_escape(failure)
+            )
+            .unwrap();
+        }
+        out.push_str("  </testcase>\n");
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+/// Golden test results as JSON. Each test has the `name`, `score`,
+/// `max_score`, `status` and `output` fields Gradescope's autograder reads,
+/// plus `steps`, `time` (seconds) and `timed_out`.
+pub fn json(results: &[TestResult]) -> String {
+    let tests: Vec<_> = results
+        .iter()
+        .map(|result| {
+            format!(
+                "{{\"name\":\"{}\",\"score\":{},\"max_score\":1,\"status\":\"{}\",\"output\":\"{}\",\"steps\":{},\"time\":{:.6},\"timed_out\":{}}}",
+                json_escape(&result.name),
+                u8::from(result.passed()),
+                if result.passed() { "passed" } else { "failed" },
+                json_escape(result.failure.as_deref().unwrap_or_default()),
+                result.steps,
+                result.elapsed.as_secs_f64(),
+                result.timed_out,
+            )
+        })
+        .collect();
+
+    let passed = results.iter().filter(|result| result.passed()).count();
+    format!(
+        "{{\"passed\":{passed},\"failed\":{},\"tests\":[\n{}\n]}}\n",
+        results.len() - passed,
+        tests.join(",\n")
+    )
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' | '\t' => out.push(ch),
+            // Not allowed in XML 1.0, even escaped
+            ch if ch.is_control() => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// End-of-run report for scripts and graders: why the run ended, the
+/// instruction count, wall time (seconds), final registers, how often each
+/// trap was used and the warnings printed
+pub fn summary_json(machine: &Machine) -> String {
+    let reason = machine
+        .exit_reason()
+        .map_or("not run", |reason| reason.as_str());
+    let fault = match machine.fault() {
+        Some(fault) => format!("\"{}\"", json_escape(&fault.reason)),
+        None => "null".to_owned(),
+    };
+    let reg = machine.reg();
+    let registers: Vec<_> = Register::GENERAL
+        .into_iter()
+        .chain([Register::PC])
+        .map(|r| format!("\"{r:?}\":{}", reg.get(r)))
+        .chain([format!(
+            "\"COND\":\"{}\"",
+            flag_name(reg.get(Register::COND))
+        )])
+        .collect();
+    let traps: Vec<_> = machine
+        .trap_counts()
+        .iter()
+        .map(|(vector, count)| format!("\"{}\":{count}", trap_name(*vector)))
+        .collect();
+    let warnings: Vec<_> = machine
+        .warnings()
+        .iter()
+        .map(|warning| format!("\"{}\"", json_escape(warning)))
+        .collect();
+
+    format!(
+        "{{\"exit_reason\":\"{reason}\",\"fault\":{fault},\"steps\":{},\"time\":{:.6},\"registers\":{{{}}},\"traps\":{{{}}},\"warnings\":[{}]}}\n",
+        machine.step_count(),
+        machine.run_time().as_secs_f64(),
+        registers.join(","),
+        traps.join(","),
+        warnings.join(",")
+    )
+}
+
+/// End-of-run statistics for `--stats`: the instruction count and how often
+/// each trap was used
+pub fn stats(machine: &Machine) -> String {
+    let mut out = format!("Instructions: {}\nTraps:\n", machine.step_count());
+    if machine.trap_counts().is_empty() {
+        out.push_str("  none\n");
+    }
+    for (vector, count) in machine.trap_counts() {
+        writeln!(out, "  {:<8}  {count:>10}", trap_name(*vector)).unwrap();
+    }
+    out
+}
+
+/// One line on why the run ended, printed after every `simulator run`,
+/// e.g. `Exit: halted after 1234 instructions in 0.015s`
+pub fn exit_summary(machine: &Machine) -> String {
+    let reason = machine
+        .exit_reason()
+        .map_or("not run", |reason| reason.as_str());
+    format!(
+        "Exit: {reason} after {} instructions in {:.3}s\n",
+        machine.step_count(),
+        machine.run_time().as_secs_f64()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+    use std::time::Duration;
+
+    fn results() -> Vec<TestResult> {
+        vec![
+            TestResult {
+                name: "tests/hello.obj".to_owned(),
+                failure: None,
+                timed_out: false,
+                steps: 3,
+                elapsed: Duration::from_millis(1),
+            },
+            TestResult {
+                name: "tests/loop.obj".to_owned(),
+                failure: Some("output mismatch\n--- expected\n<a>\n--- actual\n\"b\"".to_owned()),
+                timed_out: false,
+                steps: 10,
+                elapsed: Duration::from_millis(2),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_exit_summary() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        assert!(exit_summary(&test_mach).starts_with("Exit: not run after 0 instructions in "));
+        // ADD R1, R1, #1; HALT
+        test_mach.load_words(0x3000, &[0x1261, 0xF025]).unwrap();
+        test_mach.run().unwrap();
+        let summary = exit_summary(&test_mach);
+        assert!(summary.starts_with("Exit: halted after 2 instructions in "));
+        assert!(summary.ends_with("s\n"));
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let xml = junit_xml(&results());
+        assert!(xml.contains(
+            "<testsuite name=\"lc3\" tests=\"2\" failures=\"1\" errors=\"0\" time=\"0.003000\">"
+        ));
+        assert!(xml.contains("<property name=\"steps\" value=\"10\"/>"));
+        assert!(xml.contains(
+            "<failure type=\"mismatch\" message=\"output mismatch\">output mismatch\n--- expected\n&lt;a&gt;\n"
+        ));
+        assert_eq!(xml.matches("<failure").count(), 1);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = json(&results());
+        let lines: Vec<_> = json.lines().collect();
+        assert_eq!(lines[0], "{\"passed\":1,\"failed\":1,\"tests\":[");
+        assert_eq!(
+            lines[1],
+            "{\"name\":\"tests/hello.obj\",\"score\":1,\"max_score\":1,\"status\":\"passed\",\"output\":\"\",\"steps\":3,\"time\":0.001000,\"timed_out\":false},"
+        );
+        assert!(lines[2].contains("\"status\":\"failed\",\"output\":\"output mismatch\\u000a"));
+        assert_eq!(lines[3], "]}");
+    }
+
+    #[test]
+    fn test_summary_json() {
+        use crate::console::BufferConsole;
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // OUT, OUT, TRAP x30
+        test_mach
+            .load_words(0x3000, &[0xF021, 0xF021, 0xF030])
+            .unwrap();
+        test_mach.reg_mut().set(Register::R0, b'a' as u16);
+        test_mach.reg_mut().set(Register::COND, 0b010);
+        assert!(test_mach.run().is_err());
+
+        let json = summary_json(&test_mach);
+        assert!(json.starts_with(
+            "{\"exit_reason\":\"error\",\"fault\":\"unknown trap vector x30 at x3002\",\"steps\":2,"
+        ));
+        assert!(json.contains("\"registers\":{\"R0\":97,"));
+        assert!(json.contains("\"PC\":12291,\"COND\":\"Z\"}"));
+        assert!(json.ends_with("\"traps\":{\"OUT\":2,\"x30\":1},\"warnings\":[]}\n"));
+        assert_eq!(
+            stats(&test_mach),
+            "Instructions: 2\nTraps:\n  OUT                2\n  x30                1\n"
+        );
+    }
+}
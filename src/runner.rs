@@ -0,0 +1,352 @@
+use std::{
+    fs,
+    io::ErrorKind as IoErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    console::{BufferConsole, Echo},
+    constants::USER_START,
+    enums::Register,
+    error::Result,
+    hypercall::HostReport,
+    project::Devices,
+    vm::Machine,
+};
+
+/// Instructions [`run_program`] executes before giving up on a program that
+/// doesn't halt
+pub const RUN_PROGRAM_STEP_LIMIT: u64 = 10_000_000;
+
+/// Outcome of running an object file headlessly with scripted input
+pub struct Run {
+    pub output: String,
+    pub halted: bool,
+    pub steps: u64,
+    pub elapsed: Duration,
+    /// What the program reported through the hypercall register
+    pub host: HostReport,
+}
+
+/// Runs `image` with `input` as its keyboard input and its output captured.
+/// Hypercalls are enabled so test programs can report their own results.
+pub fn run_headless(
+    image: &Path,
+    input: &[u8],
+    step_limit: Option<u64>,
+    echo: Echo,
+) -> Result<Run> {
+    run_headless_with(image, input, step_limit, echo, |_| Ok(()))
+}
+
+/// Like [`run_headless`], with `setup` preparing the machine (devices, OS
+/// images) before `image` is loaded
+pub fn run_headless_with(
+    image: &Path,
+    input: &[u8],
+    step_limit: Option<u64>,
+    echo: Echo,
+    setup: impl FnOnce(&mut Machine) -> Result<()>,
+) -> Result<Run> {
+    let console = BufferConsole::new(input);
+    let output = console.output();
+
+    let mut machine = Machine::with_console(Box::new(console));
+    machine.set_step_limit(step_limit);
+    machine.set_echo(echo);
+    machine.enable_hypercalls();
+    setup(&mut machine)?;
+    machine.load_image(image.to_path_buf())?;
+
+    let start = Instant::now();
+    machine.run()?;
+    let elapsed = start.elapsed();
+
+    let output = output.lock().unwrap().clone();
+    Ok(Run {
+        output,
+        halted: machine.halted(),
+        steps: machine.step_count(),
+        elapsed,
+        host: machine.host_report().cloned().unwrap_or_default(),
+    })
+}
+
+/// Outcome of [`run_program`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    pub output: String,
+    /// R0-R7 when the program stopped
+    pub registers: [u16; 8],
+    pub halted: bool,
+    pub steps: u64,
+}
+
+/// Runs the program `words`, loaded at x3000, with `input` as its keyboard
+/// input and its console output captured, e.g. for tests of LC-3 code. The
+/// program stops when it halts, reads past the end of `input`, or after
+/// [`RUN_PROGRAM_STEP_LIMIT`] instructions.
+pub fn run_program(words: &[u16], input: &str) -> Result<RunResult> {
+    let console = BufferConsole::new(input.as_bytes());
+    let output = console.output();
+
+    let mut machine = Machine::with_console(Box::new(console));
+    machine.set_step_limit(Some(RUN_PROGRAM_STEP_LIMIT));
+    machine.load_words(USER_START, words)?;
+    machine.run()?;
+
+    let output = output.lock().unwrap().clone();
+    Ok(RunResult {
+        output,
+        registers: Register::GENERAL.map(|reg| machine.reg().get(reg)),
+        halted: machine.halted(),
+        steps: machine.step_count(),
+    })
+}
+
+/// A golden test: `name.obj`, with optional `name.in` fed as keyboard input
+/// and optional `name.out` holding the expected console output. The program
+/// passes by halting, or by signalling a pass through the hypercall
+/// register, without failing an assertion on the way or going over its
+/// instruction budget.
+pub struct GoldenTest {
+    /// The image's path, unless the test comes from a project manifest
+    pub name: String,
+    pub image: PathBuf,
+    pub input: Option<PathBuf>,
+    pub expected: Option<PathBuf>,
+    /// Most instructions the program may take to finish
+    pub max_steps: Option<u64>,
+    /// Loaded into the OS region ahead of the image
+    pub os: Vec<PathBuf>,
+    pub devices: Devices,
+}
+
+impl GoldenTest {
+    pub fn for_image(image: PathBuf) -> Self {
+        let input = Some(image.with_extension("in")).filter(|path| path.exists());
+        let expected = Some(image.with_extension("out")).filter(|path| path.exists());
+        Self {
+            name: image.display().to_string(),
+            image,
+            input,
+            expected,
+            max_steps: None,
+            os: Vec::new(),
+            devices: Devices::default(),
+        }
+    }
+
+    pub fn run(&self, step_limit: Option<u64>, echo: Echo) -> Result<TestResult> {
+        let input = match &self.input {
+            Some(path) => fs::read(path)?,
+            None => Vec::new(),
+        };
+        // One instruction past the budget is enough to tell it was exceeded
+        let step_limit = match (step_limit, self.max_steps) {
+            (Some(limit), Some(budget)) => Some(limit.min(budget + 1)),
+            (limit, budget) => limit.or(budget.map(|budget| budget + 1)),
+        };
+        let run = run_headless_with(&self.image, &input, step_limit, echo, |machine| {
+            self.devices.apply(machine);
+            for path in &self.os {
+                machine.load_os_image(path.clone())?;
+            }
+            Ok(())
+        })?;
+
+        let timed_out = !run.halted && !run.host.finished;
+        let mut failure = None;
+        if !run.host.failures.is_empty() {
+            failure = Some(run.host.failures.join("\n"));
+        } else if let Some(budget) = self.max_steps.filter(|&budget| run.steps > budget) {
+            failure = Some(format!(
+                "took more than its budget of {budget} instructions"
+            ));
+        } else if timed_out {
+            failure = Some(format!(
+                "stopped without halting after {} instructions",
+                run.steps
+            ));
+        } else if let Some(path) = &self.expected {
+            let expected = fs::read_to_string(path)?;
+            if run.output != expected {
+                failure = Some(format!(
+                    "output mismatch\n--- expected\n{expected}\n--- actual\n{}",
+                    run.output
+                ));
+            }
+        }
+
+        Ok(TestResult {
+            name: self.name.clone(),
+            failure,
+            timed_out,
+            steps: run.steps,
+            elapsed: run.elapsed,
+        })
+    }
+}
+
+/// Outcome of a [`GoldenTest`], see [`crate::report`] for writing them out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    /// What went wrong, with the expected and actual output on a mismatch.
+    /// `None` when the test passed
+    pub failure: Option<String>,
+    /// The program hit the step limit instead of halting
+    pub timed_out: bool,
+    pub steps: u64,
+    pub elapsed: Duration,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Collects golden tests from object files and directories of object files
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<GoldenTest>> {
+    let mut images = Vec::new();
+
+    for path in paths {
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = entry?.path();
+                    if entry.extension().is_some_and(|ext| ext == "obj") {
+                        images.push(entry);
+                    }
+                }
+            }
+            Err(e) if e.kind() == IoErrorKind::NotADirectory => images.push(path.clone()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    images.sort();
+    Ok(images.into_iter().map(GoldenTest::for_image).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enums::Register::*, program::Program};
+
+    #[test]
+    fn test_run_headless() {
+        let run = run_headless(
+            Path::new("roms/hello-world.obj"),
+            b"",
+            None,
+            Echo::default(),
+        )
+        .unwrap();
+        assert_eq!(run.output, "Hello World!");
+        assert!(run.halted);
+        assert_eq!(run.steps, 3);
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let run = run_headless(
+            Path::new("roms/hello-world.obj"),
+            b"",
+            Some(2),
+            Echo::default(),
+        )
+        .unwrap();
+        assert!(!run.halted);
+        assert_eq!(run.steps, 2);
+    }
+
+    #[test]
+    fn test_run_program() {
+        let program = Program::new(USER_START)
+            .getc()
+            .out()
+            .add(R1, R0, -1)
+            .lea(R0, "DONE")
+            .puts()
+            .halt()
+            .label("DONE")
+            .stringz("!\n")
+            .assemble()
+            .unwrap();
+
+        let result = run_program(&program.words, "b").unwrap();
+        assert_eq!(result.output, "b!\n");
+        assert_eq!(result.registers[1], b'a' as u16);
+        assert!(result.halted);
+        assert_eq!(result.steps, 6);
+
+        // Out of input at the first GETC
+        let result = run_program(&program.words, "").unwrap();
+        assert!(!result.halted);
+        assert_eq!(result.output, "");
+    }
+
+    #[test]
+    fn test_golden() {
+        let tests = discover(&[PathBuf::from("roms/hello-world.obj")]).unwrap();
+        assert_eq!(tests.len(), 1);
+        let result = tests[0].run(None, Echo::default()).unwrap();
+        assert!(result.passed());
+        assert_eq!(result.name, "roms/hello-world.obj");
+        assert_eq!(result.steps, 3);
+
+        let result = tests[0].run(Some(2), Echo::default()).unwrap();
+        assert!(!result.passed());
+        assert!(result.timed_out);
+    }
+
+    #[test]
+    fn test_golden_budget() {
+        let mut test = GoldenTest::for_image(PathBuf::from("roms/hello-world.obj"));
+        test.max_steps = Some(3);
+        assert!(test.run(None, Echo::default()).unwrap().passed());
+
+        test.max_steps = Some(2);
+        let result = test.run(Some(1000), Echo::default()).unwrap();
+        assert_eq!(
+            result.failure.as_deref(),
+            Some("took more than its budget of 2 instructions")
+        );
+        assert_eq!(result.steps, 3);
+    }
+
+    #[test]
+    fn test_golden_hypercalls() {
+        use crate::{enums::MemMappedReg, hypercall::Hypercall};
+
+        // Fails an assertion, then loops forever
+        let program = Program::new(USER_START)
+            .and(R1, R1, 0)
+            .add(R1, R1, 1)
+            .ld(R2, "ASSERT")
+            .sti(R2, "HCR")
+            .label("LOOP")
+            .br("", "LOOP")
+            .label("ASSERT")
+            .fill(Hypercall::AssertEq as u16)
+            .label("HCR")
+            .fill(MemMappedReg::Hcr as u16)
+            .assemble()
+            .unwrap();
+        let image = std::env::temp_dir().join("simulator_test_hypercalls.obj");
+        fs::write(&image, program.to_bytes()).unwrap();
+
+        let result = GoldenTest::for_image(image.clone())
+            .run(Some(1000), Echo::default())
+            .unwrap();
+        assert_eq!(
+            result.failure.as_deref(),
+            Some("Assertion at 0x3003 failed: R0 = 0x0000, R1 = 0x0001")
+        );
+        assert!(result.timed_out);
+        fs::remove_file(image).unwrap();
+    }
+}
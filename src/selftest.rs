@@ -0,0 +1,89 @@
+use crate::{
+    asm,
+    error::Result,
+    runner::{self, RunResult},
+};
+
+/// A conformance program built into the simulator, with the console output
+/// a correct interpreter produces for it
+pub struct SelfTest {
+    pub name: &'static str,
+    source: &'static str,
+    input: &'static str,
+    expected: &'static str,
+}
+
+/// The built-in suite: every opcode, condition code edge cases and the trap
+/// service routines
+pub const SUITE: &[SelfTest] = &[
+    SelfTest {
+        name: "opcodes",
+        source: include_str!("../tests/fixtures/isa.asm"),
+        input: "",
+        expected: include_str!("../tests/fixtures/isa.out"),
+    },
+    SelfTest {
+        name: "flags",
+        source: include_str!("selftest/flags.asm"),
+        input: "",
+        expected: "ADD overflow ok\n\
+                   ADD wraparound ok\n\
+                   imm5 sign extension ok\n\
+                   NOT/AND flags ok\n\
+                   LD/LDR/LDI flags ok\n\
+                   ST/STI keep flags ok\n",
+    },
+    SelfTest {
+        name: "traps",
+        source: include_str!("selftest/traps.asm"),
+        input: "ab",
+        expected: "X\nPUTSP ok\naEnter a character: b\n",
+    },
+];
+
+impl SelfTest {
+    /// Assembles and runs the program, returning a description of the
+    /// failure if there was one
+    pub fn run(&self) -> Result<Option<String>> {
+        let program = asm::assemble(self.source)?;
+        let RunResult {
+            output,
+            halted,
+            steps,
+            ..
+        } = runner::run_program(&program.words, self.input)?;
+
+        if !halted {
+            return Ok(Some(format!(
+                "stopped without halting after {steps} instructions"
+            )));
+        }
+        if output != self.expected {
+            let mismatches: Vec<_> = self
+                .expected
+                .lines()
+                .zip(output.lines().chain(std::iter::repeat("")))
+                .filter(|(expected, actual)| expected != actual)
+                .map(|(expected, actual)| format!("expected `{expected}`, got `{actual}`"))
+                .collect();
+            return Ok(Some(if mismatches.is_empty() {
+                format!("output mismatch, got:\n{output}")
+            } else {
+                mismatches.join("\n")
+            }));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suite() {
+        for test in SUITE {
+            assert_eq!(test.run().unwrap(), None, "{}", test.name);
+        }
+    }
+}
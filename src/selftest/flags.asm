@@ -0,0 +1,100 @@
+; Condition codes and arithmetic edge cases, one line per check, `ok` or
+; `FAIL`. Each check starts with R3 = 1 and clears it when the flags are right.
+        .ORIG x3000
+
+; ADD overflowing into the sign bit gives a negative result
+        LD R3, ONE
+        LD R1, MAXPOS
+        ADD R1, R1, #1
+        BRzp T1
+        AND R3, R3, #0
+T1      LEA R0, T_OVF
+        JSR CHECK
+
+; ADD wraps around to zero
+        LD R3, ONE
+        LD R1, ALLONES
+        ADD R1, R1, #1
+        BRnp T2
+        AND R3, R3, #0
+T2      LEA R0, T_WRAP
+        JSR CHECK
+
+; imm5 is sign extended: 0 - 16 + 15 + 1 = 0
+        LD R3, ONE
+        AND R1, R1, #0
+        ADD R1, R1, #-16
+        BRzp T3
+        ADD R1, R1, #15
+        ADD R1, R1, #1
+        BRnp T3
+        AND R3, R3, #0
+T3      LEA R0, T_IMM
+        JSR CHECK
+
+; NOT and AND set the flags too
+        LD R3, ONE
+        LD R1, ALLONES
+        NOT R2, R1
+        BRnp T4
+        AND R2, R1, R1
+        BRzp T4
+        AND R3, R3, #0
+T4      LEA R0, T_LOGIC
+        JSR CHECK
+
+; Loads set the flags from the value loaded
+        LD R3, ONE
+        LD R1, ALLONES
+        BRzp T5
+        LEA R4, ZERO
+        LDR R1, R4, #0
+        BRnp T5
+        LDI R1, PTR_ONE
+        BRnz T5
+        AND R3, R3, #0
+T5      LEA R0, T_LOAD
+        JSR CHECK
+
+; Stores leave the flags alone
+        LD R3, ONE
+        LD R1, ALLONES
+        ST R3, SCRATCH
+        STI R3, PTR_SCR
+        BRzp T6
+        AND R3, R3, #0
+T6      LEA R0, T_STORE
+        JSR CHECK
+
+        HALT
+
+; Prints the name at R0 and whether R3 is 0
+CHECK   ST R7, SAVE_R7
+        PUTS
+        ADD R3, R3, #0
+        BRz CHECK_OK
+        LEA R0, FAIL
+        BRnzp CHECK_OUT
+CHECK_OK LEA R0, OK
+CHECK_OUT PUTS
+        LD R7, SAVE_R7
+        RET
+
+SAVE_R7 .FILL x0000
+ONE     .FILL x0001
+ZERO    .FILL x0000
+MAXPOS  .FILL x7FFF
+ALLONES .FILL xFFFF
+PTR_ONE .FILL ONE
+SCRATCH .FILL x0000
+PTR_SCR .FILL SCRATCH
+
+T_OVF   .STRINGZ "ADD overflow"
+T_WRAP  .STRINGZ "ADD wraparound"
+T_IMM   .STRINGZ "imm5 sign extension"
+T_LOGIC .STRINGZ "NOT/AND flags"
+T_LOAD  .STRINGZ "LD/LDR/LDI flags"
+T_STORE .STRINGZ "ST/STI keep flags"
+OK      .STRINGZ " ok\n"
+FAIL    .STRINGZ " FAIL\n"
+        .END
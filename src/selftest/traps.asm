@@ -0,0 +1,26 @@
+; The service routines behind the trap aliases, fed "ab" as keyboard input
+        .ORIG x3000
+
+        LD R0, CHAR_X
+        OUT
+        LEA R0, NEWLINE
+        PUTS
+        LEA R0, PACKED
+        PUTSP
+        GETC
+        OUT
+        IN
+        OUT
+        LEA R0, NEWLINE
+        PUTS
+        HALT
+
+CHAR_X  .FILL x0058
+NEWLINE .STRINGZ "\n"
+; "PUTSP ok\n", two characters per word, low byte first
+PACKED  .FILL x5550
+        .FILL x5354
+        .FILL x2050
+        .FILL x6B6F
+        .FILL x000A
+        .END
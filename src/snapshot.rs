@@ -0,0 +1,489 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{
+    disasm,
+    enums::Register,
+    error::{Error, ErrorKind, Result},
+    listing::Listing,
+    memory::{flag_name, PagedMemory, PAGE_SIZE},
+    symbols::SymbolTable,
+    vm::Machine,
+};
+
+/// Start of every snapshot file
+const MAGIC: &[u8; 8] = b"LC3SNAP\0";
+
+/// Format written by [`Snapshot::to_bytes`]. Readers reject newer versions
+/// rather than misreading them. Version 1 had no codec byte and always used
+/// PackBits
+const VERSION: u16 = 2;
+
+/// How pages are packed, the byte after the version
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Codec {
+    PackBits = 0,
+    #[cfg(feature = "lz4")]
+    Lz4 = 1,
+}
+
+impl Codec {
+    /// What new snapshots use
+    #[cfg(feature = "lz4")]
+    const DEFAULT: Self = Self::Lz4;
+    #[cfg(not(feature = "lz4"))]
+    const DEFAULT: Self = Self::PackBits;
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Self::PackBits),
+            #[cfg(feature = "lz4")]
+            1 => Ok(Self::Lz4),
+            #[cfg(not(feature = "lz4"))]
+            1 => Err(snapshot_error(
+                "snapshot pages are LZ4 compressed, which needs the lz4 feature",
+            )),
+            _ => Err(snapshot_error(&format!(
+                "unknown snapshot compression {byte}"
+            ))),
+        }
+    }
+
+    fn encode(self, words: &[u16; PAGE_SIZE]) -> Vec<u8> {
+        match self {
+            Self::PackBits => pack_words(words),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => {
+                let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+                lz4_flex::block::compress(&bytes)
+            }
+        }
+    }
+
+    /// One page's words
+    fn decode(self, bytes: &[u8]) -> Result<Vec<u16>> {
+        match self {
+            Self::PackBits => unpack_words(bytes),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => match lz4_flex::block::decompress(bytes, PAGE_SIZE * 2) {
+                Ok(bytes) if bytes.len() == PAGE_SIZE * 2 => Ok(bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect()),
+                _ => Err(snapshot_error("corrupt snapshot page")),
+            },
+        }
+    }
+}
+
+/// Longest run or literal one PackBits header byte covers
+const MAX_RUN: usize = 128;
+
+/// Machine state saved to a file, e.g. by the debugger's `snapshot` command,
+/// for `simulator diffstate` to compare
+#[derive(Clone)]
+pub struct Snapshot {
+    pub steps: u64,
+    pub registers: [u16; 8],
+    pub pc: u16,
+    pub cond: u16,
+    pub psr: u16,
+    pub memory: PagedMemory,
+}
+
+impl Snapshot {
+    pub fn capture(machine: &Machine) -> Self {
+        Self {
+            steps: machine.step_count(),
+            registers: machine.reg().general(),
+            pc: machine.reg().get(Register::PC),
+            cond: machine.reg().get(Register::COND),
+            psr: machine.psr(),
+            memory: machine.mem().contents().clone(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path)?).map_err(|e| {
+            Error::with_message(
+                ErrorKind::ParseError,
+                format!("{}: {}", path.display(), e.message()),
+            )
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    /// Big endian: the magic, the format version, the codec, the instruction
+    /// count, R0-R7, PC, COND and PSR, then the number of stored pages. Each
+    /// page is its number, the length in bytes of its encoding and its 256
+    /// words, LZ4 compressed with the `lz4` feature and run-length packed
+    /// without it. Pages that are all zero are left out, so a typical
+    /// program's snapshot takes a few hundred bytes instead of 128KB
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(Codec::DEFAULT)
+    }
+
+    fn encode(&self, codec: Codec) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_be_bytes());
+        bytes.push(codec as u8);
+        bytes.extend(self.steps.to_be_bytes());
+        for word in self
+            .registers
+            .iter()
+            .chain([&self.pc, &self.cond, &self.psr])
+        {
+            bytes.extend(word.to_be_bytes());
+        }
+
+        let pages: Vec<_> = self
+            .memory
+            .allocated()
+            .filter(|(_, words)| words.iter().any(|&word| word != 0))
+            .collect();
+        bytes.extend((pages.len() as u16).to_be_bytes());
+        for (start, words) in pages {
+            let packed = codec.encode(words);
+            bytes.push((start / PAGE_SIZE) as u8);
+            bytes.extend((packed.len() as u16).to_be_bytes());
+            bytes.extend(packed);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err(snapshot_error("not a snapshot file"));
+        };
+        let mut reader = Reader(rest);
+        let version = reader.word()?;
+        if version > VERSION {
+            return Err(snapshot_error(&format!(
+                "snapshot format version {version} is newer than this simulator supports ({VERSION})"
+            )));
+        }
+        let codec = if version >= 2 {
+            Codec::from_byte(reader.take(1)?[0])?
+        } else {
+            Codec::PackBits
+        };
+
+        let steps = u64::from_be_bytes(reader.take(8)?.try_into().unwrap());
+        let mut registers = [0; 8];
+        for reg in &mut registers {
+            *reg = reader.word()?;
+        }
+        let (pc, cond, psr) = (reader.word()?, reader.word()?, reader.word()?);
+
+        let mut memory = PagedMemory::default();
+        for _ in 0..reader.word()? {
+            let page = reader.take(1)?[0] as usize;
+            let len = reader.word()? as usize;
+            let words = codec.decode(reader.take(len)?)?;
+            for (offset, word) in words.into_iter().enumerate() {
+                if word != 0 {
+                    memory.set((page * PAGE_SIZE + offset) as u16, word);
+                }
+            }
+        }
+        if !reader.0.is_empty() {
+            return Err(snapshot_error("trailing data after snapshot"));
+        }
+
+        Ok(Self {
+            steps,
+            registers,
+            pc,
+            cond,
+            psr,
+            memory,
+        })
+    }
+}
+
+/// Big endian fields read off the front of a snapshot
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(snapshot_error("truncated snapshot"));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn word(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+/// PackBits over words: a header byte below x80 is followed by that many plus
+/// one literal words, and one of x80 or above by a single word repeated
+/// `header - x7F` times. Runs of three or more equal words are packed, so the
+/// zeroes around code and data cost a few bytes
+fn pack_words(words: &[u16]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    let flush = |out: &mut Vec<u8>, literal: &[u16]| {
+        for chunk in literal.chunks(MAX_RUN) {
+            out.push((chunk.len() - 1) as u8);
+            for word in chunk {
+                out.extend(word.to_be_bytes());
+            }
+        }
+    };
+    while i < words.len() {
+        let run = words[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&word| word == words[i])
+            .count();
+        if run >= 3 {
+            flush(&mut out, &words[literal_start..i]);
+            out.push(0x7F + run as u8);
+            out.extend(words[i].to_be_bytes());
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    flush(&mut out, &words[literal_start..]);
+    out
+}
+
+/// Inverse of [`pack_words`], for exactly one page
+fn unpack_words(bytes: &[u8]) -> Result<Vec<u16>> {
+    let mut words = Vec::with_capacity(PAGE_SIZE);
+    let mut reader = Reader(bytes);
+    while !reader.0.is_empty() {
+        let header = reader.take(1)?[0] as usize;
+        if header < 0x80 {
+            for _ in 0..=header {
+                words.push(reader.word()?);
+            }
+        } else {
+            let word = reader.word()?;
+            words.extend(std::iter::repeat_n(word, header - 0x7F));
+        }
+        if words.len() > PAGE_SIZE {
+            break;
+        }
+    }
+    if words.len() != PAGE_SIZE {
+        return Err(snapshot_error("corrupt snapshot page"));
+    }
+    Ok(words)
+}
+
+/// What changed from snapshot `a` to `b`: the instruction count, registers
+/// and every memory word that differs, named after the nearest symbol.
+/// Words the listing marks as code, or every word without a listing, are
+/// shown disassembled too
+pub fn diff(a: &Snapshot, b: &Snapshot, symbols: &SymbolTable, listing: &Listing) -> String {
+    let mut out = String::new();
+    if a.steps != b.steps {
+        writeln!(out, "Steps: {} -> {}", a.steps, b.steps).unwrap();
+    }
+
+    let regs = Register::GENERAL
+        .into_iter()
+        .zip(a.registers.iter().zip(&b.registers));
+    for (reg, (before, after)) in regs.filter(|(_, (before, after))| before != after) {
+        writeln!(out, "{reg:?}: x{before:04X} -> x{after:04X}").unwrap();
+    }
+    if a.pc != b.pc {
+        writeln!(
+            out,
+            "PC: {} -> {}",
+            describe(a.pc, symbols),
+            describe(b.pc, symbols)
+        )
+        .unwrap();
+    }
+    if a.cond != b.cond {
+        writeln!(out, "COND: {} -> {}", flag_name(a.cond), flag_name(b.cond)).unwrap();
+    }
+    if a.psr != b.psr {
+        writeln!(out, "PSR: x{:04X} -> x{:04X}", a.psr, b.psr).unwrap();
+    }
+
+    let mut changed = 0;
+    for addr in 0..=u16::MAX {
+        let (before, after) = (a.memory.get(addr), b.memory.get(addr));
+        if before == after {
+            continue;
+        }
+        if changed == 0 {
+            out.push_str("Memory:\n");
+        }
+        changed += 1;
+        write!(
+            out,
+            "  {}: x{before:04X} -> x{after:04X}",
+            describe(addr, symbols)
+        )
+        .unwrap();
+        if listing.is_empty() || listing.is_code(addr) {
+            write!(
+                out,
+                "  {} -> {}",
+                disasm::disassemble_with(addr, before, symbols),
+                disasm::disassemble_with(addr, after, symbols)
+            )
+            .unwrap();
+        }
+        out.push('\n');
+    }
+
+    if out.is_empty() {
+        out.push_str("No differences\n");
+    } else if changed > 0 {
+        writeln!(out, "{changed} memory word(s) differ").unwrap();
+    }
+    out
+}
+
+/// An address with the closest symbol at or before it, e.g. `x3004 <LOOP+2>`
+fn describe(addr: u16, symbols: &SymbolTable) -> String {
+    match symbols.nearest(addr) {
+        Some((name, base)) if base == addr => format!("x{addr:04X} <{name}>"),
+        Some((name, base)) => format!("x{addr:04X} <{name}+{}>", addr - base),
+        None => format!("x{addr:04X}"),
+    }
+}
+
+fn snapshot_error(msg: &str) -> Error {
+    Error::with_message(ErrorKind::ParseError, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::BufferConsole;
+
+    #[test]
+    fn test_round_trip() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // ADD R1, R1, #1; HALT
+        test_mach.load_words(0x3000, &[0x1261, 0xF025]).unwrap();
+        test_mach.run().unwrap();
+
+        let snapshot = Snapshot::capture(&test_mach);
+        let bytes = snapshot.to_bytes();
+        assert!(bytes.len() < 16 * 1024, "{} bytes", bytes.len());
+        let loaded = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.steps, 2);
+        assert_eq!(loaded.registers[1], 1);
+        assert_eq!(loaded.memory.get(0x3000), 0x1261);
+        assert_eq!(loaded.to_bytes(), bytes);
+
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::from_bytes(b"LC3").is_err());
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        let err = Snapshot::from_bytes(&newer).err().unwrap();
+        assert!(err.message().contains("newer than this simulator supports"));
+    }
+
+    #[test]
+    fn test_codecs() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach
+            .load_words(0x3000, &[0x1261, 0x1261, 0x1261, 0xF025])
+            .unwrap();
+        test_mach.load_words(0x4000, &[0x4242; 300]).unwrap();
+        let snapshot = Snapshot::capture(&test_mach);
+
+        let codecs = [
+            Codec::PackBits,
+            #[cfg(feature = "lz4")]
+            Codec::Lz4,
+        ];
+        for codec in codecs {
+            let loaded = Snapshot::from_bytes(&snapshot.encode(codec)).unwrap();
+            assert_eq!(loaded.encode(codec), snapshot.encode(codec), "{codec:?}");
+            assert_eq!(loaded.memory.get(0x412B), 0x4242);
+        }
+
+        // Version 1 had no codec byte and always used PackBits
+        let mut v1 = snapshot.encode(Codec::PackBits);
+        v1.remove(MAGIC.len() + 2);
+        v1[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&1u16.to_be_bytes());
+        assert_eq!(
+            Snapshot::from_bytes(&v1).unwrap().memory.get(0x3003),
+            0xF025
+        );
+
+        let mut unknown = snapshot.to_bytes();
+        unknown[MAGIC.len() + 2] = 9;
+        let err = Snapshot::from_bytes(&unknown).err().unwrap();
+        assert_eq!(err.message(), "unknown snapshot compression 9");
+    }
+
+    #[test]
+    fn test_pack_words() {
+        let mut page = [0; PAGE_SIZE];
+        page[10..14].copy_from_slice(&[0x1261, 0x1261, 0xF025, 0xFFFF]);
+        page[100..250].fill(0x4242);
+        let packed = pack_words(&page);
+        assert!(packed.len() < 32, "{} bytes", packed.len());
+        assert_eq!(unpack_words(&packed).unwrap(), page);
+
+        let noisy: Vec<u16> = (0..PAGE_SIZE as u16).collect();
+        assert_eq!(unpack_words(&pack_words(&noisy)).unwrap(), noisy);
+        assert!(unpack_words(&packed[..packed.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // ADD R1, R1, #1; ST R1, #1; HALT
+        test_mach
+            .load_words(0x3000, &[0x1261, 0x3201, 0xF025])
+            .unwrap();
+        let before = Snapshot::capture(&test_mach);
+        assert_eq!(
+            diff(
+                &before,
+                &before,
+                &SymbolTable::default(),
+                &Listing::default()
+            ),
+            "No differences\n"
+        );
+
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+        let mut symbols = SymbolTable::default();
+        symbols.insert("START", 0x3000);
+        let after = Snapshot::capture(&test_mach);
+        assert_eq!(
+            diff(&before, &after, &symbols, &Listing::default()),
+            "Steps: 0 -> 2
+R1: x0000 -> x0001
+PC: x3000 <START> -> x3002 <START+2>
+COND: - -> P
+PSR: x8000 -> x8001
+Memory:
+  x3003 <START+3>: x0000 -> x0001  NOP -> NOP
+1 memory word(s) differ
+"
+        );
+
+        let mut listing = Listing::default();
+        listing.insert(0x3003, 4, "COUNT .FILL #0");
+        assert!(
+            diff(&before, &after, &symbols, &listing).contains("x3003 <START+3>: x0000 -> x0001\n")
+        );
+    }
+}
@@ -0,0 +1,574 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    rc::Rc,
+};
+
+use num_traits::FromPrimitive;
+
+use crate::{
+    enums::{MemMappedReg, RawOpCode, Register, TrapCode},
+    explore::ExploreLimits,
+    memory::PagedMemory,
+    utils::sign_extend,
+    vm::Machine,
+};
+
+/// Candidate assignments the solver may try per query before giving up
+const SOLVER_BUDGET: u64 = 1_000_000;
+
+/// 16-bit value built from constants and keyboard input bytes
+#[derive(Debug, PartialEq, Eq)]
+pub enum Expr {
+    Const(u16),
+    /// The n-th byte read from the keyboard
+    Input(usize),
+    Add(Value, Value),
+    And(Value, Value),
+    Not(Value),
+}
+
+pub type Value = Rc<Expr>;
+
+impl Expr {
+    pub fn eval(&self, inputs: &[u8]) -> u16 {
+        match self {
+            Expr::Const(val) => *val,
+            Expr::Input(idx) => inputs[*idx] as u16,
+            Expr::Add(a, b) => a.eval(inputs).wrapping_add(b.eval(inputs)),
+            Expr::And(a, b) => a.eval(inputs) & b.eval(inputs),
+            Expr::Not(a) => !a.eval(inputs),
+        }
+    }
+
+    fn as_const(&self) -> Option<u16> {
+        match self {
+            Expr::Const(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// Index of the latest input the value depends on
+    fn last_input(&self) -> Option<usize> {
+        match self {
+            Expr::Const(_) => None,
+            Expr::Input(idx) => Some(*idx),
+            Expr::Add(a, b) | Expr::And(a, b) => a.last_input().max(b.last_input()),
+            Expr::Not(a) => a.last_input(),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Const(val) => write!(f, "x{val:04X}"),
+            Expr::Input(idx) => write!(f, "in{idx}"),
+            Expr::Add(a, b) => write!(f, "({a} + {b})"),
+            Expr::And(a, b) => write!(f, "({a} & {b})"),
+            Expr::Not(a) => write!(f, "~{a}"),
+        }
+    }
+}
+
+fn konst(val: u16) -> Value {
+    Rc::new(Expr::Const(val))
+}
+
+fn add(a: &Value, b: &Value) -> Value {
+    match (a.as_const(), b.as_const()) {
+        (Some(x), Some(y)) => konst(x.wrapping_add(y)),
+        (_, Some(0)) => Rc::clone(a),
+        (Some(0), _) => Rc::clone(b),
+        _ => Rc::new(Expr::Add(Rc::clone(a), Rc::clone(b))),
+    }
+}
+
+fn and(a: &Value, b: &Value) -> Value {
+    match (a.as_const(), b.as_const()) {
+        (Some(x), Some(y)) => konst(x & y),
+        (Some(0), _) | (_, Some(0)) => konst(0),
+        (_, Some(0xFFFF)) => Rc::clone(a),
+        (Some(0xFFFF), _) => Rc::clone(b),
+        _ => Rc::new(Expr::And(Rc::clone(a), Rc::clone(b))),
+    }
+}
+
+fn not(a: &Value) -> Value {
+    match &**a {
+        Expr::Const(x) => konst(!x),
+        Expr::Not(inner) => Rc::clone(inner),
+        _ => Rc::new(Expr::Not(Rc::clone(a))),
+    }
+}
+
+/// Condition flag a value sets, as the NZP bits of a BR instruction
+fn flag_of(val: u16) -> u16 {
+    if val == 0 {
+        0b010
+    } else if val >> 15 == 1 {
+        0b100
+    } else {
+        0b001
+    }
+}
+
+/// The flag set by `value` is one of `flags` (NZP bits)
+#[derive(Clone, Debug)]
+struct Constraint {
+    value: Value,
+    flags: u16,
+    last_input: Option<usize>,
+}
+
+impl Constraint {
+    fn new(value: Value, flags: u16) -> Self {
+        Self {
+            last_input: value.last_input(),
+            value,
+            flags,
+        }
+    }
+
+    fn holds(&self, inputs: &[u8]) -> bool {
+        flag_of(self.value.eval(inputs)) & self.flags != 0
+    }
+}
+
+/// Finds input bytes from `alphabet` satisfying every constraint, by
+/// backtracking over the inputs in the order the program read them.
+/// Each constraint is checked as soon as its last input is assigned.
+fn solve(constraints: &[Constraint], inputs: usize, alphabet: &[u8]) -> Option<Vec<u8>> {
+    let mut by_input = vec![Vec::new(); inputs];
+    for constraint in constraints {
+        match constraint.last_input {
+            Some(idx) => by_input[idx].push(constraint),
+            None if !constraint.holds(&[]) => return None,
+            None => (),
+        }
+    }
+
+    let mut model = Vec::with_capacity(inputs);
+    let mut budget = SOLVER_BUDGET;
+    search(&by_input, alphabet, &mut model, &mut budget).then_some(model)
+}
+
+fn search(
+    by_input: &[Vec<&Constraint>],
+    alphabet: &[u8],
+    model: &mut Vec<u8>,
+    budget: &mut u64,
+) -> bool {
+    let idx = model.len();
+    if idx == by_input.len() {
+        return true;
+    }
+
+    for &byte in alphabet {
+        if *budget == 0 {
+            return false;
+        }
+        *budget -= 1;
+
+        model.push(byte);
+        if by_input[idx].iter().all(|c| c.holds(model)) && search(by_input, alphabet, model, budget)
+        {
+            return true;
+        }
+        model.pop();
+    }
+    false
+}
+
+#[derive(Clone, Debug)]
+enum Cond {
+    /// NZP bits, e.g. before any instruction set them
+    Flags(u16),
+    /// Flags set by the last value written to a register
+    Of(Value),
+}
+
+/// One execution path: symbolic registers and memory plus the branch
+/// decisions taken to get here
+#[derive(Clone)]
+struct State {
+    reg: [Value; 8],
+    pc: u16,
+    cond: Cond,
+    /// Cells written along this path, read through to the machine's memory otherwise
+    mem: HashMap<u16, Value>,
+    inputs: usize,
+    constraints: Vec<Constraint>,
+    steps: u64,
+}
+
+impl State {
+    fn new(machine: &Machine) -> Self {
+        let reg = machine.reg();
+        Self {
+            reg: reg.general().map(konst),
+            pc: reg.get(Register::PC),
+            cond: Cond::Flags(reg.get(Register::COND)),
+            mem: HashMap::new(),
+            inputs: 0,
+            constraints: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    fn get(&self, bits: u16) -> Value {
+        Rc::clone(&self.reg[(bits & 0x7) as usize])
+    }
+
+    fn set(&mut self, bits: u16, value: Value) {
+        self.cond = Cond::Of(Rc::clone(&value));
+        self.reg[(bits & 0x7) as usize] = value;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FindingKind {
+    Reached,
+    IllegalOpcode,
+    UnknownTrap,
+}
+
+/// An input that drives the program to the target or into a crash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub pc: u16,
+    pub input: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct SymbolicReport {
+    pub findings: Vec<Finding>,
+    pub paths: usize,
+    /// Whether some path was cut short by the limits
+    pub truncated: bool,
+}
+
+/// Explores `machine` with keyboard input treated as unknown values.
+///
+/// Branches on input-derived values fork the path when both sides are
+/// feasible; each finding comes with a concrete input, drawn from the
+/// alphabet, that reproduces it. Experimental: addresses computed from input
+/// are pinned to a single value rather than explored.
+pub fn explore(machine: &Machine, target: u16, limits: &ExploreLimits) -> SymbolicReport {
+    let mut engine = Engine {
+        memory: machine.mem().contents(),
+        target,
+        limits,
+        report: SymbolicReport::default(),
+    };
+
+    let mut queue = VecDeque::from([State::new(machine)]);
+    while let Some(mut state) = queue.pop_front() {
+        loop {
+            match engine.step(state) {
+                Step::Continue(next) => state = next,
+                Step::Fork(taken, other) => {
+                    if engine.report.paths + queue.len() + 1 < limits.max_paths {
+                        queue.push_back(other);
+                    } else {
+                        engine.report.truncated = true;
+                    }
+                    state = taken;
+                }
+                Step::End => break,
+            }
+        }
+        engine.report.paths += 1;
+    }
+
+    engine.report
+}
+
+enum Step {
+    Continue(State),
+    Fork(State, State),
+    End,
+}
+
+struct Engine<'a> {
+    memory: &'a PagedMemory,
+    target: u16,
+    limits: &'a ExploreLimits,
+    report: SymbolicReport,
+}
+
+impl Engine<'_> {
+    fn step(&mut self, mut state: State) -> Step {
+        if state.pc == self.target {
+            self.found(&state, FindingKind::Reached, state.pc);
+            return Step::End;
+        }
+        if state.steps >= self.limits.max_steps || state.inputs > self.limits.max_inputs {
+            self.report.truncated = true;
+            return Step::End;
+        }
+
+        // Code is assumed not to depend on input, like the rest of the program image
+        let pc = state.pc;
+        let Some(instr) = self.read(&mut state, pc).as_const() else {
+            self.report.truncated = true;
+            return Step::End;
+        };
+        let next = pc.wrapping_add(1);
+        state.pc = next;
+        state.steps += 1;
+
+        let dest = instr >> 9;
+        let base = instr >> 6;
+        let pc_offset = sign_extend(instr & 0x1FF, 9);
+        match RawOpCode::from_u16(instr >> 12).unwrap() {
+            RawOpCode::Add | RawOpCode::And => {
+                let rhs = if (instr >> 5) & 0x1 == 1 {
+                    konst(sign_extend(instr & 0x1F, 5))
+                } else {
+                    state.get(instr)
+                };
+                let lhs = state.get(base);
+                let result = if instr >> 12 == RawOpCode::Add as u16 {
+                    add(&lhs, &rhs)
+                } else {
+                    and(&lhs, &rhs)
+                };
+                state.set(dest, result);
+            }
+            RawOpCode::Not => {
+                let result = not(&state.get(base));
+                state.set(dest, result);
+            }
+            RawOpCode::Br => return self.branch(state, (instr >> 9) & 0x7, pc_offset),
+            RawOpCode::Jmp => {
+                let addr = state.get(base);
+                let Some(addr) = self.concretize(&mut state, &addr) else {
+                    return Step::End;
+                };
+                state.pc = addr;
+            }
+            RawOpCode::Jsr => {
+                let addr = if (instr >> 11) & 0x1 == 1 {
+                    state.pc.wrapping_add(sign_extend(instr & 0x7FF, 11))
+                } else {
+                    let addr = state.get(base);
+                    match self.concretize(&mut state, &addr) {
+                        Some(addr) => addr,
+                        None => return Step::End,
+                    }
+                };
+                state.reg[7] = konst(state.pc);
+                state.pc = addr;
+            }
+            RawOpCode::Ld => {
+                let val = self.read(&mut state, next.wrapping_add(pc_offset));
+                state.set(dest, val);
+            }
+            RawOpCode::Ldi => {
+                let ptr = self.read(&mut state, next.wrapping_add(pc_offset));
+                let Some(addr) = self.concretize(&mut state, &ptr) else {
+                    return Step::End;
+                };
+                let val = self.read(&mut state, addr);
+                state.set(dest, val);
+            }
+            RawOpCode::Ldr => {
+                let addr = add(&state.get(base), &konst(sign_extend(instr & 0x3F, 6)));
+                let Some(addr) = self.concretize(&mut state, &addr) else {
+                    return Step::End;
+                };
+                let val = self.read(&mut state, addr);
+                state.set(dest, val);
+            }
+            RawOpCode::Lea => {
+                let addr = konst(state.pc.wrapping_add(pc_offset));
+                state.set(dest, addr);
+            }
+            RawOpCode::St => {
+                let val = state.get(dest);
+                state.mem.insert(state.pc.wrapping_add(pc_offset), val);
+            }
+            RawOpCode::Sti => {
+                let ptr = self.read(&mut state, next.wrapping_add(pc_offset));
+                let Some(addr) = self.concretize(&mut state, &ptr) else {
+                    return Step::End;
+                };
+                let val = state.get(dest);
+                state.mem.insert(addr, val);
+            }
+            RawOpCode::Str => {
+                let addr = add(&state.get(base), &konst(sign_extend(instr & 0x3F, 6)));
+                let Some(addr) = self.concretize(&mut state, &addr) else {
+                    return Step::End;
+                };
+                let val = state.get(dest);
+                state.mem.insert(addr, val);
+            }
+            RawOpCode::Trap => match TrapCode::from_u16(instr & 0xFF) {
+                Some(TrapCode::GetC | TrapCode::In) => {
+                    let input = self.fresh_input(&mut state);
+                    state.reg[0] = input;
+                }
+                Some(TrapCode::Halt) => return Step::End,
+                // Output doesn't affect where execution goes
+                Some(TrapCode::Out | TrapCode::Puts | TrapCode::PutsP) => (),
+                None => {
+                    self.found(&state, FindingKind::UnknownTrap, pc);
+                    return Step::End;
+                }
+            },
+            RawOpCode::Rti => (),
+            RawOpCode::Noop => {
+                self.found(&state, FindingKind::IllegalOpcode, pc);
+                return Step::End;
+            }
+        }
+
+        Step::Continue(state)
+    }
+
+    fn branch(&mut self, mut state: State, mask: u16, pc_offset: u16) -> Step {
+        let target = state.pc.wrapping_add(pc_offset);
+        let value = match &state.cond {
+            Cond::Flags(flags) => {
+                if flags & mask != 0 {
+                    state.pc = target;
+                }
+                return Step::Continue(state);
+            }
+            Cond::Of(value) => Rc::clone(value),
+        };
+        if let Some(val) = value.as_const() {
+            if flag_of(val) & mask != 0 {
+                state.pc = target;
+            }
+            return Step::Continue(state);
+        }
+
+        let mut taken = state.clone();
+        taken.pc = target;
+        taken
+            .constraints
+            .push(Constraint::new(Rc::clone(&value), mask));
+        state
+            .constraints
+            .push(Constraint::new(value, !mask & 0b111));
+
+        match (self.feasible(&taken), self.feasible(&state)) {
+            (true, true) => Step::Fork(taken, state),
+            (true, false) => Step::Continue(taken),
+            (false, true) => Step::Continue(state),
+            (false, false) => Step::End,
+        }
+    }
+
+    fn feasible(&self, state: &State) -> bool {
+        state.constraints.last().is_none_or(|c| c.flags != 0)
+            && solve(&state.constraints, state.inputs, &self.limits.alphabet).is_some()
+    }
+
+    /// Pins an input-derived value to one it can take on this path
+    fn concretize(&mut self, state: &mut State, value: &Value) -> Option<u16> {
+        if let Some(val) = value.as_const() {
+            return Some(val);
+        }
+
+        let model = solve(&state.constraints, state.inputs, &self.limits.alphabet)?;
+        let val = value.eval(&model);
+        let diff = add(value, &konst(val.wrapping_neg()));
+        state.constraints.push(Constraint::new(diff, 0b010));
+        Some(val)
+    }
+
+    /// Memory read as seen by the program. Reading KBSR consumes a key, like the VM
+    fn read(&mut self, state: &mut State, addr: u16) -> Value {
+        if addr == MemMappedReg::Kbsr as u16 {
+            let input = self.fresh_input(state);
+            state.mem.insert(MemMappedReg::Kbdr as u16, input);
+            return konst(1 << 15);
+        }
+        match state.mem.get(&addr) {
+            Some(val) => Rc::clone(val),
+            None => konst(self.memory.get(addr)),
+        }
+    }
+
+    fn fresh_input(&mut self, state: &mut State) -> Value {
+        state.inputs += 1;
+        Rc::new(Expr::Input(state.inputs - 1))
+    }
+
+    fn found(&mut self, state: &State, kind: FindingKind, pc: u16) {
+        if let Some(input) = solve(&state.constraints, state.inputs, &self.limits.alphabet) {
+            self.report.findings.push(Finding { kind, pc, input });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn limits(alphabet: &[u8]) -> ExploreLimits {
+        ExploreLimits {
+            alphabet: alphabet.to_vec(),
+            max_inputs: 4,
+            max_steps: 1000,
+            max_paths: 100,
+        }
+    }
+
+    #[test]
+    fn test_solve() {
+        // in0 + in1 == x64, e.g. '1' + '3'
+        let sum = add(&Rc::new(Expr::Input(0)), &Rc::new(Expr::Input(1)));
+        let constraints = [Constraint::new(
+            add(&sum, &konst(0x64u16.wrapping_neg())),
+            0b010,
+        )];
+        assert_eq!(solve(&constraints, 2, b"0123"), Some(b"13".to_vec()));
+        assert_eq!(solve(&constraints, 2, b"01"), None);
+    }
+
+    #[test]
+    fn test_symbolic_explore() {
+        let assembly = asm::assemble(
+            "
+            .ORIG x3000
+            GETC
+            ADD R1, R0, #0
+            GETC
+            ADD R0, R0, R1
+            LD R2, NEG_SUM
+            ADD R0, R0, R2
+            BRz SECRET
+            HALT
+            SECRET .FILL xD000
+            NEG_SUM .FILL #-103
+            .END
+            ",
+        )
+        .unwrap();
+        let mut test_mach = Machine::default();
+        test_mach.load_bytes(&assembly.to_bytes()).unwrap();
+
+        let secret = assembly.symbols.lookup("SECRET").unwrap();
+        let report = explore(&test_mach, 0x3100, &limits(b"0123456789"));
+        assert_eq!(report.paths, 2);
+        assert_eq!(
+            report.findings,
+            vec![Finding {
+                kind: FindingKind::IllegalOpcode,
+                pc: secret,
+                input: b"07".to_vec(),
+            }]
+        );
+
+        let report = explore(&test_mach, secret, &limits(b"0123456789"));
+        assert_eq!(report.findings[0].kind, FindingKind::Reached);
+        assert!(!report.truncated);
+    }
+}
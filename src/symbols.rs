@@ -0,0 +1,130 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::Path,
+};
+
+use crate::error::Result;
+
+/// Label <-> address mapping, as produced by `lc3as` in `.sym` files
+#[derive(Default, Clone, Debug)]
+pub struct SymbolTable {
+    by_name: HashMap<String, u16>,
+    by_addr: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parses the lc3as symbol table format:
+    ///
+    /// ```text
+    /// //  Symbol Name       Page Address
+    /// //  ----------------  ------------
+    /// //  LOOP              3002
+    /// ```
+    ///
+    /// Plain `NAME ADDR` lines (without the comment prefix) are accepted too.
+    pub fn parse(src: &str) -> Self {
+        let mut table = Self::default();
+
+        for line in src.lines() {
+            let line = line.trim_start_matches("//").trim();
+            let mut parts = line.split_whitespace();
+            let (Some(name), Some(addr), None) = (parts.next(), parts.next(), parts.next()) else {
+                continue;
+            };
+
+            let addr = addr.trim_start_matches(['x', 'X']);
+            if let Ok(addr) = u16::from_str_radix(addr, 16) {
+                table.insert(name, addr);
+            }
+        }
+
+        table
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.by_name.insert(name.to_owned(), addr);
+        self.by_addr.entry(addr).or_insert_with(|| name.to_owned());
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn name_at(&self, addr: u16) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    /// Closest symbol at or below `addr`, along with its address
+    pub fn nearest(&self, addr: u16) -> Option<(&str, u16)> {
+        self.by_addr
+            .range(..=addr)
+            .next_back()
+            .map(|(addr, name)| (name.as_str(), *addr))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// All symbols, ordered by address
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        let mut entries: Vec<_> = self
+            .by_name
+            .iter()
+            .map(|(name, addr)| (name.as_str(), *addr))
+            .collect();
+        entries.sort_by_key(|(name, addr)| (*addr, *name));
+        entries.into_iter()
+    }
+}
+
+/// Writes the table in the same format `lc3as` uses, so it can be read back by [`SymbolTable::parse`]
+impl fmt::Display for SymbolTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "// Symbol table")?;
+        writeln!(f, "// Scope level 0:")?;
+        writeln!(f, "//\tSymbol Name       Page Address")?;
+        writeln!(f, "//\t----------------  ------------")?;
+        for (name, addr) in self.iter() {
+            writeln!(f, "//\t{name:<16}  {addr:04X}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let src = "// Symbol table\n\
+                   // Scope level 0:\n\
+                   //\tSymbol Name       Page Address\n\
+                   //\t----------------  ------------\n\
+                   //\tLOOP              3002\n\
+                   //\tDONE              300A\n\
+                   \n\
+                   DATA x4000\n";
+        let table = SymbolTable::parse(src);
+
+        assert_eq!(table.lookup("LOOP"), Some(0x3002));
+        assert_eq!(table.lookup("DONE"), Some(0x300a));
+        assert_eq!(table.lookup("DATA"), Some(0x4000));
+        assert_eq!(table.lookup("Symbol"), None);
+        assert_eq!(table.name_at(0x3002), Some("LOOP"));
+        assert_eq!(table.nearest(0x3005), Some(("LOOP", 0x3002)));
+        assert_eq!(table.nearest(0x2fff), None);
+
+        let reparsed = SymbolTable::parse(&table.to_string());
+        assert_eq!(
+            reparsed.iter().collect::<Vec<_>>(),
+            table.iter().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt::Write;
+
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use crate::{
+    enums::{MemMappedReg, RawOpCode, Register, TrapCode},
+    memory::{AddrSet, MemoryManager, RegisterManager},
+    utils::sign_extend,
+};
+
+/// Tracks which registers and memory cells hold data derived from keyboard input.
+///
+/// Only data flow is tracked: a branch on a tainted value doesn't taint what
+/// the branch goes on to compute.
+#[derive(Default, Clone)]
+pub struct Taint {
+    /// Bit per R0-R7
+    regs: u8,
+    mem: AddrSet,
+    output: u64,
+    tainted_output: u64,
+}
+
+impl Taint {
+    /// Propagates taint for `instr`, about to execute with the machine in its
+    /// pre-execution state (PC already incremented past the instruction)
+    pub fn propagate(&mut self, instr: u16, reg: &RegisterManager, mem: &MemoryManager) {
+        let dest = ((instr >> 9) & 0x7) as usize;
+        let base = ((instr >> 6) & 0x7) as usize;
+        let pc = reg.get(Register::PC);
+
+        match RawOpCode::from_u16(instr >> 12).unwrap() {
+            RawOpCode::Add | RawOpCode::And => {
+                let imm = (instr >> 5) & 0x1 == 1;
+                // AND Rx, Rx, #0 is the idiom for clearing a register
+                let cleared = imm && instr >> 12 == RawOpCode::And as u16 && instr & 0x1F == 0;
+                let tainted =
+                    !cleared && (self.reg(base) || (!imm && self.reg((instr & 0x7) as usize)));
+                self.set_reg(dest, tainted);
+            }
+            RawOpCode::Not => self.set_reg(dest, self.reg(base)),
+            RawOpCode::Ld => {
+                let addr = pc.wrapping_add(sign_extend(instr & 0x1FF, 9));
+                self.set_reg(dest, self.cell(addr));
+            }
+            RawOpCode::Ldr => {
+                let addr = reg
+                    .get(reg_at(base))
+                    .wrapping_add(sign_extend(instr & 0x3F, 6));
+                self.set_reg(dest, self.cell(addr));
+            }
+            RawOpCode::Ldi => {
+                let addr = mem.peek(pc.wrapping_add(sign_extend(instr & 0x1FF, 9)));
+                self.set_reg(dest, self.cell(addr));
+            }
+            RawOpCode::Lea => self.set_reg(dest, false),
+            RawOpCode::St => {
+                let addr = pc.wrapping_add(sign_extend(instr & 0x1FF, 9));
+                self.set_cell(addr, self.reg(dest));
+            }
+            RawOpCode::Str => {
+                let addr = reg
+                    .get(reg_at(base))
+                    .wrapping_add(sign_extend(instr & 0x3F, 6));
+                self.set_cell(addr, self.reg(dest));
+            }
+            RawOpCode::Sti => {
+                let addr = mem.peek(pc.wrapping_add(sign_extend(instr & 0x1FF, 9)));
+                self.set_cell(addr, self.reg(dest));
+            }
+            RawOpCode::Jsr => self.set_reg(7, false),
+            RawOpCode::Trap => match TrapCode::from_u16(instr & 0xFF) {
+                Some(TrapCode::GetC | TrapCode::In) => self.set_reg(0, true),
+                Some(TrapCode::Out) => self.output(1, self.reg(0) as u64),
+                Some(TrapCode::Puts) => self.output_string(reg.get(Register::R0), mem, false),
+                Some(TrapCode::PutsP) => self.output_string(reg.get(Register::R0), mem, true),
+                _ => (),
+            },
+            RawOpCode::Br | RawOpCode::Jmp | RawOpCode::Rti | RawOpCode::Noop => (),
+        }
+    }
+
+    pub fn is_reg_tainted(&self, reg: usize) -> bool {
+        self.reg(reg)
+    }
+
+    pub fn is_mem_tainted(&self, addr: u16) -> bool {
+        self.cell(addr)
+    }
+
+    /// Summary of what ended up influenced by input
+    pub fn report(&self) -> String {
+        let mut out = String::from("Taint report:\n");
+        writeln!(
+            out,
+            "  {} of {} output characters derived from input",
+            self.tainted_output, self.output
+        )
+        .unwrap();
+
+        let ranges: Vec<_> = self
+            .mem
+            .ranges()
+            .into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    format!("x{first:04X}")
+                } else {
+                    format!("x{first:04X}-x{last:04X}")
+                }
+            })
+            .collect();
+        let ranges = if ranges.is_empty() {
+            "none".to_owned()
+        } else {
+            ranges.join(", ")
+        };
+        writeln!(out, "  Tainted memory: {ranges}").unwrap();
+
+        let regs: Vec<_> = (0..8)
+            .filter(|reg| self.reg(*reg))
+            .map(|reg| format!("R{reg}"))
+            .collect();
+        let regs = if regs.is_empty() {
+            "none".to_owned()
+        } else {
+            regs.join(", ")
+        };
+        writeln!(out, "  Tainted registers: {regs}").unwrap();
+
+        out
+    }
+
+    fn reg(&self, reg: usize) -> bool {
+        self.regs & (1 << reg) != 0
+    }
+
+    fn set_reg(&mut self, reg: usize, tainted: bool) {
+        if tainted {
+            self.regs |= 1 << reg;
+        } else {
+            self.regs &= !(1 << reg);
+        }
+    }
+
+    /// The keyboard data register always holds input
+    fn cell(&self, addr: u16) -> bool {
+        addr == MemMappedReg::Kbdr.to_u16().unwrap() || self.mem.contains(addr)
+    }
+
+    fn set_cell(&mut self, addr: u16, tainted: bool) {
+        if tainted {
+            self.mem.insert(addr);
+        } else {
+            self.mem.remove(addr);
+        }
+    }
+
+    fn output(&mut self, chars: u64, tainted: u64) {
+        self.output += chars;
+        self.tainted_output += tainted;
+    }
+
+    fn output_string(&mut self, mut addr: u16, mem: &MemoryManager, packed: bool) {
+        while mem.peek(addr) != 0 {
+            let chars = if packed && mem.peek(addr) >> 8 != 0 {
+                2
+            } else {
+                1
+            };
+            self.output(chars, if self.cell(addr) { chars } else { 0 });
+            addr = addr.wrapping_add(1);
+        }
+    }
+}
+
+fn reg_at(idx: usize) -> Register {
+    Register::from_usize(idx).unwrap()
+}
+
+#[allow(clippy::unusual_byte_groupings)]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_propagation() {
+        let mut taint = Taint::default();
+        let mut reg = RegisterManager::default();
+        let mut mem = MemoryManager::default();
+
+        // GETC; ADD R1, R0, #1; ST R1, #5; AND R0, R0, #0
+        taint.propagate(0xF020, &reg, &mem);
+        taint.propagate(0b0001_001_000_1_00001, &reg, &mem);
+        reg.set(Register::PC, 0x3003);
+        taint.propagate(0b0011_001_000000101, &reg, &mem);
+        taint.propagate(0b0101_000_000_1_00000, &reg, &mem);
+
+        assert!(!taint.is_reg_tainted(0));
+        assert!(taint.is_reg_tainted(1));
+        assert!(taint.is_mem_tainted(0x3008));
+
+        // LDI R2 through a pointer to the tainted cell, then PUTS it
+        mem.write(0x3010, 0x3008);
+        mem.write(0x3008, 'a' as u16);
+        reg.set(Register::PC, 0x3001);
+        taint.propagate(0b1010_010_000001111, &reg, &mem);
+        assert!(taint.is_reg_tainted(2));
+
+        reg.set(Register::R0, 0x3008);
+        taint.propagate(0xF022, &reg, &mem);
+        let report = taint.report();
+        assert!(report.contains("1 of 1 output characters"));
+        assert!(report.contains("Tainted memory: x3008\n"));
+        assert!(report.contains("Tainted registers: R1, R2\n"));
+    }
+}
@@ -0,0 +1,154 @@
+//! The host terminal's raw mode, behind a [`Backend`] picked at build time:
+//!
+//! - `crossterm` (the default) works on Unix and Windows
+//! - `stty` runs the Unix `stty` tool, for builds without crossterm
+//! - `dummy` leaves the terminal alone, which suits tests and CI. It's also
+//!   what a build without any backend gets
+//!
+//! `dummy` wins over the others, and `stty` over `crossterm`.
+
+use std::io;
+
+/// Turns raw mode, with bracketed paste, on and off
+pub trait Backend: Sync {
+    fn enable_raw_mode(&self) -> io::Result<()>;
+
+    fn disable_raw_mode(&self) -> io::Result<()>;
+
+    fn name(&self) -> &'static str;
+}
+
+#[cfg(any(feature = "crossterm", all(unix, feature = "stty")))]
+fn bracketed_paste(on: bool) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[?2004{}", if on { 'h' } else { 'l' })?;
+    stdout.flush()
+}
+
+/// The backend this build uses
+pub fn backend() -> &'static dyn Backend {
+    #[cfg(all(unix, feature = "stty", not(feature = "dummy")))]
+    return &Stty;
+    #[cfg(all(
+        feature = "crossterm",
+        not(all(unix, feature = "stty")),
+        not(feature = "dummy")
+    ))]
+    return &Crossterm;
+    #[allow(unreachable_code)]
+    &Dummy
+}
+
+/// Leaves the terminal as it is
+pub struct Dummy;
+
+impl Backend for Dummy {
+    fn enable_raw_mode(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "built without a terminal backend",
+        ))
+    }
+
+    fn disable_raw_mode(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "dummy"
+    }
+}
+
+#[cfg(feature = "crossterm")]
+pub struct Crossterm;
+
+#[cfg(feature = "crossterm")]
+impl Backend for Crossterm {
+    fn enable_raw_mode(&self) -> io::Result<()> {
+        crossterm::terminal::enable_raw_mode()?;
+        bracketed_paste(true)
+    }
+
+    fn disable_raw_mode(&self) -> io::Result<()> {
+        bracketed_paste(false)?;
+        crossterm::terminal::disable_raw_mode()
+    }
+
+    fn name(&self) -> &'static str {
+        "crossterm"
+    }
+}
+
+/// Raw mode through `stty`, restoring the settings it found
+#[cfg(all(unix, feature = "stty"))]
+pub struct Stty;
+
+#[cfg(all(unix, feature = "stty"))]
+static STTY_SAVED: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+#[cfg(all(unix, feature = "stty"))]
+impl Stty {
+    /// Runs `stty` on the terminal at stdin
+    fn stty(args: &[&str]) -> io::Result<String> {
+        let output = std::process::Command::new("stty")
+            .args(args)
+            .stdin(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other("stdin is not a terminal"));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+}
+
+#[cfg(all(unix, feature = "stty"))]
+impl Backend for Stty {
+    fn enable_raw_mode(&self) -> io::Result<()> {
+        let mut saved = STTY_SAVED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if saved.is_none() {
+            let settings = Self::stty(&["-g"])?;
+            Self::stty(&["raw", "-echo"])?;
+            *saved = Some(settings);
+        }
+        bracketed_paste(true)
+    }
+
+    fn disable_raw_mode(&self) -> io::Result<()> {
+        let saved = STTY_SAVED
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take();
+        match saved {
+            Some(settings) => {
+                bracketed_paste(false)?;
+                Self::stty(&[&settings]).map(drop)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "stty"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dummy() {
+        assert!(Dummy.enable_raw_mode().is_err());
+        assert!(Dummy.disable_raw_mode().is_ok());
+        #[cfg(any(
+            feature = "dummy",
+            not(any(feature = "crossterm", all(unix, feature = "stty")))
+        ))]
+        assert_eq!(backend().name(), "dummy");
+    }
+}
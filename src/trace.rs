@@ -0,0 +1,97 @@
+use num_traits::FromPrimitive;
+use std::str::FromStr;
+
+use crate::{checks::MemRegion, enums::RawOpCode};
+
+/// Opcodes to trace, as a bit per opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodeSet(u16);
+
+impl OpcodeSet {
+    pub fn contains(&self, opcode: RawOpCode) -> bool {
+        self.0 & (1 << opcode as u16) != 0
+    }
+}
+
+impl FromStr for OpcodeSet {
+    type Err = String;
+
+    /// Parses a comma separated list of mnemonics, e.g. `BR,JSR`. Aliases
+    /// select the opcode they share: RET is JMP, JSRR is JSR and the trap
+    /// aliases (HALT, PUTS, ...) are TRAP.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mask = 0;
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let opcode = match name.to_ascii_uppercase().as_str() {
+                "ADD" => RawOpCode::Add,
+                "AND" => RawOpCode::And,
+                "NOT" => RawOpCode::Not,
+                "BR" => RawOpCode::Br,
+                "JMP" | "RET" => RawOpCode::Jmp,
+                "JSR" | "JSRR" => RawOpCode::Jsr,
+                "LD" => RawOpCode::Ld,
+                "LDI" => RawOpCode::Ldi,
+                "LDR" => RawOpCode::Ldr,
+                "LEA" => RawOpCode::Lea,
+                "ST" => RawOpCode::St,
+                "STI" => RawOpCode::Sti,
+                "STR" => RawOpCode::Str,
+                "RTI" => RawOpCode::Rti,
+                "TRAP" | "GETC" | "OUT" | "PUTS" | "IN" | "PUTSP" | "HALT" => RawOpCode::Trap,
+                _ => return Err(format!("`{name}` is not an LC-3 mnemonic")),
+            };
+            mask |= 1 << opcode as u16;
+        }
+        Ok(Self(mask))
+    }
+}
+
+/// Which executed instructions the instruction trace logs. Checked as each
+/// instruction executes, so a long run only pays for formatting the lines
+/// that are kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only these opcodes, all when unset
+    pub opcodes: Option<OpcodeSet>,
+    /// Only instructions fetched from this region, anywhere when unset
+    pub range: Option<MemRegion>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, word: u16) -> bool {
+        let opcode = RawOpCode::from_u16(word >> 12).unwrap_or(RawOpCode::Noop);
+        self.opcodes.is_none_or(|opcodes| opcodes.contains(opcode))
+            && self
+                .range
+                .is_none_or(|range| (range.first..=range.last).contains(&pc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opcode_set() {
+        let set: OpcodeSet = "br, jsrr,HALT".parse().unwrap();
+        assert!(set.contains(RawOpCode::Br));
+        assert!(set.contains(RawOpCode::Jsr));
+        assert!(set.contains(RawOpCode::Trap));
+        assert!(!set.contains(RawOpCode::Add));
+        assert!("BR,FOO".parse::<OpcodeSet>().is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        assert!(TraceFilter::default().matches(0x3000, 0x1261));
+
+        let filter = TraceFilter {
+            opcodes: Some("BR,JSR".parse().unwrap()),
+            range: Some("x3000-x30FF".parse().unwrap()),
+        };
+        assert!(filter.matches(0x3000, 0x0FFE));
+        assert!(filter.matches(0x30FF, 0x4803));
+        assert!(!filter.matches(0x3000, 0x1261));
+        assert!(!filter.matches(0x3100, 0x0FFE));
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt::Write;
+
+use crate::enums::Register;
+
 pub fn sign_extend(mut x: u16, bit_count: u16) -> u16 {
     // Early return if bit_count is 0
     if bit_count == 0 {
@@ -14,6 +18,64 @@ pub fn handle_newline(s: &str) -> String {
     s.replace("\n", "\r\n")
 }
 
+/// Escapes `s` for use inside a JSON string literal
+pub fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if ch.is_control() => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Parses a numeric literal (`x3000`, `0x3000`, `#-5`, `12`)
+pub fn parse_number(s: &str) -> Option<u16> {
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+
+    let val = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix(['x', 'X']))
+    {
+        u16::from_str_radix(hex, 16).ok()?
+    } else {
+        let dec = digits.strip_prefix('#').unwrap_or(digits);
+        if let Some(dec) = dec.strip_prefix('-') {
+            (dec.parse::<i32>().ok()? as u16).wrapping_neg()
+        } else {
+            let val = dec.parse::<i32>().ok()?;
+            if !(-32768..=65535).contains(&val) {
+                return None;
+            }
+            val as u16
+        }
+    };
+
+    Some(if neg { val.wrapping_neg() } else { val })
+}
+
+pub fn parse_register(s: &str) -> Option<Register> {
+    match s.to_ascii_uppercase().as_str() {
+        "R0" => Some(Register::R0),
+        "R1" => Some(Register::R1),
+        "R2" => Some(Register::R2),
+        "R3" => Some(Register::R3),
+        "R4" => Some(Register::R4),
+        "R5" => Some(Register::R5),
+        "R6" => Some(Register::R6),
+        "R7" => Some(Register::R7),
+        "PC" => Some(Register::PC),
+        "COND" => Some(Register::COND),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -36,4 +98,22 @@ mod tests {
             0b1101_0110_1100_1010u16
         );
     }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("x3000"), Some(0x3000));
+        assert_eq!(parse_number("0xFE00"), Some(0xfe00));
+        assert_eq!(parse_number("#10"), Some(10));
+        assert_eq!(parse_number("#-1"), Some(0xffff));
+        assert_eq!(parse_number("-5"), Some(0xfffb));
+        assert_eq!(parse_number("65535"), Some(0xffff));
+        assert_eq!(parse_number("65536"), None);
+        assert_eq!(parse_number("LOOP"), None);
+    }
+
+    #[test]
+    fn test_json_escape() {
+        assert_eq!(json_escape("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(json_escape("1\n2"), "1\\u000a2");
+    }
 }
@@ -1,332 +1,2098 @@
-use byteorder::{BigEndian, ReadBytesExt};
 use colored::Colorize;
-use num_traits::{FromPrimitive, ToPrimitive};
+use num_traits::FromPrimitive;
 use std::{
-    fs::File,
-    io::{self, BufReader, Read, Write},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt, fs,
+    io::{self, Write},
     path::PathBuf,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    constants::MAX_MEMORY,
-    enums::{CondFlag, RawOpCode, Register, TrapCode},
-    error::{Error, ErrorKind, Result},
+    banking::Banks,
+    branches::BranchStats,
+    callstack::{CallStack, Frame},
+    checkpoint::{Checkpoint, Checkpoints},
+    checks::{
+        CalleeSaved, Check, CheckMode, ConventionCheck, ForbiddenOpcodes, MemRegion, StackBounds,
+        TrapSet,
+    },
+    chrome_trace::ChromeTrace,
+    console::{Console, Echo, InStyle},
+    constants::{
+        ACCESS_VIOLATION_VECTOR, DEVICE_START, EXCEPTION_TABLE, IDLE_SPIN_GAP, IDLE_SPIN_POLLS,
+        IDLE_WAIT, ILLEGAL_OPCODE_VECTOR, INTERRUPT_TABLE, KBSR_INTERRUPT_ENABLE, KBSR_READY,
+        KEYBOARD_PRIORITY, KEYBOARD_VECTOR, MCR_CLOCK_ENABLE, PRIVILEGE_VECTOR, USER_START,
+        WATCHDOG_PRIORITY, WATCHDOG_VECTOR,
+    },
+    coverage::Coverage,
+    crash::Fault,
+    debugger::Debugger,
+    disasm,
+    dump::{self, StateDump},
+    enums::{ArithTrap, CondFlag, IoTrap, MemMappedReg, RawOpCode, Register, TrapCode},
+    error::{Error, ErrorKind, Result, VmError, VmErrorKind},
+    ext::{ExtInstruction, Extensions},
+    handle::MachineHandle,
+    history::{Executed, History},
+    hypercall::{HostReport, Hypercall},
+    image::{DataFile, Image},
+    instruction::{AluOperand, Instruction},
+    interrupt::{InterruptController, Psr},
+    keylog::Keystroke,
     memory::{MemoryManager, RegisterManager},
+    perfcount::PerfCounters,
+    profile::Profiler,
+    symbols::SymbolTable,
+    taint::Taint,
+    trace::TraceFilter,
     utils::{handle_newline, sign_extend},
+    vsync::Vsync,
+    watchdog::{Watchdog, WatchdogAction},
+    writelog::MemWrite,
 };
 
+/// Result of executing a single instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The machine can keep going
+    Continue,
+    /// The program executed HALT
+    Halted,
+    /// The machine stopped for another reason, e.g. input ran out or a strict check fired
+    Stopped,
+}
+
+/// Why [`Machine::run`] last returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The program executed HALT
+    Halted,
+    /// The program reported passing or failing through the hypercall register
+    Hypercall,
+    /// The program waited for input that had run out
+    InputExhausted,
+    /// The step limit was reached
+    StepLimit,
+    /// A store cleared the clock enable bit of the machine control register
+    McrCleared,
+    /// Execution ran past xFFFF and would have wrapped around to x0000
+    PcOverflow,
+    /// A strict check stopped the program, see [`Machine::fault`]
+    CheckFailed,
+    /// An instruction failed to execute, see [`Machine::fault`]
+    Error,
+    /// Stopped through a [`MachineHandle`]
+    Stopped,
+    /// The user quit the debugger
+    Quit,
+}
+
+impl ExitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Halted => "halted",
+            Self::Hypercall => "hypercall",
+            Self::InputExhausted => "input exhausted",
+            Self::StepLimit => "step limit",
+            Self::McrCleared => "MCR cleared",
+            Self::PcOverflow => "PC overflow",
+            Self::CheckFailed => "check failed",
+            Self::Error => "error",
+            Self::Stopped => "stopped",
+            Self::Quit => "quit",
+        }
+    }
+}
+
+/// What a single step executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepInfo {
+    /// Address the instruction was fetched from
+    pub pc: u16,
+    pub word: u16,
+    pub opcode: RawOpCode,
+    pub outcome: StepOutcome,
+}
+
+impl StepInfo {
+    pub fn halted(&self) -> bool {
+        self.outcome == StepOutcome::Halted
+    }
+
+    /// The instruction in assembly syntax, e.g. `ADD R0, R0, #1`
+    pub fn disassembly(&self) -> String {
+        disasm::disassemble(self.pc, self.word)
+    }
+}
+
+/// Differences between two machines, see [`Machine::diff`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// (register, value in this machine, value in the other)
+    pub registers: Vec<(Register, u16, u16)>,
+    /// (address, value in this machine, value in the other)
+    pub memory: Vec<(u16, u16, u16)>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.memory.is_empty()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (reg, ours, theirs) in &self.registers {
+            writeln!(f, "{reg:?}: x{ours:04X} -> x{theirs:04X}")?;
+        }
+        for (addr, ours, theirs) in &self.memory {
+            writeln!(f, "x{addr:04X}: x{ours:04X} -> x{theirs:04X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over executed instructions, see [`Machine::steps`]
+pub struct Steps<'a> {
+    machine: &'a mut Machine,
+    done: bool,
+    error: Option<Error>,
+}
+
+impl Steps<'_> {
+    /// The error that ended iteration early, if any
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<StepInfo> {
+        if self.done {
+            return None;
+        }
+
+        match self.machine.step() {
+            Ok(info) => {
+                self.done = info.outcome != StepOutcome::Continue;
+                Some(info)
+            }
+            Err(e) => {
+                self.done = true;
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Machine {
     reg: RegisterManager,
     mem: MemoryManager,
+    console: Box<dyn Console>,
     is_running: bool,
+    halted: bool,
     debug_mode: bool,
+    /// Code whose execution debug output is shown for, everywhere when empty
+    log_ranges: Vec<MemRegion>,
+    /// Executing an instruction outside `log_ranges`
+    muted: bool,
+    debugger: Option<Debugger>,
+    steps: u64,
+    /// Instruction register, the word being executed
+    ir: u16,
+    /// Address `ir` was fetched from
+    ir_addr: u16,
+    step_limit: Option<u64>,
+    call_stack: CallStack,
+    checkpoints: Option<Checkpoints>,
+    /// Every input byte consumed while checkpoints are enabled, so a rewind
+    /// can feed the program the same input again
+    input_log: Vec<Keystroke>,
+    /// Keys to hand out ahead of the console, each once the program has
+    /// executed as many instructions as when it was recorded
+    replay_input: VecDeque<Keystroke>,
+    /// Log input even without checkpoints, see [`Machine::input_log`]
+    record_input: bool,
+    /// Every store, when enabled, see [`Machine::write_log`]
+    write_log: Option<Vec<MemWrite>>,
+    /// Re-executing after a rewind, console output is suppressed
+    replaying: bool,
+    profiler: Option<Profiler>,
+    branch_stats: Option<BranchStats>,
+    coverage: Option<Coverage>,
+    chrome_trace: Option<ChromeTrace>,
+    trace: Option<TraceFilter>,
+    uninit_check: Option<CheckMode>,
+    smc_check: Option<CheckMode>,
+    stack_check: Option<(CheckMode, StackBounds)>,
+    convention_check: Option<ConventionCheck>,
+    taint: Option<Taint>,
+    /// Addresses below the stack pointer read by the current instruction
+    stack_reads: Vec<u16>,
+    /// Addresses already warned about, so loops don't repeat the same warning
+    warned: BTreeSet<(Check, u16)>,
+    /// Set by strict checks for the debugger to pause on
+    break_request: Option<String>,
+    /// Inclusive address range and name of every image loaded so far
+    loaded: Vec<(u16, u16, String)>,
+    read_only_check: Option<CheckMode>,
+    /// Regions stores may go to besides the loaded images
+    bounds_check: Option<(CheckMode, Vec<MemRegion>)>,
+    /// Out-of-bounds addresses stored to by the current instruction
+    wild_writes: Vec<u16>,
+    /// KBSR reads in a row that found no key, to tell a polling loop is idle
+    idle_polls: u32,
+    /// Instruction count at the last KBSR read that found no key
+    last_idle_poll: u64,
+    psr: Psr,
+    interrupts: InterruptController,
+    echo: Echo,
+    state_dump: Option<StateDump>,
+    handle: Option<MachineHandle>,
+    watchdog: Option<Watchdog>,
+    vsync: Vsync,
+    perf: Option<PerfCounters>,
+    #[cfg(feature = "gamepad")]
+    gamepad: Option<crate::gamepad::Gamepad>,
+    host_report: Option<HostReport>,
+    banks: Option<Banks>,
+    extensions: Extensions,
+    arith_traps: bool,
+    io_traps: bool,
+    history: Option<History>,
+    fault: Option<Fault>,
+    exit_reason: Option<ExitReason>,
+    /// Times each trap vector was invoked
+    trap_counts: BTreeMap<u8, u64>,
+    forbidden_traps: TrapSet,
+    forbidden_opcodes: Option<ForbiddenOpcodes>,
+    /// Every warning printed, in order
+    warnings: Vec<String>,
+    /// Wall time spent in [`Machine::run`]
+    run_time: Duration,
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.halted {
+            "halted"
+        } else if self.is_running {
+            "running"
+        } else {
+            "stopped"
+        };
+        writeln!(f, "{}", self.reg)?;
+        write!(f, "{} instructions executed, {state}", self.steps)
+    }
+}
+
+impl fmt::Debug for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("reg", &self.reg)
+            .field("steps", &self.steps)
+            .field("running", &self.is_running)
+            .field("halted", &self.halted)
+            .field("loaded", &self.loaded)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Clones run independently of the original. The debugger is interactive and
+/// stays with the original machine, clones start without one, and handles
+/// keep controlling the original. The gamepad is shared, it's one controller.
+impl Clone for Machine {
+    fn clone(&self) -> Self {
+        Self {
+            reg: self.reg.clone(),
+            mem: self.mem.clone(),
+            console: self.console.clone(),
+            is_running: self.is_running,
+            halted: self.halted,
+            debug_mode: self.debug_mode,
+            log_ranges: self.log_ranges.clone(),
+            muted: self.muted,
+            debugger: None,
+            steps: self.steps,
+            ir: self.ir,
+            ir_addr: self.ir_addr,
+            step_limit: self.step_limit,
+            call_stack: self.call_stack.clone(),
+            checkpoints: self.checkpoints.clone(),
+            input_log: self.input_log.clone(),
+            replay_input: self.replay_input.clone(),
+            record_input: self.record_input,
+            write_log: self.write_log.clone(),
+            replaying: self.replaying,
+            profiler: self.profiler.clone(),
+            branch_stats: self.branch_stats.clone(),
+            coverage: self.coverage.clone(),
+            chrome_trace: self.chrome_trace.clone(),
+            trace: self.trace,
+            uninit_check: self.uninit_check,
+            smc_check: self.smc_check,
+            stack_check: self.stack_check,
+            convention_check: self.convention_check.clone(),
+            taint: self.taint.clone(),
+            stack_reads: self.stack_reads.clone(),
+            warned: self.warned.clone(),
+            break_request: self.break_request.clone(),
+            loaded: self.loaded.clone(),
+            read_only_check: self.read_only_check,
+            bounds_check: self.bounds_check.clone(),
+            wild_writes: self.wild_writes.clone(),
+            idle_polls: self.idle_polls,
+            last_idle_poll: self.last_idle_poll,
+            psr: self.psr,
+            interrupts: self.interrupts.clone(),
+            echo: self.echo,
+            state_dump: self.state_dump.clone(),
+            handle: None,
+            watchdog: self.watchdog,
+            vsync: self.vsync,
+            perf: self.perf,
+            #[cfg(feature = "gamepad")]
+            gamepad: self.gamepad.clone(),
+            host_report: self.host_report.clone(),
+            banks: self.banks.clone(),
+            extensions: self.extensions,
+            arith_traps: self.arith_traps,
+            io_traps: self.io_traps,
+            history: self.history.clone(),
+            fault: self.fault.clone(),
+            exit_reason: self.exit_reason,
+            trap_counts: self.trap_counts.clone(),
+            forbidden_traps: self.forbidden_traps,
+            forbidden_opcodes: self.forbidden_opcodes,
+            warnings: self.warnings.clone(),
+            run_time: self.run_time,
+        }
+    }
+}
+
+// Machines can be moved to (and shared with) other threads, e.g. to run in
+// the background or in parallel batches. This fails to compile if a field
+// stops being Send or Sync.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Machine>();
+};
+
+/// Registers, memory and progress of a machine. Debugging and instrumentation
+/// state isn't included, a deserialized machine starts without any.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Machine {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Machine", 4)?;
+        state.serialize_field("reg", &self.reg)?;
+        state.serialize_field("mem", &self.mem)?;
+        state.serialize_field("steps", &self.steps)?;
+        state.serialize_field("halted", &self.halted)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Machine {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct State {
+            reg: RegisterManager,
+            mem: MemoryManager,
+            #[serde(default)]
+            steps: u64,
+            #[serde(default)]
+            halted: bool,
+        }
+
+        let state = State::deserialize(deserializer)?;
+        Ok(Self {
+            reg: state.reg,
+            mem: state.mem,
+            steps: state.steps,
+            halted: state.halted,
+            ..Default::default()
+        })
+    }
 }
 
 impl Machine {
+    pub fn with_console(console: Box<dyn Console>) -> Self {
+        Self {
+            console,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_console(&mut self, console: Box<dyn Console>) {
+        self.console = console;
+    }
+
+    pub fn set_echo(&mut self, echo: Echo) {
+        self.echo = echo;
+    }
+
+    /// Queues bytes to be read by the program ahead of anything from the console
+    pub fn feed_input(&mut self, bytes: &[u8]) {
+        self.replay_input
+            .extend(bytes.iter().copied().map(Keystroke::now));
+    }
+
+    /// Queues recorded keys, each held back until the program has executed as
+    /// many instructions as when it was recorded, so polling and interrupt
+    /// driven programs see keys arrive as they originally did
+    pub fn replay_keys(&mut self, keys: &[Keystroke]) {
+        self.replay_input.extend(keys);
+    }
+
+    /// Starts logging every key the program reads, see [`Machine::input_log`]
+    pub fn record_input(&mut self) {
+        self.record_input = true;
+    }
+
+    /// Keys read so far with the instruction count each arrived at, when
+    /// recording or checkpointing
+    pub fn input_log(&self) -> &[Keystroke] {
+        &self.input_log
+    }
+
+    /// Starts logging every store the program executes, see [`Machine::write_log`]
+    pub fn enable_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stores executed so far with the values they replaced, when enabled
+    pub fn write_log(&self) -> &[MemWrite] {
+        self.write_log.as_deref().unwrap_or_default()
+    }
+
+    /// Whether the next instruction reads a keyboard byte that hasn't been fed with
+    /// [`Machine::feed_input`]: a GETC or IN trap, or a load from KBSR
+    pub fn needs_input(&self) -> bool {
+        let latched = self.mem.peek(MemMappedReg::Kbsr as u16) & KBSR_READY != 0;
+        if !self.replay_input.is_empty() || latched {
+            return false;
+        }
+
+        let pc = self.reg.get(Register::PC);
+        let instr = self.mem.peek(pc);
+        let next = pc.wrapping_add(1);
+        let kbsr = MemMappedReg::Kbsr as u16;
+        match RawOpCode::from_u16(instr >> 12) {
+            Some(RawOpCode::Trap) => matches!(
+                TrapCode::from_u16(instr & 0xFF),
+                Some(TrapCode::GetC | TrapCode::In)
+            ),
+            Some(RawOpCode::Ld) => next.wrapping_add(sign_extend(instr & 0x1FF, 9)) == kbsr,
+            Some(RawOpCode::Ldi) => {
+                self.mem
+                    .peek(next.wrapping_add(sign_extend(instr & 0x1FF, 9)))
+                    == kbsr
+            }
+            Some(RawOpCode::Ldr) => {
+                let base = self.reg.get(Register::from_field(instr >> 6));
+                base.wrapping_add(sign_extend(instr & 0x3F, 6)) == kbsr
+            }
+            _ => false,
+        }
+    }
+
+    /// Stop running after `limit` instructions, e.g. to catch runaway programs in tests
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Number of instructions executed so far
+    pub fn step_count(&self) -> u64 {
+        self.steps
+    }
+
+    /// Registers (including PC and COND) and memory cells that differ from `other`
+    pub fn diff(&self, other: &Machine) -> StateDiff {
+        let registers = other.reg.changes(&self.reg);
+        let memory = (0..=u16::MAX)
+            .map(|addr| (addr, self.mem.peek(addr), other.mem.peek(addr)))
+            .filter(|(_, ours, theirs)| ours != theirs)
+            .collect();
+
+        StateDiff { registers, memory }
+    }
+
+    /// Whether the program stopped by executing the HALT trap
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
     pub fn enter_debug_mode(&mut self) {
+        self.attach_debugger(Debugger::default());
+    }
+
+    pub fn attach_debugger(&mut self, debugger: Debugger) {
         self.debug_mode = true;
+        self.debugger = Some(debugger);
     }
 
-    pub fn debug(&self, s: &str) {
-        if self.debug_mode {
-            let s = handle_newline(s);
-            let prompt = "[Debug]".cyan().bold();
+    pub fn reg(&self) -> &RegisterManager {
+        &self.reg
+    }
+
+    pub fn reg_mut(&mut self) -> &mut RegisterManager {
+        &mut self.reg
+    }
+
+    pub fn mem(&self) -> &MemoryManager {
+        &self.mem
+    }
+
+    pub fn mem_mut(&mut self) -> &mut MemoryManager {
+        &mut self.mem
+    }
+
+    /// Waits for the console to write out the program's output so far
+    pub fn flush_output(&mut self) {
+        self.console.flush();
+    }
+
+    /// Calls the subroutine at `entry` as a JSR placed just before PC would,
+    /// so it returns to the instruction PC points at. Lets the debugger run a
+    /// routine in isolation
+    pub fn call(&mut self, entry: u16) {
+        self.reg.copy(Register::R7, Register::PC);
+        self.reg.set(Register::PC, entry);
+        self.called();
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    /// Snapshot the machine every `interval` instructions, keeping the last `capacity` snapshots
+    pub fn enable_checkpoints(&mut self, interval: u64, capacity: usize) {
+        self.checkpoints = Some(Checkpoints::new(interval, capacity));
+    }
+
+    pub fn checkpoints(&self) -> Option<&Checkpoints> {
+        self.checkpoints.as_ref()
+    }
+
+    /// Attribute executed instructions to subroutines, see [`Profiler`]
+    pub fn enable_profiling(&mut self) {
+        self.profiler = Some(Profiler::default());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Count how often each conditional branch is taken, see [`BranchStats`]
+    pub fn enable_branch_stats(&mut self) {
+        self.branch_stats = Some(BranchStats::default());
+    }
+
+    pub fn branch_stats(&self) -> Option<&BranchStats> {
+        self.branch_stats.as_ref()
+    }
+
+    /// Count how often each instruction executes, see [`Coverage`]
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Record subroutine calls and traps for export as a Chrome trace
+    pub fn enable_chrome_trace(&mut self) {
+        self.chrome_trace = Some(ChromeTrace::default());
+    }
+
+    pub fn chrome_trace(&self) -> Option<&ChromeTrace> {
+        self.chrome_trace.as_ref()
+    }
+
+    /// Log every executed instruction that passes `filter` to stderr, with
+    /// the instruction count, address, word and disassembly
+    pub fn enable_trace(&mut self, filter: TraceFilter) {
+        self.trace = Some(filter);
+    }
+
+    /// Report program reads of memory that was never written by the loader or the program
+    pub fn check_uninit(&mut self, mode: Option<CheckMode>) {
+        self.uninit_check = mode;
+        self.mem.check_uninit(mode.is_some());
+    }
+
+    /// Report writes to addresses that have already been executed.
+    ///
+    /// Instructions are decoded from memory on every fetch, so self-modifying
+    /// code runs correctly either way; this only flags it.
+    pub fn check_self_modifying(&mut self, mode: Option<CheckMode>) {
+        self.smc_check = mode;
+    }
+
+    /// Report R6 leaving `bounds` and reads from the dead area below the stack pointer
+    pub fn check_stack(&mut self, mode: Option<CheckMode>, bounds: StackBounds) {
+        self.stack_check = mode.map(|mode| (mode, bounds));
+    }
+
+    /// Write-protect `regions` and any OS image loaded from now on. Program
+    /// writes to protected memory are dropped and reported.
+    pub fn check_read_only(&mut self, mode: Option<CheckMode>, regions: &[MemRegion]) {
+        self.read_only_check = mode;
+        if mode.is_some() {
+            for region in regions {
+                self.mem.protect(region.first, region.last);
+            }
+        }
+    }
+
+    /// Report stores outside the loaded images, the `stack` region and
+    /// `writable`, e.g. a wild ST or STR through a bad pointer. The stores
+    /// still happen. Device registers are always writable, and code running
+    /// in supervisor mode isn't checked.
+    pub fn check_bounds(
+        &mut self,
+        mode: Option<CheckMode>,
+        stack: StackBounds,
+        writable: &[MemRegion],
+    ) {
+        self.bounds_check = mode.map(|mode| {
+            let stack = MemRegion {
+                first: stack.low,
+                last: stack.high,
+            };
+            (mode, [&[stack], writable].concat())
+        });
+    }
+
+    /// Expects the program to write to the watchdog register (xFE10) at least
+    /// once every `watchdog.timeout` instructions
+    pub fn enable_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Frame rate of the vsync register (xFE12). Without one, or with 0, every
+    /// read finds a new frame
+    pub fn set_fps(&mut self, fps: u32) {
+        self.vsync = Vsync::new(fps);
+    }
+
+    /// The gamepad behind the gamepad register (xFE14), for a frontend to
+    /// press and release buttons on. Until the first call xFE14 is plain memory.
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad(&mut self) -> crate::gamepad::Gamepad {
+        self.gamepad.get_or_insert_with(Default::default).clone()
+    }
+
+    /// Value of the gamepad register, `None` while it's plain memory
+    #[cfg(feature = "gamepad")]
+    fn gamepad_state(&self) -> Option<u16> {
+        self.gamepad.as_ref().map(|gamepad| gamepad.state())
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn gamepad_state(&self) -> Option<u16> {
+        None
+    }
+
+    /// Map the instruction and cycle counters at xFE1A-xFE20, see [`PerfCounters`]
+    pub fn enable_perf_counters(&mut self, enabled: bool) {
+        self.perf = enabled.then(PerfCounters::default);
+    }
+
+    /// Handle writes to the hypercall register (xFE16), see [`Hypercall`]
+    pub fn enable_hypercalls(&mut self) {
+        self.host_report = Some(HostReport::default());
+    }
+
+    /// What the program reported through hypercalls, when enabled
+    pub fn host_report(&self) -> Option<&HostReport> {
+        self.host_report.as_ref()
+    }
+
+    /// Map `count` banks of extra memory into `window`, switched through the
+    /// bank select register (xFE18). Until then xFE18 is plain memory.
+    pub fn enable_banking(&mut self, count: u16, window: MemRegion) {
+        self.banks = Some(Banks::new(count, window));
+    }
+
+    pub fn banks(&self) -> Option<&Banks> {
+        self.banks.as_ref()
+    }
+
+    /// Execute the extended instructions in `ext` rather than raising an
+    /// illegal opcode for them, see [`ExtInstruction`]
+    pub fn set_extensions(&mut self, ext: Extensions) {
+        self.extensions = ext;
+    }
+
+    /// Implement the MUL, DIV and MOD traps (x40-x42), see [`ArithTrap`].
+    /// Disabled they are unknown traps like any other
+    pub fn enable_arith_traps(&mut self, enabled: bool) {
+        self.arith_traps = enabled;
+    }
+
+    /// Implement the decimal number and line input traps (x26-x28), see
+    /// [`IoTrap`]. Disabled they are unknown traps like any other
+    pub fn enable_io_traps(&mut self, enabled: bool) {
+        self.io_traps = enabled;
+    }
+
+    /// Remember the last `count` instructions executed, see [`History`]. They
+    /// are printed when the program crashes or hits the step limit
+    pub fn enable_history(&mut self, count: usize) {
+        self.history = (count > 0).then(|| History::new(count));
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// The strict check or execution error that stopped the program, if one did
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    pub fn exit_reason(&self) -> Option<ExitReason> {
+        self.exit_reason
+    }
+
+    /// Times each trap vector was invoked, by vector
+    pub fn trap_counts(&self) -> &BTreeMap<u8, u64> {
+        &self.trap_counts
+    }
+
+    /// Stop with an error when the program uses one of `traps`, for
+    /// assignments that rule out OS services
+    pub fn forbid_traps(&mut self, traps: TrapSet) {
+        self.forbidden_traps = traps;
+    }
+
+    /// Stop with an error, naming the instruction, when the program executes
+    /// one of `opcodes`, e.g. LDI in an assignment that rules it out
+    pub fn forbid_opcodes(&mut self, opcodes: Option<ForbiddenOpcodes>) {
+        self.forbidden_opcodes = opcodes;
+    }
+
+    /// Warnings printed so far, by checks and devices
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Wall time spent running, summed over calls to [`Machine::run`]
+    pub fn run_time(&self) -> Duration {
+        self.run_time
+    }
+
+    /// Address and word of the instruction executing, or executed last
+    pub fn last_instruction(&self) -> (u16, u16) {
+        (self.ir_addr, self.ir)
+    }
+
+    /// Report subroutines that return with `callee_saved` registers changed,
+    /// or whose return address in R7 got clobbered
+    pub fn check_convention(&mut self, mode: Option<CheckMode>, callee_saved: CalleeSaved) {
+        self.convention_check = mode.map(|mode| ConventionCheck::new(mode, callee_saved));
+    }
+
+    /// Track which data is derived from keyboard input, see [`Taint`]
+    pub fn enable_taint(&mut self) {
+        self.taint = Some(Taint::default());
+    }
+
+    pub fn taint(&self) -> Option<&Taint> {
+        self.taint.as_ref()
+    }
 
-            write!(io::stdout(), "{prompt} {s}\r\n").expect("Failed to write to stdout");
+    /// The processor status register, as the program would see it
+    pub fn psr(&self) -> u16 {
+        self.psr.word(self.reg.get(Register::COND))
+    }
+
+    /// Requests an interrupt through `vector` of the interrupt vector table on
+    /// behalf of a device with the given priority (0-7). It's serviced before
+    /// the next instruction once the program runs at a lower priority.
+    pub fn raise_interrupt(&mut self, priority: u8, vector: u8) {
+        self.interrupts.raise(priority, vector);
+    }
+
+    /// Handle for pausing, resuming and stopping [`Machine::run`] from another thread
+    pub fn handle(&mut self) -> MachineHandle {
+        self.handle
+            .get_or_insert_with(MachineHandle::default)
+            .clone()
+    }
+
+    /// Writes the machine state whenever `dump.trigger` is set, e.g. by a
+    /// signal handler. Also sets the memory window [`Machine::state_json`] covers.
+    pub fn enable_state_dumps(&mut self, dump: StateDump) {
+        self.state_dump = Some(dump);
+    }
+
+    /// The machine state as JSON, see [`dump::state_json`]
+    pub fn state_json(&self) -> String {
+        let window = match &self.state_dump {
+            Some(dump) => dump.window(self),
+            None => StateDump::default().window(self),
+        };
+        dump::state_json(self, window)
+    }
+
+    fn write_state_dump(&mut self) {
+        let json = self.state_json();
+        let result = match self.state_dump.as_ref().and_then(|dump| dump.path.as_ref()) {
+            Some(path) => fs::write(path, json + "\n"),
+            None => write!(io::stderr(), "{json}\r\n"),
+        };
+        if let Err(e) = result {
+            self.warn(&format!("Failed to write state dump: {e}"));
         }
     }
 
-    pub fn run(&mut self) {
+    /// Reason a strict check wants the debugger to pause, if any
+    pub fn take_break_request(&mut self) -> Option<String> {
+        self.break_request.take()
+    }
+
+    /// Travels back to the point where `target` instructions had been executed,
+    /// by restoring the nearest earlier checkpoint and re-executing from there
+    pub fn rewind_to(&mut self, target: u64) -> Result<()> {
+        if target > self.steps {
+            return Err(Error::with_message(
+                ErrorKind::CheckpointError,
+                format!("can't rewind forward to step {target}"),
+            ));
+        }
+        let Some(checkpoints) = self.checkpoints.as_mut() else {
+            return Err(Error::with_message(
+                ErrorKind::CheckpointError,
+                "checkpoints are not enabled",
+            ));
+        };
+        let Some(checkpoint) = checkpoints.rewind(target) else {
+            return Err(Error::with_message(
+                ErrorKind::CheckpointError,
+                format!("no checkpoint at or before step {target}"),
+            ));
+        };
+
+        self.reg = checkpoint.reg;
+        self.mem.restore(&checkpoint.memory);
+        self.call_stack = checkpoint.call_stack;
+        self.psr = checkpoint.psr;
+        self.interrupts = checkpoint.interrupts;
+        self.perf = checkpoint.perf;
+        self.watchdog = checkpoint.watchdog;
+        self.banks = checkpoint.banks;
+        self.convention_check = checkpoint.convention_check;
+        self.taint = checkpoint.taint;
+        self.steps = checkpoint.steps;
         self.is_running = true;
+        self.halted = false;
+        if let Some(history) = self.history.as_mut() {
+            history.truncate_from(checkpoint.steps);
+        }
 
-        while self.is_running && (self.reg.get(Register::PC) as usize) < MAX_MEMORY {
-            let posn = format!("[PC = {:#x}]", self.reg.get(Register::PC)).yellow();
-            self.debug(format!("Paused at {posn}").as_str());
-            let raw_instr = self.fetch();
-            let formatted = format!("{:#b}", raw_instr).green();
-            self.debug(format!("Next Instruction: {formatted}").as_str());
-
-            if self.debug_mode {
-                self.reg.debug_all();
-                self.debug("Press q to quit, any other key to continue");
-                let mut buff = [0; 1];
-                io::stdin().read_exact(&mut buff).unwrap();
-                if buff[0] == b'q' {
-                    return;
-                }
+        // Input read after the checkpoint is handed out again, ahead of any
+        // input still waiting to be replayed from an earlier rewind
+        let mut replay: VecDeque<_> = self.input_log.drain(checkpoint.input_pos..).collect();
+        replay.extend(self.replay_input.drain(..));
+        self.replay_input = replay;
+
+        self.replaying = true;
+        while self.is_running && self.steps < target {
+            if let Err(e) = self.advance() {
+                self.replaying = false;
+                return Err(e.into());
             }
+        }
+        self.replaying = false;
+        self.mem.take_read_hits();
+
+        Ok(())
+    }
 
-            self.decode_and_execute(raw_instr);
+    /// Simulator messages go to stderr, keeping stdout for the program's own output
+    pub fn warn(&mut self, s: &str) {
+        if !self.replaying {
+            self.warnings.push(s.to_owned());
+            let prompt = "[Warning]".yellow().bold();
+            write!(io::stderr(), "{prompt} {s}\r\n").expect("Failed to write to stderr");
         }
     }
 
-    pub fn load_image(&mut self, path: PathBuf) -> Result<()> {
-        self.debug(format!("Attempting to load image file: {}", path.display()).as_str());
+    pub fn debug_enabled(&self) -> bool {
+        self.debug_mode && !self.muted
+    }
 
-        let mut file = BufReader::new(File::open(path)?);
-        let origin = file.read_u16::<BigEndian>()?;
-        let mut addr = origin;
+    /// Only show debug output produced while executing code in `ranges`,
+    /// e.g. the subroutine being debugged, keeping the OS and trap routines
+    /// quiet. Messages from the debugger prompt are always shown.
+    pub fn set_log_ranges(&mut self, ranges: Vec<MemRegion>) {
+        self.log_ranges = ranges;
+    }
 
-        loop {
-            match file.read_u16::<BigEndian>() {
-                Ok(instr) => {
-                    self.mem.write(addr, instr);
-                    addr = addr.wrapping_add(1);
+    /// Whether code at `pc` is in the log ranges
+    pub fn logs_at(&self, pc: u16) -> bool {
+        self.log_ranges.is_empty()
+            || self
+                .log_ranges
+                .iter()
+                .any(|range| (range.first..=range.last).contains(&pc))
+    }
+
+    /// Prints `s` in debug mode. Use [`debug!`](crate::debug!) instead when
+    /// the message has to be formatted
+    pub fn debug(&self, s: &str) {
+        if self.debug_enabled() {
+            let s = handle_newline(s);
+            let prompt = "[Debug]".cyan().bold();
+
+            write!(io::stderr(), "{prompt} {s}\r\n").expect("Failed to write to stderr");
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        self.is_running = true;
+        self.exit_reason = None;
+        let start = Instant::now();
+        let mut result = Ok(());
+
+        while self.is_running {
+            if self.handle.as_ref().is_some_and(MachineHandle::should_stop) {
+                self.debug("Stopped by handle");
+                self.exit_reason = Some(ExitReason::Stopped);
+                break;
+            }
+            if self.state_dump.as_ref().is_some_and(StateDump::requested) {
+                self.write_state_dump();
+            }
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                self.console.flush();
+                self.debug("Step limit reached");
+                self.print_history("Step limit reached");
+                self.exit_reason = Some(ExitReason::StepLimit);
+                break;
+            }
+
+            // The debugger needs the whole machine, so move it out while it runs
+            if let Some(mut debugger) = self.debugger.take() {
+                let keep_going = debugger.before_instruction(self);
+                self.debugger = Some(debugger);
+                if !keep_going {
+                    self.exit_reason = Some(ExitReason::Quit);
+                    break;
                 }
+            }
+
+            match self.advance() {
                 Err(e) => {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        self.debug("Image loaded successfully");
-                    } else {
-                        self.debug(e.to_string().as_str());
-                        return Err(Error::new(ErrorKind::IOError));
-                    }
+                    self.is_running = false;
+                    self.fault = Some(Fault::from(&e));
+                    self.exit_reason = Some(ExitReason::Error);
+                    result = Err(e);
                     break;
                 }
+                Ok(info) if info.pc == u16::MAX && self.reg.get(Register::PC) == 0 => {
+                    self.debug("PC ran past the end of memory");
+                    self.is_running = false;
+                    self.exit_reason = Some(ExitReason::PcOverflow);
+                    break;
+                }
+                Ok(_) => {}
+            }
+
+            if let Some(mut debugger) = self.debugger.take() {
+                debugger.after_instruction(self);
+                self.debugger = Some(debugger);
             }
         }
 
-        Ok(())
-    }
+        // Diagnostics on stderr come after the program's output
+        self.console.flush();
+        if let Some(mut debugger) = self.debugger.take() {
+            debugger.finish(self);
+            self.debugger = Some(debugger);
+        }
+        self.run_time += start.elapsed();
+        self.exit_reason.get_or_insert(ExitReason::Stopped);
 
-    fn fetch(&mut self) -> u16 {
-        let instr = self.mem.read(self.reg.get(Register::PC));
-        self.reg.incr(Register::PC);
-        instr
+        result
     }
 
-    fn decode_and_execute(&mut self, raw_instr: u16) {
-        if raw_instr == 0 {
+    /// Prints the instruction history to stderr after `reason`, if kept
+    pub fn print_history(&self, reason: &str) {
+        let Some(history) = self.history.as_ref().filter(|history| !history.is_empty()) else {
             return;
+        };
+        let mut stderr = io::stderr();
+        write!(stderr, "{reason}, last {} instructions:\r\n", history.len())
+            .expect("Failed to write to stderr");
+        for line in history
+            .report(history.len(), &SymbolTable::default())
+            .lines()
+        {
+            write!(stderr, "  {line}\r\n").expect("Failed to write to stderr");
         }
-        let raw_op = RawOpCode::from_u16(raw_instr >> 12).unwrap();
-
-        match raw_op {
-            RawOpCode::Add => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let src1 = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
+    }
 
-                // Check if we are in immediate mode
-                let imm_flag = (raw_instr >> 5) & 0x1;
+    /// Executes a single instruction, without consulting an attached debugger.
+    ///
+    /// Lets external drivers (graders, visualizers) control execution one
+    /// instruction at a time instead of going through [`Machine::run`].
+    pub fn step(&mut self) -> Result<StepInfo> {
+        self.is_running = true;
+        Ok(self.advance()?)
+    }
 
-                if imm_flag == 1 {
-                    let imm5 = sign_extend(raw_instr & 0x1F, 5);
-                    self.reg.set(dest, self.reg.get(src1).wrapping_add(imm5));
-                } else {
-                    let src2 = Register::from_u16(raw_instr & 0x7).unwrap();
-                    self.reg
-                        .set(dest, self.reg.get(src1).wrapping_add(self.reg.get(src2)));
-                }
+    /// Executes instructions one at a time until the program halts or stops.
+    ///
+    /// The last item yielded is the HALT (or whichever instruction stopped the
+    /// machine). Execution errors end iteration early, see [`Steps::error`].
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps {
+            machine: self,
+            done: false,
+            error: None,
+        }
+    }
 
-                self.update_flags(dest);
+    fn advance(&mut self) -> Result<StepInfo, VmError> {
+        if self
+            .checkpoints
+            .as_ref()
+            .is_some_and(|checkpoints| checkpoints.is_due(self.steps))
+        {
+            let checkpoint = Checkpoint {
+                steps: self.steps,
+                reg: self.reg.clone(),
+                memory: self.mem.contents().clone(),
+                call_stack: self.call_stack.clone(),
+                psr: self.psr,
+                interrupts: self.interrupts.clone(),
+                perf: self.perf,
+                watchdog: self.watchdog,
+                banks: self.banks.clone(),
+                convention_check: self.convention_check.clone(),
+                taint: self.taint.clone(),
+                input_pos: self.input_log.len(),
+            };
+            if let Some(checkpoints) = self.checkpoints.as_mut() {
+                checkpoints.push(checkpoint);
             }
+        }
 
-            RawOpCode::And => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let src1 = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
+        if let Some(profiler) = self.profiler.as_mut().filter(|_| !self.replaying) {
+            profiler.record(&self.call_stack, self.reg.get(Register::PC));
+        }
 
-                // Check if we are in immediate mode
-                let imm_flag = (raw_instr >> 5) & 0x1;
+        self.poll_keyboard();
+        if let Some(irq) = self.interrupts.accept(self.psr.priority) {
+            self.enter_service(INTERRUPT_TABLE + irq.vector as u16, irq.priority);
+        }
 
-                if imm_flag == 1 {
-                    let imm5 = sign_extend(raw_instr & 0x1F, 5);
-                    self.reg.set(dest, self.reg.get(src1) & imm5);
-                } else {
-                    let src2 = Register::from_u16(raw_instr & 0x7).unwrap();
-                    self.reg.set(dest, self.reg.get(src1) & self.reg.get(src2));
+        let pc = self.reg.get(Register::PC);
+        let sp = self.reg.get(Register::R6);
+        let raw_instr = self.fetch();
+        if self
+            .trace
+            .is_some_and(|filter| filter.matches(pc, raw_instr) && !self.replaying)
+        {
+            let line = format!(
+                "[{:>8}] x{pc:04X}  x{raw_instr:04X}  {}",
+                self.steps,
+                disasm::disassemble(pc, raw_instr)
+            );
+            write!(io::stderr(), "{line}\r\n").expect("Failed to write to stderr");
+        }
+        if let Some(taint) = self.taint.as_mut() {
+            taint.propagate(raw_instr, &self.reg, &self.mem);
+        }
+        self.muted = !self.logs_at(pc);
+        self.ir_addr = pc;
+        let executed = self.decode_and_execute(raw_instr);
+        self.muted = false;
+        executed?;
+        if let Some(coverage) = self.coverage.as_mut().filter(|_| !self.replaying) {
+            coverage.record(pc);
+        }
+        if let Some(history) = self.history.as_mut() {
+            let regs = Register::GENERAL.map(|reg| self.reg.get(reg));
+            history.push(Executed {
+                step: self.steps,
+                pc,
+                word: raw_instr,
+                regs,
+                cond: self.reg.get(Register::COND),
+            });
+        }
+        self.steps += 1;
+
+        if let Some(mode) = self.uninit_check {
+            for addr in self.mem.take_uninit_reads() {
+                let msg = format!("Read of uninitialized memory at {addr:#06x} by {pc:#06x}");
+                self.report(mode, (Check::Uninit, addr), msg);
+            }
+        }
+        if let Some(mode) = self.smc_check {
+            for addr in self.mem.take_code_writes() {
+                let msg =
+                    format!("Self-modifying code: {pc:#06x} wrote executed address {addr:#06x}");
+                if let Some(trace) = self.chrome_trace.as_mut().filter(|_| !self.replaying) {
+                    trace.instant(self.steps, &format!("write to code x{addr:04X}"));
                 }
+                self.report(mode, (Check::SelfModify, addr), msg);
+            }
+        }
+        if let Some((mode, bounds)) = self.stack_check {
+            self.check_stack_pointer(mode, bounds, pc, sp);
+        }
+        if self
+            .watchdog
+            .as_mut()
+            .is_some_and(|watchdog| watchdog.expired(self.steps))
+        {
+            self.watchdog_expired(pc);
+        }
+        if let Some(mode) = self.read_only_check {
+            for addr in self.mem.take_read_only_writes() {
+                let msg =
+                    format!("Access violation: {pc:#06x} wrote read-only address {addr:#06x}");
+                self.report(mode, (Check::ReadOnly, addr), msg);
+            }
+        }
+        if let Some((mode, _)) = self.bounds_check {
+            for addr in std::mem::take(&mut self.wild_writes) {
+                let msg = format!(
+                    "Out-of-bounds write: {pc:#06x} stored to {addr:#06x}, outside the program and the stack"
+                );
+                self.report(mode, (Check::Bounds, addr), msg);
+            }
+        }
+
+        let outcome = if self.halted {
+            StepOutcome::Halted
+        } else if self.is_running {
+            StepOutcome::Continue
+        } else {
+            StepOutcome::Stopped
+        };
+        Ok(StepInfo {
+            pc,
+            word: raw_instr,
+            opcode: RawOpCode::from_u16(raw_instr >> 12).unwrap_or(RawOpCode::Noop),
+            outcome,
+        })
+    }
+
+    /// Raises the keyboard interrupt when the program enabled it in KBSR and
+    /// a key is waiting. Never blocks, and under the debugger only fed input
+    /// counts since the debugger reads the terminal itself.
+    fn poll_keyboard(&mut self) {
+        let kbsr = self.mem.peek(MemMappedReg::Kbsr as u16);
+        if kbsr & KBSR_INTERRUPT_ENABLE == 0 || self.interrupts.is_pending(KEYBOARD_VECTOR) {
+            return;
+        }
+        let arrived = kbsr & KBSR_READY != 0
+            || self.key_due()
+            || (self.replay_input.is_empty()
+                && !self.debug_mode
+                && self.console.poll(Duration::ZERO));
+        // The handler finds the key in KBDR already, like on the real device
+        if arrived && self.latch_key() {
+            self.interrupts.raise(KEYBOARD_PRIORITY, KEYBOARD_VECTOR);
+        }
+    }
 
-                self.update_flags(dest);
+    /// Moves the next key into KBDR and sets KBSR's ready bit, unless KBDR
+    /// still holds a key the program hasn't read. Keys that arrive in the
+    /// meantime queue up behind it instead of replacing it.
+    fn latch_key(&mut self) -> bool {
+        let kbsr = self.mem.peek(MemMappedReg::Kbsr as u16);
+        if kbsr & KBSR_READY != 0 {
+            return true;
+        }
+        match self.read_input() {
+            Some(ch) if ch != 0 => {
+                self.mem
+                    .set_device(MemMappedReg::Kbsr as u16, KBSR_READY | kbsr);
+                self.mem.set_device(MemMappedReg::Kbdr as u16, ch as u16);
+                true
             }
+            _ => false,
+        }
+    }
 
-            RawOpCode::Not => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let src = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
+    /// Key for GETC and IN, taking the one waiting in KBDR first
+    fn read_key(&mut self) -> Option<u8> {
+        let kbsr = self.mem.peek(MemMappedReg::Kbsr as u16);
+        if kbsr & KBSR_READY == 0 {
+            return self.read_input();
+        }
+        self.mem
+            .set_device(MemMappedReg::Kbsr as u16, kbsr & !KBSR_READY);
+        Some(self.mem.peek(MemMappedReg::Kbdr as u16) as u8)
+    }
+
+    /// Starts a service routine: saves PSR and PC on the supervisor stack
+    /// (switching to it from user mode), then jumps to the routine whose
+    /// address is stored at `entry` of the vector table
+    fn enter_service(&mut self, entry: u16, priority: u8) {
+        let psr = self.psr();
+        if !self.psr.supervisor {
+            let usp = self.reg.get(Register::R6);
+            self.reg.set(Register::R6, self.psr.saved_sp);
+            self.psr.saved_sp = usp;
+            self.psr.supervisor = true;
+        }
+        self.psr.priority = priority;
+
+        self.push(psr);
+        self.push(self.reg.get(Register::PC));
+        self.reg.set(Register::PC, self.mem.peek(entry));
+    }
+
+    /// Whether the OS installed a handler for exception `vector`
+    fn handles(&self, vector: u8) -> bool {
+        self.mem.peek(EXCEPTION_TABLE + vector as u16) != 0
+    }
 
-                self.reg.set(dest, !self.reg.get(src));
+    /// Runs the handler for exception `vector`, at the current priority
+    fn exception(&mut self, vector: u8) {
+        self.enter_service(EXCEPTION_TABLE + vector as u16, self.psr.priority);
+    }
+
+    /// Raises an access control violation when user mode touches system space
+    /// or the device registers. Only enforced once the OS handles it, plain
+    /// user programs may poll devices directly.
+    fn access_denied(&mut self, addr: u16) -> bool {
+        let system = !(USER_START..DEVICE_START).contains(&addr);
+        if !self.psr.supervisor && system && self.handles(ACCESS_VIOLATION_VECTOR) {
+            self.exception(ACCESS_VIOLATION_VECTOR);
+            return true;
+        }
+        false
+    }
+
+    fn push(&mut self, val: u16) {
+        self.reg.incr_by(Register::R6, 0xFFFF);
+        self.mem.write(self.reg.get(Register::R6), val);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let val = self.mem.peek(self.reg.get(Register::R6));
+        self.reg.incr(Register::R6);
+        val
+    }
 
-                self.update_flags(dest);
+    fn watchdog_expired(&mut self, pc: u16) {
+        let Some(watchdog) = self.watchdog else {
+            return;
+        };
+        match watchdog.action {
+            WatchdogAction::Halt => {
+                let routine = match self.call_stack.top() {
+                    Some(frame) => format!(" in the subroutine at {:#06x}", frame.entry),
+                    None => String::new(),
+                };
+                let msg = format!(
+                    "Watchdog expired: no write to {:#06x} in {} instructions, now at {pc:#06x}{routine}",
+                    MemMappedReg::Wdt as u16,
+                    watchdog.timeout
+                );
+                self.report(CheckMode::Strict, (Check::Watchdog, pc), msg);
             }
+            WatchdogAction::Interrupt => {
+                self.interrupts.raise(WATCHDOG_PRIORITY, WATCHDOG_VECTOR);
+            }
+        }
+    }
+
+    fn check_stack_pointer(&mut self, mode: CheckMode, bounds: StackBounds, pc: u16, prev_sp: u16) {
+        for addr in std::mem::take(&mut self.stack_reads) {
+            let msg =
+                format!("{pc:#06x} read {addr:#06x}, below the stack pointer R6 = {prev_sp:#06x}");
+            self.report(mode, (Check::Stack, addr), msg);
+        }
+
+        let sp = self.reg.get(Register::R6);
+        if sp == prev_sp {
+            return;
+        }
+        if sp < bounds.low {
+            let msg = format!(
+                "Stack overflow: {pc:#06x} moved R6 to {sp:#06x}, past the stack limit {:#06x}",
+                bounds.low
+            );
+            self.report(mode, (Check::Stack, bounds.low), msg);
+        } else if sp > bounds.high {
+            let msg = format!(
+                "Stack underflow: {pc:#06x} moved R6 to {sp:#06x}, above the stack base {:#06x}",
+                bounds.high
+            );
+            self.report(mode, (Check::Stack, bounds.high), msg);
+        }
+    }
 
-            RawOpCode::Br => {
-                let cond_flag = (raw_instr >> 9) & 0x7;
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
+    /// Surfaces a failed runtime check according to `mode`
+    fn report(&mut self, mode: CheckMode, key: (Check, u16), msg: String) {
+        if self.replaying {
+            return;
+        }
 
-                if (cond_flag & self.reg.get(Register::COND)) != 0 {
-                    self.reg.incr_by(Register::PC, pc_offset);
+        match mode {
+            CheckMode::Warn => {
+                if self.warned.insert(key) {
+                    self.warn(&msg);
                 }
             }
+            CheckMode::Strict if self.debug_mode => self.break_request = Some(msg),
+            CheckMode::Strict => {
+                self.warn(&msg);
+                self.is_running = false;
+                self.exit_reason = Some(ExitReason::CheckFailed);
+                self.fault.get_or_insert(Fault {
+                    pc: self.ir_addr,
+                    word: self.ir,
+                    reason: msg,
+                });
+            }
+        }
+    }
+
+    /// Loads a user program. Several images can be loaded as long as they don't overlap.
+    pub fn load_image(&mut self, path: PathBuf) -> Result<()> {
+        debug!(self, "Attempting to load image file: {}", path.display());
+
+        let image = Image::read(&path)?;
+        image.validate()?;
+        self.place_image(image, path.display().to_string())?;
+        self.debug("Image loaded successfully");
+
+        Ok(())
+    }
+
+    /// Loads an image that may occupy the OS region, e.g. a trap vector table
+    /// and service routines
+    pub fn load_os_image(&mut self, path: PathBuf) -> Result<()> {
+        debug!(self, "Attempting to load OS image file: {}", path.display());
+
+        let image = Image::read(&path)?;
+        image.validate_system()?;
+        let (first, last) = image.range();
+        self.place_image(image, path.display().to_string())?;
+        if self.read_only_check.is_some() {
+            self.mem.protect(first, last);
+        }
+        self.debug("OS image loaded successfully");
+
+        Ok(())
+    }
+
+    /// Loads a raw data file into user memory, see [`Image::read_data`]. Like
+    /// images, data must not overlap anything loaded before.
+    pub fn load_data(&mut self, data: &DataFile, bytes: bool) -> Result<()> {
+        let image = Image::read_data(&data.path, data.addr, bytes)?;
+        image.validate()?;
+        self.place_image(image, data.path.display().to_string())
+    }
+
+    /// Loads a user program from the contents of an object file
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        let image = Image::from_reader(bytes)?;
+        image.validate()?;
+        self.place_image(image, "<bytes>".to_owned())
+    }
+
+    /// Loads a user program given as words to place starting at `origin`
+    pub fn load_words(&mut self, origin: u16, words: &[u16]) -> Result<()> {
+        let image = Image {
+            origin,
+            words: words.to_vec(),
+        };
+        image.validate()?;
+        self.place_image(image, "<words>".to_owned())
+    }
+
+    fn place_image(&mut self, image: Image, name: String) -> Result<()> {
+        let (first, last) = image.range();
+        if let Some((prev_first, prev_last, prev_name)) = self
+            .loaded
+            .iter()
+            .find(|(prev_first, prev_last, _)| first <= *prev_last && *prev_first <= last)
+        {
+            return Err(Error::with_message(
+                ErrorKind::ImageOverlap {
+                    first: *prev_first,
+                    last: *prev_last,
+                },
+                format!(
+                    "{name} (x{first:04X}-x{last:04X}) overlaps {prev_name} (x{prev_first:04X}-x{prev_last:04X})"
+                ),
+            ));
+        }
+
+        for (addr, word) in image.iter() {
+            self.mem.load(addr, word);
+        }
+        self.loaded.push((first, last, name));
 
-            RawOpCode::Jmp => {
-                let base = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
-                self.reg.copy(Register::PC, base);
+        Ok(())
+    }
+
+    fn fetch(&mut self) -> u16 {
+        // Instruction fetches aren't data reads, so they bypass devices and read watches
+        let pc = self.reg.get(Register::PC);
+        if self.smc_check.is_some() {
+            self.mem.mark_executed(pc);
+        }
+        let instr = self.mem.peek(pc);
+        self.reg.incr(Register::PC);
+        if let Some(perf) = self.perf.as_mut() {
+            // The fetch and the execution
+            perf.tick(2);
+        }
+        instr
+    }
+
+    /// Memory read as seen by the program, including memory-mapped devices
+    fn read_mem(&mut self, addr: u16) -> u16 {
+        if let Some((_, bounds)) = self.stack_check {
+            let sp = self.reg.get(Register::R6);
+            if addr >= bounds.low && addr < sp && sp <= bounds.high {
+                self.stack_reads.push(addr);
             }
+        }
+        if let Some(perf) = self.perf.as_mut() {
+            perf.tick(1);
+        }
 
-            RawOpCode::Jsr => {
-                // Check if instruction was JSR or JSRR
-                let miku_bit = (raw_instr >> 11) & 0x1;
+        let kbsr = self.mem.peek(MemMappedReg::Kbsr as u16);
+        if addr == MemMappedReg::Kbsr as u16 && kbsr & KBSR_READY == 0 && self.input_ready() {
+            self.latch_key();
+        } else if addr == MemMappedReg::Kbdr as u16 {
+            // Reading the key frees KBDR for the next one
+            self.mem
+                .set_device(MemMappedReg::Kbsr as u16, kbsr & !KBSR_READY);
+        } else if addr == MemMappedReg::Vsr as u16 {
+            let vsr = self.vsync.read();
+            self.mem.set_device(addr, vsr);
+        } else if addr == MemMappedReg::Gpr as u16 {
+            if let Some(state) = self.gamepad_state() {
+                self.mem.set_device(addr, state);
+            }
+        } else if addr == MemMappedReg::Bsr as u16 {
+            if let Some(banks) = &self.banks {
+                self.mem.set_device(addr, banks.current());
+            }
+        } else if let Some(count) = self
+            .perf
+            .as_mut()
+            .and_then(|perf| perf.read(addr, self.steps))
+        {
+            self.mem.set_device(addr, count);
+        }
 
-                self.reg.copy(Register::R7, Register::PC);
+        self.mem.read(addr)
+    }
 
-                if miku_bit == 1 {
-                    /* JSR */
-                    let pc_offset = sign_extend(raw_instr & 0x7FF, 11);
-                    self.reg.incr_by(Register::PC, pc_offset);
-                } else {
-                    /* JSRR */
-                    let base = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
-                    self.reg.copy(Register::PC, base);
+    /// Memory write by a store instruction, including memory-mapped devices
+    fn write_mem(&mut self, addr: u16, val: u16) {
+        if let Some(perf) = self.perf.as_mut() {
+            perf.tick(1);
+            if PerfCounters::is_counter(addr) {
+                return;
+            }
+        }
+        if !self.psr.supervisor && !self.in_bounds(addr) {
+            self.wild_writes.push(addr);
+        }
+        if addr == MemMappedReg::Wdt as u16 {
+            if let Some(watchdog) = self.watchdog.as_mut() {
+                watchdog.kick(self.steps);
+            }
+        } else if addr == MemMappedReg::Hcr as u16 && self.host_report.is_some() {
+            self.hypercall(val);
+        } else if addr == MemMappedReg::Mcr as u16 && val & MCR_CLOCK_ENABLE == 0 {
+            self.debug("MCR cleared");
+            self.is_running = false;
+            self.exit_reason = Some(ExitReason::McrCleared);
+        } else if addr == MemMappedReg::Bsr as u16 {
+            if let Some(banks) = self.banks.as_mut() {
+                if !banks.select(val, &mut self.mem) {
+                    let count = banks.count();
+                    self.warn(&format!("no memory bank {val}, there are {count}"));
                 }
             }
+        }
+        let old = self.mem.peek(addr);
+        self.mem.write(addr, val);
+        if let Some(log) = self.write_log.as_mut() {
+            log.push(MemWrite {
+                step: self.steps,
+                // Stores leave PC one past themselves
+                pc: self.reg.get(Register::PC).wrapping_sub(1),
+                addr,
+                old,
+                new: self.mem.peek(addr),
+            });
+        }
+    }
 
-            RawOpCode::Ld => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
-                let addr = self.reg.get(Register::PC).wrapping_add(pc_offset);
-
-                self.reg.set(dest, self.mem.read(addr));
-                self.update_flags(dest);
+    fn hypercall(&mut self, cmd: u16) {
+        if self.replaying {
+            return;
+        }
+        let pc = self.reg.get(Register::PC).wrapping_sub(1);
+        let (r0, r1) = (self.reg.get(Register::R0), self.reg.get(Register::R1));
+
+        let failure = match Hypercall::from_u16(cmd) {
+            Some(Hypercall::AssertEq) if r0 != r1 => Some(format!(
+                "Assertion at {pc:#06x} failed: R0 = {r0:#06x}, R1 = {r1:#06x}"
+            )),
+            Some(Hypercall::AssertEq) => None,
+            Some(Hypercall::Pass) | Some(Hypercall::Fail) => {
+                let passed = cmd == Hypercall::Pass as u16;
+                if let Some(report) = self.host_report.as_mut() {
+                    report.passed = passed;
+                    report.finished = true;
+                }
+                self.is_running = false;
+                self.exit_reason = Some(ExitReason::Hypercall);
+                (!passed).then(|| format!("{pc:#06x} reported a failure"))
+            }
+            Some(Hypercall::Print) => {
+                write!(io::stderr(), "[Host] {pc:#06x}: R0 = {r0:#06x} ({r0})\r\n")
+                    .expect("Failed to write to stderr");
+                None
+            }
+            None => {
+                self.warn(&format!("Unknown hypercall {cmd:#06x} at {pc:#06x}"));
+                None
+            }
+        };
+        if let Some(msg) = failure {
+            self.warn(&msg);
+            if let Some(report) = self.host_report.as_mut() {
+                report.failures.push(msg);
             }
+        }
+    }
 
-            RawOpCode::Ldr => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let base = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
-                let offset = sign_extend(raw_instr & 0x3F, 6);
-                let data = self.mem.read(self.reg.get(base).wrapping_add(offset));
+    /// Whether a store to `addr` is expected under [`Machine::check_bounds`],
+    /// always when the check is off
+    fn in_bounds(&self, addr: u16) -> bool {
+        let Some((_, writable)) = &self.bounds_check else {
+            return true;
+        };
+        addr >= DEVICE_START
+            || self
+                .loaded
+                .iter()
+                .any(|(first, last, _)| (*first..=*last).contains(&addr))
+            || writable
+                .iter()
+                .any(|region| (region.first..=region.last).contains(&addr))
+    }
 
-                self.reg.set(dest, data);
-                self.update_flags(dest);
+    /// Whether a KBSR read finds a key. Once a program has been polling for a
+    /// while without one, each poll blocks briefly on the console instead of
+    /// spinning, so waiting for a keypress doesn't peg the host CPU.
+    ///
+    /// The debugger reads its commands from the terminal too, so under it
+    /// KBSR waits for a key like GETC does.
+    fn input_ready(&mut self) -> bool {
+        if self.key_due() {
+            return true;
+        }
+        // Replaying a key that hasn't arrived yet
+        if !self.replay_input.is_empty() {
+            return false;
+        }
+        if self.debug_mode {
+            return true;
+        }
+
+        // Only back-to-back polls are a spin, a game checking for keys once a
+        // frame shouldn't be slowed down
+        if self.steps.abs_diff(self.last_idle_poll) > IDLE_SPIN_GAP {
+            self.idle_polls = 0;
+        }
+        let timeout = if self.idle_polls < IDLE_SPIN_POLLS {
+            Duration::ZERO
+        } else {
+            IDLE_WAIT
+        };
+        if self.console.poll(timeout) {
+            self.idle_polls = 0;
+            return true;
+        }
+
+        self.idle_polls += 1;
+        self.last_idle_poll = self.steps;
+        false
+    }
+
+    /// Whether the next replayed key has arrived by now
+    fn key_due(&self) -> bool {
+        self.replay_input
+            .front()
+            .is_some_and(|keystroke| keystroke.step <= self.steps)
+    }
+
+    /// Reads a byte from the console, stopping the machine once input runs out.
+    /// Like a blocking read, a replayed key is taken even if it's early.
+    fn read_input(&mut self) -> Option<u8> {
+        let ch = match self.replay_input.pop_front() {
+            Some(keystroke) => Some(keystroke.key),
+            None => self.console.read_byte(),
+        };
+        match ch {
+            Some(key) if self.checkpoints.is_some() || self.record_input => {
+                self.input_log.push(Keystroke {
+                    step: self.steps,
+                    key,
+                });
+            }
+            Some(_) => (),
+            None => {
+                self.debug("Input exhausted");
+                self.is_running = false;
+                self.exit_reason = Some(ExitReason::InputExhausted);
             }
+        }
+        ch
+    }
+
+    fn write_output(&mut self, s: &str) -> Result<(), VmError> {
+        if self.replaying {
+            return Ok(());
+        }
+        self.console
+            .write_str(s)
+            .map_err(|e| self.vm_error(VmErrorKind::IoFailure(e.to_string())))
+    }
 
-            RawOpCode::Ldi => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
-                let addr = self.reg.get(Register::PC).wrapping_add(pc_offset);
-                let miku_addr = self.mem.read(addr);
+    fn decode_and_execute(&mut self, raw_instr: u16) -> Result<(), VmError> {
+        self.ir = raw_instr;
+        if let Some(instr) = ExtInstruction::decode(raw_instr, self.extensions) {
+            if self
+                .forbidden_opcodes
+                .is_some_and(|forbidden| forbidden.contains_ext(&instr))
+            {
+                return Err(self.vm_error(VmErrorKind::ForbiddenOpcode(instr.to_string())));
+            }
+            self.execute_ext(instr);
+            return Ok(());
+        }
+        let opcode = RawOpCode::from_u16(raw_instr >> 12).unwrap_or(RawOpCode::Noop);
+        if self
+            .forbidden_opcodes
+            .is_some_and(|forbidden| forbidden.contains(opcode))
+        {
+            let pc = self.reg.get(Register::PC).wrapping_sub(1);
+            let asm = disasm::disassemble(pc, raw_instr);
+            return Err(self.vm_error(VmErrorKind::ForbiddenOpcode(asm)));
+        }
+        match Instruction::decode(raw_instr) {
+            Ok(instr) => self.execute(instr),
+            Err(VmErrorKind::IllegalOpcode) if self.handles(ILLEGAL_OPCODE_VECTOR) => {
+                self.exception(ILLEGAL_OPCODE_VECTOR);
+                Ok(())
+            }
+            Err(kind) => Err(self.vm_error(kind)),
+        }
+    }
 
-                self.reg.set(dest, self.mem.read(miku_addr));
-                self.update_flags(dest);
+    /// Executes `instr` as if it had just been fetched, i.e. with PC already
+    /// pointing past it
+    pub fn execute(&mut self, instr: Instruction) -> Result<(), VmError> {
+        match instr {
+            Instruction::Add { dst, src1, src2 } => {
+                let val = self.alu_operand(src2);
+                self.reg.set(dst, self.reg.get(src1).wrapping_add(val));
+                self.update_flags(dst);
             }
+            Instruction::And { dst, src1, src2 } => {
+                let val = self.alu_operand(src2);
+                self.reg.set(dst, self.reg.get(src1) & val);
+                self.update_flags(dst);
+            }
+            Instruction::Not { dst, src } => {
+                self.reg.set(dst, !self.reg.get(src));
+                self.update_flags(dst);
+            }
+            Instruction::Br { nzp, offset } => {
+                let taken = (nzp as u16 & self.reg.get(Register::COND)) != 0;
+                if let Some(stats) = self.branch_stats.as_mut().filter(|_| !self.replaying) {
+                    let pc = self.reg.get(Register::PC).wrapping_sub(1);
+                    stats.record(pc, self.ir, nzp, taken);
+                }
+                if taken {
+                    self.reg.incr_by(Register::PC, offset as u16);
+                }
+            }
+            Instruction::Jmp { base } => self.jmp(base),
+            Instruction::Jsr { offset } => {
+                self.reg.copy(Register::R7, Register::PC);
+                self.reg.incr_by(Register::PC, offset as u16);
+                self.called();
+            }
+            Instruction::Jsrr { base } => {
+                let target = self.reg.get(base);
+                self.reg.copy(Register::R7, Register::PC);
+                self.reg.set(Register::PC, target);
+                self.called();
+            }
+            Instruction::Ld { dst, offset } => {
+                let addr = self.pc_relative(offset);
+                self.load(dst, addr);
+            }
+            Instruction::Ldr { dst, base, offset } => {
+                let addr = self.reg.get(base).wrapping_add_signed(offset);
+                self.load(dst, addr);
+            }
+            Instruction::Ldi { dst, offset } => {
+                let addr = self.pc_relative(offset);
+                if self.access_denied(addr) {
+                    return Ok(());
+                }
+                let miku_addr = self.read_mem(addr);
+                self.load(dst, miku_addr);
+            }
+            Instruction::Lea { dst, offset } => {
+                let eff_addr = self.pc_relative(offset);
+                self.reg.set(dst, eff_addr);
+                self.update_flags(dst);
+            }
+            Instruction::St { src, offset } => {
+                let addr = self.pc_relative(offset);
+                self.store(src, addr);
+            }
+            Instruction::Sti { src, offset } => {
+                let miku_addr = self.pc_relative(offset);
+                if self.access_denied(miku_addr) {
+                    return Ok(());
+                }
+                let addr = self.read_mem(miku_addr);
+                self.store(src, addr);
+            }
+            Instruction::Str { src, base, offset } => {
+                let addr = self.reg.get(base).wrapping_add_signed(offset);
+                self.store(src, addr);
+            }
+            Instruction::Trap { vector } => self.trap(vector)?,
+            Instruction::Rti if self.psr.supervisor => {
+                let pc = self.pop();
+                let psr = self.pop();
+                self.reg.set(Register::PC, pc);
+                self.reg.set(Register::COND, psr & 0x7);
+                self.psr.priority = ((psr >> 8) & 0x7) as u8;
+                if psr & (1 << 15) != 0 {
+                    let ssp = self.reg.get(Register::R6);
+                    self.reg.set(Register::R6, self.psr.saved_sp);
+                    self.psr.saved_sp = ssp;
+                    self.psr.supervisor = false;
+                }
+            }
+            Instruction::Rti if self.handles(PRIVILEGE_VECTOR) => self.exception(PRIVILEGE_VECTOR),
+            Instruction::Rti => return Err(self.vm_error(VmErrorKind::PrivilegeViolation)),
+        }
 
-            RawOpCode::Lea => {
-                let dest = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
-                let eff_addr = self.reg.get(Register::PC).wrapping_add(pc_offset);
+        Ok(())
+    }
 
-                self.reg.set(dest, eff_addr);
-                self.update_flags(dest);
+    fn execute_ext(&mut self, instr: ExtInstruction) {
+        let (dst, val) = match instr {
+            ExtInstruction::Shf { dst, src, .. } => (dst, instr.apply(self.reg.get(src), 0)),
+            ExtInstruction::Xor { dst, src1, src2 } => {
+                (dst, instr.apply(self.reg.get(src1), self.reg.get(src2)))
             }
+        };
+        self.reg.set(dst, val);
+        self.update_flags(dst);
+    }
 
-            RawOpCode::St => {
-                let src = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
-                let addr = self.reg.get(Register::PC).wrapping_add(pc_offset);
+    fn io_trap(&mut self, op: IoTrap) -> Result<(), VmError> {
+        match op {
+            IoTrap::PutN => {
+                let num = self.reg.get(Register::R0) as i16;
+                self.write_output(&num.to_string())?;
+            }
+            IoTrap::GetN => {
+                let line = String::from_utf8_lossy(&self.read_line(usize::MAX)?).into_owned();
+                let num = line.trim().parse::<i16>().unwrap_or_else(|_| {
+                    self.warn(&format!("GETN read `{line}`, which is not a number"));
+                    0
+                });
+                self.reg.set(Register::R0, num as u16);
+            }
+            IoTrap::GetS => {
+                let start = self.reg.get(Register::R0);
+                let line = self.read_line(self.reg.get(Register::R1) as usize)?;
+                let mut addr = start;
+                for byte in line.iter().copied().chain([0]) {
+                    self.write_mem(addr, byte as u16);
+                    addr = addr.wrapping_add(1);
+                }
+                self.reg.set(Register::R1, line.len() as u16);
+            }
+        }
+        Ok(())
+    }
 
-                self.mem.write(addr, self.reg.get(src));
+    /// Reads keys up to a newline, echoing them, with backspace erasing the
+    /// last one. Keys past `max` characters are dropped. Bytes stay as typed,
+    /// e.g. UTF-8 sequences or extended key codes
+    fn read_line(&mut self, max: usize) -> Result<Vec<u8>, VmError> {
+        let mut line = Vec::new();
+        while let Some(ch) = self.read_key() {
+            match ch {
+                b'\n' | b'\r' => {
+                    self.write_output("\n")?;
+                    break;
+                }
+                0x08 | 0x7F if !line.is_empty() => {
+                    line.pop();
+                    self.write_output("\x08 \x08")?;
+                }
+                0x08 | 0x7F => (),
+                _ if line.len() < max => {
+                    line.push(ch);
+                    self.write_output(String::from(ch as char).as_str())?;
+                }
+                _ => (),
             }
+        }
+        Ok(line)
+    }
 
-            RawOpCode::Sti => {
-                let src = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let pc_offset = sign_extend(raw_instr & 0x1FF, 9);
-                let miku_addr = self.reg.get(Register::PC).wrapping_add(pc_offset);
+    /// Sets the condition codes from R0 like an ALU instruction would
+    fn arith_trap(&mut self, op: ArithTrap) -> Result<(), VmError> {
+        let (a, b) = (
+            self.reg.get(Register::R0) as i16,
+            self.reg.get(Register::R1) as i16,
+        );
+        if b == 0 && op != ArithTrap::Mul {
+            return Err(self.vm_error(VmErrorKind::DivideByZero));
+        }
+        let (r0, r1) = match op {
+            ArithTrap::Mul => {
+                let product = a as i32 * b as i32;
+                (product as u16, (product >> 16) as u16)
+            }
+            ArithTrap::Div => (a.wrapping_div(b) as u16, a.wrapping_rem(b) as u16),
+            ArithTrap::Mod => (a.wrapping_rem_euclid(b) as u16, b as u16),
+        };
+        self.reg.set(Register::R0, r0);
+        self.reg.set(Register::R1, r1);
+        self.update_flags(Register::R0);
+        Ok(())
+    }
+
+    fn alu_operand(&self, operand: AluOperand) -> u16 {
+        match operand {
+            AluOperand::Reg(reg) => self.reg.get(reg),
+            AluOperand::Imm(imm) => imm as u16,
+        }
+    }
 
-                let addr = self.mem.read(miku_addr);
-                self.mem.write(addr, self.reg.get(src));
+    fn pc_relative(&self, offset: i16) -> u16 {
+        self.reg.get(Register::PC).wrapping_add_signed(offset)
+    }
+
+    /// LD, LDR and LDI once the address is known
+    fn load(&mut self, dst: Register, addr: u16) {
+        if self.access_denied(addr) {
+            return;
+        }
+        let data = self.read_mem(addr);
+        self.reg.set(dst, data);
+        self.update_flags(dst);
+    }
+
+    /// ST, STR and STI once the address is known
+    fn store(&mut self, src: Register, addr: u16) {
+        if self.access_denied(addr) {
+            return;
+        }
+        self.write_mem(addr, self.reg.get(src));
+    }
+
+    /// JMP, and RET when `base` is R7
+    fn jmp(&mut self, base: Register) {
+        let ret_site = self.reg.get(Register::PC).wrapping_sub(1);
+        self.reg.copy(Register::PC, base);
+
+        if let Register::R7 = base {
+            /* RET */
+            let depth = self.call_stack.depth();
+            let frame = self.call_stack.ret(self.reg.get(Register::PC));
+            if let Some(trace) = self.chrome_trace.as_mut().filter(|_| !self.replaying) {
+                trace.ret(self.steps, depth - self.call_stack.depth());
+            }
+            if let Some(check) = self.convention_check.as_mut() {
+                let mode = check.mode;
+                let found = check.ret(
+                    ret_site,
+                    self.reg.general(),
+                    frame,
+                    self.call_stack.top(),
+                    self.call_stack.depth(),
+                );
+                for (key, msg) in found {
+                    self.report(mode, (Check::Convention, key), msg);
+                }
             }
+        }
+    }
 
-            RawOpCode::Str => {
-                let src = Register::from_u16((raw_instr >> 9) & 0x7).unwrap();
-                let base = Register::from_u16((raw_instr >> 6) & 0x7).unwrap();
-                let offset = sign_extend(raw_instr & 0x3F, 6);
-                let addr = self.reg.get(base).wrapping_add(offset);
+    /// Bookkeeping after JSR or JSRR, with R7 and PC already updated
+    fn called(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut().filter(|_| !self.replaying) {
+            profiler.call(&self.call_stack, self.reg.get(Register::PC));
+        }
+        self.call_stack.call(Frame {
+            call_site: self.reg.get(Register::R7).wrapping_sub(1),
+            entry: self.reg.get(Register::PC),
+            return_addr: self.reg.get(Register::R7),
+        });
+        if let Some(trace) = self.chrome_trace.as_mut().filter(|_| !self.replaying) {
+            trace.call(self.steps, self.reg.get(Register::PC));
+        }
+        if let Some(check) = self.convention_check.as_mut() {
+            check.call(self.reg.general(), self.call_stack.depth());
+        }
+    }
+
+    fn trap(&mut self, vector: u8) -> Result<(), VmError> {
+        if let Some(trace) = self.chrome_trace.as_mut().filter(|_| !self.replaying) {
+            trace.trap(self.steps, vector);
+        }
+        if !self.replaying {
+            *self.trap_counts.entry(vector).or_default() += 1;
+        }
+        if self.forbidden_traps.contains(vector) {
+            return Err(self.vm_error(VmErrorKind::ForbiddenTrap));
+        }
+        if let Some(op) = ArithTrap::from_u8(vector).filter(|_| self.arith_traps) {
+            if self
+                .forbidden_opcodes
+                .is_some_and(|forbidden| forbidden.contains_arith(op))
+            {
+                return Err(self.vm_error(VmErrorKind::ForbiddenOpcode(op.name().to_owned())));
+            }
+            return self.arith_trap(op);
+        }
+        if let Some(op) = IoTrap::from_u8(vector).filter(|_| self.io_traps) {
+            return self.io_trap(op);
+        }
+        let Some(trap_code) = TrapCode::from_u8(vector) else {
+            return Err(self.vm_error(VmErrorKind::UnknownTrap));
+        };
 
-                self.mem.write(addr, self.reg.get(src));
+        match trap_code {
+            TrapCode::GetC => {
+                let ch = self.read_key();
+                if let Some(ch) = ch.filter(|_| self.echo.getc) {
+                    self.write_output(&(ch as char).to_string())?;
+                }
+                self.reg.set(Register::R0, ch.unwrap_or_default() as u16);
             }
 
-            RawOpCode::Trap => {
-                let trap_code = TrapCode::from_u16(raw_instr & 0xFF);
+            TrapCode::Out => {
+                let ch = self.reg.get(Register::R0) as u8 as char;
+                self.write_output(String::from(ch).as_str())?;
+            }
 
-                if let Some(trap_code) = trap_code {
-                    match trap_code {
-                        TrapCode::GetC => {
-                            let mut buff = [0; 1];
-                            io::stdin().read_exact(&mut buff).unwrap();
+            TrapCode::Puts => {
+                let miku_str = self.read_string(false)?;
+                self.write_output(&miku_str)?;
+            }
 
-                            self.reg.set(Register::R0, buff[0] as u16);
+            TrapCode::In => {
+                let ch = match self.echo.in_style {
+                    InStyle::Plain => {
+                        self.write_output("Enter a character: ")?;
+                        self.read_key()
+                    }
+                    InStyle::Lc3sim => {
+                        self.write_output("\nInput a character> ")?;
+                        let ch = self.read_key();
+                        if let Some(ch) = ch {
+                            self.write_output(&format!("{}\n", ch as char))?;
                         }
+                        ch
+                    }
+                };
+                self.reg.set(Register::R0, ch.unwrap_or_default() as u16);
+            }
 
-                        TrapCode::Out => {
-                            let ch = self.reg.get(Register::R0) as u8 as char;
-                            let miku_str = String::from(ch);
-                            let miku_str = handle_newline(&miku_str);
-                            write!(io::stdout(), "{miku_str}").expect("Failed to write to stdout");
-                            io::stdout().flush().expect("Failed to flush stdout");
-                        }
+            TrapCode::PutsP => {
+                let miku_str = self.read_string(true)?;
+                self.write_output(&miku_str)?;
+            }
+
+            TrapCode::Halt => {
+                if !self.replaying {
+                    self.console.status("Machine Halted");
+                }
+                self.is_running = false;
+                self.halted = true;
+                self.exit_reason = Some(ExitReason::Halted);
+            }
+        }
 
-                        TrapCode::Puts => {
-                            let mut miku_str = String::new();
-                            let mut miku_addr = self.reg.get(Register::R0);
-                            while self.mem.read(miku_addr) != 0x0000 {
-                                let ch = self.mem.read(miku_addr) as u8 as char;
-                                miku_str.push(ch);
-                                miku_addr = miku_addr.wrapping_add(1);
-                            }
-                            miku_str = handle_newline(&miku_str);
-                            write!(io::stdout(), "{miku_str}").expect("Failed to write to stdout");
-                            io::stdout().flush().expect("Failed to flush stdout");
-                        }
+        Ok(())
+    }
 
-                        TrapCode::In => {
-                            write!(io::stdout(), "Enter a character: ")
-                                .expect("Failed to write to stdout");
-                            io::stdout().flush().expect("Failed to flush stdout");
-                            let ch = io::stdin()
-                                .bytes()
-                                .next()
-                                .and_then(|result| result.ok())
-                                .unwrap() as u16;
-                            self.reg.set(Register::R0, ch);
-                        }
+    /// The NUL-terminated string at R0 for PUTS, or PUTSP when `packed` (two
+    /// characters per word, low byte first). Gives up once it has looked at
+    /// every address without finding the terminator.
+    fn read_string(&mut self, packed: bool) -> Result<String, VmError> {
+        let start = self.reg.get(Register::R0);
+        let mut miku_str = String::new();
+
+        for offset in 0..=u16::MAX {
+            let val = self.read_mem(start.wrapping_add(offset));
+            if val == 0x0000 {
+                return Ok(miku_str);
+            }
+            miku_str.push((val & 0xFF) as u8 as char);
+            let c2 = (val >> 8) as u8 as char;
+            if packed && c2 != '\0' {
+                miku_str.push(c2);
+            }
+        }
 
-                        TrapCode::PutsP => {
-                            let mut miku_str = String::new();
-                            let mut miku_addr = self.reg.get(Register::R0);
-
-                            while self.mem.read(miku_addr) != 0x0000 {
-                                let val = self.mem.read(miku_addr);
-                                let c1 = (val & 0xFF) as u8 as char;
-                                miku_str.push(c1);
-                                let c2 = (val >> 8) as u8 as char;
-                                if c2 != '\0' {
-                                    miku_str.push(c2);
-                                }
-                                miku_addr = miku_addr.wrapping_add(1);
-                            }
-                            miku_str = handle_newline(&miku_str);
-                            write!(io::stdout(), "{miku_str}").expect("Failed to write to stdout");
-                            io::stdout().flush().expect("Failed to flush stdout");
-                        }
+        Err(self.vm_error(VmErrorKind::UnterminatedString { start }))
+    }
 
-                        TrapCode::Halt => {
-                            writeln!(io::stdout(), "Machine Halted")
-                                .expect("Faield to write to stdout");
-                            io::stdout().flush().expect("Failed to flush stdout");
-                            self.is_running = false;
-                        }
-                    }
-                } else {
-                    println!("Something fucked");
-                    println!("{raw_instr}");
-                }
-            }
-            RawOpCode::Rti => (),
-            RawOpCode::Noop => (),
-        };
+    /// Error for the instruction that was just fetched
+    fn vm_error(&self, kind: VmErrorKind) -> VmError {
+        VmError {
+            kind,
+            pc: self.reg.get(Register::PC).wrapping_sub(1),
+            word: self.ir,
+        }
     }
 
     fn update_flags(&mut self, register: Register) {
         let flag = CondFlag::from_reg_value(self.reg.get(register));
-        self.reg.set(Register::COND, flag.to_u16().unwrap());
+        self.reg.set(Register::COND, flag as u16);
     }
 }
 
@@ -334,6 +2100,8 @@ impl Machine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::console::BufferConsole;
+    use std::sync::{Arc, Mutex};
     #[test]
     fn test_add() {
         let mut test_mach = Machine::default();
@@ -343,16 +2111,24 @@ mod tests {
         test_mach.reg.set(Register::R4, 7);
         test_mach.reg.set(Register::R7, 13);
 
-        test_mach.decode_and_execute(0b0001_011_000_0_00_001);
+        test_mach
+            .decode_and_execute(0b0001_011_000_0_00_001)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 56);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0001_011_000_0_00_111);
+        test_mach
+            .decode_and_execute(0b0001_011_000_0_00_111)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 69);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0001_100_010_1_10001);
+        test_mach
+            .decode_and_execute(0b0001_100_010_1_10001)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R4), 0b1111_1111_1111_0101);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
-        test_mach.decode_and_execute(0b0001_111_111_1_10011);
+        test_mach
+            .decode_and_execute(0b0001_111_111_1_10011)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R7), 0);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Zero as u16);
     }
@@ -366,16 +2142,24 @@ mod tests {
         test_mach.reg.set(Register::R4, 0b1111_1111_1111_1111);
         test_mach.reg.set(Register::R7, 0b0101_1100_0100_1110);
 
-        test_mach.decode_and_execute(0b0101_011_000_0_00_010);
+        test_mach
+            .decode_and_execute(0b0101_011_000_0_00_010)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 0b0000_0000_0000_0000);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Zero as u16);
-        test_mach.decode_and_execute(0b0101_011_000_0_00_111);
+        test_mach
+            .decode_and_execute(0b0101_011_000_0_00_111)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 0b0000_1000_0100_1000);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0101_010_100_1_00110);
+        test_mach
+            .decode_and_execute(0b0101_010_100_1_00110)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R2), 0b0000_0000_0000_0110);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0101_111_100_1_10011);
+        test_mach
+            .decode_and_execute(0b0101_111_100_1_10011)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::R7), 0b1111_1111_1111_0011);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
     }
@@ -387,13 +2171,13 @@ mod tests {
         test_mach.reg.set(Register::R1, 0b1010_1010_1010_1010);
         test_mach.reg.set(Register::R2, 0b1111_1111_1111_1111);
 
-        test_mach.decode_and_execute(0b1001_011_000_111111);
+        test_mach.decode_and_execute(0b1001_011_000_111111).unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 0b1101_0101_0001_0111);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
-        test_mach.decode_and_execute(0b1001_011_001_111111);
+        test_mach.decode_and_execute(0b1001_011_001_111111).unwrap();
         assert_eq!(test_mach.reg.get(Register::R3), 0b0101_0101_0101_0101);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b1001_110_010_111111);
+        test_mach.decode_and_execute(0b1001_110_010_111111).unwrap();
         assert_eq!(test_mach.reg.get(Register::R6), 0b0000_0000_0000_0000);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Zero as u16);
     }
@@ -404,9 +2188,13 @@ mod tests {
         test_mach.reg.set(Register::PC, 0b0010_1010_1110_1000);
         test_mach.reg.set(Register::COND, 0b010);
 
-        test_mach.decode_and_execute(0b0000_1_0_0_000100110);
+        test_mach
+            .decode_and_execute(0b0000_1_0_0_000100110)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 0b0010_1010_1110_1000);
-        test_mach.decode_and_execute(0b0000_0_1_0_000100110);
+        test_mach
+            .decode_and_execute(0b0000_0_1_0_000100110)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 0b0010_1011_0000_1110);
     }
 
@@ -417,9 +2205,9 @@ mod tests {
         test_mach.reg.set(Register::R0, 15);
         test_mach.reg.set(Register::R5, 69);
 
-        test_mach.decode_and_execute(0b1100_000_101_000000);
+        test_mach.decode_and_execute(0b1100_000_101_000000).unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 69);
-        test_mach.decode_and_execute(0b1100_000_000_000000);
+        test_mach.decode_and_execute(0b1100_000_000_000000).unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 15);
     }
 
@@ -429,10 +2217,1238 @@ mod tests {
         test_mach.reg.set(Register::PC, 0b0010_1010_1110_1000);
         test_mach.reg.set(Register::R5, 420);
 
-        test_mach.decode_and_execute(0b0100_1_01001010110);
+        test_mach.decode_and_execute(0b0100_1_01001010110).unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 0b0010_1101_0011_1110);
-        test_mach.decode_and_execute(0b0100_0_00_101_000000);
+        test_mach
+            .decode_and_execute(0b0100_0_00_101_000000)
+            .unwrap();
         assert_eq!(test_mach.reg.get(Register::PC), 420);
+
+        // JSRR R7 jumps to the old R7, not the return address
+        test_mach.reg.set(Register::R7, 0x4000);
+        test_mach
+            .execute(Instruction::Jsrr { base: Register::R7 })
+            .unwrap();
+        assert_eq!(test_mach.reg.get(Register::PC), 0x4000);
+        assert_eq!(test_mach.reg.get(Register::R7), 420);
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut test_mach = Machine::default();
+        test_mach.reg.set(Register::PC, 0x3001);
+        test_mach.reg.set(Register::R1, 5);
+
+        test_mach
+            .execute(Instruction::Add {
+                dst: Register::R2,
+                src1: Register::R1,
+                src2: AluOperand::Imm(-7),
+            })
+            .unwrap();
+        assert_eq!(test_mach.reg.get(Register::R2), (-2i16) as u16);
+        assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
+
+        test_mach
+            .execute(Instruction::Str {
+                src: Register::R1,
+                base: Register::R2,
+                offset: 0x12,
+            })
+            .unwrap();
+        assert_eq!(test_mach.mem.peek(0x0010), 5);
+
+        test_mach
+            .execute(Instruction::Br {
+                nzp: 0b100,
+                offset: -1,
+            })
+            .unwrap();
+        assert_eq!(test_mach.reg.get(Register::PC), 0x3000);
+
+        let err = test_mach.decode_and_execute(0xF0FF).unwrap_err();
+        assert_eq!(err.kind, VmErrorKind::UnknownTrap);
+        assert_eq!(err.word, 0xF0FF);
+    }
+
+    #[test]
+    fn test_call_stack() {
+        let mut test_mach = Machine::default();
+        test_mach.reg.set(Register::PC, 0x3001);
+
+        test_mach.decode_and_execute(0b0100_1_00000001111).unwrap();
+        let frame = *test_mach.call_stack.top().unwrap();
+        assert_eq!(frame.call_site, 0x3000);
+        assert_eq!(frame.entry, 0x3010);
+        assert_eq!(frame.return_addr, 0x3001);
+
+        test_mach.decode_and_execute(0b1100_000_111_000000).unwrap();
+        assert_eq!(test_mach.reg.get(Register::PC), 0x3001);
+        assert_eq!(test_mach.call_stack.depth(), 0);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let console = BufferConsole::new(b"abc");
+        let output = console.output();
+        let mut test_mach = Machine::with_console(Box::new(console));
+        // GETC; OUT; BR back to the GETC
+        test_mach.mem.write(0x3000, 0xF020);
+        test_mach.mem.write(0x3001, 0xF021);
+        test_mach.mem.write(0x3002, 0x0FFD);
+        test_mach.reg.set(Register::COND, CondFlag::Zero as u16);
+        test_mach.enable_checkpoints(3, 4);
+        test_mach.set_step_limit(Some(6));
+        test_mach.run().unwrap();
+        assert_eq!(*output.lock().unwrap(), "ab");
+
+        // Replays the first GETC with the logged input and without output
+        test_mach.rewind_to(1).unwrap();
+        assert_eq!(test_mach.step_count(), 1);
+        assert_eq!(test_mach.reg.get(Register::R0), 'a' as u16);
+        assert_eq!(*output.lock().unwrap(), "ab");
+
+        test_mach.set_step_limit(Some(9));
+        test_mach.run().unwrap();
+        assert_eq!(*output.lock().unwrap(), "ababc");
+        assert!(test_mach.rewind_to(20).is_err());
+    }
+
+    #[test]
+    fn test_uninit_check() {
+        let mut test_mach = Machine::default();
+        test_mach.check_uninit(Some(CheckMode::Strict));
+        // LD R0, #5 reads x3006, which nothing wrote
+        test_mach.mem.write(0x3000, 0b0010_000_000000101);
+        test_mach.is_running = true;
+
+        test_mach.step().unwrap();
+        assert!(!test_mach.is_running);
+
+        test_mach.enter_debug_mode();
+        test_mach.reg.set(Register::PC, 0x3000);
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+
+        test_mach.mem.write(0x3006, 1);
+        test_mach.reg.set(Register::PC, 0x3000);
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_none());
+    }
+
+    #[test]
+    fn test_self_modifying_check() {
+        let mut test_mach = Machine::default();
+        test_mach.check_self_modifying(Some(CheckMode::Strict));
+        test_mach.enter_debug_mode();
+        // ST R0, #-1 overwrites itself, ST R0, #5 writes data
+        test_mach.mem.write(0x3000, 0b0011_000_111111111);
+        test_mach.mem.write(0x3001, 0b0011_000_000000101);
+
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_none());
+    }
+
+    #[test]
+    fn test_read_only_check() {
+        let mut test_mach = Machine::default();
+        test_mach.check_read_only(
+            Some(CheckMode::Strict),
+            &[MemRegion {
+                first: 0x3000,
+                last: 0x3001,
+            }],
+        );
+        // ST R0, #0 writes the protected word after it
+        test_mach
+            .load_words(0x3000, &[0b0011_000_000000000])
+            .unwrap();
+        test_mach.reg.set(Register::R0, 0x1234);
+
+        test_mach.step().unwrap();
+        assert!(!test_mach.is_running);
+        assert_eq!(test_mach.mem.peek(0x3001), 0);
+    }
+
+    #[test]
+    fn test_hypercalls() {
+        use crate::{enums::Register::*, hypercall::Hypercall, program::Program};
+
+        // Asserts 2 + 2 = 5, then 2 + 2 = 4, and passes
+        let program = Program::new(0x3000)
+            .and(R0, R0, 0)
+            .add(R0, R0, 4)
+            .and(R1, R1, 0)
+            .add(R1, R1, 5)
+            .ld(R2, "ASSERT")
+            .sti(R2, "HCR")
+            .add(R1, R1, -1)
+            .sti(R2, "HCR")
+            .ld(R2, "PASS")
+            .sti(R2, "HCR")
+            .halt()
+            .label("ASSERT")
+            .fill(Hypercall::AssertEq as u16)
+            .label("PASS")
+            .fill(Hypercall::Pass as u16)
+            .label("HCR")
+            .fill(MemMappedReg::Hcr as u16)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_hypercalls();
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.run().unwrap();
+
+        let report = test_mach.host_report().unwrap();
+        assert!(report.passed);
+        assert_eq!(
+            report.failures,
+            ["Assertion at 0x3005 failed: R0 = 0x0004, R1 = 0x0005"]
+        );
+        // Stopped by the pass, before the HALT
+        assert!(!test_mach.halted());
+        assert_eq!(test_mach.reg.get(Register::PC), 0x300A);
+    }
+
+    #[test]
+    fn test_branch_stats() {
+        use crate::{branches::BranchCounts, enums::Register::*, program::Program};
+
+        let program = Program::new(0x3000)
+            .and(R1, R1, 0)
+            .add(R1, R1, 3)
+            .label("LOOP")
+            .add(R1, R1, -1)
+            .br("p", "LOOP")
+            .halt()
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_branch_stats();
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.run().unwrap();
+        assert_eq!(
+            test_mach.branch_stats().unwrap().get(0x3003),
+            Some(BranchCounts {
+                taken: 2,
+                not_taken: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_write_log() {
+        use crate::{enums::Register::*, program::Program};
+
+        let program = Program::new(0x3000)
+            .ld(R0, "VALUE")
+            .st(R0, "SLOT")
+            .add(R0, R0, 1)
+            .st(R0, "SLOT")
+            .halt()
+            .label("VALUE")
+            .fill(0x41)
+            .label("SLOT")
+            .fill(7)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_write_log();
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.run().unwrap();
+        assert_eq!(
+            test_mach.write_log(),
+            [
+                MemWrite {
+                    step: 1,
+                    pc: 0x3001,
+                    addr: 0x3006,
+                    old: 7,
+                    new: 0x41
+                },
+                MemWrite {
+                    step: 3,
+                    pc: 0x3003,
+                    addr: 0x3006,
+                    old: 0x41,
+                    new: 0x42
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_ranges() {
+        let mut test_mach = Machine::default();
+        assert!(test_mach.logs_at(0x0400));
+
+        test_mach.set_log_ranges(vec!["x3000-x30FF".parse().unwrap()]);
+        assert!(test_mach.logs_at(0x3000));
+        assert!(test_mach.logs_at(0x30FF));
+        assert!(!test_mach.logs_at(0x0400));
+
+        // Only muted while executing outside the ranges
+        test_mach.enter_debug_mode();
+        test_mach.muted = !test_mach.logs_at(0x0400);
+        assert!(!test_mach.debug_enabled());
+        test_mach.muted = false;
+        assert!(test_mach.debug_enabled());
+    }
+
+    #[test]
+    fn test_bounds_check() {
+        let mut test_mach = Machine::default();
+        let stack = StackBounds {
+            low: 0x4000,
+            high: 0x4002,
+        };
+        let writable = MemRegion {
+            first: 0x5000,
+            last: 0x5000,
+        };
+        test_mach.check_bounds(Some(CheckMode::Strict), stack, &[writable]);
+        test_mach.enter_debug_mode();
+        // STR R0, R1, #0 four times, then .BLKW 1
+        test_mach
+            .load_words(0x3000, &[0x7040, 0x7040, 0x7040, 0x7040, 0])
+            .unwrap();
+
+        // Into the program, the stack and the extra region
+        for addr in [0x3004, 0x4001, 0x5000] {
+            test_mach.reg.set(Register::R1, addr);
+            test_mach.step().unwrap();
+            assert!(test_mach.take_break_request().is_none(), "{addr:#06x}");
+        }
+
+        // A wild pointer, the store still happens
+        test_mach.reg.set(Register::R0, 0x1234);
+        test_mach.reg.set(Register::R1, 0x6000);
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+        assert_eq!(test_mach.mem.peek(0x6000), 0x1234);
+    }
+
+    #[test]
+    fn test_stack_check() {
+        let mut test_mach = Machine::default();
+        let bounds = StackBounds {
+            low: 0x4000,
+            high: 0x4002,
+        };
+        test_mach.check_stack(Some(CheckMode::Strict), bounds);
+        test_mach.enter_debug_mode();
+        test_mach.reg.set(Register::R6, 0x4002);
+
+        // Two pushes fit, the third overflows
+        for addr in 0x3000..0x3003 {
+            test_mach.mem.write(addr, 0b0001_110_110_1_11111);
+        }
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_none());
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+
+        // LDR R0, R6, #-1 reads below the stack pointer
+        test_mach.reg.set(Register::R6, 0x4001);
+        test_mach.mem.write(0x3003, 0b0110_000_110_111111);
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+
+        // Popping past the base underflows
+        test_mach.reg.set(Register::R6, 0x4002);
+        test_mach.mem.write(0x3004, 0b0001_110_110_1_00001);
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+    }
+
+    #[test]
+    fn test_convention_check() {
+        let mut test_mach = Machine::default();
+        test_mach.check_convention(Some(CheckMode::Strict), "R1".parse().unwrap());
+        test_mach.enter_debug_mode();
+        // JSR #1; HALT; ADD R1, R1, #1; RET
+        test_mach.mem.write(0x3000, 0b0100_1_00000000001);
+        test_mach.mem.write(0x3002, 0b0001_001_001_1_00001);
+        test_mach.mem.write(0x3003, 0b1100_000_111_000000);
+
+        for _ in 0..3 {
+            test_mach.step().unwrap();
+        }
+        assert!(test_mach.take_break_request().is_some());
+        assert_eq!(test_mach.reg.get(Register::PC), 0x3001);
+    }
+
+    #[test]
+    fn test_rewind_convention_check() {
+        let mut test_mach = Machine::default();
+        test_mach.check_convention(Some(CheckMode::Strict), "R1".parse().unwrap());
+        test_mach.enter_debug_mode();
+        test_mach.enable_checkpoints(1, 8);
+        // JSR #1; HALT; ADD R1, R1, #1; RET
+        test_mach.mem.write(0x3000, 0b0100_1_00000000001);
+        test_mach.mem.write(0x3002, 0b0001_001_001_1_00001);
+        test_mach.mem.write(0x3003, 0b1100_000_111_000000);
+        for _ in 0..3 {
+            test_mach.step().unwrap();
+        }
+        assert!(test_mach.take_break_request().is_some());
+
+        // Back inside the subroutine, the registers saved at its entry are too
+        test_mach.rewind_to(1).unwrap();
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+        assert!(test_mach.take_break_request().is_some());
+    }
+
+    #[test]
+    fn test_rewind_taint() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::new(b"a")));
+        test_mach.enable_taint();
+        test_mach.enable_checkpoints(3, 4);
+        // GETC; OUT; HALT
+        test_mach
+            .load_words(0x3000, &[0xF020, 0xF021, 0xF025])
+            .unwrap();
+        test_mach.run().unwrap();
+        assert!(test_mach.halted());
+
+        // Replaying GETC and OUT from the first checkpoint counts them once
+        test_mach.rewind_to(2).unwrap();
+        test_mach.run().unwrap();
+        assert!(test_mach
+            .taint()
+            .unwrap()
+            .report()
+            .contains("1 of 1 output characters derived from input"));
+    }
+
+    #[test]
+    fn test_step_info() {
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x3000, 0b0001_000_000_1_00001);
+
+        let info = test_mach.step().unwrap();
+        assert_eq!(info.pc, 0x3000);
+        assert_eq!(info.opcode, RawOpCode::Add);
+        assert_eq!(info.outcome, StepOutcome::Continue);
+        assert_eq!(info.disassembly(), "ADD R0, R0, #1");
+        assert_eq!(test_mach.reg.get(Register::R0), 1);
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b0101_001_001_1_00000, // AND R1, R1, #0
+                    0b0001_001_001_1_00011, // ADD R1, R1, #3
+                    0b0001_001_001_1_11111, // ADD R1, R1, #-1
+                    0b0000_001_111111110,   // BRp #-2
+                    0xF025,                 // HALT
+                ],
+            )
+            .unwrap();
+
+        let branches = test_mach
+            .steps()
+            .filter(|s| s.opcode == RawOpCode::Br)
+            .count();
+        assert_eq!(branches, 3);
+        assert!(test_mach.halted());
+        assert_eq!(test_mach.step_count(), 9);
+
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x3000, 0xD000);
+        let mut steps = test_mach.steps();
+        assert_eq!(steps.next(), None);
+        assert!(steps.error().is_some());
+    }
+
+    #[test]
+    fn test_history() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_history(3);
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b0001_001_001_1_00001, // ADD R1, R1, #1
+                    0b0000_111_111111110,   // BRnzp #-2
+                ],
+            )
+            .unwrap();
+        test_mach.set_step_limit(Some(5));
+        test_mach.run().unwrap();
+
+        let history = test_mach.history().unwrap();
+        let pcs: Vec<u16> = history.iter().map(|executed| executed.pc).collect();
+        assert_eq!(pcs, [0x3000, 0x3001, 0x3000]);
+        let last = history.iter().last().unwrap();
+        assert_eq!((last.step, last.regs[1], last.cond), (4, 3, 0b001));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x3000, 0b0001_000_000_1_11111); // ADD R0, R0, #-1
+        test_mach.step().unwrap();
+
+        assert_eq!(
+            test_mach.to_string(),
+            "R0 = xFFFF  R1 = x0000  R2 = x0000  R3 = x0000\n\
+             R4 = x0000  R5 = x0000  R6 = x0000  R7 = x0000\n\
+             PC = x3001  COND = N\n\
+             1 instructions executed, running"
+        );
+        assert!(format!("{test_mach:?}").contains("r0: 0xffff"));
+        assert_eq!(
+            test_mach.mem.view(0x3000, 10).to_string(),
+            "x3000: x103F x0000 x0000 x0000 x0000 x0000 x0000 x0000\nx3008: x0000 x0000"
+        );
+    }
+
+    #[test]
+    fn test_clone_diff() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b0001_000_000_1_00010, // ADD R0, R0, #2
+                    0b0011_000_000000001,   // ST R0, #1
+                    0xF025,                 // HALT
+                ],
+            )
+            .unwrap();
+
+        let mut variant = test_mach.clone();
+        assert!(test_mach.diff(&variant).is_empty());
+
+        variant.mem.write(0x3000, 0b0001_000_000_1_00011); // ADD R0, R0, #3
+        test_mach.run().unwrap();
+        variant.run().unwrap();
+
+        let diff = test_mach.diff(&variant);
+        assert_eq!(diff.registers, vec![(Register::R0, 2, 3)]);
+        assert_eq!(diff.memory, vec![(0x3000, 0x1022, 0x1023), (0x3003, 2, 3)]);
+        assert!(diff.to_string().starts_with("R0: x0002 -> x0003\n"));
+    }
+
+    /// Console whose single key only shows up after a number of polls
+    struct SlowConsole {
+        empty_polls: u32,
+        timeouts: Arc<Mutex<Vec<Duration>>>,
+    }
+
+    impl Console for SlowConsole {
+        fn read_byte(&mut self) -> Option<u8> {
+            Some(b'k')
+        }
+
+        fn write_str(&mut self, _s: &str) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn poll(&mut self, timeout: Duration) -> bool {
+            self.timeouts.lock().unwrap().push(timeout);
+            self.empty_polls = self.empty_polls.saturating_sub(1);
+            self.empty_polls == 0
+        }
+
+        fn boxed_clone(&self) -> Box<dyn Console> {
+            Box::new(SlowConsole {
+                empty_polls: self.empty_polls,
+                timeouts: Arc::clone(&self.timeouts),
+            })
+        }
+    }
+
+    #[test]
+    fn test_idle_polling() {
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+        let mut test_mach = Machine::with_console(Box::new(SlowConsole {
+            empty_polls: IDLE_SPIN_POLLS + 3,
+            timeouts: Arc::clone(&timeouts),
+        }));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b1010_000_000000011, // LDI R0, KBSR_PTR
+                    0b0000_011_111111110, // BRzp #-2
+                    0b1010_000_000000010, // LDI R0, KBDR_PTR
+                    0xF025,               // HALT
+                    MemMappedReg::Kbsr as u16,
+                    MemMappedReg::Kbdr as u16,
+                ],
+            )
+            .unwrap();
+        test_mach.run().unwrap();
+
+        assert_eq!(test_mach.reg.get(Register::R0), b'k' as u16);
+        let timeouts = timeouts.lock().unwrap();
+        assert_eq!(timeouts.len() as u32, IDLE_SPIN_POLLS + 3);
+        assert_eq!(timeouts[0], Duration::ZERO);
+        assert_eq!(*timeouts.last().unwrap(), IDLE_WAIT);
+        assert_eq!(test_mach.idle_polls, 0);
+    }
+
+    #[test]
+    fn test_idle_polling_with_work() {
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+        let mut test_mach = Machine::with_console(Box::new(SlowConsole {
+            empty_polls: u32::MAX,
+            timeouts: Arc::clone(&timeouts),
+        }));
+        // A program polling once per frame, with work in between
+        for _ in 0..IDLE_SPIN_POLLS * 2 {
+            test_mach.steps += IDLE_SPIN_GAP + 1;
+            assert!(!test_mach.input_ready());
+        }
+        assert!(timeouts.lock().unwrap().iter().all(Duration::is_zero));
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b1010_000_000000011, // LDI R0, KBSR_PTR
+                    0b0000_011_111111110, // BRzp #-2
+                    0b1010_000_000000010, // LDI R0, KBDR_PTR
+                    0xF025,               // HALT
+                    MemMappedReg::Kbsr as u16,
+                    MemMappedReg::Kbdr as u16,
+                ],
+            )
+            .unwrap();
+        test_mach.replay_keys(&[Keystroke {
+            step: 20,
+            key: b'k',
+        }]);
+        test_mach.record_input();
+        test_mach.run().unwrap();
+
+        assert_eq!(test_mach.reg.get(Register::R0), b'k' as u16);
+        assert_eq!(test_mach.step_count(), 24);
+        assert_eq!(
+            test_mach.input_log(),
+            [Keystroke {
+                step: 20,
+                key: b'k'
+            }]
+        );
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x0180, 0x1000);
+        test_mach.mem.write(0x0181, 0x1100);
+        test_mach.mem.write(0x1000, 0b0001_001_001_1_00001); // ADD R1, R1, #1
+        test_mach.mem.write(0x1001, 0x8000); // RTI
+        test_mach.mem.write(0x1100, 0x8000); // RTI
+        test_mach.reg.set(Register::R6, 0xFE00);
+        test_mach.reg.set(Register::COND, CondFlag::Pos as u16);
+
+        test_mach.raise_interrupt(4, 0x80);
+        let info = test_mach.step().unwrap();
+        assert_eq!(info.pc, 0x1000);
+        assert_eq!(test_mach.psr(), 0x0401);
+        assert_eq!(test_mach.reg.get(Register::R6), 0x2FFE);
+        assert_eq!(test_mach.mem.peek(0x2FFF), 0x8001);
+        assert_eq!(test_mach.mem.peek(0x2FFE), 0x3000);
+
+        // Same or lower priority waits for the handler to return
+        test_mach.raise_interrupt(4, 0x81);
+        test_mach.step().unwrap();
+        assert_eq!(test_mach.reg.get(Register::PC), 0x3000);
+        assert_eq!(test_mach.reg.get(Register::R6), 0xFE00);
+        assert_eq!(test_mach.psr(), 0x8001);
+
+        let info = test_mach.step().unwrap();
+        assert_eq!(info.pc, 0x1100);
+        assert_eq!(test_mach.reg.get(Register::R1), 1);
+    }
+
+    #[test]
+    fn test_keyboard_interrupt() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::new(b"k")));
+        test_mach.mem.write(0x0180, 0x1000);
+        test_mach.mem.write(0x1000, 0b1010_000_000000010); // LDI R0, KBSR
+        test_mach.mem.write(0x1001, 0b1010_000_000000010); // LDI R0, KBDR
+        test_mach.mem.write(0x1002, 0x8000); // RTI
+        test_mach.mem.write(0x1003, 0xFE00);
+        test_mach.mem.write(0x1004, 0xFE02);
+        test_mach.mem.write(0xFE00, KBSR_INTERRUPT_ENABLE);
+
+        let info = test_mach.step().unwrap();
+        assert_eq!(info.pc, 0x1000);
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+        assert_eq!(test_mach.reg.get(Register::R0), 'k' as u16);
+        assert_eq!(test_mach.reg.get(Register::PC), 0x3000);
+        assert_ne!(test_mach.mem.peek(0xFE00) & KBSR_INTERRUPT_ENABLE, 0);
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x3000, 0xD123);
+        test_mach.mem.write(0x3001, 0x8000); // RTI
+        test_mach.mem.write(0x3002, 0b0010_000_111111111); // LD R0, x3002
+        test_mach.mem.write(0x3003, 0b0110_000_001_000000); // LDR R0, R1, #0
+        test_mach.reg.set(Register::R6, 0xFE00);
+        assert_eq!(
+            test_mach.step().unwrap_err().kind(),
+            &ErrorKind::IllegalOpcode {
+                pc: 0x3000,
+                word: 0xD123
+            }
+        );
+        assert_eq!(
+            test_mach.step().unwrap_err().kind(),
+            &ErrorKind::PrivilegeViolation { pc: 0x3001 }
+        );
+        // No access control without an OS to handle violations
+        test_mach.reg.set(Register::R1, 0xFE00);
+        test_mach.step().unwrap();
+        test_mach.step().unwrap();
+
+        // Each handler returns to the instruction after the one that raised it
+        for (vector, handler) in [(0x00, 0x1000), (0x01, 0x1010), (0x02, 0x1020)] {
+            test_mach.mem.write(vector, handler);
+            test_mach.mem.write(handler, 0x8000); // RTI
+        }
+        test_mach.reg.set(Register::R0, 0x1234);
+        for (pc, handler) in [(0x3000, 0x1010), (0x3001, 0x1000), (0x3003, 0x1020)] {
+            test_mach.reg.set(Register::PC, pc);
+            test_mach.step().unwrap();
+            assert_eq!(test_mach.reg.get(Register::PC), handler);
+            assert_eq!(test_mach.psr() >> 15, 0);
+            assert_eq!(test_mach.mem.peek(test_mach.reg.get(Register::R6)), pc + 1);
+            test_mach.step().unwrap();
+            assert_eq!(test_mach.reg.get(Register::PC), pc + 1);
+            assert_eq!(test_mach.reg.get(Register::R6), 0xFE00);
+        }
+        assert_eq!(test_mach.reg.get(Register::R0), 0x1234);
+    }
+
+    #[test]
+    fn test_echo() {
+        let console = BufferConsole::new(b"ab");
+        let output = console.output();
+        let mut test_mach = Machine::with_console(Box::new(console));
+        test_mach.set_echo(Echo {
+            getc: true,
+            in_style: InStyle::Lc3sim,
+        });
+
+        test_mach.decode_and_execute(0xF020).unwrap();
+        test_mach.decode_and_execute(0xF023).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R0), 'b' as u16);
+        assert_eq!(*output.lock().unwrap(), "a\nInput a character> b\n");
+    }
+
+    #[test]
+    fn test_puts() {
+        let console = BufferConsole::new(b"x");
+        let output = console.output();
+        let mut test_mach = Machine::with_console(Box::new(console));
+        test_mach.mem.write(0x4000, 0x6948); // "Hi" packed
+        test_mach.mem.write(0x4001, 0x0021); // "!"
+        test_mach.reg.set(Register::R0, 0x4000);
+        test_mach.decode_and_execute(0xF024).unwrap();
+        assert_eq!(*output.lock().unwrap(), "Hi!");
+
+        for addr in 0..=u16::MAX {
+            test_mach.mem.write(addr, 0x0041);
+        }
+        test_mach.reg.set(Register::PC, 0x3001);
+        let err = test_mach.decode_and_execute(0xF022).unwrap_err();
+        assert_eq!(err.kind, VmErrorKind::UnterminatedString { start: 0x4000 });
+        assert_eq!(*output.lock().unwrap(), "Hi!");
+    }
+
+    #[test]
+    fn test_io_traps() {
+        let console = BufferConsole::new(b"-12\x7f3\nx\n");
+        let output = console.output();
+        let mut test_mach = Machine::with_console(Box::new(console));
+        test_mach.enable_io_traps(true);
+        test_mach.decode_and_execute(0xF027).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R0) as i16, -13);
+        test_mach.decode_and_execute(0xF026).unwrap();
+        assert_eq!(*output.lock().unwrap(), "-12\x08 \x083\n-13");
+
+        test_mach.decode_and_execute(0xF027).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R0), 0);
+
+        // The line is cut short at R1 characters, after backspaces are applied
+        let console = BufferConsole::new(b"ab\x08cdef\n");
+        let mut test_mach = Machine::with_console(Box::new(console));
+        test_mach.enable_io_traps(true);
+        test_mach.reg.set(Register::R0, 0x4000);
+        test_mach.reg.set(Register::R1, 3);
+        test_mach.mem.write(0x4003, 0xFFFF);
+        test_mach.decode_and_execute(0xF028).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R1), 3);
+        assert_eq!(
+            (0x4000..0x4004)
+                .map(|addr| test_mach.mem.peek(addr))
+                .collect::<Vec<_>>(),
+            [b'a' as u16, b'c' as u16, b'd' as u16, 0]
+        );
+
+        // Bytes past x7F, e.g. UTF-8 or extended keys, are stored as read
+        let console = BufferConsole::new(b"a\xC3\xA9\x80\n\xC3\n");
+        let mut test_mach = Machine::with_console(Box::new(console));
+        test_mach.enable_io_traps(true);
+        test_mach.reg.set(Register::R0, 0x4000);
+        test_mach.reg.set(Register::R1, 10);
+        test_mach.decode_and_execute(0xF028).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R1), 4);
+        assert_eq!(
+            (0x4000..0x4005)
+                .map(|addr| test_mach.mem.peek(addr))
+                .collect::<Vec<_>>(),
+            [b'a' as u16, 0xC3, 0xA9, 0x80, 0]
+        );
+        test_mach.decode_and_execute(0xF027).unwrap();
+        assert_eq!(test_mach.reg.get(Register::R0), 0);
+
+        test_mach.enable_io_traps(false);
+        assert!(test_mach.decode_and_execute(0xF026).is_err());
+    }
+
+    #[test]
+    fn test_vsync() {
+        use crate::{enums::Register::*, program::Program};
+
+        // Counts down R1 frames, waiting for each on the vsync register
+        let program = Program::new(0x3000)
+            .ld(R1, "FRAMES")
+            .label("WAIT")
+            .ldi(R0, "VSR")
+            .br("zp", "WAIT")
+            .add(R1, R1, -1)
+            .br("p", "WAIT")
+            .halt()
+            .label("FRAMES")
+            .fill(3)
+            .label("VSR")
+            .fill(MemMappedReg::Vsr as u16)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), 1 + 3 * 4 + 1);
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.set_fps(200);
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        let start = std::time::Instant::now();
+        test_mach.run().unwrap();
+        assert!(test_mach.halted());
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_perf_counters() {
+        use crate::{enums::Register::*, program::Program};
+
+        // Times ADD, LDR and LDI by the instruction and cycle counters
+        let program = Program::new(0x3000)
+            .ldi(R1, "ICL")
+            .ldi(R2, "CCL")
+            .add(R0, R0, 1)
+            .ldr(R0, R6, 0)
+            .ldi(R0, "ICL")
+            .ldi(R3, "ICL")
+            .ldi(R4, "CCL")
+            .sti(R0, "ICL")
+            .ldi(R5, "ICH")
+            .halt()
+            .label("ICL")
+            .fill(MemMappedReg::Icl as u16)
+            .label("CCL")
+            .fill(MemMappedReg::Ccl as u16)
+            .label("ICH")
+            .fill(MemMappedReg::Ich as u16)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_perf_counters(true);
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.run().unwrap();
+        let reg = |r| test_mach.reg().get(r);
+        assert_eq!(reg(R1), 0);
+        assert_eq!(reg(R3) - reg(R1), 5);
+        // LDI, ADD, LDR, LDI, LDI: 4 + 2 + 3 + 4 + 4 cycles
+        assert_eq!(reg(R4) - reg(R2), 17);
+        assert_eq!(reg(R5), 0);
+        assert_eq!(test_mach.mem().peek(MemMappedReg::Icl as u16), reg(R3));
+    }
+
+    #[cfg(feature = "gamepad")]
+    #[test]
+    fn test_gamepad() {
+        use crate::{
+            enums::Register::*,
+            gamepad::{Button, GPR_CONNECTED},
+            program::Program,
+        };
+
+        // Waits for Start, then returns the buttons held with it
+        let program = Program::new(0x3000)
+            .ld(R1, "START")
+            .label("WAIT")
+            .ldi(R0, "GPR")
+            .and(R2, R0, R1)
+            .br("z", "WAIT")
+            .halt()
+            .label("START")
+            .fill(Button::Start as u16)
+            .label("GPR")
+            .fill(MemMappedReg::Gpr as u16)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.set_step_limit(Some(100));
+        test_mach.run().unwrap();
+        assert!(!test_mach.halted());
+
+        let gamepad = test_mach.gamepad();
+        gamepad.set_connected(true);
+        gamepad.press(Button::B);
+        gamepad.press(Button::Start);
+        test_mach.set_step_limit(None);
+        test_mach.run().unwrap();
+        assert!(test_mach.halted());
+        assert_eq!(
+            test_mach.reg().get(R0),
+            GPR_CONNECTED | Button::B as u16 | Button::Start as u16
+        );
+    }
+
+    #[test]
+    fn test_arith_traps() {
+        let run = |op: ArithTrap, r0: i16, r1: i16| {
+            let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+            test_mach.enable_arith_traps(true);
+            test_mach
+                .load_words(0x3000, &[0xF000 | op as u16, 0xF025])
+                .unwrap();
+            test_mach.reg_mut().set(Register::R0, r0 as u16);
+            test_mach.reg_mut().set(Register::R1, r1 as u16);
+            test_mach.run().map(|_| {
+                (
+                    test_mach.reg().get(Register::R0) as i16,
+                    test_mach.reg().get(Register::R1) as i16,
+                )
+            })
+        };
+
+        assert_eq!(run(ArithTrap::Mul, -3, 7).unwrap(), (-21, -1));
+        assert_eq!(run(ArithTrap::Mul, 300, 300).unwrap(), (0x5F90, 1));
+        assert_eq!(run(ArithTrap::Div, -7, 2).unwrap(), (-3, -1));
+        assert_eq!(run(ArithTrap::Mod, -7, 2).unwrap(), (1, 2));
+        assert_eq!(
+            run(ArithTrap::Div, 1, 0).unwrap_err().kind,
+            VmErrorKind::DivideByZero
+        );
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.load_words(0x3000, &[0xF040]).unwrap();
+        assert!(test_mach.run().is_err());
+    }
+
+    #[test]
+    fn test_forbid_traps() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.forbid_traps("PUTS,IN".parse().unwrap());
+        // OUT, PUTS, HALT
+        test_mach
+            .load_words(0x3000, &[0xF021, 0xF022, 0xF025])
+            .unwrap();
+        let error = test_mach.run().unwrap_err();
+        assert_eq!(error.kind, VmErrorKind::ForbiddenTrap);
+        assert_eq!(error.pc, 0x3001);
+        assert_eq!(
+            error.to_string(),
+            "PUTS is forbidden in this run, used at x3001"
+        );
+        assert_eq!(test_mach.exit_reason(), Some(ExitReason::Error));
+        assert_eq!(test_mach.trap_counts().get(&0x22), Some(&1));
+    }
+
+    #[test]
+    fn test_exit_reasons() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // AND R0, R0, #0; STI R0, #0; .FILL xFFFE
+        test_mach
+            .load_words(0x3000, &[0x5020, 0xB000, 0xFFFE])
+            .unwrap();
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.exit_reason(), Some(ExitReason::McrCleared));
+        assert_eq!(test_mach.step_count(), 2);
+
+        // A NOP in the last word falls through to x0000
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.reg_mut().set(Register::PC, 0xFFFF);
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.exit_reason(), Some(ExitReason::PcOverflow));
+        assert_eq!(test_mach.step_count(), 1);
+    }
+
+    #[test]
+    fn test_forbid_opcodes() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.forbid_opcodes(Some("LDI".parse().unwrap()));
+        // LD R0, #1; LDI R1, #0; HALT
+        test_mach
+            .load_words(0x3000, &[0x2001, 0xA200, 0xF025])
+            .unwrap();
+        let error = test_mach.run().unwrap_err();
+        assert_eq!(error.pc, 0x3001);
+        assert_eq!(
+            error.to_string(),
+            "LDI R1, x3002 is forbidden in this run, at x3001"
+        );
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.set_extensions("xor".parse().unwrap());
+        test_mach.forbid_opcodes(Some("xor-ext".parse().unwrap()));
+        test_mach.load_words(0x3000, &[0xD020]).unwrap();
+        assert_eq!(
+            test_mach.run().unwrap_err().kind,
+            VmErrorKind::ForbiddenOpcode("XOR R0, R0, R0".to_owned())
+        );
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.enable_arith_traps(true);
+        test_mach.forbid_opcodes(Some("MUL-ext".parse().unwrap()));
+        // ADD R1, R1, #1; DIV; MUL
+        test_mach
+            .load_words(0x3000, &[0x1261, 0xF041, 0xF040])
+            .unwrap();
+        let error = test_mach.run().unwrap_err();
+        assert_eq!(error.pc, 0x3002);
+        assert_eq!(error.to_string(), "MUL is forbidden in this run, at x3002");
+    }
+
+    #[test]
+    fn test_extensions() {
+        let program = [
+            0b1101_001_000_00_0100,  // LSHF R1, R0, #4
+            0b1101_010_001_10_0_000, // XOR R2, R1, R0
+            0xF025,                  // HALT
+        ];
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.load_words(0x3000, &program).unwrap();
+        test_mach.reg_mut().set(Register::R0, 0x0F0F);
+        assert!(test_mach.run().is_err());
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach.set_extensions("shf,xor".parse().unwrap());
+        test_mach.load_words(0x3000, &program).unwrap();
+        test_mach.reg_mut().set(Register::R0, 0x0F0F);
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.reg().get(Register::R1), 0xF0F0);
+        assert_eq!(test_mach.reg().get(Register::R2), 0xFFFF);
+        assert_eq!(test_mach.reg().get(Register::COND), CondFlag::Neg as u16);
+    }
+
+    #[test]
+    fn test_banking() {
+        use crate::{enums::Register::*, program::Program};
+
+        // Writes 1 at xC000 in bank 1, then reads xC000 back in banks 0 and 1
+        let program = Program::new(0x3000)
+            .and(R1, R1, 0)
+            .add(R1, R1, 1)
+            .sti(R1, "BSR")
+            .sti(R1, "WINDOW")
+            .and(R2, R2, 0)
+            .sti(R2, "BSR")
+            .ldi(R3, "WINDOW")
+            .sti(R1, "BSR")
+            .ldi(R4, "WINDOW")
+            .ldi(R5, "BSR")
+            .halt()
+            .label("BSR")
+            .fill(MemMappedReg::Bsr as u16)
+            .label("WINDOW")
+            .fill(0xC000)
+            .assemble()
+            .unwrap();
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        let window = MemRegion {
+            first: 0xC000,
+            last: 0xDFFF,
+        };
+        test_mach.enable_banking(2, window);
+        test_mach.load_words(0x3000, &program.words).unwrap();
+        test_mach.load_words(0xC000, &[7]).unwrap();
+        test_mach.run().unwrap();
+        assert!(test_mach.halted());
+        assert_eq!(test_mach.reg().get(R3), 7);
+        assert_eq!(test_mach.reg().get(R4), 1);
+        assert_eq!(test_mach.reg().get(R5), 1);
+        assert_eq!(test_mach.banks().unwrap().current(), 1);
+
+        // Rewinding to before the first bank switch brings bank 0 back
+        let mut rewound = Machine::with_console(Box::new(BufferConsole::default()));
+        rewound.enable_banking(2, window);
+        rewound.enable_checkpoints(2, 8);
+        rewound.load_words(0x3000, &program.words).unwrap();
+        rewound.load_words(0xC000, &[7]).unwrap();
+        rewound.run().unwrap();
+        rewound.rewind_to(2).unwrap();
+        assert_eq!(rewound.banks().unwrap().current(), 0);
+        assert_eq!(rewound.mem().peek(0xC000), 7);
+
+        rewound.run().unwrap();
+        assert!(rewound.halted());
+        assert_eq!(rewound.reg().get(R3), 7);
+        assert_eq!(rewound.reg().get(R4), 1);
+        assert_eq!(rewound.banks().unwrap().current(), 1);
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b0001_001_001_1_00001, // ADD R1, R1, #1
+                    0b1011_001_000000010,   // STI R1, WDT_PTR
+                    0b0000_001_111111101,   // BRp #-3
+                    0xF025,                 // HALT
+                    MemMappedReg::Wdt as u16,
+                ],
+            )
+            .unwrap();
+        test_mach.enable_watchdog(Some(Watchdog::new(5, WatchdogAction::Halt)));
+        test_mach.set_step_limit(Some(30));
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), 30);
+
+        // Without the STI the loop starves the watchdog
+        test_mach.mem.write(0x3001, 0x0000);
+        test_mach.reg.set(Register::PC, 0x3000);
+        test_mach.set_step_limit(Some(60));
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), 33);
+        assert!(!test_mach.halted());
+    }
+
+    #[test]
+    fn test_rewind_watchdog() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // ADD R1, R1, #1; BRnzp #-2, never writing the watchdog
+        test_mach.load_words(0x3000, &[0x1261, 0x0FFE]).unwrap();
+        test_mach.enable_watchdog(Some(Watchdog::new(5, WatchdogAction::Halt)));
+        test_mach.enable_checkpoints(2, 8);
+        test_mach.run().unwrap();
+        let fired_at = test_mach.step_count();
+        assert_eq!(test_mach.exit_reason(), Some(ExitReason::CheckFailed));
+
+        // Going back before it fired restores the timer, so it fires again at the same point
+        test_mach.rewind_to(2).unwrap();
+        test_mach.run().unwrap();
+        assert_eq!(test_mach.step_count(), fired_at);
+        assert_eq!(test_mach.exit_reason(), Some(ExitReason::CheckFailed));
+    }
+
+    #[test]
+    fn test_keyboard_queue() {
+        use crate::memory::Access;
+
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::new(b"ab")));
+        test_mach
+            .load_words(
+                0x3000,
+                &[
+                    0b1010_000_000000100, // LDI R0, KBSR_PTR
+                    0b1010_000_000000011, // LDI R0, KBSR_PTR
+                    0b1010_001_000000011, // LDI R1, KBDR_PTR
+                    0xF020,               // GETC
+                    0xF025,               // HALT
+                    MemMappedReg::Kbsr as u16,
+                    MemMappedReg::Kbdr as u16,
+                ],
+            )
+            .unwrap();
+        let accesses = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&accesses);
+        test_mach
+            .mem_mut()
+            .observe(0xFE00..=0xFE02, move |addr, _, access| {
+                seen.lock().unwrap().push((addr, access));
+            });
+        test_mach.run().unwrap();
+
+        // Observers see the program's reads, not the keyboard latching keys
+        assert_eq!(
+            *accesses.lock().unwrap(),
+            [
+                (0xFE00, Access::Read),
+                (0xFE00, Access::Read),
+                (0xFE02, Access::Read)
+            ]
+        );
+
+        // Polling twice doesn't skip a key
+        assert_eq!(test_mach.reg.get(Register::R1), b'a' as u16);
+        assert_eq!(test_mach.reg.get(Register::R0), b'b' as u16);
+        assert!(test_mach.halted());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut test_mach = Machine::default();
+        test_mach.mem.write(0x3000, 0xD123);
+        assert_eq!(
+            test_mach.advance(),
+            Err(VmError {
+                kind: VmErrorKind::IllegalOpcode,
+                pc: 0x3000,
+                word: 0xD123,
+            })
+        );
+
+        test_mach.mem.write(0x3001, 0xF0FF);
+        let err = test_mach.step().unwrap_err();
+        assert_eq!(
+            *err.kind(),
+            ErrorKind::UnknownTrap {
+                vector: 0xFF,
+                pc: 0x3001
+            }
+        );
+
+        test_mach.mem.write(0x3002, 0xF025);
+        test_mach.console = Box::new(BufferConsole::default());
+        assert!(test_mach.step().unwrap().halted());
     }
 
     #[test]
@@ -442,10 +3458,10 @@ mod tests {
         test_mach.mem.write(0b0010_1011_0011_1110, 1205);
         test_mach.mem.write(0b0010_1010_1111_1100, 65142);
 
-        test_mach.decode_and_execute(0b0010_101_001010110);
+        test_mach.decode_and_execute(0b0010_101_001010110).unwrap();
         assert_eq!(test_mach.reg.get(Register::R5), 1205);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0010_001_000010100);
+        test_mach.decode_and_execute(0b0010_001_000010100).unwrap();
         assert_eq!(test_mach.reg.get(Register::R1), 65142);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
     }
@@ -462,10 +3478,10 @@ mod tests {
             .write(0b0010_1010_1111_1100, 0b1110_0011_0111_0101);
         test_mach.mem.write(0b1110_0011_0111_0101, 0);
 
-        test_mach.decode_and_execute(0b1010_101_001010110);
+        test_mach.decode_and_execute(0b1010_101_001010110).unwrap();
         assert_eq!(test_mach.reg.get(Register::R5), 0b1110_0011_0111_0101);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
-        test_mach.decode_and_execute(0b1010_001_000010100);
+        test_mach.decode_and_execute(0b1010_001_000010100).unwrap();
         assert_eq!(test_mach.reg.get(Register::R1), 0);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Zero as u16);
     }
@@ -478,10 +3494,10 @@ mod tests {
         test_mach.mem.write(0b0010_1010_0000_0011, 5087);
         test_mach.mem.write(0b0011_1101_0000_1100, 63251);
 
-        test_mach.decode_and_execute(0b0110_101_000_100101);
+        test_mach.decode_and_execute(0b0110_101_000_100101).unwrap();
         assert_eq!(test_mach.reg.get(Register::R5), 5087);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b0110_100_100_010110);
+        test_mach.decode_and_execute(0b0110_100_100_010110).unwrap();
         assert_eq!(test_mach.reg.get(Register::R4), 63251);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Neg as u16);
     }
@@ -491,10 +3507,10 @@ mod tests {
         let mut test_mach = Machine::default();
         test_mach.reg.set(Register::PC, 0b0111_0101_1011_0110);
 
-        test_mach.decode_and_execute(0b1110_101_001111101);
+        test_mach.decode_and_execute(0b1110_101_001111101).unwrap();
         assert_eq!(test_mach.reg.get(Register::R5), 0b0111_0110_0011_0011);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
-        test_mach.decode_and_execute(0b1110_100_111110001);
+        test_mach.decode_and_execute(0b1110_100_111110001).unwrap();
         assert_eq!(test_mach.reg.get(Register::R4), 0b0111_0101_1010_0111);
         assert_eq!(test_mach.reg.get(Register::COND), CondFlag::Pos as u16);
     }
@@ -506,9 +3522,9 @@ mod tests {
         test_mach.reg.set(Register::R6, 1131);
         test_mach.reg.set(Register::R2, 9999);
 
-        test_mach.decode_and_execute(0b0011_110_000101111);
+        test_mach.decode_and_execute(0b0011_110_000101111).unwrap();
         assert_eq!(test_mach.mem.read(0b1001_1001_1010_1000), 1131);
-        test_mach.decode_and_execute(0b0011_010_100001011);
+        test_mach.decode_and_execute(0b0011_010_100001011).unwrap();
         assert_eq!(test_mach.mem.read(0b1001_1000_1000_0100), 9999);
     }
 
@@ -525,9 +3541,9 @@ mod tests {
         test_mach.reg.set(Register::R6, 6969);
         test_mach.reg.set(Register::R2, 1034);
 
-        test_mach.decode_and_execute(0b1011_110_000101111);
+        test_mach.decode_and_execute(0b1011_110_000101111).unwrap();
         assert_eq!(test_mach.mem.read(0b1000_0011_1011_1111), 6969);
-        test_mach.decode_and_execute(0b1011_010_100001011);
+        test_mach.decode_and_execute(0b1011_010_100001011).unwrap();
         assert_eq!(test_mach.mem.read(0b0111_1001_1000_1101), 1034);
     }
 
@@ -539,9 +3555,9 @@ mod tests {
         test_mach.reg.set(Register::R6, 38292);
         test_mach.reg.set(Register::R2, 15503);
 
-        test_mach.decode_and_execute(0b0111_110_000_101111);
+        test_mach.decode_and_execute(0b0111_110_000_101111).unwrap();
         assert_eq!(test_mach.mem.read(0b1001_0100_1001_0000), 38292);
-        test_mach.decode_and_execute(0b0111_010_100_001011);
+        test_mach.decode_and_execute(0b0111_010_100_001011).unwrap();
         assert_eq!(test_mach.mem.read(0b0111_1000_0111_0011), 15503);
     }
 
@@ -550,6 +3566,76 @@ mod tests {
         let mut test_mach = Machine::default();
         test_mach.enter_debug_mode();
         test_mach.debug("test_debug");
+        debug!(test_mach, "test_debug {}", 1);
+
+        // Arguments aren't evaluated when debug mode is off
+        let quiet = Machine::default();
+        let mut formatted = 0;
+        debug!(quiet, "{}", {
+            formatted += 1;
+            formatted
+        });
+        assert_eq!(formatted, 0);
+    }
+
+    #[test]
+    fn test_threads() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // ADD R0, R0, #1; HALT
+        test_mach.load_words(0x3000, &[0x1021, 0xF025]).unwrap();
+
+        // Run copies in parallel, each on its own thread
+        let runs: Vec<_> = (0..4)
+            .map(|_| {
+                let mut machine = test_mach.clone();
+                std::thread::spawn(move || {
+                    machine.run().unwrap();
+                    machine
+                })
+            })
+            .collect();
+        for run in runs {
+            let machine = run.join().unwrap();
+            assert!(machine.halted());
+            assert_eq!(machine.reg().get(Register::R0), 1);
+        }
+
+        // Inspect one from several threads at once
+        test_mach.run().unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| assert_eq!(test_mach.step_count(), 2));
+            }
+        });
+    }
+
+    #[test]
+    fn test_load_overlap() {
+        let mut test_mach = Machine::default();
+        let path = PathBuf::from("roms/hello-world.obj");
+        test_mach.load_image(path.clone()).unwrap();
+
+        let err = test_mach.load_image(path).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::ImageOverlap { first: 0x3000, .. }
+        ));
+    }
+
+    #[test]
+    fn test_load_from_memory() {
+        let mut test_mach = Machine::with_console(Box::new(BufferConsole::default()));
+        // LEA R0, #2; PUTS; HALT; "hi"
+        let words = [0xE002, 0xF022, 0xF025, 'h' as u16, 'i' as u16, 0];
+        test_mach.load_words(0x3000, &words).unwrap();
+        assert!(test_mach.load_bytes(&[0x30, 0x05, 0x00, 0x00]).is_err());
+        assert!(test_mach.load_bytes(&[0x40, 0x00]).is_err());
+
+        test_mach.load_bytes(&[0x40, 0x00, 0x12, 0x34]).unwrap();
+        assert_eq!(test_mach.mem.peek(0x4000), 0x1234);
+
+        test_mach.run().unwrap();
+        assert!(test_mach.halted());
     }
 
     #[test]
@@ -557,19 +3643,19 @@ mod tests {
         let mut test_mach = Machine::default();
         let res = test_mach.load_image(PathBuf::from("roms/hello-world.obj"));
         assert!(res.is_ok());
-        test_mach.run();
+        test_mach.run().unwrap();
     }
 
     /* TODO: Not sure how to test these, maybe simulate input somehow??
     #[test]
     fn test_trap() {
         let mut test_mach = Machine::default();
-        test_mach.decode_and_execute(0b1111_0000_00100000);
-        test_mach.decode_and_execute(0b1111_0000_00100001);
-        test_mach.decode_and_execute(0b1111_0000_00100010);
-        test_mach.decode_and_execute(0b1111_0000_00100011);
-        test_mach.decode_and_execute(0b1111_0000_00100100);
-        test_mach.decode_and_execute(0b1111_0000_00100101);
+        test_mach.decode_and_execute(0b1111_0000_00100000).unwrap();
+        test_mach.decode_and_execute(0b1111_0000_00100001).unwrap();
+        test_mach.decode_and_execute(0b1111_0000_00100010).unwrap();
+        test_mach.decode_and_execute(0b1111_0000_00100011).unwrap();
+        test_mach.decode_and_execute(0b1111_0000_00100100).unwrap();
+        test_mach.decode_and_execute(0b1111_0000_00100101).unwrap();
     }
     */
 }
@@ -0,0 +1,72 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Bit set in the vsync register when a new frame has started
+pub const VSR_READY: u16 = 1 << 15;
+
+/// Display refresh signal at the vsync register (xFE12), for games to pace
+/// their main loop: a read finds [`VSR_READY`] set once per frame and clear
+/// until the next one starts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Vsync {
+    /// Time between frames. `None` makes every read find a new frame, so
+    /// headless runs don't wait
+    period: Option<Duration>,
+    /// When the next frame starts
+    next_frame: Option<Instant>,
+}
+
+impl Vsync {
+    /// Frames at `fps` per second, or instant frames when `fps` is 0
+    pub fn new(fps: u32) -> Self {
+        Self {
+            period: (fps > 0).then(|| Duration::from_secs(1) / fps),
+            next_frame: None,
+        }
+    }
+
+    /// Value of the register as seen by a read. A read before the next frame
+    /// sleeps until it starts, so a loop polling the register doesn't spin
+    /// the host CPU; the following read finds the frame.
+    pub fn read(&mut self) -> u16 {
+        let Some(period) = self.period else {
+            return VSR_READY;
+        };
+        let now = Instant::now();
+        let next_frame = *self.next_frame.get_or_insert(now);
+
+        if now < next_frame {
+            thread::sleep(next_frame - now);
+            return 0;
+        }
+        // Frames missed while the program was busy aren't made up for
+        let following = next_frame + period;
+        self.next_frame = Some(if following > now {
+            following
+        } else {
+            now + period
+        });
+        VSR_READY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut vsync = Vsync::default();
+        assert_eq!(vsync.read(), VSR_READY);
+        assert_eq!(vsync.read(), VSR_READY);
+
+        let mut vsync = Vsync::new(100);
+        let start = Instant::now();
+        assert_eq!(vsync.read(), VSR_READY);
+        assert_eq!(vsync.read(), 0);
+        assert_eq!(vsync.read(), VSR_READY);
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How often [`Watcher::wait`] looks at the files
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Notices changes to a set of files by polling their modification time and
+/// size, for `run --watch`. A missing file counts as a state of its own, so
+/// deleting and recreating it is a change too.
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+}
+
+impl Watcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let state = state(&path);
+                (path, state)
+            })
+            .collect();
+        Self { files }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.iter().map(|(path, _)| path)
+    }
+
+    /// Whether any file changed since the watcher was made or last asked
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, seen) in &mut self.files {
+            let now = state(path);
+            if now != *seen {
+                *seen = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Blocks until a file changes
+    pub fn wait(&mut self) {
+        while !self.changed() {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn state(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let path = std::env::temp_dir().join("simulator_test_watch.obj");
+        fs::write(&path, [0x30, 0x00]).unwrap();
+
+        let mut watcher = Watcher::new([path.clone()]);
+        assert!(!watcher.changed());
+        fs::write(&path, [0x30, 0x00, 0xF0, 0x25]).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+    }
+}
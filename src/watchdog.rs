@@ -0,0 +1,60 @@
+/// What the watchdog does when the program stops writing to it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum WatchdogAction {
+    /// Stop the machine with a diagnostic, or pause in the debugger
+    #[default]
+    Halt,
+    /// Raise an interrupt through vector x81 at PL7, for the program to recover
+    Interrupt,
+}
+
+/// Memory-mapped timer the program must write to at least once every
+/// `timeout` instructions. Any write to the watchdog register counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchdog {
+    pub timeout: u64,
+    pub action: WatchdogAction,
+    /// Instruction count of the last write, or when the watchdog last fired
+    last_kick: u64,
+}
+
+impl Watchdog {
+    pub fn new(timeout: u64, action: WatchdogAction) -> Self {
+        Self {
+            timeout: timeout.max(1),
+            action,
+            last_kick: 0,
+        }
+    }
+
+    pub fn kick(&mut self, steps: u64) {
+        self.last_kick = steps;
+    }
+
+    /// Whether `timeout` instructions went by without a write. The count
+    /// restarts when it fires, so a program that carries on gets another period.
+    pub fn expired(&mut self, steps: u64) -> bool {
+        if steps.saturating_sub(self.last_kick) < self.timeout {
+            return false;
+        }
+        self.last_kick = steps;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired() {
+        let mut watchdog = Watchdog::new(10, WatchdogAction::Halt);
+        assert!(!watchdog.expired(9));
+        watchdog.kick(5);
+        assert!(!watchdog.expired(14));
+        assert!(watchdog.expired(15));
+        assert!(!watchdog.expired(16));
+        assert!(watchdog.expired(25));
+    }
+}
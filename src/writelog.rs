@@ -0,0 +1,55 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::error::Result;
+
+/// A store the program executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemWrite {
+    /// Instructions executed before the store
+    pub step: u64,
+    /// Address of the storing instruction
+    pub pc: u16,
+    pub addr: u16,
+    pub old: u16,
+    /// What the address holds after the store, `old` if it was dropped
+    pub new: u16,
+}
+
+pub fn save(path: &Path, writes: &[MemWrite]) -> Result<()> {
+    Ok(fs::write(path, format(writes))?)
+}
+
+/// One `STEP xPC xADDR xOLD xNEW` line per store, after a `;` comment
+/// naming the columns
+pub fn format(writes: &[MemWrite]) -> String {
+    let mut out = String::from("; step pc addr old new\n");
+    for write in writes {
+        writeln!(
+            out,
+            "{} x{:04X} x{:04X} x{:04X} x{:04X}",
+            write.step, write.pc, write.addr, write.old, write.new
+        )
+        .unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let writes = [MemWrite {
+            step: 7,
+            pc: 0x3004,
+            addr: 0x4000,
+            old: 0,
+            new: 0x41,
+        }];
+        assert_eq!(
+            format(&writes),
+            "; step pc addr old new\n7 x3004 x4000 x0000 x0041\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Runs the example programs in `tests/fixtures` end to end through the
+//! library, with scripted keyboard input and captured output
+
+use std::{fs, path::PathBuf};
+
+use simulator::{
+    console::{BufferConsole, Echo},
+    runner::run_headless,
+    vm::Machine,
+};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn test_hello_world() {
+    let run = run_headless(&fixture("hello-world.obj"), b"", None, Echo::default()).unwrap();
+    assert!(run.halted);
+    assert_eq!(
+        run.output,
+        fs::read_to_string(fixture("hello-world.out")).unwrap()
+    );
+}
+
+#[test]
+fn test_isa() {
+    let run = run_headless(&fixture("isa.obj"), b"", Some(10_000), Echo::default()).unwrap();
+    assert!(run.halted);
+    assert!(!run.output.contains("FAIL"), "{}", run.output);
+    assert_eq!(run.output, fs::read_to_string(fixture("isa.out")).unwrap());
+}
+
+#[test]
+fn test_2048() {
+    // No ANSI terminal, then one move in each direction
+    let run = run_headless(
+        &fixture("2048.obj"),
+        b"nwasd",
+        Some(1_000_000),
+        Echo::default(),
+    )
+    .unwrap();
+
+    // The game waits for the next move once the input runs out
+    assert!(!run.halted);
+    assert!(run.output.contains("Are you on an ANSI terminal (y/n)?"));
+    assert_eq!(
+        run.output.matches("+--------------------------+").count(),
+        10
+    );
+}
+
+#[test]
+fn test_load_and_run() {
+    let console = BufferConsole::default();
+    let output = console.output();
+    let mut test_mach = Machine::with_console(Box::new(console));
+    test_mach.load_image(fixture("isa.obj")).unwrap();
+    test_mach
+        .load_image(fixture("hello-world.obj"))
+        .unwrap_err();
+
+    let image = fs::read(fixture("isa.obj")).unwrap();
+    assert_eq!(
+        test_mach.mem().peek(0x3000),
+        u16::from_be_bytes([image[2], image[3]])
+    );
+
+    test_mach.run().unwrap();
+    assert!(test_mach.halted());
+    assert_eq!(
+        *output.lock().unwrap(),
+        fs::read_to_string(fixture("isa.out")).unwrap()
+    );
+}
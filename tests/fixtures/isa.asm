@@ -0,0 +1,134 @@
+; ISA unit tests: one line per instruction group, `ok` or `FAIL`.
+; Each test leaves 0 in R3 when the instruction behaved.
+        .ORIG x3000
+
+; ADD, register and immediate forms
+        AND R1, R1, #0
+        ADD R1, R1, #7
+        ADD R2, R1, #-3
+        ADD R3, R1, R2
+        ADD R3, R3, #-11
+        LEA R0, T_ADD
+        JSR CHECK
+
+; AND
+        LD R1, MASK_A
+        LD R2, MASK_B
+        AND R3, R1, R2
+        LD R4, NEG_AB
+        ADD R3, R3, R4
+        LEA R0, T_AND
+        JSR CHECK
+
+; NOT
+        LD R1, MASK_A
+        NOT R3, R1
+        ADD R3, R3, R1
+        ADD R3, R3, #1
+        LEA R0, T_NOT
+        JSR CHECK
+
+; BR on each condition
+        AND R3, R3, #0
+        ADD R3, R3, #1
+        ADD R1, R3, #-1
+        BRnp BR_DONE
+        ADD R1, R1, #-1
+        BRzp BR_DONE
+        ADD R1, R1, #2
+        BRnz BR_DONE
+        BRp BR_PASS
+        BRnzp BR_DONE
+BR_PASS AND R3, R3, #0
+BR_DONE LEA R0, T_BR
+        JSR CHECK
+
+; LD and ST
+        LD R1, VALUE
+        ST R1, SLOT
+        LD R2, SLOT
+        NOT R2, R2
+        ADD R2, R2, #1
+        ADD R3, R1, R2
+        LEA R0, T_LDST
+        JSR CHECK
+
+; LDI and STI
+        LD R1, VALUE
+        STI R1, SLOT_PTR
+        LDI R2, SLOT_PTR
+        NOT R2, R2
+        ADD R2, R2, #1
+        ADD R3, R1, R2
+        LEA R0, T_LDIS
+        JSR CHECK
+
+; LEA, LDR and STR
+        LEA R4, ARRAY
+        LDR R1, R4, #2
+        STR R1, R4, #0
+        LD R2, ARRAY
+        ADD R3, R2, #-5
+        LEA R0, T_LDRS
+        JSR CHECK
+
+; JMP
+        AND R3, R3, #0
+        ADD R3, R3, #1
+        LEA R5, JMP_TO
+        JMP R5
+        ADD R3, R3, #5
+JMP_TO  ADD R3, R3, #-1
+        LEA R0, T_JMP
+        JSR CHECK
+
+; JSR, JSRR and RET
+        AND R3, R3, #0
+        ADD R3, R3, #2
+        JSR DEC_R3
+        LEA R5, DEC_R3
+        JSRR R5
+        LEA R0, T_JSR
+        JSR CHECK
+
+        HALT
+
+DEC_R3  ADD R3, R3, #-1
+        RET
+
+; Prints the name at R0 and whether R3 is 0
+CHECK   ST R7, SAVE_R7
+        PUTS
+        ADD R3, R3, #0
+        BRz CHECK_OK
+        LEA R0, FAIL
+        BRnzp CHECK_OUT
+CHECK_OK LEA R0, OK
+CHECK_OUT PUTS
+        LD R7, SAVE_R7
+        RET
+
+SAVE_R7 .FILL x0000
+MASK_A  .FILL x0FF0
+MASK_B  .FILL x00FF
+NEG_AB  .FILL xFF10
+VALUE   .FILL x1234
+SLOT    .FILL x0000
+SLOT2   .FILL x0000
+SLOT_PTR .FILL SLOT2
+ARRAY   .FILL x0001
+        .FILL x0003
+        .FILL x0005
+
+T_ADD   .STRINGZ "ADD"
+T_AND   .STRINGZ "AND"
+T_NOT   .STRINGZ "NOT"
+T_BR    .STRINGZ "BR"
+T_LDST  .STRINGZ "LD/ST"
+T_LDIS  .STRINGZ "LDI/STI"
+T_LDRS  .STRINGZ "LEA/LDR/STR"
+T_JMP   .STRINGZ "JMP"
+T_JSR   .STRINGZ "JSR/JSRR/RET"
+OK      .STRINGZ " ok\n"
+FAIL    .STRINGZ " FAIL\n"
+        .END